@@ -0,0 +1,160 @@
+use crate::runner_proto::{Artifact, CodeId};
+
+// Artifacts of runs in terminal output of yscript. Markers are OSC sequences ignored by
+// terminals, runners strip them from output and send artifacts as responses.
+
+const MARKER_PREFIX: &[u8] = b"\x1b]5379;ye-artifact=";
+const MARKER_SUFFIX: u8 = 0x07;
+
+/// Largest artifact written by yscript
+pub const MAX_ARTIFACT_SIZE: usize = 4 * 1024 * 1024;
+// prefix, name and mime, base64 of data
+const MAX_MARKER_LEN: usize = 1024 + (MAX_ARTIFACT_SIZE + 2) / 3 * 4;
+
+/// Artifacts in output of a run, markers may be split across chunks
+#[derive(Debug)]
+pub struct ArtifactScanner {
+    id: CodeId,
+    pending: Vec<u8>,
+    // bytes of pending marker searched for suffix
+    searched: usize,
+}
+
+impl ArtifactScanner {
+    pub fn new(id: CodeId) -> Self {
+        ArtifactScanner {
+            id,
+            pending: vec![],
+            searched: 0,
+        }
+    }
+
+    /// Output without markers and artifacts of complete markers, in order
+    pub fn scan(&mut self, data: &[u8]) -> (Vec<u8>, Vec<Artifact>) {
+        let mut buf = std::mem::replace(&mut self.pending, vec![]);
+        let searched = std::mem::replace(&mut self.searched, 0);
+        buf.extend_from_slice(data);
+
+        let mut output = vec![];
+        let mut artifacts = vec![];
+        let mut pos = 0;
+        while let Some(start) = find(&buf[pos..], MARKER_PREFIX).map(|i| pos + i) {
+            output.extend_from_slice(&buf[pos..start]);
+            let value_start = start + MARKER_PREFIX.len();
+            let search_start = if start == 0 {
+                value_start.max(searched)
+            } else {
+                value_start
+            };
+            match buf[search_start..].iter().position(|b| *b == MARKER_SUFFIX) {
+                Some(len) => {
+                    // invalid markers are dropped
+                    let value_end = search_start + len;
+                    artifacts.extend(self.parse(&buf[value_start..value_end]));
+                    pos = value_end + 1;
+                }
+                None if buf.len() - start < MAX_MARKER_LEN => {
+                    buf.drain(..start);
+                    self.searched = buf.len();
+                    self.pending = buf;
+                    return (output, artifacts);
+                }
+                // too large for an artifact, not a marker of yscript
+                None => {
+                    output.extend_from_slice(&buf[start..]);
+                    return (output, artifacts);
+                }
+            }
+        }
+
+        // a marker may start at the end of chunk
+        let tail = &buf[pos..];
+        let mut keep = 0;
+        for len in (1..std::cmp::min(tail.len() + 1, MARKER_PREFIX.len())).rev() {
+            if tail[tail.len() - len..] == MARKER_PREFIX[..len] {
+                keep = len;
+                break;
+            }
+        }
+        output.extend_from_slice(&tail[..tail.len() - keep]);
+        self.pending = tail[tail.len() - keep..].to_vec();
+        (output, artifacts)
+    }
+
+    /// Rest of output at the end of run, an incomplete marker is output as is
+    pub fn finish(&mut self) -> Vec<u8> {
+        self.searched = 0;
+        std::mem::replace(&mut self.pending, vec![])
+    }
+
+    fn parse(&self, value: &[u8]) -> Option<Artifact> {
+        let value = std::str::from_utf8(value).ok()?;
+        let mut parts = value.splitn(3, ';');
+        let name = parts.next().filter(|s| !s.is_empty())?;
+        let mime = parts.next().filter(|s| !s.is_empty())?;
+        let data = base64::decode(parts.next()?).ok()?;
+        Some(Artifact {
+            id: self.id,
+            name: name.to_owned(),
+            mime: mime.to_owned(),
+            data,
+        })
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn marker(name: &str, mime: &str, data: &[u8]) -> Vec<u8> {
+        let mut marker = MARKER_PREFIX.to_vec();
+        marker.extend_from_slice(format!("{};{};{}", name, mime, base64::encode(data)).as_bytes());
+        marker.push(MARKER_SUFFIX);
+        marker
+    }
+
+    #[test]
+    fn scan_artifacts_across_chunks() {
+        let data = [
+            b"done\r\n".to_vec(),
+            marker("plot.svg", "image/svg+xml", b"<svg/>"),
+            marker("notes.md", "text/markdown", b"# notes"),
+            b"\x1b[0m".to_vec(),
+        ]
+        .concat();
+
+        for split in 0..data.len() {
+            let mut scanner = ArtifactScanner::new(1);
+            let (mut output, mut artifacts) = scanner.scan(&data[..split]);
+            let (rest_output, rest_artifacts) = scanner.scan(&data[split..]);
+            output.extend(rest_output);
+            output.extend(scanner.finish());
+            artifacts.extend(rest_artifacts);
+
+            assert_eq!(b"done\r\n\x1b[0m".to_vec(), output, "split at {}", split);
+            let names: Vec<_> = artifacts.iter().map(|a| a.name.as_str()).collect();
+            assert_eq!(vec!["plot.svg", "notes.md"], names);
+            assert_eq!(b"<svg/>".to_vec(), artifacts[0].data);
+            assert_eq!("text/markdown", artifacts[1].mime);
+        }
+    }
+
+    #[test]
+    fn scan_invalid_markers() {
+        let mut scanner = ArtifactScanner::new(1);
+        let (output, artifacts) = scanner.scan(b"a\x1b]5379;ye-artifact=x.png;image/png\x07b");
+        assert_eq!(b"ab".to_vec(), output);
+        assert!(artifacts.is_empty());
+
+        // incomplete marker at the end of run
+        let (output, _) = scanner.scan(b"c\x1b]5379;ye-art");
+        assert_eq!(b"c".to_vec(), output);
+        assert_eq!(b"\x1b]5379;ye-art".to_vec(), scanner.finish());
+    }
+}
@@ -1,2 +1,3 @@
 
+pub mod artifact;
 pub mod runner_proto;
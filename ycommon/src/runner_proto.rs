@@ -1,34 +1,98 @@
 use actix::prelude::Message;
+use schemars::JsonSchema;
 use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-#[derive(Debug, Clone, Deserialize, Serialize, Message)]
+/// Protocol version of yeditor, sent to runner in PROTOCOL_VERSION_HEADER on connecting.
+/// Runners of version 1 ignore the header and send stdout as utf-8 text,
+/// runners of version 2 send raw output bytes as `stdout_raw`,
+/// runners of version 3 start with a `hello` response, answered by a `hello` request.
+pub const PROTOCOL_VERSION: u32 = 3;
+pub const PROTOCOL_VERSION_HEADER: &str = "X-Yeditor-Runner-Proto";
+
+/// Runner sends output as `stdout_raw`
+pub const CAP_STDOUT_RAW: &str = "stdout_raw";
+
+/// Runner formats and lints code, `format` and `lint` requests
+pub const CAP_CODE_TOOLS: &str = "code_tools";
+
+/// Runner starts a language server of room, `lsp_start`, `lsp` and `lsp_stop` requests
+pub const CAP_LSP: &str = "lsp";
+
+/// Runner runs code under debugger, `run` requests with `debug`
+pub const CAP_DEBUG: &str = "debug";
+
+/// Runner creates a database of room on `reset` with `database`, dropped with the room
+pub const CAP_DATABASE: &str = "database";
+
+/// Runner sends files written by code to `output/` as `artifact` responses
+pub const CAP_ARTIFACT: &str = "artifact";
+
+/// Runner keeps files of the latest run for a while, `list_files` and `fetch_file` requests
+pub const CAP_RUN_FILES: &str = "run_files";
+
+/// Capabilities supported by yeditor
+pub const CAPABILITIES: &[&str] = &[
+    CAP_STDOUT_RAW,
+    CAP_CODE_TOOLS,
+    CAP_LSP,
+    CAP_DEBUG,
+    CAP_DATABASE,
+    CAP_ARTIFACT,
+    CAP_RUN_FILES,
+];
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema, Message)]
 #[serde(rename_all = "snake_case")]
 pub struct ServiceStats {
     pub rooms: Vec<String>,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize, Message)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema, Message)]
 #[serde(rename_all = "snake_case")]
 pub enum ServiceRequests {
+    Hello(Hello),
     Reset(RunEnv),
     Run(Code),
     WinSize(WinSize),
     Stdin(String),
+    // formatter and linter of language, not shown in terminal
+    Format(Code),
+    Lint(Code),
+    // language server of room, messages are json-rpc without Content-Length framing
+    LspStart(LspStart),
+    Lsp(LspMessage),
+    LspStop(LspStop),
+    // files of the latest run, kept by runner until its ttl or the next run
+    ListFiles(ListFiles),
+    FetchFile(FetchFile),
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize, Message)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema, Message)]
 #[serde(rename_all = "snake_case")]
 pub enum ServiceResponses {
+    Hello(ServiceResult<Hello>),
     Init(ServiceResult<InitResp>),
     Reset(ServiceResult<ResetResp>),
     Run(ServiceResult<RunResp>),
     WinSize(ServiceResult<WinSize>),
     Stdout(ServiceResult<StdoutResp>),
+    StdoutRaw(ServiceResult<StdoutRawResp>),
+    Format(ServiceResult<FormatResp>),
+    Lint(ServiceResult<LintResp>),
+    LspStart(ServiceResult<LspStartResp>),
+    Lsp(ServiceResult<LspMessage>),
+    // language server exited, requested or not
+    LspStop(ServiceResult<LspStop>),
+    // rich output of run, after its stdout
+    Artifact(ServiceResult<Artifact>),
+    ListFiles(ServiceResult<FileList>),
+    FetchFile(ServiceResult<FileData>),
 }
 
 // service result
 
-#[derive(Debug, Clone, Deserialize, Serialize, Message)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema, Message)]
 #[serde(rename_all = "snake_case")]
 pub enum ServiceResult<T> {
     Ok(T),
@@ -53,32 +117,93 @@ impl<T> Into<Result<T, ServiceError>> for ServiceResult<T> {
     }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize, Message)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema, Message)]
 #[serde(rename_all = "snake_case")]
 pub enum ServiceError {
     // service errors
     ErrServiceInternal,
     // init errors
     ErrInitRoomExists,
+    // hello errors
+    ErrProtocolVersion,
+    // format, lint, language server, debug and run files errors
+    ErrNotSupported,
+    // run files errors
+    ErrFileNotFound,
+    ErrFileTooLarge,
+}
+
+// hello
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct Hello {
+    pub version: u32,
+    pub capabilities: Vec<String>,
+}
+
+impl Hello {
+    /// Hello of this side
+    pub fn current() -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            capabilities: CAPABILITIES.iter().map(|cap| (*cap).to_owned()).collect(),
+        }
+    }
+
+    /// Lower version and common capabilities of both sides
+    pub fn negotiate(&self, peer: &Hello) -> Hello {
+        Hello {
+            version: std::cmp::min(self.version, peer.version),
+            capabilities: self
+                .capabilities
+                .iter()
+                .filter(|cap| peer.has_capability(cap))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    pub fn has_capability(&self, cap: &str) -> bool {
+        self.capabilities.iter().any(|c| c == cap)
+    }
 }
 
 // init
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct InitResp {}
 
 // reset
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct RunEnv {
     pub win_size: WinSize,
     pub language: String,
     pub boot: Option<Code>,
+    // options of runs in this environment
+    #[serde(default, skip_serializing_if = "RunOptions::is_empty")]
+    pub options: RunOptions,
+    // database of room for sql languages, runs connect to it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub database: Option<DatabaseParams>,
+}
+
+/// Throwaway database of room, recreated from seed on every reset and dropped with the room.
+/// Runs connect as a user of the same name, granted nothing but this database.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct DatabaseParams {
+    // lowercase letters, digits and underscores, unique per room
+    pub name: String,
+    // sql statements run in the new database
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub seed: String,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct ResetResp {}
 
@@ -86,16 +211,71 @@ pub struct ResetResp {}
 
 pub type CodeId = u32;
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct Code {
     pub id: CodeId,
     pub language: String,
     pub filename: String,
     pub content: String,
+    // program input, piped to stdin of the code instead of terminal
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdin: Option<String>,
+    #[serde(default, skip_serializing_if = "RunOptions::is_empty")]
+    pub options: RunOptions,
+    // dependency manifest of pad, resolved by yscript from offline packages
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<Dependency>,
+    // run under debugger of language, stdin of terminal goes to the debugger
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub debug: Option<DebugParams>,
+}
+
+/// Debug run of code, the debugger writes stop locations to output as
+/// `ESC ] 5379 ; ye-debug-stop=<line> BEL`, line is empty when stopped outside the code
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct DebugParams {
+    // 1-based lines of code
+    pub breakpoints: Vec<u32>,
+}
+
+/// Package of pad dependency manifest, crate, npm package or python distribution by language
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct Dependency {
+    pub name: String,
+    // version requirement, any version if empty
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub version: String,
+}
+
+/// Compiler flags, program arguments, language version and environment of a run,
+/// validated by yscript against allow list of the language
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct RunOptions {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub compiler_flags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    // toolchain default if none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+}
+
+impl RunOptions {
+    pub fn is_empty(&self) -> bool {
+        self.compiler_flags.is_empty()
+            && self.args.is_empty()
+            && self.version.is_none()
+            && self.env.is_empty()
+    }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct RunResp {
     pub id: CodeId,
@@ -103,9 +283,86 @@ pub struct RunResp {
     pub duration_ms: f64,
 }
 
+// format and lint
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct FormatResp {
+    pub id: CodeId,
+    // formatted code, empty on error
+    pub content: String,
+    // formatter output when code can not be formatted, e.g. syntax error
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct LintResp {
+    pub id: CodeId,
+    pub diagnostics: Vec<Diagnostic>,
+    // linter output when it failed to run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Linter message at 1-based line and column of code
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct Diagnostic {
+    pub line: u32,
+    pub column: u32,
+    pub severity: Severity,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+// language server
+
+/// Starts language server of language with code as the only source file,
+/// a running server of room is stopped first. Servers are stopped by reset.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct LspStart {
+    pub id: CodeId,
+    pub language: String,
+    pub filename: String,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct LspStartResp {
+    pub id: CodeId,
+    // workspace of server and uri of the source file in it
+    pub root_uri: String,
+    pub uri: String,
+}
+
+/// Message of language server started by `lsp_start` of same id
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct LspMessage {
+    pub id: CodeId,
+    pub message: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct LspStop {
+    pub id: CodeId,
+}
+
 // win size
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct WinSize {
     pub row: u16,
@@ -114,13 +371,104 @@ pub struct WinSize {
 
 // stdout
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct StdoutResp {
     pub id: CodeId,
     pub data: String,
 }
 
+// raw stdout, protocol v2
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct StdoutRawResp {
+    pub id: CodeId,
+    #[serde(with = "base64_bytes")]
+    #[schemars(with = "String")]
+    pub data: Vec<u8>,
+}
+
+/// File written by code to `output/` of its directory, markdown, html or image by mime type.
+/// yscript writes artifacts to output as `ESC ] 5379 ; ye-artifact=<name>;<mime>;<base64> BEL`
+/// at the end of run, runners strip them from stdout.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct Artifact {
+    pub id: CodeId,
+    pub name: String,
+    pub mime: String,
+    #[serde(with = "base64_bytes")]
+    #[schemars(with = "String")]
+    pub data: Vec<u8>,
+}
+
+// run files
+
+/// Lists files in work directory of the latest run
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct ListFiles {
+    pub id: CodeId,
+}
+
+/// Fetches a file of the latest run by its name in `list_files`,
+/// files over `max_size` bytes are refused with `err_file_too_large`
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct FetchFile {
+    pub id: CodeId,
+    pub name: String,
+    pub max_size: u64,
+}
+
+/// Regular file of run, name is relative to its work directory with `/` separators
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct RunFile {
+    pub name: String,
+    pub size: u64,
+}
+
+// errors are in payload rather than result, so they are matched to requests by id
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct FileList {
+    pub id: CodeId,
+    // empty when no run finished, or its files expired
+    pub files: Vec<RunFile>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ServiceError>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct FileData {
+    pub id: CodeId,
+    pub name: String,
+    // empty on error
+    #[serde(with = "base64_bytes")]
+    #[schemars(with = "String")]
+    pub data: Vec<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ServiceError>,
+}
+
+/// Bytes as base64 string in json
+pub mod base64_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(base64::encode(data).as_str())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        base64::decode(text.as_str()).map_err(serde::de::Error::custom)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -132,12 +480,16 @@ mod test {
             language: "python3".into(),
             filename: "".into(),
             content: r#"print("hello")"#.into(),
+            stdin: None,
+            options: RunOptions::default(),
+            dependencies: vec![],
+            debug: None,
         });
         match serde_json::to_string(&req) {
             Ok(json) => {
                 println!("ServiceRequests = {}", json);
                 assert_eq!(
-                    r#"{"t":"run","c":{"id":0,"language":"python3","filename":"","content":"print(\"hello\")"}}"#, 
+                    r#"{"run":{"id":0,"language":"python3","filename":"","content":"print(\"hello\")"}}"#,
                     json,
                 );
             }
@@ -153,7 +505,7 @@ mod test {
         match serde_json::to_string(&resp) {
             Ok(json) => {
                 println!("ServiceResponses = {}", json);
-                assert_eq!(r#"{"t":"init","c":{"err":"err_init_room_exists"}}"#, json);
+                assert_eq!(r#"{"init":{"err":"err_init_room_exists"}}"#, json);
             }
             Err(err) => {
                 panic!("json encoding failure: {:?}", err);
@@ -170,14 +522,58 @@ mod test {
         match serde_json::to_string(&resp) {
             Ok(json) => {
                 println!("ServiceResponse = {}", json);
-                assert_eq!(
-                    r#"{"t":"stdout","c":{"ok":{"id":3,"data":"code output"}}}"#,
-                    json
-                );
+                assert_eq!(r#"{"stdout":{"ok":{"id":3,"data":"code output"}}}"#, json);
             }
             Err(err) => {
                 panic!("json encoding failure: {:?}", err);
             }
         }
     }
+
+    #[test]
+    fn json_service_responses_stdout_raw() {
+        // invalid utf-8 and a multibyte char split across chunks survive
+        let data = vec![0xff, b'o', b'k', 0xe4, 0xb8];
+        let resp = ServiceResponses::StdoutRaw(
+            Ok(StdoutRawResp {
+                id: 3,
+                data: data.clone(),
+            })
+            .into(),
+        );
+        let json = serde_json::to_string(&resp).unwrap();
+        assert_eq!(r#"{"stdout_raw":{"ok":{"id":3,"data":"/29r5Lg="}}}"#, json);
+
+        match serde_json::from_str::<ServiceResponses>(json.as_str()).unwrap() {
+            ServiceResponses::StdoutRaw(ServiceResult::Ok(resp)) => assert_eq!(data, resp.data),
+            other => panic!("unexpected response: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn hello_negotiate() {
+        let peer = Hello {
+            version: 5,
+            capabilities: vec![CAP_STDOUT_RAW.to_owned(), "future".to_owned()],
+        };
+        let hello = Hello::current().negotiate(&peer);
+        assert_eq!(PROTOCOL_VERSION, hello.version);
+        assert!(hello.has_capability(CAP_STDOUT_RAW));
+        assert!(!hello.has_capability("future"));
+    }
+
+    // messages shared with yrunner-go tests, both sides must read and write them unchanged
+    #[test]
+    fn json_round_trip_fixtures() {
+        let fixtures: serde_json::Value =
+            serde_json::from_str(include_str!("../../docs/protocol/runner_messages.json")).unwrap();
+        for expected in fixtures["requests"].as_array().unwrap() {
+            let req: ServiceRequests = serde_json::from_value(expected.clone()).unwrap();
+            assert_eq!(expected, &serde_json::to_value(&req).unwrap());
+        }
+        for expected in fixtures["responses"].as_array().unwrap() {
+            let resp: ServiceResponses = serde_json::from_value(expected.clone()).unwrap();
+            assert_eq!(expected, &serde_json::to_value(&resp).unwrap());
+        }
+    }
 }
@@ -0,0 +1,4 @@
+DROP TRIGGER IF EXISTS set_updated_at ON webhook_deliveries;
+DROP TABLE IF EXISTS webhook_deliveries;
+DROP TRIGGER IF EXISTS set_updated_at ON webhooks;
+DROP TABLE IF EXISTS webhooks;
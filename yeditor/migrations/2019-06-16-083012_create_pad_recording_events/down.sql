@@ -0,0 +1 @@
+DROP TABLE pad_recording_events;
@@ -0,0 +1,9 @@
+CREATE TABLE pad_recording_events (
+    id BIGSERIAL PRIMARY KEY,
+    pad_id INTEGER NOT NULL REFERENCES pads(id) ON DELETE CASCADE,
+    event_type VARCHAR(8) NOT NULL,
+    data BYTEA NOT NULL,
+    created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX pad_recording_events_pad_id ON pad_recording_events (pad_id, id);
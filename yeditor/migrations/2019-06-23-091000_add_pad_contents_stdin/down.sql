@@ -0,0 +1 @@
+ALTER TABLE pad_contents DROP COLUMN IF EXISTS stdin;
@@ -0,0 +1 @@
+ALTER TABLE pad_contents ADD COLUMN stdin TEXT NOT NULL DEFAULT '';
@@ -0,0 +1 @@
+ALTER TABLE pad_contents ADD COLUMN dependencies TEXT NOT NULL DEFAULT '[]';
@@ -0,0 +1 @@
+ALTER TABLE pad_contents ADD COLUMN database_seed TEXT NOT NULL DEFAULT '';
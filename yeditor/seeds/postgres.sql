@@ -0,0 +1,23 @@
+-- template seed of postgres pads, run in the database of the room on every reset
+
+CREATE TABLE departments (
+  id INT PRIMARY KEY,
+  name TEXT NOT NULL
+);
+
+CREATE TABLE employees (
+  id INT PRIMARY KEY,
+  name TEXT NOT NULL,
+  department_id INT REFERENCES departments (id),
+  salary INT NOT NULL
+);
+
+INSERT INTO departments (id, name) VALUES
+  (1, 'Engineering'),
+  (2, 'Sales');
+
+INSERT INTO employees (id, name, department_id, salary) VALUES
+  (1, 'Alice', 1, 120000),
+  (2, 'Bob', 1, 95000),
+  (3, 'Carol', 2, 70000),
+  (4, 'Dave', 2, 65000);
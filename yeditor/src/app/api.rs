@@ -2,11 +2,17 @@ use actix::prelude::*;
 use failure::{err_msg, Fallible, ResultExt};
 use juniper::http::GraphQLRequest;
 use std::sync::Arc;
+use std::time::Instant;
 
 use super::config::Config;
 use super::launch;
-use crate::db;
-use crate::graphql::{self, api::GraphSchema, ctx::GraphContext};
+use crate::{db, metrics, recording, webhook};
+use crate::graphql::{
+    self,
+    api::GraphSchema,
+    ctx::GraphContext,
+    subscription::{self, SubscriptionEvent},
+};
 
 lazy_static! {
     static ref DB_POOL: db::DBPool = {
@@ -105,12 +111,39 @@ impl Handler<GraphQLData> for GraphQLExecutor {
         let auth_token = parse_authorization(&msg.authorization).unwrap_or("".to_owned());
         let session = dao.user.get_session_by_auth_token(&auth_token)?;
         let graphql_ctx = GraphContext::new(auth_token, session, dao);
+        let started = Instant::now();
         let res = msg.request.execute(&self.schema, &graphql_ctx);
-        let res_text = serde_json::to_string_pretty(&res)?;
+        let res_value = serde_json::to_value(&res)?;
+        metrics::graphql_request(started.elapsed(), &res_value);
+        let res_text = serde_json::to_string_pretty(&res_value)?;
         Ok(res_text)
     }
 }
 
+// handle session queries from realtime connections
+#[derive(Debug)]
+pub struct ReqQuerySession {
+    pub authorization: String,
+}
+impl Message for ReqQuerySession {
+    type Result = Result<Option<db::Session>, actix_web::Error>;
+}
+impl Handler<ReqQuerySession> for GraphQLExecutor {
+    type Result = MessageResult<ReqQuerySession>;
+
+    fn handle(&mut self, msg: ReqQuerySession, ctx: &mut Self::Context) -> Self::Result {
+        let user_dao = db::dao::user::UserDao::new(&self.pool);
+        let auth_token = parse_authorization(&msg.authorization).unwrap_or("".to_owned());
+        match user_dao.get_session_by_auth_token(&auth_token) {
+            Err(err) => {
+                info!("ReqQuerySession failure: err = {:?}", err);
+                MessageResult(Err(err.into()))
+            }
+            Ok(session) => MessageResult(Ok(session)),
+        }
+    }
+}
+
 // handle queries from room
 #[derive(Debug)]
 pub struct ReqQueryPad {
@@ -185,6 +218,67 @@ impl Handler<ReqSavePadContent> for GraphQLExecutor {
     }
 }
 
+#[derive(Debug)]
+pub struct ReqSavePadRecording {
+    pub events: Vec<db::NewPadRecordingEvent>,
+}
+impl Message for ReqSavePadRecording {
+    type Result = Result<(), actix_web::Error>;
+}
+impl Handler<ReqSavePadRecording> for GraphQLExecutor {
+    type Result = MessageResult<ReqSavePadRecording>;
+
+    fn handle(&mut self, msg: ReqSavePadRecording, ctx: &mut Self::Context) -> Self::Result {
+        let pads_dao = db::dao::pads::PadsDao::new(&self.pool);
+        match pads_dao.save_recording_events(&msg.events) {
+            Err(err) => {
+                warn!("ReqSavePadRecording failure, err = {:?}", err);
+                MessageResult(Err(err.into()))
+            }
+            Ok(_) => MessageResult(Ok(())),
+        }
+    }
+}
+
+// asciicast download
+#[derive(Debug)]
+pub struct ReqQueryPadRecording {
+    pub authorization: String,
+    pub hash: String,
+}
+pub enum RespQueryPadRecording {
+    Unauthorized,
+    NotFound,
+    Found(String), // asciicast
+}
+impl Message for ReqQueryPadRecording {
+    type Result = Result<RespQueryPadRecording, actix_web::Error>;
+}
+impl Handler<ReqQueryPadRecording> for GraphQLExecutor {
+    type Result = MessageResult<ReqQueryPadRecording>;
+
+    fn handle(&mut self, msg: ReqQueryPadRecording, ctx: &mut Self::Context) -> Self::Result {
+        let dao = db::ApiDao::new(&self.pool);
+        let auth_token = parse_authorization(&msg.authorization).unwrap_or("".to_owned());
+        let session = match dao.user.get_session_by_auth_token(&auth_token) {
+            Err(err) => return MessageResult(Err(err.into())),
+            Ok(None) => return MessageResult(Ok(RespQueryPadRecording::Unauthorized)),
+            Ok(Some(session)) => session,
+        };
+        match recording::query_asciicast(&dao, session.user_id, msg.hash.as_str()) {
+            Err(err) => {
+                warn!(
+                    "ReqQueryPadRecording failure: hash = {}, err = {:?}",
+                    msg.hash, err
+                );
+                MessageResult(Err(err.into()))
+            }
+            Ok(None) => MessageResult(Ok(RespQueryPadRecording::NotFound)),
+            Ok(Some(cast)) => MessageResult(Ok(RespQueryPadRecording::Found(cast))),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ReqUpdatePad {
     pub pad_id: i32,
@@ -204,6 +298,115 @@ impl Handler<ReqUpdatePad> for GraphQLExecutor {
                 warn!("ReqUpdatePad failure: id = {}, err = {:?}", msg.pad_id, err);
                 MessageResult(Err(err.into()))
             }
+            Ok((pad, previous_status)) => {
+                subscription::publish(SubscriptionEvent::PadsChanged(pad.user_id));
+                webhook::notify_pad_status(previous_status.as_str(), &pad);
+                MessageResult(Ok(()))
+            }
+        }
+    }
+}
+
+// handle queries from webhook dispatcher
+#[derive(Debug)]
+pub struct ReqQueueWebhookDeliveries {
+    pub user_id: i32,
+    pub event: String,
+    pub payload: String,
+}
+impl Message for ReqQueueWebhookDeliveries {
+    type Result = Result<Vec<QueuedWebhookDelivery>, actix_web::Error>;
+}
+pub struct QueuedWebhookDelivery {
+    pub webhook: db::Webhook,
+    pub delivery: db::WebhookDelivery,
+}
+impl Handler<ReqQueueWebhookDeliveries> for GraphQLExecutor {
+    type Result = MessageResult<ReqQueueWebhookDeliveries>;
+
+    fn handle(&mut self, msg: ReqQueueWebhookDeliveries, ctx: &mut Self::Context) -> Self::Result {
+        let webhooks_dao = db::dao::webhooks::WebhooksDao::new(&self.pool);
+        let hooks = match webhooks_dao.query_subscribed(msg.user_id, msg.event.as_str()) {
+            Err(err) => {
+                warn!(
+                    "ReqQueueWebhookDeliveries failure: user_id = {}, err = {:?}",
+                    msg.user_id, err
+                );
+                return MessageResult(Err(err.into()));
+            }
+            Ok(val) => val,
+        };
+        let mut queued = vec![];
+        for hook in hooks {
+            match webhooks_dao.create_delivery(hook.id, msg.event.as_str(), msg.payload.as_str()) {
+                Err(err) => {
+                    warn!(
+                        "create webhook delivery failure: webhook_id = {}, err = {:?}",
+                        hook.id, err
+                    );
+                }
+                Ok(delivery) => queued.push(QueuedWebhookDelivery {
+                    webhook: hook,
+                    delivery: delivery,
+                }),
+            }
+        }
+        MessageResult(Ok(queued))
+    }
+}
+
+/// pending deliveries whose retries were lost, see WebhooksDao::claim_stale_deliveries
+#[derive(Debug)]
+pub struct ReqClaimStaleWebhookDeliveries {
+    pub stale_ms: i64,
+    pub limit: i64,
+}
+impl Message for ReqClaimStaleWebhookDeliveries {
+    type Result = Result<Vec<QueuedWebhookDelivery>, actix_web::Error>;
+}
+impl Handler<ReqClaimStaleWebhookDeliveries> for GraphQLExecutor {
+    type Result = MessageResult<ReqClaimStaleWebhookDeliveries>;
+
+    fn handle(
+        &mut self,
+        msg: ReqClaimStaleWebhookDeliveries,
+        ctx: &mut Self::Context,
+    ) -> Self::Result {
+        let webhooks_dao = db::dao::webhooks::WebhooksDao::new(&self.pool);
+        match webhooks_dao.claim_stale_deliveries(msg.stale_ms, msg.limit) {
+            Err(err) => {
+                warn!("ReqClaimStaleWebhookDeliveries failure: err = {:?}", err);
+                MessageResult(Err(err.into()))
+            }
+            Ok(claimed) => MessageResult(Ok(claimed
+                .into_iter()
+                .map(|(webhook, delivery)| QueuedWebhookDelivery { webhook, delivery })
+                .collect())),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ReqUpdateWebhookDelivery {
+    pub delivery_id: i32,
+    pub changeset: db::WebhookDeliveryChangeset,
+}
+impl Message for ReqUpdateWebhookDelivery {
+    type Result = Result<(), actix_web::Error>;
+}
+impl Handler<ReqUpdateWebhookDelivery> for GraphQLExecutor {
+    type Result = MessageResult<ReqUpdateWebhookDelivery>;
+
+    fn handle(&mut self, msg: ReqUpdateWebhookDelivery, ctx: &mut Self::Context) -> Self::Result {
+        let webhooks_dao = db::dao::webhooks::WebhooksDao::new(&self.pool);
+        match webhooks_dao.update_delivery(msg.delivery_id, msg.changeset) {
+            Err(err) => {
+                warn!(
+                    "ReqUpdateWebhookDelivery failure: id = {}, err = {:?}",
+                    msg.delivery_id, err
+                );
+                MessageResult(Err(err.into()))
+            }
             Ok(_) => MessageResult(Ok(())),
         }
     }
@@ -8,4 +8,8 @@ pub struct CmdLineOpt {
 
     #[structopt(long = "migration")]
     pub migration: bool,
+
+    /// Write json schema of realtime and runner protocols to directory
+    #[structopt(long = "dump-schema")]
+    pub dump_schema: Option<String>,
 }
@@ -8,6 +8,12 @@ pub struct Config {
     pub postgres: DatabaseConfig,
     pub log: LogConfig,
     pub room: RoomConfig,
+    #[serde(default)]
+    pub webhook: WebhookConfig,
+    #[serde(default)]
+    pub cluster: ClusterConfig,
+    #[serde(default)]
+    pub runner: RunnerConfig,
     pub languages: collections::BTreeMap<String, LangItem>,
 }
 
@@ -53,6 +59,13 @@ pub struct ServerConfig {
     pub github_client_secret: String,
     pub github_auth_url: String,
     pub github_token_url: String,
+
+    #[serde(default = "server_default_drain_timeout_ms")]
+    pub drain_timeout_ms: u64,
+}
+
+fn server_default_drain_timeout_ms() -> u64 {
+    30000
 }
 
 #[derive(Clone, Deserialize, Debug)]
@@ -77,8 +90,9 @@ pub struct RoomConfig {
     #[serde(default = "room_default_close_delay_ms")]
     pub close_delay_ms: u64,
 
-    #[serde(default = "room_default_cache_lines")]
-    pub cache_lines: usize,
+    // terminal history sent to late joiners, besides the screen
+    #[serde(default = "room_default_scrollback_lines")]
+    pub scrollback_lines: usize,
 
     #[serde(default = "room_default_client_keep_alive_ms")]
     pub client_keep_alive_ms: u64,
@@ -86,22 +100,53 @@ pub struct RoomConfig {
     #[serde(default = "room_default_client_timeout_ms")]
     pub client_timeout_ms: u64,
 
-    #[serde(default = "room_default_auto_save_seconds")]
-    pub auto_save_seconds: u64,
+    // save pad after edits are quiet for a while
+    #[serde(default = "room_default_save_quiet_ms")]
+    pub save_quiet_ms: u64,
+
+    // save pad no later than this after first unsaved edit
+    #[serde(default = "room_default_save_max_stale_ms")]
+    pub save_max_stale_ms: u64,
+
+    #[serde(default = "room_default_save_retry_delay_ms")]
+    pub save_retry_delay_ms: u64,
+
+    #[serde(default = "room_default_save_max_retries")]
+    pub save_max_retries: u32,
+
+    // record terminal output of pads for asciicast export
+    #[serde(default = "room_default_record_terminal")]
+    pub record_terminal: bool,
 
     #[serde(default = "room_default_agent_keep_alive_seconds")]
     pub agent_keep_alive_seconds: u64,
 
     #[serde(default = "room_default_max_pads_per_user")]
     pub max_pads_per_user: u64,
+
+    // language server of room for completions and diagnostics, if runner supports it
+    #[serde(default = "room_default_language_server")]
+    pub language_server: bool,
+
+    // code buffer is synced to language server at most once in this period
+    #[serde(default = "room_default_language_server_sync_ms")]
+    pub language_server_sync_ms: u64,
+
+    // largest file of runs downloaded by participants
+    #[serde(default = "room_default_run_file_max_size_mb")]
+    pub run_file_max_size_mb: u64,
+
+    // download links of run files expire after this period
+    #[serde(default = "room_default_run_file_token_ttl_ms")]
+    pub run_file_token_ttl_ms: u64,
 }
 
 fn room_default_close_delay_ms() -> u64 {
     10000
 }
 
-fn room_default_cache_lines() -> usize {
-    30
+fn room_default_scrollback_lines() -> usize {
+    500
 }
 
 fn room_default_client_keep_alive_ms() -> u64 {
@@ -112,8 +157,24 @@ fn room_default_client_timeout_ms() -> u64 {
     15000
 }
 
-fn room_default_auto_save_seconds() -> u64 {
-    300
+fn room_default_save_quiet_ms() -> u64 {
+    2000
+}
+
+fn room_default_save_max_stale_ms() -> u64 {
+    30000
+}
+
+fn room_default_save_retry_delay_ms() -> u64 {
+    2000
+}
+
+fn room_default_save_max_retries() -> u32 {
+    5
+}
+
+fn room_default_record_terminal() -> bool {
+    true
 }
 
 fn room_default_agent_keep_alive_seconds() -> u64 {
@@ -124,10 +185,275 @@ fn room_default_max_pads_per_user() -> u64 {
     0
 }
 
+fn room_default_language_server() -> bool {
+    true
+}
+
+fn room_default_language_server_sync_ms() -> u64 {
+    300
+}
+
+fn room_default_run_file_max_size_mb() -> u64 {
+    16
+}
+
+fn room_default_run_file_token_ttl_ms() -> u64 {
+    300000
+}
+
+// webhook config
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct WebhookConfig {
+    #[serde(default = "webhook_default_max_attempts")]
+    pub max_attempts: u32,
+
+    #[serde(default = "webhook_default_retry_delay_ms")]
+    pub retry_delay_ms: u64,
+
+    #[serde(default = "webhook_default_max_retry_delay_ms")]
+    pub max_retry_delay_ms: u64,
+
+    #[serde(default = "webhook_default_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig {
+            max_attempts: webhook_default_max_attempts(),
+            retry_delay_ms: webhook_default_retry_delay_ms(),
+            max_retry_delay_ms: webhook_default_max_retry_delay_ms(),
+            timeout_ms: webhook_default_timeout_ms(),
+        }
+    }
+}
+
+fn webhook_default_max_attempts() -> u32 {
+    6
+}
+
+fn webhook_default_retry_delay_ms() -> u64 {
+    2000
+}
+
+fn webhook_default_max_retry_delay_ms() -> u64 {
+    600000
+}
+
+fn webhook_default_timeout_ms() -> u64 {
+    10000
+}
+
+// cluster config
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct ClusterConfig {
+    // generated on startup if empty
+    #[serde(default)]
+    pub node_id: String,
+
+    // url other nodes use to reach this node, server.bind_url if empty
+    #[serde(default)]
+    pub advertise_url: String,
+
+    // single node with in-process registry if empty
+    #[serde(default)]
+    pub redis_url: String,
+
+    #[serde(default = "cluster_default_room_ttl_ms")]
+    pub room_ttl_ms: u64,
+
+    #[serde(default = "cluster_default_renew_interval_ms")]
+    pub renew_interval_ms: u64,
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        ClusterConfig {
+            node_id: "".to_owned(),
+            advertise_url: "".to_owned(),
+            redis_url: "".to_owned(),
+            room_ttl_ms: cluster_default_room_ttl_ms(),
+            renew_interval_ms: cluster_default_renew_interval_ms(),
+        }
+    }
+}
+
+fn cluster_default_room_ttl_ms() -> u64 {
+    15000
+}
+
+fn cluster_default_renew_interval_ms() -> u64 {
+    5000
+}
+
+// runner config
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct RunnerConfig {
+    // websocket (room.runner_service_url) or local
+    #[serde(default = "runner_default_backend")]
+    pub backend: String,
+
+    // local backend, yscript runs code in work_dir/{room_key}
+    #[serde(default = "runner_default_yscript_path")]
+    pub yscript_path: String,
+
+    // system temporary directory if empty
+    #[serde(default)]
+    pub work_dir: String,
+
+    #[serde(default = "runner_default_run_timeout_ms")]
+    pub run_timeout_ms: u64,
+
+    // debug runs wait for participants at breakpoints
+    #[serde(default = "runner_default_debug_timeout_ms")]
+    pub debug_timeout_ms: u64,
+
+    // run yscript in bubblewrap sandbox, without network
+    #[serde(default)]
+    pub bubblewrap: bool,
+
+    #[serde(default = "runner_default_bubblewrap_path")]
+    pub bubblewrap_path: String,
+
+    // host directories mounted read only in sandbox
+    #[serde(default = "runner_default_bubblewrap_ro_binds")]
+    pub bubblewrap_ro_binds: Vec<String>,
+
+    // offline packages for pad dependencies (yscript --deps-dir), writable in sandbox
+    #[serde(default)]
+    pub deps_dir: String,
+
+    // compile cache of yscript (--cache-dir), writable in sandbox, yscript default if empty
+    #[serde(default)]
+    pub cache_dir: String,
+
+    // files of the latest run are kept for download, removed after run if zero
+    #[serde(default = "runner_default_files_ttl_ms")]
+    pub files_ttl_ms: u64,
+
+    // websocket backend, room.runner_service_url if no endpoints
+    #[serde(default)]
+    pub endpoints: Vec<RunnerEndpointConfig>,
+
+    // least_load or hash (consistent hashing of room_key)
+    #[serde(default = "runner_default_placement")]
+    pub placement: String,
+
+    #[serde(default = "runner_default_health_check_interval_ms")]
+    pub health_check_interval_ms: u64,
+
+    #[serde(default = "runner_default_health_check_timeout_ms")]
+    pub health_check_timeout_ms: u64,
+}
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct RunnerEndpointConfig {
+    // websocket url template, {room_key} is replaced
+    pub url: String,
+
+    #[serde(default = "runner_endpoint_default_weight")]
+    pub weight: u32,
+
+    // http://host:port/health of url if empty
+    #[serde(default)]
+    pub health_url: String,
+
+    // host:port of url if empty
+    #[serde(default)]
+    pub name: String,
+}
+
+impl Default for RunnerConfig {
+    fn default() -> Self {
+        RunnerConfig {
+            backend: runner_default_backend(),
+            yscript_path: runner_default_yscript_path(),
+            work_dir: "".to_owned(),
+            run_timeout_ms: runner_default_run_timeout_ms(),
+            debug_timeout_ms: runner_default_debug_timeout_ms(),
+            bubblewrap: false,
+            bubblewrap_path: runner_default_bubblewrap_path(),
+            bubblewrap_ro_binds: runner_default_bubblewrap_ro_binds(),
+            deps_dir: "".to_owned(),
+            cache_dir: "".to_owned(),
+            files_ttl_ms: runner_default_files_ttl_ms(),
+            endpoints: vec![],
+            placement: runner_default_placement(),
+            health_check_interval_ms: runner_default_health_check_interval_ms(),
+            health_check_timeout_ms: runner_default_health_check_timeout_ms(),
+        }
+    }
+}
+
+fn runner_default_backend() -> String {
+    "websocket".to_owned()
+}
+
+fn runner_default_yscript_path() -> String {
+    "yscript".to_owned()
+}
+
+fn runner_default_run_timeout_ms() -> u64 {
+    30000
+}
+
+fn runner_default_debug_timeout_ms() -> u64 {
+    600000
+}
+
+fn runner_default_files_ttl_ms() -> u64 {
+    600000
+}
+
+fn runner_default_bubblewrap_path() -> String {
+    "bwrap".to_owned()
+}
+
+fn runner_default_bubblewrap_ro_binds() -> Vec<String> {
+    ["/usr", "/bin", "/lib", "/lib64", "/etc"]
+        .iter()
+        .map(|dir| (*dir).to_owned())
+        .collect()
+}
+
+fn runner_default_placement() -> String {
+    "least_load".to_owned()
+}
+
+fn runner_default_health_check_interval_ms() -> u64 {
+    5000
+}
+
+fn runner_default_health_check_timeout_ms() -> u64 {
+    2000
+}
+
+fn runner_endpoint_default_weight() -> u32 {
+    1
+}
+
 #[derive(Clone, Deserialize, Debug)]
 pub struct LangItem {
     pub ui: String,
     pub editor: String,
+    // source file extension, used by local runner
+    #[serde(default)]
+    pub ext: String,
+    // language server command, used by local runner
+    #[serde(default)]
+    pub lsp: Vec<String>,
+    // sql language, rooms get a database of their own
+    #[serde(default)]
+    pub database: bool,
+    // sql file run in new databases of pads without a seed
+    #[serde(default)]
+    pub seed: String,
+    // interpreted language, a selection of code runs on its own
+    #[serde(default)]
+    pub run_selection: bool,
 }
 
 #[derive(Clone, Debug)]
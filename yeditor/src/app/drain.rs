@@ -0,0 +1,118 @@
+use actix::prelude::*;
+use actix::signal;
+use actix_web::server;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use super::launch;
+use crate::room;
+
+// Drain mode on SIGTERM: new realtime connections are rejected, rooms save
+// their pads and ask clients to reconnect, process exits after rooms are closed.
+
+static DRAINING: AtomicBool = AtomicBool::new(false);
+
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+pub fn is_draining() -> bool {
+    DRAINING.load(Ordering::SeqCst)
+}
+
+pub struct Drainer {
+    server: Addr<server::Server>,
+    started_at: Option<Instant>,
+}
+
+impl Drainer {
+    pub fn new(server: Addr<server::Server>) -> Self {
+        Self {
+            server,
+            started_at: None,
+        }
+    }
+
+    fn start_drain(&mut self, ctx: &mut <Self as Actor>::Context) {
+        if self.started_at.is_some() {
+            return;
+        }
+        info!("drain mode started");
+        DRAINING.store(true, Ordering::SeqCst);
+        self.started_at = Some(Instant::now());
+
+        room::RoomManager::from_registry().do_send(room::MsgDrainRooms);
+        self.wait_rooms_closed(ctx);
+    }
+
+    fn wait_rooms_closed(&mut self, ctx: &mut <Self as Actor>::Context) {
+        room::RoomManager::from_registry()
+            .send(room::MsgListRooms {})
+            .into_actor(self)
+            .then(|res, act, context| {
+                let remaining = match res {
+                    Ok(Ok(rooms)) => rooms.len(),
+                    _ => 0,
+                };
+                let drain_timeout = Duration::from_millis(launch::config().server.drain_timeout_ms);
+                let elapsed = act.started_at.map(|t| t.elapsed()).unwrap_or_default();
+                if remaining == 0 {
+                    info!("all rooms closed, stopping server");
+                    act.stop_server(context);
+                } else if elapsed >= drain_timeout {
+                    warn!(
+                        "drain timeout, {} rooms not closed, stopping server",
+                        remaining
+                    );
+                    act.stop_server(context);
+                } else {
+                    context.run_later(DRAIN_POLL_INTERVAL, |this: &mut Self, ctx| {
+                        this.wait_rooms_closed(ctx);
+                    });
+                }
+                fut::ok(())
+            })
+            .spawn(ctx);
+    }
+
+    fn stop_server(&mut self, ctx: &mut <Self as Actor>::Context) {
+        self.server
+            .send(server::StopServer { graceful: true })
+            .into_actor(self)
+            .then(|res, act, context| {
+                info!("server stopped: {:?}", res.is_ok());
+                System::current().stop();
+                fut::ok(())
+            })
+            .spawn(ctx);
+    }
+}
+
+impl Actor for Drainer {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        signal::ProcessSignals::from_registry()
+            .do_send(signal::Subscribe(ctx.address().recipient()));
+    }
+}
+
+impl Handler<signal::Signal> for Drainer {
+    type Result = ();
+
+    fn handle(&mut self, msg: signal::Signal, ctx: &mut Self::Context) -> Self::Result {
+        match msg.0 {
+            signal::SignalType::Int if self.started_at.is_some() => {
+                info!("received interrupt signal while draining, stopping now");
+                System::current().stop();
+            }
+            signal::SignalType::Term | signal::SignalType::Int => {
+                info!("received signal {:?}, draining", msg.0);
+                self.start_drain(ctx);
+            }
+            signal::SignalType::Quit => {
+                info!("received quit signal, stopping now");
+                System::current().stop();
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use actix::prelude::*;
+use diesel::prelude::*;
+use failure::Fallible;
+
+use super::api::{self, GraphQLExecutor};
+use super::drain;
+use crate::{db, room};
+
+#[derive(Debug, Serialize)]
+pub struct HealthCheck {
+    pub name: &'static str,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl HealthCheck {
+    fn from_result(name: &'static str, res: Fallible<()>) -> Self {
+        match res {
+            Ok(_) => HealthCheck {
+                name,
+                ok: true,
+                error: None,
+            },
+            Err(err) => HealthCheck {
+                name,
+                ok: false,
+                error: Some(err.to_string()),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct HealthStatus {
+    pub ok: bool,
+    pub draining: bool,
+    pub checks: Vec<HealthCheck>,
+}
+
+fn check_database(pool: &db::DBPool) -> Fallible<()> {
+    let conn = db::get_connection(pool)?;
+    diesel::sql_query("SELECT 1").execute(&conn)?;
+    Ok(())
+}
+
+/// Liveness checks database only, readiness checks runner and drain mode as well
+#[derive(Debug)]
+pub struct ReqHealthCheck {
+    pub readiness: bool,
+}
+
+impl Message for ReqHealthCheck {
+    type Result = HealthStatus;
+}
+
+impl Handler<ReqHealthCheck> for GraphQLExecutor {
+    type Result = MessageResult<ReqHealthCheck>;
+
+    fn handle(&mut self, msg: ReqHealthCheck, ctx: &mut Self::Context) -> Self::Result {
+        let mut checks = vec![HealthCheck::from_result(
+            "database",
+            check_database(api::db_pool()),
+        )];
+        let draining = drain::is_draining();
+        // health checks of runner endpoints, nothing to check for the local backend
+        if msg.readiness {
+            if let Some(res) = room::runner_backend().check_ready() {
+                checks.push(HealthCheck::from_result("runner", res));
+            }
+        }
+        let ok = checks.iter().all(|check| check.ok) && !(msg.readiness && draining);
+        MessageResult(HealthStatus {
+            ok,
+            draining,
+            checks,
+        })
+    }
+}
@@ -3,6 +3,7 @@ use structopt::StructOpt;
 
 use super::api;
 use super::config::Config;
+use super::schema;
 use super::server;
 use super::cli;
 
@@ -24,6 +25,11 @@ pub fn config() -> &'static Config {
 }
 
 pub fn launch() -> Fallible<()> {
+    // no config needed
+    if let Some(dir) = cmd_line_opt().dump_schema.as_ref() {
+        return schema::dump_protocol_schema(dir);
+    }
+
     let config = config();
     setup_logger(config)?;
     info!("yeditor config file:\n{:#?}", config);
@@ -1,7 +1,10 @@
 pub mod api;
 pub mod cli;
 pub mod config;
+pub mod drain;
+pub mod health;
 pub mod launch;
+pub mod schema;
 pub mod server;
 
 pub use self::server::{AppState, Server};
@@ -0,0 +1,33 @@
+use failure::Fallible;
+use schemars::{schema::RootSchema, schema_for};
+use std::path::Path;
+use ycommon::runner_proto;
+
+use crate::room;
+
+// Json schema of websocket protocols, for generating typescript definitions
+// of angular client and checking other runner implementations.
+
+pub fn dump_protocol_schema(dir: &str) -> Fallible<()> {
+    let dir = Path::new(dir);
+    std::fs::create_dir_all(dir)?;
+
+    let schemas: Vec<(&str, RootSchema)> = vec![
+        ("client_requests", schema_for!(room::ClientRequests)),
+        ("client_responses", schema_for!(room::ClientResponses)),
+        (
+            "runner_requests",
+            schema_for!(runner_proto::ServiceRequests),
+        ),
+        (
+            "runner_responses",
+            schema_for!(runner_proto::ServiceResponses),
+        ),
+    ];
+    for (name, schema) in schemas {
+        let path = dir.join(format!("{}.schema.json", name));
+        std::fs::write(&path, serde_json::to_string_pretty(&schema)?)?;
+        println!("schema written: {}", path.display());
+    }
+    Ok(())
+}
@@ -1,18 +1,25 @@
 use actix::prelude::*;
 use actix::SystemRunner;
 use actix_web::{
-    http, middleware, server, ws, App, AsyncResponder, Error, FutureResponse, HttpRequest,
-    HttpResponse, Json,
+    client, http, middleware, server, ws, App, AsyncResponder, Error, FutureResponse, HttpMessage,
+    HttpRequest, HttpResponse, Json,
 };
 use failure::{Fallible, ResultExt};
-use futures::future::Future;
+use futures::future::{self, Either, Future};
 use juniper::http::GraphQLRequest;
 use openssl::ssl::{SslAcceptor, SslAcceptorBuilder, SslFiletype, SslMethod};
+use std::time::Duration;
 
 use super::api;
 use super::config::Config;
+use super::drain::{self, Drainer};
+use super::health;
 use super::launch;
-use crate::{graphql::playground, room};
+use crate::{
+    cluster,
+    graphql::{dashboard, playground, subscription},
+    metrics, room, webhook,
+};
 
 // AppState
 
@@ -23,6 +30,7 @@ pub struct AppState {
 pub struct Server {
     runner: SystemRunner,
     bind_url: url::Url,
+    server_addr: Addr<server::Server>,
 }
 
 impl Server {
@@ -32,6 +40,11 @@ impl Server {
         let graphql_addr = api::api_executor();
 
         room::RoomManager::from_registry();
+        // starts runner health checks
+        room::runner_backend();
+        // resumes pending webhook deliveries
+        webhook::WebhookDispatcher::from_registry();
+        cluster::start()?;
 
         // Start http server
         let server = server::new(move || {
@@ -44,13 +57,34 @@ impl Server {
                 .resource("/graphql", |r| {
                     r.method(http::Method::POST).with(handle_graphql)
                 })
+                .resource("/graphql/subscriptions", |r| {
+                    r.method(http::Method::GET).f(handle_graphql_subscriptions)
+                })
                 .resource("/playground", |r| {
                     r.method(http::Method::GET).f(handle_playground)
                 })
+                .resource("/admin", |r| {
+                    r.method(http::Method::GET).f(handle_admin_dashboard)
+                })
+                .resource("/metrics", |r| {
+                    r.method(http::Method::GET).f(handle_metrics)
+                })
+                .resource("/healthz", |r| {
+                    r.method(http::Method::GET).f(handle_healthz)
+                })
+                .resource("/readyz", |r| r.method(http::Method::GET).f(handle_readyz))
+                .resource("/pads/{hash}/recording.cast", |r| {
+                    r.method(http::Method::GET).f(handle_pad_recording)
+                })
+                .resource("/pads/{hash}/files/{name:.+}", |r| {
+                    r.method(http::Method::GET).f(handle_run_file)
+                })
                 .resource("/realtime/{room_key}", |r| {
                     r.method(http::Method::GET).f(handle_room_socket)
                 })
-        });
+        })
+        // SIGTERM is handled by Drainer
+        .disable_signals();
 
         let config = launch::config();
         let bind_url = url::Url::parse(&config.server.bind_url)?;
@@ -62,11 +96,12 @@ impl Server {
         } else {
             server.bind(&bind_url)?
         };
-        server.start();
+        let server_addr = server.start();
 
         Ok(Server {
             runner: runner,
             bind_url: bind_url,
+            server_addr: server_addr,
         })
     }
 
@@ -79,6 +114,8 @@ impl Server {
 
     pub fn run(self) -> i32 {
         info!("server start running: {}", self.bind_url.as_str());
+        // drain rooms on SIGTERM before exiting
+        Drainer::new(self.server_addr).start();
         self.runner.run()
     }
 }
@@ -90,6 +127,198 @@ fn handle_playground(_req: &HttpRequest<AppState>) -> Result<HttpResponse, Error
         .body(html))
 }
 
+fn handle_admin_dashboard(_req: &HttpRequest<AppState>) -> Result<HttpResponse, Error> {
+    let html = dashboard::admin_dashboard_source("/graphql");
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(html))
+}
+
+fn handle_metrics(_req: &HttpRequest<AppState>) -> Result<HttpResponse, Error> {
+    match metrics::render(api::db_pool()) {
+        Ok(text) => Ok(HttpResponse::Ok()
+            .content_type("text/plain; version=0.0.4")
+            .body(text)),
+        Err(err) => {
+            error!("render metrics error: {:?}", err);
+            Ok(HttpResponse::InternalServerError().into())
+        }
+    }
+}
+
+fn handle_healthz(req: &HttpRequest<AppState>) -> FutureResponse<HttpResponse> {
+    handle_health_check(req, false)
+}
+
+fn handle_readyz(req: &HttpRequest<AppState>) -> FutureResponse<HttpResponse> {
+    handle_health_check(req, true)
+}
+
+fn handle_health_check(
+    req: &HttpRequest<AppState>,
+    readiness: bool,
+) -> FutureResponse<HttpResponse> {
+    req.state()
+        .executor
+        .send(health::ReqHealthCheck { readiness })
+        .from_err()
+        .and_then(|status: health::HealthStatus| {
+            let mut resp = if status.ok {
+                HttpResponse::Ok()
+            } else {
+                HttpResponse::ServiceUnavailable()
+            };
+            Ok(resp.json(status))
+        })
+        .responder()
+}
+
+// asciicast download, authorized as graphql requests
+fn handle_pad_recording(req: &HttpRequest<AppState>) -> FutureResponse<HttpResponse> {
+    let authorization = req
+        .headers()
+        .get(http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+    let hash = req.match_info().get("hash").unwrap_or("").to_owned();
+    let filename = format!("{}.cast", hash);
+
+    let msg = api::ReqQueryPadRecording {
+        authorization,
+        hash,
+    };
+    req.state()
+        .executor
+        .send(msg)
+        .from_err()
+        .and_then(move |res| match res {
+            Ok(api::RespQueryPadRecording::Found(cast)) => Ok(HttpResponse::Ok()
+                .content_type("application/x-asciicast")
+                .header(
+                    http::header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{}\"", filename),
+                )
+                .body(cast)),
+            Ok(api::RespQueryPadRecording::Unauthorized) => Ok(HttpResponse::Unauthorized().into()),
+            Ok(api::RespQueryPadRecording::NotFound) => Ok(HttpResponse::NotFound().into()),
+            Err(err) => {
+                error!("pad recording error: {:?}", err);
+                Ok(HttpResponse::InternalServerError().into())
+            }
+        })
+        .responder()
+}
+
+// file of the latest run, authorized by the token of the file sent to a participant.
+// only the node owning the room has the runner of the pad, other nodes proxy to it.
+fn handle_run_file(req: &HttpRequest<AppState>) -> FutureResponse<HttpResponse> {
+    let room_key = req.match_info().get("hash").unwrap_or("").to_owned();
+    let name = req.match_info().get("name").unwrap_or("").to_owned();
+    let token = req.query().get("token").cloned().unwrap_or_default();
+    let filename = name.rsplit('/').next().unwrap_or("").replace('"', "_");
+    let proxied = req.headers().contains_key(cluster::PROXIED_BY_HEADER);
+    let path = req
+        .uri()
+        .path_and_query()
+        .map(|val| val.as_str().to_owned())
+        .unwrap_or_default();
+
+    room::RoomManager::from_registry()
+        .send(room::MsgLocateRoom { room_key })
+        .from_err()
+        .and_then(move |res| match res {
+            Ok(Some(room::RoomLocation::Local(room))) => Either::A(
+                room.addr
+                    .send(room::MsgFetchRunFile { token, name })
+                    .from_err()
+                    .map(move |res| run_file_response(res, filename)),
+            ),
+            // proxied requests are never proxied again
+            Ok(Some(room::RoomLocation::Remote(ref owner))) if !proxied => {
+                Either::B(Either::A(proxy_run_file(owner, path)))
+            }
+            Ok(_) => Either::B(Either::B(future::ok(HttpResponse::NotFound().into()))),
+            Err(err) => {
+                error!("locate room failure: {:?}", err);
+                Either::B(Either::B(future::ok(
+                    HttpResponse::InternalServerError().into(),
+                )))
+            }
+        })
+        .responder()
+}
+
+fn run_file_response(res: Result<Vec<u8>, room::RunFileError>, filename: String) -> HttpResponse {
+    match res {
+        Ok(data) => HttpResponse::Ok()
+            .content_type("application/octet-stream")
+            .header(
+                http::header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            )
+            // token is in the url
+            .header(http::header::REFERRER_POLICY, "no-referrer")
+            .header(http::header::CACHE_CONTROL, "no-store")
+            .body(data),
+        Err(room::RunFileError::Unauthorized) => HttpResponse::Unauthorized().into(),
+        Err(room::RunFileError::NotFound) => HttpResponse::NotFound().into(),
+        Err(room::RunFileError::TooLarge) => HttpResponse::PayloadTooLarge().into(),
+        Err(room::RunFileError::NotSupported) => HttpResponse::NotImplemented().into(),
+        Err(room::RunFileError::Timeout) => HttpResponse::GatewayTimeout().into(),
+        Err(room::RunFileError::Internal) => HttpResponse::InternalServerError().into(),
+    }
+}
+
+// relays download from the owner node, path keeps the query with the token
+fn proxy_run_file(
+    owner: &cluster::NodeInfo,
+    path: String,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let url = format!("{}{}", owner.url.trim_end_matches('/'), path);
+    let max_size = room::Room::room_config().run_file_max_size_mb as usize * 1024 * 1024;
+    let request = client::get(url.as_str())
+        .header(cluster::PROXIED_BY_HEADER, cluster::node().id.as_str())
+        // owner answers within the fetch timeout of room
+        .timeout(room::RUN_FILES_TIMEOUT + Duration::from_secs(5))
+        .finish();
+    let request = match request {
+        Ok(request) => request,
+        Err(err) => {
+            error!("invalid run file url: {}, err = {}", url, err);
+            return Either::A(future::ok(HttpResponse::InternalServerError().into()));
+        }
+    };
+    let fut = request.send().then(move |res| {
+        let resp = match res {
+            Ok(resp) => resp,
+            Err(err) => {
+                warn!("proxy run file failure: {}, err = {}", url, err);
+                return Either::A(future::ok(HttpResponse::BadGateway().into()));
+            }
+        };
+        let mut builder = HttpResponse::build(resp.status());
+        for header in &[
+            http::header::CONTENT_TYPE,
+            http::header::CONTENT_DISPOSITION,
+            http::header::REFERRER_POLICY,
+            http::header::CACHE_CONTROL,
+        ] {
+            if let Some(value) = resp.headers().get(header) {
+                builder.header(header.clone(), value.clone());
+            }
+        }
+        Either::B(resp.body().limit(max_size).then(move |body| match body {
+            Ok(body) => Ok(builder.body(body)),
+            Err(err) => {
+                warn!("proxy run file body failure: {}, err = {}", url, err);
+                Ok(HttpResponse::BadGateway().into())
+            }
+        }))
+    });
+    Either::B(fut)
+}
+
 fn handle_graphql(
     (req, graphql_request): (HttpRequest<AppState>, Json<GraphQLRequest>),
 ) -> FutureResponse<HttpResponse> {
@@ -122,6 +351,38 @@ fn handle_graphql(
         .responder()
 }
 
+fn handle_graphql_subscriptions(r: &HttpRequest<AppState>) -> Result<HttpResponse, Error> {
+    let mut resp = ws::handshake(r)?;
+    // apollo clients require the subprotocol to be accepted
+    let protocols = r
+        .headers()
+        .get(http::header::SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("");
+    if protocols
+        .split(',')
+        .any(|p| p.trim() == subscription::GRAPHQL_WS_PROTOCOL)
+    {
+        resp.header(
+            http::header::SEC_WEBSOCKET_PROTOCOL,
+            subscription::GRAPHQL_WS_PROTOCOL,
+        );
+    }
+    let stream = ws::WsStream::new(r.payload());
+    let body =
+        ws::WebsocketContext::create(r.clone(), subscription::SubscriptionSession::new(), stream);
+    Ok(resp.body(body))
+}
+
 fn handle_room_socket(r: &HttpRequest<AppState>) -> Result<HttpResponse, Error> {
-    ws::start(r, room::RoomClientSession::new())
+    if drain::is_draining() {
+        return Ok(HttpResponse::ServiceUnavailable().finish());
+    }
+    let mut resp = ws::handshake(r)?;
+    if let Some(subprotocol) = room::WireFormat::from_request(r).subprotocol {
+        resp.header(http::header::SEC_WEBSOCKET_PROTOCOL, subprotocol);
+    }
+    let stream = ws::WsStream::new(r.payload());
+    let body = ws::WebsocketContext::create(r.clone(), room::RoomClientSession::new(), stream);
+    Ok(resp.body(body))
 }
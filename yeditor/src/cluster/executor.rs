@@ -0,0 +1,116 @@
+use actix::prelude::*;
+use failure::Fallible;
+use std::time::Duration;
+
+use super::registry::*;
+
+// Registry calls may block on network, run them in sync actors
+
+pub struct ClusterExecutor {
+    node: NodeInfo,
+    room_ttl: Duration,
+    registry: Box<dyn RoomRegistry>,
+}
+
+impl ClusterExecutor {
+    pub fn new(node: NodeInfo, room_ttl: Duration, registry: Box<dyn RoomRegistry>) -> Self {
+        Self {
+            node,
+            room_ttl,
+            registry,
+        }
+    }
+}
+
+impl Actor for ClusterExecutor {
+    type Context = SyncContext<Self>;
+}
+
+// Messages
+
+#[derive(Debug)]
+pub struct MsgClaimRoom {
+    pub room_key: String,
+}
+
+impl Message for MsgClaimRoom {
+    type Result = Fallible<NodeInfo>; // owner
+}
+
+#[derive(Debug)]
+pub struct MsgGetRoomOwner {
+    pub room_key: String,
+}
+
+impl Message for MsgGetRoomOwner {
+    type Result = Fallible<Option<NodeInfo>>; // owner, none if room is not live
+}
+
+#[derive(Debug)]
+pub struct MsgRenewRooms {
+    pub room_keys: Vec<String>,
+}
+
+impl Message for MsgRenewRooms {
+    type Result = Fallible<Vec<String>>; // lost rooms
+}
+
+#[derive(Debug, Message)]
+pub struct MsgReleaseRoom {
+    pub room_key: String,
+}
+
+#[derive(Debug, Message)]
+pub struct MsgPublish(pub ClusterEvent);
+
+impl Handler<MsgClaimRoom> for ClusterExecutor {
+    type Result = Fallible<NodeInfo>;
+
+    fn handle(&mut self, msg: MsgClaimRoom, ctx: &mut Self::Context) -> Self::Result {
+        self.registry
+            .claim_room(msg.room_key.as_str(), &self.node, self.room_ttl)
+    }
+}
+
+impl Handler<MsgGetRoomOwner> for ClusterExecutor {
+    type Result = Fallible<Option<NodeInfo>>;
+
+    fn handle(&mut self, msg: MsgGetRoomOwner, ctx: &mut Self::Context) -> Self::Result {
+        self.registry.room_owner(msg.room_key.as_str())
+    }
+}
+
+impl Handler<MsgRenewRooms> for ClusterExecutor {
+    type Result = Fallible<Vec<String>>;
+
+    fn handle(&mut self, msg: MsgRenewRooms, ctx: &mut Self::Context) -> Self::Result {
+        self.registry
+            .renew_rooms(&msg.room_keys, &self.node, self.room_ttl)
+    }
+}
+
+impl Handler<MsgReleaseRoom> for ClusterExecutor {
+    type Result = ();
+
+    fn handle(&mut self, msg: MsgReleaseRoom, ctx: &mut Self::Context) -> Self::Result {
+        if let Err(err) = self
+            .registry
+            .release_room(msg.room_key.as_str(), &self.node)
+        {
+            warn!("release room failure: {}, err = {:?}", msg.room_key, err);
+        }
+    }
+}
+
+impl Handler<MsgPublish> for ClusterExecutor {
+    type Result = ();
+
+    fn handle(&mut self, msg: MsgPublish, ctx: &mut Self::Context) -> Self::Result {
+        if let Err(err) = self.registry.publish(&msg.0) {
+            warn!(
+                "publish cluster event failure: {:?}, err = {:?}",
+                msg.0, err
+            );
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use actix::prelude::*;
+use failure::Fallible;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::registry::*;
+
+pub type LocalRooms = Arc<Mutex<HashMap<String, (NodeInfo, Instant)>>>;
+
+/// In-process registry for single node deployments
+pub struct LocalRoomRegistry {
+    rooms: LocalRooms, // owner and lease deadline
+    listener: Option<Recipient<ClusterEvent>>,
+}
+
+impl LocalRoomRegistry {
+    pub fn new(rooms: LocalRooms, listener: Option<Recipient<ClusterEvent>>) -> Self {
+        Self { rooms, listener }
+    }
+}
+
+impl RoomRegistry for LocalRoomRegistry {
+    fn claim_room(&mut self, room_key: &str, node: &NodeInfo, ttl: Duration) -> Fallible<NodeInfo> {
+        let mut rooms = self.rooms.lock().unwrap();
+        let now = Instant::now();
+        match rooms.get(room_key) {
+            Some((owner, deadline)) if *deadline > now => Ok(owner.clone()),
+            _ => {
+                rooms.insert(room_key.to_owned(), (node.clone(), now + ttl));
+                Ok(node.clone())
+            }
+        }
+    }
+
+    fn room_owner(&mut self, room_key: &str) -> Fallible<Option<NodeInfo>> {
+        let rooms = self.rooms.lock().unwrap();
+        match rooms.get(room_key) {
+            Some((owner, deadline)) if *deadline > Instant::now() => Ok(Some(owner.clone())),
+            _ => Ok(None),
+        }
+    }
+
+    fn renew_rooms(
+        &mut self,
+        room_keys: &[String],
+        node: &NodeInfo,
+        ttl: Duration,
+    ) -> Fallible<Vec<String>> {
+        let mut rooms = self.rooms.lock().unwrap();
+        let now = Instant::now();
+        let mut lost = vec![];
+        for room_key in room_keys {
+            match rooms.get_mut(room_key) {
+                Some((owner, deadline)) if owner.id == node.id => *deadline = now + ttl,
+                Some((_, deadline)) if *deadline > now => lost.push(room_key.clone()),
+                _ => {
+                    rooms.insert(room_key.clone(), (node.clone(), now + ttl));
+                }
+            }
+        }
+        Ok(lost)
+    }
+
+    fn release_room(&mut self, room_key: &str, node: &NodeInfo) -> Fallible<()> {
+        let mut rooms = self.rooms.lock().unwrap();
+        let owned = match rooms.get(room_key) {
+            Some((owner, _)) => owner.id == node.id,
+            None => false,
+        };
+        if owned {
+            rooms.remove(room_key);
+        }
+        Ok(())
+    }
+
+    fn publish(&mut self, event: &ClusterEvent) -> Fallible<()> {
+        if let Some(listener) = self.listener.as_ref() {
+            listener
+                .do_send(event.clone())
+                .map_err(|err| format_err!("deliver cluster event failure: {:?}", err))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn node(id: &str) -> NodeInfo {
+        NodeInfo {
+            id: id.to_owned(),
+            url: format!("http://{}:4000", id),
+        }
+    }
+
+    #[test]
+    fn local_registry_ownership() {
+        let rooms = LocalRooms::default();
+        let mut registry = LocalRoomRegistry::new(rooms.clone(), None);
+        let (a, b) = (node("a"), node("b"));
+        let ttl = Duration::from_secs(60);
+
+        assert_eq!(None, registry.room_owner("room").unwrap());
+        assert_eq!(a, registry.claim_room("room", &a, ttl).unwrap());
+        assert_eq!(a, registry.claim_room("room", &b, ttl).unwrap());
+        assert_eq!(Some(a.clone()), registry.room_owner("room").unwrap());
+        assert_eq!(
+            vec!["room".to_owned()],
+            registry.renew_rooms(&["room".to_owned()], &b, ttl).unwrap()
+        );
+
+        // only owner releases room
+        registry.release_room("room", &b).unwrap();
+        assert_eq!(a, registry.claim_room("room", &b, ttl).unwrap());
+        registry.release_room("room", &a).unwrap();
+        assert_eq!(b, registry.claim_room("room", &b, ttl).unwrap());
+
+        // expired lease is claimable
+        registry
+            .claim_room("other", &a, Duration::from_millis(0))
+            .unwrap();
+        assert_eq!(b, registry.claim_room("other", &b, ttl).unwrap());
+    }
+}
@@ -0,0 +1,93 @@
+mod executor;
+mod local_registry;
+mod redis_registry;
+mod registry;
+
+pub use self::executor::*;
+pub use self::local_registry::*;
+pub use self::redis_registry::*;
+pub use self::registry::*;
+
+use actix::prelude::*;
+use failure::{err_msg, Fallible, ResultExt};
+use std::time::Duration;
+
+use crate::{app, room};
+
+/// header set on connections proxied from another node
+pub const PROXIED_BY_HEADER: &'static str = "X-Yeditor-Proxied-By";
+
+lazy_static! {
+    static ref NODE: NodeInfo = {
+        let config = app::config();
+        let cluster = &config.cluster;
+        NodeInfo {
+            id: if cluster.node_id.is_empty() {
+                uuid::Uuid::new_v4().to_string()
+            } else {
+                cluster.node_id.clone()
+            },
+            url: if cluster.advertise_url.is_empty() {
+                config.server.bind_url.clone()
+            } else {
+                cluster.advertise_url.clone()
+            },
+        }
+    };
+    static ref CLUSTER_EXECUTOR: Addr<ClusterExecutor> = {
+        start_cluster_executor()
+            .context(err_msg("start cluster executor failure"))
+            .unwrap()
+    };
+}
+
+/// Current node
+pub fn node() -> &'static NodeInfo {
+    &NODE
+}
+
+pub fn cluster_executor() -> &'static Addr<ClusterExecutor> {
+    &CLUSTER_EXECUTOR
+}
+
+/// Start registry executors and cluster event subscriber, RoomManager must be started
+pub fn start() -> Fallible<()> {
+    let config = &app::config().cluster;
+    info!("cluster node: {:?}", node());
+    cluster_executor();
+    if !config.redis_url.is_empty() {
+        let listener = room::RoomManager::from_registry().recipient();
+        start_redis_subscriber(config.redis_url.as_str(), listener)?;
+    }
+    Ok(())
+}
+
+/// Publish event to all nodes
+pub fn publish(event: ClusterEvent) {
+    cluster_executor().do_send(MsgPublish(event));
+}
+
+fn start_cluster_executor() -> Fallible<Addr<ClusterExecutor>> {
+    let config = &app::config().cluster;
+    let room_ttl = Duration::from_millis(config.room_ttl_ms);
+    let redis_url = config.redis_url.clone();
+    if !redis_url.is_empty() {
+        // fail fast on invalid url
+        RedisRoomRegistry::new(redis_url.as_str())?;
+    }
+
+    let local_rooms = LocalRooms::default();
+    let listener = room::RoomManager::from_registry().recipient();
+    let executor = SyncArbiter::start(2, move || {
+        let registry: Box<dyn RoomRegistry> = if redis_url.is_empty() {
+            Box::new(LocalRoomRegistry::new(
+                local_rooms.clone(),
+                Some(listener.clone()),
+            ))
+        } else {
+            Box::new(RedisRoomRegistry::new(redis_url.as_str()).unwrap())
+        };
+        ClusterExecutor::new(node().clone(), room_ttl, registry)
+    });
+    Ok(executor)
+}
@@ -0,0 +1,234 @@
+use actix::prelude::*;
+use failure::Fallible;
+use std::thread;
+use std::time::Duration;
+
+use super::registry::*;
+
+const ROOM_KEY_PREFIX: &'static str = "yeditor:room:";
+const CLUSTER_CHANNEL: &'static str = "yeditor:cluster";
+
+// extend lease if owned, take over if expired
+const RENEW_SCRIPT: &'static str = r#"
+local owner = redis.call('GET', KEYS[1])
+if owner == ARGV[1] then
+    redis.call('PEXPIRE', KEYS[1], ARGV[2])
+    return 1
+end
+if not owner then
+    redis.call('SET', KEYS[1], ARGV[1], 'PX', ARGV[2])
+    return 1
+end
+return 0
+"#;
+
+const RELEASE_SCRIPT: &'static str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0
+"#;
+
+fn room_registry_key(room_key: &str) -> String {
+    format!("{}{}", ROOM_KEY_PREFIX, room_key)
+}
+
+/// Registry shared by nodes through redis, owner is stored as json with lease as ttl
+pub struct RedisRoomRegistry {
+    client: redis::Client,
+    conn: Option<redis::Connection>,
+}
+
+impl RedisRoomRegistry {
+    pub fn new(redis_url: &str) -> Fallible<Self> {
+        let client = redis::Client::open(redis_url)?;
+        Ok(Self { client, conn: None })
+    }
+
+    // connection is dropped on error, reconnected on next call
+    fn with_connection<T, F>(&mut self, f: F) -> Fallible<T>
+    where
+        F: FnOnce(&redis::Connection) -> redis::RedisResult<T>,
+    {
+        if self.conn.is_none() {
+            self.conn = Some(self.client.get_connection()?);
+        }
+        let res = f(self.conn.as_ref().unwrap());
+        if res.is_err() {
+            self.conn = None;
+        }
+        Ok(res?)
+    }
+}
+
+impl RoomRegistry for RedisRoomRegistry {
+    fn claim_room(&mut self, room_key: &str, node: &NodeInfo, ttl: Duration) -> Fallible<NodeInfo> {
+        let key = room_registry_key(room_key);
+        let value = serde_json::to_string(node)?;
+        let ttl_ms = ttl.as_millis() as u64;
+
+        // owner may expire between SET and GET
+        for _ in 0..3 {
+            let owner: Option<String> = self.with_connection(|conn| {
+                let claimed: Option<String> = redis::cmd("SET")
+                    .arg(&key)
+                    .arg(&value)
+                    .arg("NX")
+                    .arg("PX")
+                    .arg(ttl_ms)
+                    .query(conn)?;
+                match claimed {
+                    Some(_) => Ok(Some(value.clone())),
+                    None => redis::cmd("GET").arg(&key).query(conn),
+                }
+            })?;
+            if let Some(owner) = owner {
+                return Ok(serde_json::from_str::<NodeInfo>(owner.as_str())?);
+            }
+        }
+        Err(format_err!("claim room failure: {}", room_key))
+    }
+
+    fn room_owner(&mut self, room_key: &str) -> Fallible<Option<NodeInfo>> {
+        let key = room_registry_key(room_key);
+        let owner: Option<String> =
+            self.with_connection(|conn| redis::cmd("GET").arg(&key).query(conn))?;
+        match owner {
+            Some(owner) => Ok(Some(serde_json::from_str::<NodeInfo>(owner.as_str())?)),
+            None => Ok(None),
+        }
+    }
+
+    fn renew_rooms(
+        &mut self,
+        room_keys: &[String],
+        node: &NodeInfo,
+        ttl: Duration,
+    ) -> Fallible<Vec<String>> {
+        let value = serde_json::to_string(node)?;
+        let ttl_ms = ttl.as_millis() as u64;
+        let script = redis::Script::new(RENEW_SCRIPT);
+
+        let mut lost = vec![];
+        for room_key in room_keys {
+            let renewed: i32 = self.with_connection(|conn| {
+                script
+                    .key(room_registry_key(room_key))
+                    .arg(&value)
+                    .arg(ttl_ms)
+                    .invoke(conn)
+            })?;
+            if renewed == 0 {
+                lost.push(room_key.clone());
+            }
+        }
+        Ok(lost)
+    }
+
+    fn release_room(&mut self, room_key: &str, node: &NodeInfo) -> Fallible<()> {
+        let value = serde_json::to_string(node)?;
+        let script = redis::Script::new(RELEASE_SCRIPT);
+        let _: i32 = self.with_connection(|conn| {
+            script
+                .key(room_registry_key(room_key))
+                .arg(&value)
+                .invoke(conn)
+        })?;
+        Ok(())
+    }
+
+    fn publish(&mut self, event: &ClusterEvent) -> Fallible<()> {
+        let payload = serde_json::to_string(event)?;
+        let _: i32 = self.with_connection(|conn| {
+            redis::cmd("PUBLISH")
+                .arg(CLUSTER_CHANNEL)
+                .arg(&payload)
+                .query(conn)
+        })?;
+        Ok(())
+    }
+}
+
+/// Forward cluster events to listener, resubscribes on connection errors
+pub fn start_redis_subscriber(redis_url: &str, listener: Recipient<ClusterEvent>) -> Fallible<()> {
+    let client = redis::Client::open(redis_url)?;
+    thread::Builder::new()
+        .name("cluster-subscriber".to_owned())
+        .spawn(move || loop {
+            if let Err(err) = redis_subscribe(&client, &listener) {
+                warn!("cluster subscriber error = {:?}", err);
+            }
+            thread::sleep(Duration::from_secs(1));
+        })?;
+    Ok(())
+}
+
+fn redis_subscribe(client: &redis::Client, listener: &Recipient<ClusterEvent>) -> Fallible<()> {
+    let mut pubsub = client.get_pubsub()?;
+    pubsub.subscribe(CLUSTER_CHANNEL)?;
+    info!("cluster subscriber subscribed: {}", CLUSTER_CHANNEL);
+    loop {
+        let msg = pubsub.get_message()?;
+        let payload: String = msg.get_payload()?;
+        match serde_json::from_str::<ClusterEvent>(payload.as_str()) {
+            Err(err) => warn!("invalid cluster event: {}, err = {:?}", payload, err),
+            Ok(event) => {
+                if let Err(err) = listener.do_send(event) {
+                    warn!("deliver cluster event failure: {:?}", err);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // requires a local redis-server, e.g. YEDITOR_TEST_REDIS_URL=redis://127.0.0.1:6379
+    #[test]
+    fn redis_registry_ownership() {
+        let redis_url = match std::env::var("YEDITOR_TEST_REDIS_URL") {
+            Err(_) => return,
+            Ok(val) => val,
+        };
+        let mut registry = RedisRoomRegistry::new(redis_url.as_str()).unwrap();
+        let a = NodeInfo {
+            id: "test-a".to_owned(),
+            url: "http://a:4000".to_owned(),
+        };
+        let b = NodeInfo {
+            id: "test-b".to_owned(),
+            url: "http://b:4000".to_owned(),
+        };
+        let room_key = format!("test-{}", uuid::Uuid::new_v4());
+        let ttl = Duration::from_secs(60);
+
+        assert_eq!(None, registry.room_owner(&room_key).unwrap());
+        assert_eq!(a, registry.claim_room(&room_key, &a, ttl).unwrap());
+        assert_eq!(a, registry.claim_room(&room_key, &b, ttl).unwrap());
+        assert_eq!(Some(a.clone()), registry.room_owner(&room_key).unwrap());
+        assert_eq!(
+            vec![room_key.clone()],
+            registry.renew_rooms(&[room_key.clone()], &b, ttl).unwrap()
+        );
+        assert!(registry
+            .renew_rooms(&[room_key.clone()], &a, ttl)
+            .unwrap()
+            .is_empty());
+
+        // ownership migrates after release or lease expiry
+        registry.release_room(&room_key, &b).unwrap();
+        assert_eq!(a, registry.claim_room(&room_key, &b, ttl).unwrap());
+        registry.release_room(&room_key, &a).unwrap();
+        assert_eq!(
+            b,
+            registry
+                .claim_room(&room_key, &b, Duration::from_millis(50))
+                .unwrap()
+        );
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(a, registry.claim_room(&room_key, &a, ttl).unwrap());
+        registry.release_room(&room_key, &a).unwrap();
+    }
+}
@@ -0,0 +1,47 @@
+use failure::Fallible;
+use std::time::Duration;
+
+use crate::graphql::subscription::SubscriptionEvent;
+
+/// A yeditor server instance
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct NodeInfo {
+    pub id: String,
+    pub url: String, // base url reachable by other nodes
+}
+
+/// Events fanned out to every node of the cluster
+#[derive(Debug, Clone, Deserialize, Serialize, Message)]
+#[serde(rename_all = "snake_case")]
+pub enum ClusterEvent {
+    BroadcastNotice(String), // message
+    DestroyRoom(String),     // room_key
+    KickClient(String, u32), // room_key, client_id
+    Subscription(SubscriptionEvent),
+}
+
+/// Registry of room owners, every room is owned by a single node.
+///
+/// Ownership is a lease of `ttl`, owners renew their rooms periodically,
+/// rooms of a dead node are claimable again after the lease expired.
+pub trait RoomRegistry: Send {
+    /// Claim room for node, returns current owner which may be another node
+    fn claim_room(&mut self, room_key: &str, node: &NodeInfo, ttl: Duration) -> Fallible<NodeInfo>;
+
+    /// Current owner of room, never claims it
+    fn room_owner(&mut self, room_key: &str) -> Fallible<Option<NodeInfo>>;
+
+    /// Extend leases of rooms, returns rooms no longer owned by node
+    fn renew_rooms(
+        &mut self,
+        room_keys: &[String],
+        node: &NodeInfo,
+        ttl: Duration,
+    ) -> Fallible<Vec<String>>;
+
+    /// Release room if it is owned by node
+    fn release_room(&mut self, room_key: &str, node: &NodeInfo) -> Fallible<()>;
+
+    /// Publish event to all nodes, including current node
+    fn publish(&mut self, event: &ClusterEvent) -> Fallible<()>;
+}
@@ -3,10 +3,12 @@ use crate::db;
 
 pub mod user;
 pub mod pads;
+pub mod webhooks;
 
 pub struct ApiDao {
     pub user: user::UserDao,
     pub pads: pads::PadsDao,
+    pub webhooks: webhooks::WebhooksDao,
 }
 
 impl ApiDao {
@@ -14,6 +16,7 @@ impl ApiDao {
         Self {
             user: user::UserDao::new(pool),
             pads: pads::PadsDao::new(pool),
+            webhooks: webhooks::WebhooksDao::new(pool),
         }
     }
 }
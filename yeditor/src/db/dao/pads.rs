@@ -190,23 +190,63 @@ impl PadsDao {
     pub fn save_pad_content(&self, content: NewPadContent) -> Fallible<()> {
         let conn = db::get_connection(&self.pool)?;
         let code = &content.code;
+        let stdin = &content.stdin;
+        let dependencies = &content.dependencies;
+        let database_seed = &content.database_seed;
 
         diesel::insert_into(pad_contents::table)
             .values(&content)
             .on_conflict(pad_contents::pad_id)
             .do_update()
-            .set(pad_contents::code.eq(code))
+            .set((
+                pad_contents::code.eq(code),
+                pad_contents::stdin.eq(stdin),
+                pad_contents::dependencies.eq(dependencies),
+                pad_contents::database_seed.eq(database_seed),
+            ))
             .execute(&conn)?;
 
         Ok(())
     }
 
-    pub fn update_pad(&self, pad_id: i32, changeset: PadChangeset) -> Fallible<()> {
+    pub fn save_recording_events(&self, events: &[NewPadRecordingEvent]) -> Fallible<usize> {
         let conn = db::get_connection(&self.pool)?;
-        diesel::update(pads::table)
-            .filter(pads::id.eq(pad_id))
-            .set(changeset)
-            .execute(&conn)?;
-        Ok(())
+        let count = diesel::insert_into(pad_recording_events::table)
+            .values(events)
+            .execute(&conn)
+            .context("save_recording_events_failure")?;
+        Ok(count)
+    }
+
+    pub fn query_recording_events(&self, pad_id: i32) -> Fallible<Vec<PadRecordingEvent>> {
+        let conn = db::get_connection(&self.pool)?;
+        let events = pad_recording_events::table
+            .filter(pad_recording_events::pad_id.eq(pad_id))
+            .order((
+                pad_recording_events::created_at.asc(),
+                pad_recording_events::id.asc(),
+            ))
+            .load::<PadRecordingEvent>(&conn)
+            .context("query_recording_events_failure")?;
+        Ok(events)
+    }
+
+    /// Returns updated pad and its status before the update
+    pub fn update_pad(&self, pad_id: i32, changeset: PadChangeset) -> Fallible<(Pad, String)> {
+        let conn = db::get_connection(&self.pool)?;
+        // status is locked so concurrent updates see each change once
+        let updated = conn.transaction::<_, failure::Error, _>(|| {
+            let previous_status: String = pads::table
+                .find(pad_id)
+                .select(pads::status)
+                .for_update()
+                .first(&conn)?;
+            let pad = diesel::update(pads::table)
+                .filter(pads::id.eq(pad_id))
+                .set(changeset)
+                .get_result(&conn)?;
+            Ok((pad, previous_status))
+        })?;
+        Ok(updated)
     }
 }
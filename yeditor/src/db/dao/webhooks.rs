@@ -0,0 +1,207 @@
+use diesel::dsl::{now, IntervalDsl};
+use diesel::prelude::*;
+use failure::{Fallible, ResultExt};
+use std::{cmp, str};
+
+use crate::db::{self, model::*, schema::*, Paginate};
+
+pub struct WebhooksDao {
+    pool: db::DBPool,
+}
+
+impl WebhooksDao {
+    pub fn new(pool: &db::DBPool) -> Self {
+        Self { pool: pool.clone() }
+    }
+
+    pub fn query_all(&self, user_id: i32) -> Fallible<Vec<Webhook>> {
+        let conn = db::get_connection(&self.pool)?;
+        let hooks = webhooks::table
+            .filter(webhooks::user_id.eq(user_id))
+            .order(webhooks::created_at.desc())
+            .load::<Webhook>(&conn)
+            .context("query_webhooks_failure")?;
+        Ok(hooks)
+    }
+
+    pub fn query_webhook(&self, user_id: i32, webhook_id: i32) -> Fallible<Option<Webhook>> {
+        let conn = db::get_connection(&self.pool)?;
+        let hook = webhooks::table
+            .filter(webhooks::id.eq(webhook_id))
+            .filter(webhooks::user_id.eq(user_id))
+            .first::<Webhook>(&conn)
+            .optional()?;
+        Ok(hook)
+    }
+
+    /// active webhooks of user which subscribed to event
+    pub fn query_subscribed(&self, user_id: i32, event: &str) -> Fallible<Vec<Webhook>> {
+        let conn = db::get_connection(&self.pool)?;
+        let hooks = webhooks::table
+            .filter(webhooks::user_id.eq(user_id))
+            .filter(webhooks::active.eq(true))
+            .load::<Webhook>(&conn)
+            .context("query_subscribed_webhooks_failure")?;
+
+        let hooks = hooks
+            .into_iter()
+            .filter(|hook| hook.events.split(',').any(|name| name == event))
+            .collect();
+        Ok(hooks)
+    }
+
+    pub fn create_webhook(&self, new_webhook: NewWebhook) -> Fallible<Webhook> {
+        let conn = db::get_connection(&self.pool)?;
+        let hook = diesel::insert_into(webhooks::table)
+            .values(new_webhook)
+            .get_result(&conn)
+            .context("create_webhook_failure")?;
+        Ok(hook)
+    }
+
+    pub fn delete_webhook(&self, user_id: i32, webhook_id: i32) -> Fallible<usize> {
+        let conn = db::get_connection(&self.pool)?;
+        let rows = diesel::delete(
+            webhooks::table
+                .filter(webhooks::id.eq(webhook_id))
+                .filter(webhooks::user_id.eq(user_id)),
+        )
+        .execute(&conn)
+        .context("delete_webhook_failure")?;
+        Ok(rows)
+    }
+}
+
+// deliveries
+
+const DELIVERY_STATUS_PENDING: &'static str = "pending";
+const DELIVERY_STATUS_SUCCEEDED: &'static str = "succeeded";
+const DELIVERY_STATUS_FAILED: &'static str = "failed";
+
+pub enum DeliveryStatus {
+    Pending,
+    Succeeded,
+    Failed,
+}
+
+impl str::FromStr for DeliveryStatus {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            DELIVERY_STATUS_PENDING => Ok(DeliveryStatus::Pending),
+            DELIVERY_STATUS_SUCCEEDED => Ok(DeliveryStatus::Succeeded),
+            DELIVERY_STATUS_FAILED => Ok(DeliveryStatus::Failed),
+            _ => Err("unknown status"),
+        }
+    }
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => DELIVERY_STATUS_PENDING,
+            DeliveryStatus::Succeeded => DELIVERY_STATUS_SUCCEEDED,
+            DeliveryStatus::Failed => DELIVERY_STATUS_FAILED,
+        }
+    }
+}
+
+impl WebhooksDao {
+    pub fn create_delivery(
+        &self,
+        webhook_id: i32,
+        event: &str,
+        payload: &str,
+    ) -> Fallible<WebhookDelivery> {
+        let conn = db::get_connection(&self.pool)?;
+        let new_delivery = NewWebhookDelivery {
+            webhook_id: webhook_id,
+            event: event.into(),
+            payload: payload.into(),
+            status: DeliveryStatus::Pending.as_str().into(),
+        };
+        let delivery = diesel::insert_into(webhook_deliveries::table)
+            .values(new_delivery)
+            .get_result(&conn)
+            .context("create_webhook_delivery_failure")?;
+        Ok(delivery)
+    }
+
+    pub fn update_delivery(
+        &self,
+        delivery_id: i32,
+        changeset: WebhookDeliveryChangeset,
+    ) -> Fallible<()> {
+        let conn = db::get_connection(&self.pool)?;
+        diesel::update(webhook_deliveries::table)
+            .filter(webhook_deliveries::id.eq(delivery_id))
+            .set(changeset)
+            .execute(&conn)?;
+        Ok(())
+    }
+
+    /// Claims pending deliveries of active webhooks untouched for stale_ms, e.g. retries lost by
+    /// a restart. Claiming touches updated_at, so a delivery is claimed by one node only.
+    pub fn claim_stale_deliveries(
+        &self,
+        stale_ms: i64,
+        limit: i64,
+    ) -> Fallible<Vec<(Webhook, WebhookDelivery)>> {
+        let conn = db::get_connection(&self.pool)?;
+        let stale = webhook_deliveries::table
+            .inner_join(webhooks::table)
+            .filter(webhook_deliveries::status.eq(DeliveryStatus::Pending.as_str()))
+            .filter(webhook_deliveries::updated_at.lt(now - stale_ms.milliseconds()))
+            .filter(webhooks::active.eq(true))
+            .select((webhooks::all_columns, webhook_deliveries::all_columns))
+            .order(webhook_deliveries::id.asc())
+            .limit(limit)
+            .load::<(Webhook, WebhookDelivery)>(&conn)
+            .context("query_stale_webhook_deliveries_failure")?;
+
+        let mut claimed = vec![];
+        for (hook, delivery) in stale {
+            let rows = diesel::update(
+                webhook_deliveries::table
+                    .filter(webhook_deliveries::id.eq(delivery.id))
+                    .filter(webhook_deliveries::updated_at.eq(delivery.updated_at)),
+            )
+            .set(webhook_deliveries::updated_at.eq(now))
+            .execute(&conn)
+            .context("claim_webhook_delivery_failure")?;
+            if rows == 1 {
+                claimed.push((hook, delivery));
+            }
+        }
+        Ok(claimed)
+    }
+
+    pub fn query_deliveries(
+        &self,
+        webhook_id: i32,
+        pagination: &Pagination,
+    ) -> Fallible<PaginatedData<WebhookDelivery>> {
+        let conn = db::get_connection(&self.pool)?;
+
+        let page_size = cmp::max(1, cmp::min(pagination.page_size, 50));
+
+        let query = webhook_deliveries::table
+            .filter(webhook_deliveries::webhook_id.eq(webhook_id))
+            .select(webhook_deliveries::all_columns)
+            .order(webhook_deliveries::created_at.desc())
+            .paginate(pagination.page_index as i64)
+            .page_size(page_size as i64);
+
+        let (data, total) = query
+            .load_and_count::<WebhookDelivery>(&conn)
+            .context("query_webhook_deliveries_failure")?;
+
+        Ok(PaginatedData {
+            page_index: pagination.page_index,
+            page_size: page_size,
+            total: total as i32,
+            data: data,
+        })
+    }
+}
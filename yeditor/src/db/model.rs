@@ -26,6 +26,7 @@ pub struct User {
     pub password_hash: String,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub is_admin: bool,
 }
 
 #[derive(Debug, Insertable)]
@@ -98,6 +99,9 @@ pub struct PadContent {
     pub pad_id: i32,
     pub code: String,
     pub updated_at: NaiveDateTime,
+    pub stdin: String,
+    pub dependencies: String,
+    pub database_seed: String,
 }
 
 #[derive(Debug, Insertable)]
@@ -105,4 +109,81 @@ pub struct PadContent {
 pub struct NewPadContent {
     pub pad_id: i32,
     pub code: String,
+    // program input of runs
+    pub stdin: String,
+    // dependency manifest as json array
+    pub dependencies: String,
+    // sql run in database of room on reset, template seed of language if empty
+    pub database_seed: String,
+}
+
+#[derive(Debug, Queryable)]
+pub struct PadRecordingEvent {
+    pub id: i64,
+    pub pad_id: i32,
+    pub event_type: String,
+    pub data: Vec<u8>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "pad_recording_events"]
+pub struct NewPadRecordingEvent {
+    pub pad_id: i32,
+    pub event_type: String,
+    pub data: Vec<u8>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Queryable)]
+pub struct Webhook {
+    pub id: i32,
+    pub user_id: i32,
+    pub url: String,
+    pub secret: String,
+    pub events: String,
+    pub active: bool,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "webhooks"]
+pub struct NewWebhook {
+    pub user_id: i32,
+    pub url: String,
+    pub secret: String,
+    pub events: String,
+}
+
+#[derive(Debug, Queryable)]
+pub struct WebhookDelivery {
+    pub id: i32,
+    pub webhook_id: i32,
+    pub event: String,
+    pub payload: String,
+    pub status: String,
+    pub attempts: i32,
+    pub response_code: Option<i32>,
+    pub last_error: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "webhook_deliveries"]
+pub struct NewWebhookDelivery {
+    pub webhook_id: i32,
+    pub event: String,
+    pub payload: String,
+    pub status: String,
+}
+
+#[derive(Debug, AsChangeset)]
+#[table_name = "webhook_deliveries"]
+pub struct WebhookDeliveryChangeset {
+    pub status: Option<String>,
+    pub attempts: Option<i32>,
+    pub response_code: Option<i32>,
+    pub last_error: Option<String>,
 }
@@ -3,6 +3,19 @@ table! {
         pad_id -> Int4,
         code -> Text,
         updated_at -> Timestamp,
+        stdin -> Text,
+        dependencies -> Text,
+        database_seed -> Text,
+    }
+}
+
+table! {
+    pad_recording_events (id) {
+        id -> Int8,
+        pad_id -> Int4,
+        event_type -> Varchar,
+        data -> Bytea,
+        created_at -> Timestamp,
     }
 }
 
@@ -51,17 +64,52 @@ table! {
         password_hash -> Varchar,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        is_admin -> Bool,
+    }
+}
+
+table! {
+    webhook_deliveries (id) {
+        id -> Int4,
+        webhook_id -> Int4,
+        event -> Varchar,
+        payload -> Text,
+        status -> Varchar,
+        attempts -> Int4,
+        response_code -> Nullable<Int4>,
+        last_error -> Nullable<Varchar>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    webhooks (id) {
+        id -> Int4,
+        user_id -> Int4,
+        url -> Varchar,
+        secret -> Varchar,
+        events -> Varchar,
+        active -> Bool,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
 
 joinable!(pad_contents -> pads (pad_id));
+joinable!(pad_recording_events -> pads (pad_id));
 joinable!(sessions -> users (user_id));
 joinable!(user_settings -> users (user_id));
+joinable!(webhook_deliveries -> webhooks (webhook_id));
+joinable!(webhooks -> users (user_id));
 
 allow_tables_to_appear_in_same_query!(
     pad_contents,
+    pad_recording_events,
     pads,
     sessions,
     user_settings,
     users,
+    webhook_deliveries,
+    webhooks,
 );
@@ -0,0 +1,179 @@
+use crate::{cluster, db, graphql::ctx::GraphContext, room};
+use actix::prelude::*;
+use futures::future::Future;
+use juniper::{FieldError, FieldResult};
+use std::time::Duration;
+
+// rooms not answering in time are left out of the list
+const ROOM_QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub struct AdminQueryRoot;
+
+graphql_object!(AdminQueryRoot: GraphContext |&self| {
+    field node(&executor) -> FieldResult<String> as "id of the node serving the request" {
+        admin_user(&executor)?;
+        Ok(cluster::node().id.clone())
+    }
+    field rooms(&executor) -> FieldResult<Vec<ApiAdminRoom>> as "live rooms of the node serving the request, every node lists its own rooms" {
+        query_rooms(&executor)
+    }
+});
+
+pub struct AdminMutationRoot;
+
+graphql_object!(AdminMutationRoot: GraphContext |&self| {
+    field close_room(&executor, room_key: String) -> FieldResult<String> as "returns room key, rooms of other nodes are closed too" {
+        close_room(&executor, room_key)
+    }
+    field kick_client(&executor, room_key: String, client_id: i32) -> FieldResult<i32> as "returns client id, rooms of other nodes kick without checking the client" {
+        kick_client(&executor, room_key, client_id)
+    }
+    field broadcast(&executor, message: String) -> FieldResult<i32> as "returns number of rooms on current node" {
+        broadcast_notice(&executor, message)
+    }
+});
+
+#[derive(GraphQLObject, Debug)]
+#[graphql(description = "participant of live room")]
+struct ApiAdminRoomClient {
+    id: i32,
+    name: String,
+}
+
+#[derive(GraphQLObject, Debug)]
+#[graphql(description = "live room detail")]
+struct ApiAdminRoom {
+    room_key: String,
+    pad_id: Option<i32>,
+    language: Option<String>,
+    participants: i32,
+    clients: Vec<ApiAdminRoomClient>,
+    runner_connected: bool,
+    running: bool,
+    uptime_seconds: i32,
+    buffer_size: i32,
+}
+
+impl From<room::RoomDetail> for ApiAdminRoom {
+    fn from(detail: room::RoomDetail) -> Self {
+        Self {
+            room_key: detail.room_key,
+            pad_id: detail.pad_id,
+            language: detail.language,
+            participants: detail.clients.len() as i32,
+            clients: detail
+                .clients
+                .into_iter()
+                .map(|client| ApiAdminRoomClient {
+                    id: client.id as i32,
+                    name: client.name,
+                })
+                .collect(),
+            runner_connected: detail.runner_connected,
+            running: detail.running,
+            uptime_seconds: detail.uptime.as_secs() as i32,
+            buffer_size: detail.buffer_size as i32,
+        }
+    }
+}
+
+fn admin_user(executor: &juniper::Executor<GraphContext>) -> FieldResult<db::User> {
+    let ctx = executor.context();
+    let session = ctx.session_ref()?;
+    let dao: &db::ApiDao = &ctx.dao;
+
+    match dao.user.get_session_user(session)? {
+        Some(user) if user.is_admin => Ok(user),
+        _ => Err(FieldError::new(
+            "Permission denied",
+            graphql_value!({"admin": "permission_denied"}),
+        )),
+    }
+}
+
+fn room_error(message: &str) -> FieldError {
+    FieldError::new(message, graphql_value!({"room": "room_error"}))
+}
+
+fn list_rooms() -> FieldResult<Vec<room::RoomInfo>> {
+    let rooms = room::RoomManager::from_registry()
+        .send(room::MsgListRooms {})
+        .wait()
+        .map_err(|_| room_error("room manager unavailable"))??;
+    Ok(rooms)
+}
+
+fn query_rooms(executor: &juniper::Executor<GraphContext>) -> FieldResult<Vec<ApiAdminRoom>> {
+    admin_user(executor)?;
+
+    // rooms closed while querying are skipped
+    let details = room::RoomManager::from_registry()
+        .send(room::MsgQueryRoomDetails {
+            timeout: ROOM_QUERY_TIMEOUT,
+        })
+        .wait()
+        .map_err(|_| room_error("room manager unavailable"))?
+        .map_err(|_| room_error("query rooms failure"))?;
+    Ok(details.into_iter().map(|detail| detail.into()).collect())
+}
+
+fn close_room(executor: &juniper::Executor<GraphContext>, room_key: String) -> FieldResult<String> {
+    let user = admin_user(executor)?;
+    info!("admin({}) closes room: {}", user.id, room_key);
+
+    // rooms owned by other nodes are closed through cluster events
+    room::RoomManager::from_registry().do_send(room::MsgDestroyRoom {
+        room_key: room_key.clone(),
+    });
+    Ok(room_key)
+}
+
+fn kick_client(
+    executor: &juniper::Executor<GraphContext>,
+    room_key: String,
+    client_id: i32,
+) -> FieldResult<i32> {
+    let user = admin_user(executor)?;
+    info!(
+        "admin({}) kicks client({}) from room: {}",
+        user.id, client_id, room_key
+    );
+
+    let found = room::RoomManager::from_registry()
+        .send(room::MsgKickRoomClient {
+            room_key,
+            client_id: client_id as u32,
+        })
+        .wait()
+        .map_err(|_| room_error("room manager unavailable"))?
+        .map_err(|_| room_error("room unavailable"))?
+        .ok_or(FieldError::new(
+            "room not found",
+            graphql_value!({"room": "not_found"}),
+        ))?;
+    if !found {
+        return Err(FieldError::new(
+            "client not found",
+            graphql_value!({"client": "not_found"}),
+        ));
+    }
+    Ok(client_id)
+}
+
+fn broadcast_notice(
+    executor: &juniper::Executor<GraphContext>,
+    message: String,
+) -> FieldResult<i32> {
+    let user = admin_user(executor)?;
+    if message.trim().is_empty() {
+        return Err(FieldError::new(
+            "Invalid notice message",
+            graphql_value!({"message": "empty"}),
+        ));
+    }
+    info!("admin({}) broadcasts notice: {}", user.id, message);
+
+    let rooms = list_rooms()?;
+    room::RoomManager::from_registry().do_send(room::MsgBroadcastNotice { message });
+    Ok(rooms.len() as i32)
+}
@@ -1,4 +1,12 @@
-use crate::{app, db, graphql::ctx::GraphContext};
+use crate::{
+    app,
+    db::{self, dao::pads::PadStatus},
+    graphql::{
+        ctx::GraphContext,
+        subscription::{self, SubscriptionEvent},
+    },
+    recording, webhook,
+};
 use chrono::{DateTime, NaiveDateTime, Utc};
 use juniper::{FieldError, FieldResult};
 
@@ -8,6 +16,11 @@ graphql_object!(PadsQueryRoot: GraphContext |&self| {
     field all(&executor, req: ApiReqPadsQueryAll) -> FieldResult<ApiRespPadsQueryAll> {
         query_all(&executor, req)
     }
+
+    // terminal recording as asciicast v2
+    field recording(&executor, hash: String) -> FieldResult<Option<String>> {
+        query_recording(&executor, hash)
+    }
 });
 
 pub struct PadsMutationRoot;
@@ -16,6 +29,11 @@ graphql_object!(PadsMutationRoot: GraphContext |&self| {
     field create(&executor, req: ApiReqPadsCreate) -> FieldResult<ApiRespPadsCreate> {
         create_pad(&executor, req)
     }
+
+    // interview starts from unused to processing and ends at ended, see webhooks
+    field update(&executor, req: ApiReqPadsUpdate) -> FieldResult<ApiRespPadsUpdate> {
+        update_pad(&executor, req)
+    }
 });
 
 #[derive(GraphQLInputObject, Debug)]
@@ -31,6 +49,20 @@ struct ApiRespPadsCreate {
     pad: ApiPad,
 }
 
+#[derive(GraphQLInputObject, Debug)]
+#[graphql(description = "pad update params")]
+struct ApiReqPadsUpdate {
+    hash: String,
+    title: Option<String>,
+    status: Option<String>, // unused, processing or ended
+}
+
+#[derive(GraphQLObject, Debug)]
+#[graphql(description = "pad update response")]
+struct ApiRespPadsUpdate {
+    pad: ApiPad,
+}
+
 #[derive(GraphQLInputObject, Debug)]
 #[graphql(description = "pads filter")]
 struct ApiReqPadsFilter {
@@ -127,6 +159,24 @@ fn query_all(
     Ok(data.into())
 }
 
+fn query_recording(
+    executor: &juniper::Executor<GraphContext>,
+    hash: String,
+) -> FieldResult<Option<String>> {
+    let ctx = executor.context();
+    let session = ctx.session_ref()?;
+    let dao: &db::ApiDao = &ctx.dao;
+
+    let cast = recording::query_asciicast(dao, session.user_id, hash.as_str()).map_err(|e| {
+        debug!("query recording err = {:?}", e);
+        FieldError::new(
+            "query recording failure",
+            graphql_value!({"pad": "query_recording_failure"}),
+        )
+    })?;
+    Ok(cast)
+}
+
 fn create_pad(
     executor: &juniper::Executor<GraphContext>,
     req: ApiReqPadsCreate,
@@ -169,5 +219,60 @@ fn create_pad(
             )
         })?;
 
+    subscription::publish(SubscriptionEvent::PadsChanged(user_id));
+    webhook::notify(
+        user_id,
+        webhook::WebhookEvent::PadCreated,
+        serde_json::json!({ "pad": webhook::pad_event_data(&pad) }),
+    );
+
     Ok(ApiRespPadsCreate { pad: pad.into() })
 }
+
+fn update_pad(
+    executor: &juniper::Executor<GraphContext>,
+    req: ApiReqPadsUpdate,
+) -> FieldResult<ApiRespPadsUpdate> {
+    let ctx = executor.context();
+    let session = ctx.session_ref()?;
+    let dao: &db::ApiDao = &ctx.dao;
+
+    debug!("update_pad user_id = {}, req = {:?}", session.user_id, req);
+
+    if let Some(status) = req.status.as_ref() {
+        if status.parse::<PadStatus>().is_err() {
+            return Err(FieldError::new(
+                "invalid pad status",
+                graphql_value!({"pad": "invalid_pad_status"}),
+            ));
+        }
+    }
+
+    let pad = match dao.pads.query_pad_by_hash(req.hash.as_str())? {
+        Some(pad) if pad.user_id == session.user_id => pad,
+        _ => {
+            return Err(FieldError::new(
+                "pad not found",
+                graphql_value!({"pad": "pad_not_found"}),
+            ))
+        }
+    };
+
+    let changeset = db::PadChangeset {
+        title: req.title,
+        status: req.status,
+        language: None,
+    };
+    let (pad, previous_status) = dao.pads.update_pad(pad.id, changeset).map_err(|e| {
+        debug!("update pad err = {:?}", e);
+        FieldError::new(
+            "update pad failure",
+            graphql_value!({"pad": "update_pad_failure"}),
+        )
+    })?;
+
+    subscription::publish(SubscriptionEvent::PadsChanged(pad.user_id));
+    webhook::notify_pad_status(previous_status.as_str(), &pad);
+
+    Ok(ApiRespPadsUpdate { pad: pad.into() })
+}
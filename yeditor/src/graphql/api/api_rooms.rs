@@ -0,0 +1,59 @@
+use crate::{graphql::ctx::GraphContext, graphql::subscription, room::RoomStats};
+use juniper::FieldResult;
+
+pub struct RoomsQueryRoot;
+
+graphql_object!(RoomsQueryRoot: GraphContext |&self| {
+    field active(&executor) -> FieldResult<Vec<ApiRoomStats>> {
+        query_active(&executor)
+    }
+    field stats(&executor, hash: String) -> FieldResult<ApiRoomStats> {
+        query_stats(&executor, hash)
+    }
+});
+
+#[derive(GraphQLObject, Debug)]
+#[graphql(description = "live room stats")]
+struct ApiRoomStats {
+    room_key: String,
+    active: bool,
+    participants: i32,
+    running: bool,
+}
+
+impl From<RoomStats> for ApiRoomStats {
+    fn from(stats: RoomStats) -> Self {
+        Self {
+            room_key: stats.room_key,
+            active: true,
+            participants: stats.participants as i32,
+            running: stats.running,
+        }
+    }
+}
+
+fn query_active(executor: &juniper::Executor<GraphContext>) -> FieldResult<Vec<ApiRoomStats>> {
+    let ctx = executor.context();
+    let session = ctx.session_ref()?;
+
+    let stats = subscription::user_room_stats(session.user_id);
+    Ok(stats.into_iter().map(|item| item.into()).collect())
+}
+
+fn query_stats(
+    executor: &juniper::Executor<GraphContext>,
+    hash: String,
+) -> FieldResult<ApiRoomStats> {
+    let ctx = executor.context();
+    let session = ctx.session_ref()?;
+
+    match subscription::room_stats(hash.as_str()) {
+        Some(ref stats) if stats.user_id == session.user_id => Ok(stats.clone().into()),
+        _ => Ok(ApiRoomStats {
+            room_key: hash,
+            active: false,
+            participants: 0,
+            running: false,
+        }),
+    }
+}
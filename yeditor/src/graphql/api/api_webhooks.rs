@@ -0,0 +1,238 @@
+use crate::{db, graphql::ctx::GraphContext, webhook::WebhookEvent};
+use chrono::{DateTime, Utc};
+use juniper::{FieldError, FieldResult};
+use std::str::FromStr;
+
+pub struct WebhooksQueryRoot;
+
+graphql_object!(WebhooksQueryRoot: GraphContext |&self| {
+    field all(&executor) -> FieldResult<Vec<ApiWebhook>> {
+        query_all(&executor)
+    }
+    field deliveries(&executor, req: ApiReqWebhookDeliveries) -> FieldResult<ApiRespWebhookDeliveries> {
+        query_deliveries(&executor, req)
+    }
+});
+
+pub struct WebhooksMutationRoot;
+
+graphql_object!(WebhooksMutationRoot: GraphContext |&self| {
+    field create(&executor, req: ApiReqWebhookCreate) -> FieldResult<ApiRespWebhookCreate> {
+        create_webhook(&executor, req)
+    }
+    field delete(&executor, id: i32) -> FieldResult<i32> as "returns webhook id" {
+        delete_webhook(&executor, id)
+    }
+});
+
+#[derive(GraphQLInputObject, Debug)]
+#[graphql(description = "webhook create params")]
+struct ApiReqWebhookCreate {
+    url: String,
+    secret: Option<String>,
+    events: Vec<String>,
+}
+
+#[derive(GraphQLObject, Debug)]
+#[graphql(description = "webhook create response, secret is only returned once")]
+struct ApiRespWebhookCreate {
+    webhook: ApiWebhook,
+    secret: String,
+}
+
+#[derive(GraphQLObject, Debug)]
+#[graphql(description = "webhook")]
+struct ApiWebhook {
+    id: i32,
+    url: String,
+    events: Vec<String>,
+    active: bool,
+    create_time: DateTime<Utc>,
+    update_time: DateTime<Utc>,
+}
+
+impl From<db::Webhook> for ApiWebhook {
+    fn from(hook: db::Webhook) -> Self {
+        Self {
+            id: hook.id,
+            url: hook.url,
+            events: hook
+                .events
+                .split(',')
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_owned())
+                .collect(),
+            active: hook.active,
+            create_time: DateTime::from_utc(hook.created_at, Utc),
+            update_time: DateTime::from_utc(hook.updated_at, Utc),
+        }
+    }
+}
+
+#[derive(GraphQLInputObject, Debug)]
+#[graphql(description = "webhook deliveries query request")]
+struct ApiReqWebhookDeliveries {
+    webhook_id: i32,
+    page_index: i32,
+    page_size: i32,
+}
+
+#[derive(GraphQLObject, Debug)]
+#[graphql(description = "webhook delivery")]
+struct ApiWebhookDelivery {
+    id: i32,
+    event: String,
+    payload: String,
+    status: String,
+    attempts: i32,
+    response_code: Option<i32>,
+    last_error: Option<String>,
+    create_time: DateTime<Utc>,
+    update_time: DateTime<Utc>,
+}
+
+impl From<db::WebhookDelivery> for ApiWebhookDelivery {
+    fn from(delivery: db::WebhookDelivery) -> Self {
+        Self {
+            id: delivery.id,
+            event: delivery.event,
+            payload: delivery.payload,
+            status: delivery.status,
+            attempts: delivery.attempts,
+            response_code: delivery.response_code,
+            last_error: delivery.last_error,
+            create_time: DateTime::from_utc(delivery.created_at, Utc),
+            update_time: DateTime::from_utc(delivery.updated_at, Utc),
+        }
+    }
+}
+
+#[derive(GraphQLObject, Debug)]
+struct ApiRespWebhookDeliveries {
+    page_index: i32,
+    page_size: i32,
+    total: i32,
+    deliveries: Vec<ApiWebhookDelivery>,
+}
+
+impl From<db::PaginatedData<db::WebhookDelivery>> for ApiRespWebhookDeliveries {
+    fn from(data: db::PaginatedData<db::WebhookDelivery>) -> Self {
+        Self {
+            page_index: data.page_index,
+            page_size: data.page_size,
+            total: data.total,
+            deliveries: data.data.into_iter().map(|item| item.into()).collect(),
+        }
+    }
+}
+
+fn query_all(executor: &juniper::Executor<GraphContext>) -> FieldResult<Vec<ApiWebhook>> {
+    let ctx = executor.context();
+    let session = ctx.session_ref()?;
+    let dao: &db::ApiDao = &ctx.dao;
+
+    let hooks = dao.webhooks.query_all(session.user_id)?;
+    Ok(hooks.into_iter().map(|item| item.into()).collect())
+}
+
+fn query_deliveries(
+    executor: &juniper::Executor<GraphContext>,
+    req: ApiReqWebhookDeliveries,
+) -> FieldResult<ApiRespWebhookDeliveries> {
+    let ctx = executor.context();
+    let session = ctx.session_ref()?;
+    let dao: &db::ApiDao = &ctx.dao;
+
+    // only owner can read delivery logs
+    dao.webhooks
+        .query_webhook(session.user_id, req.webhook_id)?
+        .ok_or(FieldError::new(
+            "webhook not found",
+            graphql_value!({"webhook": "not_found"}),
+        ))?;
+
+    let pagination = db::Pagination {
+        page_index: req.page_index,
+        page_size: req.page_size,
+    };
+    let data = dao.webhooks.query_deliveries(req.webhook_id, &pagination)?;
+
+    Ok(data.into())
+}
+
+fn validate_webhook_url(url: &str) -> FieldResult<()> {
+    match url::Url::parse(url) {
+        Ok(ref parsed) if parsed.scheme() == "http" || parsed.scheme() == "https" => Ok(()),
+        _ => Err(FieldError::new(
+            "Invalid webhook url",
+            graphql_value!({"url": "invalid_format"}),
+        )),
+    }
+}
+
+fn validate_webhook_events(events: &Vec<String>) -> FieldResult<()> {
+    if events.is_empty() || events.iter().any(|e| WebhookEvent::from_str(e).is_err()) {
+        Err(FieldError::new(
+            "Invalid webhook events",
+            graphql_value!({"events": "invalid_events"}),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn create_webhook(
+    executor: &juniper::Executor<GraphContext>,
+    req: ApiReqWebhookCreate,
+) -> FieldResult<ApiRespWebhookCreate> {
+    let ctx = executor.context();
+    let session = ctx.session_ref()?;
+    let dao: &db::ApiDao = &ctx.dao;
+
+    debug!(
+        "create_webhook user_id = {}, req = {:?}",
+        session.user_id, req
+    );
+
+    validate_webhook_url(req.url.as_str())?;
+    validate_webhook_events(&req.events)?;
+
+    let secret = match req.secret {
+        Some(ref secret) if !secret.trim().is_empty() => secret.to_owned(),
+        _ => uuid::Uuid::new_v4().to_string().replace("-", ""),
+    };
+
+    let new_webhook = db::NewWebhook {
+        user_id: session.user_id,
+        url: req.url,
+        secret: secret.clone(),
+        events: req.events.join(","),
+    };
+    let hook = dao.webhooks.create_webhook(new_webhook).map_err(|e| {
+        debug!("create webhook err = {:?}", e);
+        FieldError::new(
+            "create webhook failure",
+            graphql_value!({"webhook": "create_webhook_failure"}),
+        )
+    })?;
+
+    Ok(ApiRespWebhookCreate {
+        webhook: hook.into(),
+        secret: secret,
+    })
+}
+
+fn delete_webhook(executor: &juniper::Executor<GraphContext>, id: i32) -> FieldResult<i32> {
+    let ctx = executor.context();
+    let session = ctx.session_ref()?;
+    let dao: &db::ApiDao = &ctx.dao;
+
+    let rows = dao.webhooks.delete_webhook(session.user_id, id)?;
+    if rows == 0 {
+        return Err(FieldError::new(
+            "webhook not found",
+            graphql_value!({"webhook": "not_found"}),
+        ));
+    }
+    Ok(id)
+}
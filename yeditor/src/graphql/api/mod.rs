@@ -1,8 +1,14 @@
+mod api_admin;
 mod api_pads;
+mod api_rooms;
 mod api_user;
+mod api_webhooks;
 
+use self::api_admin::*;
 use self::api_pads::*;
+use self::api_rooms::*;
 use self::api_user::*;
+use self::api_webhooks::*;
 use super::ctx::GraphContext;
 
 // QueryRoot
@@ -11,6 +17,9 @@ pub struct QueryRoot;
 graphql_object!(QueryRoot: GraphContext |&self| {
   field user() -> UserQueryRoot { UserQueryRoot }
   field pads() -> PadsQueryRoot { PadsQueryRoot }
+  field rooms() -> RoomsQueryRoot { RoomsQueryRoot }
+  field webhooks() -> WebhooksQueryRoot { WebhooksQueryRoot }
+  field admin() -> AdminQueryRoot { AdminQueryRoot }
 });
 
 // MutationRoot
@@ -19,6 +28,8 @@ pub struct MutationRoot;
 graphql_object!(MutationRoot: GraphContext |&self| {
   field user() -> UserMutationRoot { UserMutationRoot }
   field pads() -> PadsMutationRoot { PadsMutationRoot }
+  field webhooks() -> WebhooksMutationRoot { WebhooksMutationRoot }
+  field admin() -> AdminMutationRoot { AdminMutationRoot }
 });
 
 //
@@ -0,0 +1,161 @@
+/// Generate the HTML source of the admin dashboard for live rooms
+pub fn admin_dashboard_source(graphql_endpoint_url: &str) -> String {
+    let stylesheet_source = r#"
+    <style>
+      body {
+        margin: 0;
+        font-family: system, -apple-system, 'Segoe UI', 'Helvetica Neue', helvetica, arial, sans-serif;
+        font-size: 14px;
+        color: #333333;
+      }
+      .toolbar {
+        background: linear-gradient(#f7f7f7, #e2e2e2);
+        border-bottom: 1px solid #d0d0d0;
+        padding: 7px 14px 6px;
+      }
+      .toolbar input {
+        padding: 5px;
+        border: 0px;
+        margin: 0 5px;
+        font-size: 12px;
+        border-radius: 3px;
+      }
+      #token { width: 30%; }
+      #notice { width: 30%; }
+      #error { color: #c0392b; padding: 7px 14px; }
+      #node { color: #777777; padding: 0 14px; }
+      table { border-collapse: collapse; margin: 14px; }
+      th, td { border-bottom: 1px solid #e2e2e2; padding: 6px 10px; text-align: left; }
+      .off { color: #c0392b; }
+      .on { color: #27ae60; }
+    </style>
+    "#;
+    let script_source = r#"
+    <script>
+      var token_element = document.getElementById('token');
+      var notice_element = document.getElementById('notice');
+      var error_element = document.getElementById('error');
+      var rooms_element = document.getElementById('rooms');
+      var node_element = document.getElementById('node');
+      token_element.value = localStorage.getItem('graphiql:jwtToken') || '';
+
+      function graphql(query, variables) {
+        var token = token_element.value;
+        localStorage.setItem('graphiql:jwtToken', token);
+        return fetch(GRAPHQL_URL, {
+          method: 'post',
+          headers: {
+            'Accept': 'application/json',
+            'Content-Type': 'application/json',
+            'Authorization': 'Bearer ' + token
+          },
+          body: JSON.stringify({ query: query, variables: variables })
+        }).then(function (response) {
+          return response.json();
+        }).then(function (body) {
+          error_element.textContent = body.errors ? body.errors[0].message : '';
+          return body.data;
+        });
+      }
+
+      function cell(row, text, className) {
+        var td = document.createElement('td');
+        td.textContent = text;
+        if (className) { td.className = className; }
+        row.appendChild(td);
+        return td;
+      }
+
+      function button(td, text, onclick) {
+        var btn = document.createElement('button');
+        btn.textContent = text;
+        btn.onclick = onclick;
+        td.appendChild(btn);
+      }
+
+      function render(rooms) {
+        rooms_element.innerHTML = '';
+        rooms.forEach(function (room) {
+          var row = document.createElement('tr');
+          cell(row, room.roomKey);
+          cell(row, room.language || '');
+          var clients = cell(row, room.participants + ' ');
+          room.clients.forEach(function (client) {
+            button(clients, 'kick #' + client.id, function () {
+              graphql('mutation($key: String!, $id: Int!) { admin { kickClient(roomKey: $key, clientId: $id) } }',
+                { key: room.roomKey, id: client.id }).then(refresh);
+            });
+          });
+          cell(row, room.runnerConnected ? 'connected' : 'disconnected', room.runnerConnected ? 'on' : 'off');
+          cell(row, room.running ? 'yes' : 'no');
+          cell(row, room.uptimeSeconds + 's');
+          cell(row, room.bufferSize + ' bytes');
+          button(cell(row, ''), 'close', function () {
+            if (!confirm('Close room ' + room.roomKey + '?')) { return; }
+            graphql('mutation($key: String!) { admin { closeRoom(roomKey: $key) } }',
+              { key: room.roomKey }).then(refresh);
+          });
+          rooms_element.appendChild(row);
+        });
+      }
+
+      function refresh() {
+        graphql('{ admin { node rooms { roomKey language participants clients { id name } runnerConnected running uptimeSeconds bufferSize } } }')
+          .then(function (data) {
+            if (data && data.admin) {
+              node_element.textContent = 'Rooms of node ' + data.admin.node + ', other nodes of the cluster list their own rooms';
+              render(data.admin.rooms);
+            }
+          });
+      }
+
+      document.getElementById('broadcast').onclick = function () {
+        if (!notice_element.value) { return; }
+        graphql('mutation($message: String!) { admin { broadcast(message: $message) } }',
+          { message: notice_element.value }).then(function () {
+            notice_element.value = '';
+          });
+      };
+      document.getElementById('refresh').onclick = refresh;
+
+      refresh();
+      setInterval(refresh, 5000);
+    </script>
+    "#;
+
+    format!(r#"
+<!DOCTYPE html>
+<html>
+<head>
+    <title>Live Rooms</title>
+    {stylesheet_source}
+</head>
+<body>
+    <div class="toolbar">
+      <label>Token</label>
+      <input id="token" placeholder="Paste token (without Bearer)">
+      <button id="refresh">Refresh</button>
+      <input id="notice" placeholder="Maintenance notice">
+      <button id="broadcast">Broadcast</button>
+    </div>
+    <div id="error"></div>
+    <div id="node"></div>
+    <table>
+      <thead>
+        <tr>
+          <th>Room</th><th>Language</th><th>Participants</th><th>Runner</th>
+          <th>Running</th><th>Uptime</th><th>Buffer</th><th></th>
+        </tr>
+      </thead>
+      <tbody id="rooms"></tbody>
+    </table>
+    <script src="//cdnjs.cloudflare.com/ajax/libs/fetch/2.0.3/fetch.js"></script>
+    <script>var GRAPHQL_URL = '{graphql_url}';</script>
+    {script_source}
+</body>
+</html>
+"#,
+        graphql_url = graphql_endpoint_url,
+        stylesheet_source = stylesheet_source,
+        script_source = script_source)
+}
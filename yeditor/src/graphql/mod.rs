@@ -1,4 +1,6 @@
 pub mod api;
 pub mod ctx;
+pub mod dashboard;
 pub mod playground;
+pub mod subscription;
 
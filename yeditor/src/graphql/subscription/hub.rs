@@ -0,0 +1,194 @@
+use actix::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
+
+use super::proto::Topic;
+use crate::{cluster, room::RoomStats};
+
+lazy_static! {
+    // latest stats of live rooms of all nodes, keyed by room_key, see publish
+    static ref ROOM_STATS: RwLock<HashMap<String, RoomStats>> = RwLock::new(HashMap::new());
+}
+
+/// Stats of live rooms whose pads belong to user
+pub fn user_room_stats(user_id: i32) -> Vec<RoomStats> {
+    let all_stats = ROOM_STATS.read().unwrap();
+    let mut stats: Vec<RoomStats> = all_stats
+        .values()
+        .filter(|s| s.user_id == user_id)
+        .cloned()
+        .collect();
+    stats.sort_by(|a, b| a.room_key.cmp(&b.room_key));
+    stats
+}
+
+pub fn room_stats(room_key: &str) -> Option<RoomStats> {
+    ROOM_STATS.read().unwrap().get(room_key).cloned()
+}
+
+/// Publish a change to subscribers of all nodes, stats of rooms are kept by every node
+pub fn publish(event: SubscriptionEvent) {
+    cluster::publish(cluster::ClusterEvent::Subscription(event));
+}
+
+/// Deliver a change published by any node to subscribers of current node
+pub fn deliver(event: SubscriptionEvent) {
+    SubscriptionHub::from_registry().do_send(event);
+}
+
+#[derive(Debug, Clone, Message, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionEvent {
+    PadsChanged(i32), // user_id
+    RoomChanged(RoomStats),
+    RoomClosed(RoomStats),
+}
+
+impl SubscriptionEvent {
+    pub fn topic(&self) -> Topic {
+        match self {
+            SubscriptionEvent::PadsChanged(user_id) => Topic::Pads(*user_id),
+            SubscriptionEvent::RoomChanged(stats) => Topic::Rooms(stats.user_id),
+            SubscriptionEvent::RoomClosed(stats) => Topic::Rooms(stats.user_id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Message)]
+pub struct MsgTopicChanged(pub Topic);
+
+pub type TopicListener = Recipient<MsgTopicChanged>;
+
+struct Subscriber {
+    topics: HashSet<Topic>,
+    listener: TopicListener,
+}
+
+pub struct SubscriptionHub {
+    subscribers: HashMap<u32, Subscriber>,
+    next_subscriber_id: u32,
+}
+
+impl SubscriptionHub {
+    pub fn new() -> Self {
+        SubscriptionHub {
+            subscribers: HashMap::new(),
+            next_subscriber_id: 1,
+        }
+    }
+
+    fn next_subscriber_id(&mut self) -> u32 {
+        self.next_subscriber_id += 1;
+        self.next_subscriber_id
+    }
+}
+
+// System Service
+impl Default for SubscriptionHub {
+    fn default() -> Self {
+        SubscriptionHub::new()
+    }
+}
+
+impl SystemService for SubscriptionHub {
+    fn service_started(&mut self, ctx: &mut Context<Self>) {
+        info!("SubscriptionHub Service started");
+    }
+}
+
+impl Supervised for SubscriptionHub {
+    fn restarting(&mut self, ctx: &mut <Self as Actor>::Context) {
+        info!("SubscriptionHub Service restarting");
+    }
+}
+
+// Actor
+
+impl Actor for SubscriptionHub {
+    type Context = Context<Self>;
+}
+
+// Messages
+
+pub struct MsgRegister {
+    pub listener: TopicListener,
+}
+
+impl Message for MsgRegister {
+    type Result = u32; // subscriber id
+}
+
+#[derive(Message)]
+pub struct MsgUnregister {
+    pub subscriber_id: u32,
+}
+
+#[derive(Message)]
+pub struct MsgSetTopics {
+    pub subscriber_id: u32,
+    pub topics: HashSet<Topic>,
+}
+
+impl Handler<MsgRegister> for SubscriptionHub {
+    type Result = MessageResult<MsgRegister>;
+
+    fn handle(&mut self, msg: MsgRegister, ctx: &mut Self::Context) -> Self::Result {
+        let subscriber_id = self.next_subscriber_id();
+        let subscriber = Subscriber {
+            topics: HashSet::new(),
+            listener: msg.listener,
+        };
+        self.subscribers.insert(subscriber_id, subscriber);
+        debug!("subscriber registered: {}", subscriber_id);
+        MessageResult(subscriber_id)
+    }
+}
+
+impl Handler<MsgUnregister> for SubscriptionHub {
+    type Result = ();
+
+    fn handle(&mut self, msg: MsgUnregister, ctx: &mut Self::Context) -> Self::Result {
+        self.subscribers.remove(&msg.subscriber_id);
+        debug!("subscriber unregistered: {}", msg.subscriber_id);
+    }
+}
+
+impl Handler<MsgSetTopics> for SubscriptionHub {
+    type Result = ();
+
+    fn handle(&mut self, msg: MsgSetTopics, ctx: &mut Self::Context) -> Self::Result {
+        if let Some(subscriber) = self.subscribers.get_mut(&msg.subscriber_id) {
+            subscriber.topics = msg.topics;
+        }
+    }
+}
+
+impl Handler<SubscriptionEvent> for SubscriptionHub {
+    type Result = ();
+
+    fn handle(&mut self, msg: SubscriptionEvent, ctx: &mut Self::Context) -> Self::Result {
+        debug!("subscription event: {:?}", msg);
+        let topic = msg.topic();
+        match msg {
+            SubscriptionEvent::PadsChanged(_) => {}
+            SubscriptionEvent::RoomChanged(stats) => {
+                ROOM_STATS
+                    .write()
+                    .unwrap()
+                    .insert(stats.room_key.clone(), stats);
+            }
+            SubscriptionEvent::RoomClosed(stats) => {
+                ROOM_STATS.write().unwrap().remove(&stats.room_key);
+            }
+        }
+
+        for (subscriber_id, subscriber) in self.subscribers.iter() {
+            if !subscriber.topics.contains(&topic) {
+                continue;
+            }
+            if let Err(err) = subscriber.listener.do_send(MsgTopicChanged(topic.clone())) {
+                info!("notify subscriber {} error: {:?}", subscriber_id, err);
+            }
+        }
+    }
+}
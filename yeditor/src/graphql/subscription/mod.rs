@@ -0,0 +1,8 @@
+mod hub;
+pub use self::hub::*;
+
+mod proto;
+pub use self::proto::*;
+
+mod session;
+pub use self::session::*;
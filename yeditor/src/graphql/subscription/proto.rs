@@ -0,0 +1,247 @@
+// graphql-ws protocol (subscriptions-transport-ws)
+// https://github.com/apollographql/subscriptions-transport-ws/blob/master/PROTOCOL.md
+//
+// Subscriptions are served as live queries: the subscription document is executed
+// as a query against the regular schema, and executed again whenever one of the
+// topics of its top level fields changes.
+
+pub const GQL_CONNECTION_INIT: &'static str = "connection_init";
+pub const GQL_CONNECTION_ACK: &'static str = "connection_ack";
+pub const GQL_CONNECTION_ERROR: &'static str = "connection_error";
+pub const GQL_CONNECTION_KEEP_ALIVE: &'static str = "ka";
+pub const GQL_CONNECTION_TERMINATE: &'static str = "connection_terminate";
+pub const GQL_START: &'static str = "start";
+pub const GQL_DATA: &'static str = "data";
+pub const GQL_ERROR: &'static str = "error";
+pub const GQL_COMPLETE: &'static str = "complete";
+pub const GQL_STOP: &'static str = "stop";
+
+pub const GRAPHQL_WS_PROTOCOL: &'static str = "graphql-ws";
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OperationMessage {
+    #[serde(rename = "type")]
+    pub msg_type: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payload: Option<serde_json::Value>,
+}
+
+impl OperationMessage {
+    pub fn new(msg_type: &str, id: Option<String>, payload: Option<serde_json::Value>) -> Self {
+        OperationMessage {
+            msg_type: msg_type.to_owned(),
+            id,
+            payload,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ConnectionParams {
+    #[serde(default, alias = "Authorization")]
+    pub authorization: Option<String>,
+}
+
+/// Changes pushed to subscribers, every topic is scoped to one user
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Topic {
+    Pads(i32),  // user_id
+    Rooms(i32), // user_id
+}
+
+impl Topic {
+    /// topic of a top level query field
+    pub fn from_field(field: &str, user_id: i32) -> Option<Topic> {
+        match field {
+            "pads" => Some(Topic::Pads(user_id)),
+            "rooms" => Some(Topic::Rooms(user_id)),
+            _ => None,
+        }
+    }
+}
+
+/// Convert subscription document to a query document.
+/// Returns None if document is not a single subscription operation without fragments.
+pub fn subscription_to_query(document: &str) -> Option<String> {
+    if !is_single_subscription(&tokenize(document)) {
+        return None;
+    }
+    let trimmed = document.trim_start();
+    if !trimmed.starts_with("subscription") {
+        return None;
+    }
+    let rest = &trimmed["subscription".len()..];
+    match rest.chars().next() {
+        Some(c) if c.is_alphanumeric() || c == '_' => None,
+        _ => Some(format!("query{}", rest)),
+    }
+}
+
+enum Token {
+    Ident(String),
+    Punct(char),
+}
+
+fn tokenize(document: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut chars = document.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '#' => {
+                // comment till end of line
+                while let Some(n) = chars.next() {
+                    if n == '\n' {
+                        break;
+                    }
+                }
+            }
+            '"' => {
+                // string literal, arguments only
+                let mut escaped = false;
+                while let Some(n) = chars.next() {
+                    if n == '"' && !escaped {
+                        break;
+                    }
+                    escaped = n == '\\' && !escaped;
+                }
+            }
+            _ if c.is_alphanumeric() || c == '_' => {
+                let mut ident = c.to_string();
+                while let Some(&n) = chars.peek() {
+                    if !(n.is_alphanumeric() || n == '_') {
+                        break;
+                    }
+                    ident.push(n);
+                    chars.next();
+                }
+                tokens.push(Token::Ident(ident));
+            }
+            _ if c.is_whitespace() || c == ',' => {}
+            _ => tokens.push(Token::Punct(c)),
+        }
+    }
+    tokens
+}
+
+// one operation, anonymous or named, other definitions and fragments are rejected
+// as fields of fragments would not be seen by top_level_fields
+fn is_single_subscription(tokens: &[Token]) -> bool {
+    match tokens.first() {
+        Some(Token::Ident(keyword)) if keyword == "subscription" => {}
+        _ => return false,
+    }
+    let mut depth = 0;
+    let mut parens = 0;
+    let mut blocks = 0;
+    for token in tokens {
+        // definitions after the operation
+        if blocks > 0 && depth == 0 {
+            return false;
+        }
+        match token {
+            Token::Punct('{') => {
+                if depth == 0 {
+                    blocks += 1;
+                }
+                depth += 1;
+            }
+            Token::Punct('}') => depth -= 1,
+            Token::Punct('(') => parens += 1,
+            Token::Punct(')') => parens -= 1,
+            // fragment spreads and inline fragments, numbers in arguments have dots too
+            Token::Punct('.') if parens == 0 => return false,
+            _ => {}
+        }
+    }
+    blocks == 1 && depth == 0
+}
+
+/// Names of top level fields of the operation selection set
+pub fn top_level_fields(document: &str) -> Vec<String> {
+    let tokens = tokenize(document);
+    let mut fields: Vec<String> = vec![];
+    let mut depth = 0;
+    let mut parens = 0;
+    let mut skip_next = false;
+
+    for (idx, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Punct('{') => depth += 1,
+            Token::Punct('}') => depth -= 1,
+            Token::Punct('(') => parens += 1,
+            Token::Punct(')') => parens -= 1,
+            // directives are not fields
+            Token::Punct('@') => skip_next = true,
+            Token::Ident(name) if depth == 1 && parens == 0 => {
+                let is_alias = match tokens.get(idx + 1) {
+                    Some(Token::Punct(':')) => true,
+                    _ => false,
+                };
+                if skip_next {
+                    skip_next = false;
+                } else if !is_alias && !fields.contains(name) {
+                    fields.push(name.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+    fields
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn subscription_document_to_query() {
+        assert_eq!(
+            Some("query { pads { all } }".to_owned()),
+            subscription_to_query("subscription { pads { all } }")
+        );
+        assert_eq!(
+            Some("query PadList { pads { all } }".to_owned()),
+            subscription_to_query("  subscription PadList { pads { all } }")
+        );
+        assert_eq!(None, subscription_to_query("query { pads { all } }"));
+        assert_eq!(None, subscription_to_query("subscriptions { pads }"));
+        assert_eq!(
+            Some("query { pads { all(req: { pageSize: 1.5 }) } }".to_owned()),
+            subscription_to_query("subscription { pads { all(req: { pageSize: 1.5 }) } }")
+        );
+    }
+
+    #[test]
+    fn subscription_document_with_fragments_or_operations_rejected() {
+        let document = r#"
+            subscription { ...Live }
+            fragment Live on QueryRoot { rooms { active { roomKey } } }
+        "#;
+        assert_eq!(None, subscription_to_query(document));
+        let document = "subscription { ... on QueryRoot { pads { all } } }";
+        assert_eq!(None, subscription_to_query(document));
+        let document = "subscription A { pads { all } } subscription B { rooms { active } }";
+        assert_eq!(None, subscription_to_query(document));
+        let document = "subscription { pads { all } } query { rooms { active } }";
+        assert_eq!(None, subscription_to_query(document));
+        assert_eq!(None, subscription_to_query("subscription { pads { all }"));
+    }
+
+    #[test]
+    fn subscription_top_level_fields() {
+        let document = r#"
+            subscription Dashboard($req: ApiReqPadsQueryAll!) {
+                pads { all(req: $req) { total pads { hash status } } }
+                live: rooms { active { roomKey participants running } }
+            }
+        "#;
+        assert_eq!(vec!["pads", "rooms"], top_level_fields(document));
+
+        let document = r#"subscription { rooms { stats(hash: "a{b}") { running } } }"#;
+        assert_eq!(vec!["rooms"], top_level_fields(document));
+    }
+}
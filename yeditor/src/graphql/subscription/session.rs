@@ -0,0 +1,334 @@
+use actix::fut;
+use actix::prelude::*;
+use actix_web::{http, ws};
+use failure::{Fallible, ResultExt};
+use juniper::http::GraphQLRequest;
+use std::collections::{HashMap, HashSet};
+use std::time;
+
+use super::hub::{self, MsgTopicChanged, SubscriptionHub};
+use super::proto::*;
+use crate::app::{self, api};
+use crate::room;
+
+struct Operation {
+    query: String,
+    variables: Option<serde_json::Value>,
+    operation_name: Option<String>,
+    topics: HashSet<Topic>,
+}
+
+// subscription session
+
+pub struct SubscriptionSession {
+    subscriber_id: u32,
+    authorization: String,
+    user_id: Option<i32>,
+    operations: HashMap<String, Operation>,
+    keep_alive_time: time::Instant,
+}
+
+impl SubscriptionSession {
+    pub fn new() -> Self {
+        SubscriptionSession {
+            subscriber_id: 0,
+            authorization: "".to_owned(),
+            user_id: None,
+            operations: HashMap::new(),
+            keep_alive_time: time::Instant::now(),
+        }
+    }
+}
+
+impl Actor for SubscriptionSession {
+    type Context = ws::WebsocketContext<Self, app::AppState>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        let req = ctx.request();
+        info!("subscription session started! {:?}", req.peer_addr());
+        self.authorization = req
+            .headers()
+            .get(http::header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("")
+            .to_string();
+        self.start_keep_alive(ctx);
+        self.register(ctx);
+    }
+
+    fn stopping(&mut self, ctx: &mut Self::Context) -> Running {
+        info!("subscription session stopping: {}", self.subscriber_id);
+        SubscriptionHub::from_registry().do_send(hub::MsgUnregister {
+            subscriber_id: self.subscriber_id,
+        });
+        Running::Stop
+    }
+}
+
+impl SubscriptionSession {
+    fn start_keep_alive(&self, ctx: &mut <Self as Actor>::Context) {
+        let room_config = room::Room::room_config();
+        let keep_alive_interval = time::Duration::from_millis(room_config.client_keep_alive_ms);
+        let client_timeout = time::Duration::from_millis(room_config.client_timeout_ms);
+
+        ctx.run_interval(keep_alive_interval, move |this, context| {
+            if time::Instant::now().duration_since(this.keep_alive_time) > client_timeout {
+                info!("subscription keep alive timeout, disconnecting...");
+                context.stop();
+            } else {
+                context.ping("");
+                this.send_message(
+                    context,
+                    OperationMessage::new(GQL_CONNECTION_KEEP_ALIVE, None, None),
+                );
+            }
+        });
+    }
+
+    fn register(&mut self, ctx: &mut <Self as Actor>::Context) {
+        let msg = hub::MsgRegister {
+            listener: ctx.address().recipient(),
+        };
+        SubscriptionHub::from_registry()
+            .send(msg)
+            .into_actor(self)
+            .then(|res, act, context| {
+                match res {
+                    Err(err) => {
+                        info!("register subscriber failure: {:?}", err);
+                        context.stop();
+                    }
+                    Ok(subscriber_id) => act.subscriber_id = subscriber_id,
+                }
+                fut::ok(())
+            })
+            .wait(ctx);
+    }
+
+    fn update_topics(&self) {
+        let topics: HashSet<Topic> = self
+            .operations
+            .values()
+            .flat_map(|op| op.topics.iter().cloned())
+            .collect();
+        SubscriptionHub::from_registry().do_send(hub::MsgSetTopics {
+            subscriber_id: self.subscriber_id,
+            topics,
+        });
+    }
+
+    fn send_message(&self, ctx: &mut <Self as Actor>::Context, msg: OperationMessage) {
+        match serde_json::to_string(&msg) {
+            Err(err) => info!("serialize subscription message failure: {:?}", err),
+            Ok(text) => ctx.text(text),
+        }
+    }
+
+    fn send_error(&self, ctx: &mut <Self as Actor>::Context, id: Option<String>, message: &str) {
+        let payload = serde_json::json!({ "message": message });
+        self.send_message(ctx, OperationMessage::new(GQL_ERROR, id, Some(payload)));
+    }
+}
+
+impl StreamHandler<ws::Message, ws::ProtocolError> for SubscriptionSession {
+    fn handle(&mut self, msg: ws::Message, ctx: &mut Self::Context) {
+        match msg {
+            ws::Message::Ping(ping) => {
+                self.keep_alive_time = time::Instant::now();
+                ctx.pong(&ping);
+            }
+            ws::Message::Pong(_) => {
+                self.keep_alive_time = time::Instant::now();
+            }
+            ws::Message::Text(text) => {
+                self.keep_alive_time = time::Instant::now();
+                if let Err(err) = self.on_message(&text, ctx) {
+                    warn!("on subscription message error = {:?}", err);
+                    ctx.stop();
+                }
+            }
+            ws::Message::Binary(_) => {
+                info!("binary message not supported, stop subscription session");
+                ctx.stop();
+            }
+            ws::Message::Close(_) => {
+                info!("received closed from client, stop subscription session");
+                ctx.stop();
+            }
+        }
+    }
+}
+
+impl SubscriptionSession {
+    fn on_message(&mut self, text: &String, ctx: &mut <Self as Actor>::Context) -> Fallible<()> {
+        debug!("on subscription message: {}", text);
+        let msg = serde_json::from_str::<OperationMessage>(text.as_str())
+            .context("subscription packet error")?;
+
+        match msg.msg_type.as_str() {
+            GQL_CONNECTION_INIT => self.on_connection_init(ctx, msg.payload),
+            GQL_START => {
+                let id = msg.id.ok_or(failure::err_msg("operation id missing"))?;
+                self.on_start(ctx, id, msg.payload);
+            }
+            GQL_STOP => {
+                let id = msg.id.ok_or(failure::err_msg("operation id missing"))?;
+                self.on_stop(ctx, id);
+            }
+            GQL_CONNECTION_TERMINATE => ctx.stop(),
+            _ => {
+                warn!("unknown subscription message type: {}", msg.msg_type);
+            }
+        }
+        Ok(())
+    }
+
+    fn on_connection_init(
+        &mut self,
+        ctx: &mut <Self as Actor>::Context,
+        payload: Option<serde_json::Value>,
+    ) {
+        let params = payload.and_then(|p| serde_json::from_value::<ConnectionParams>(p).ok());
+        if let Some(authorization) = params.and_then(|p| p.authorization) {
+            self.authorization = authorization;
+        }
+
+        let req = api::ReqQuerySession {
+            authorization: self.authorization.clone(),
+        };
+        api::api_executor()
+            .send(req)
+            .into_actor(self)
+            .then(|res, act, context| {
+                match res {
+                    Ok(Ok(Some(session))) => {
+                        act.user_id = Some(session.user_id);
+                        act.send_message(
+                            context,
+                            OperationMessage::new(GQL_CONNECTION_ACK, None, None),
+                        );
+                    }
+                    _ => {
+                        info!("subscription session not authorized: {:?}", res.is_ok());
+                        let payload = serde_json::json!({ "message": "session not found" });
+                        act.send_message(
+                            context,
+                            OperationMessage::new(GQL_CONNECTION_ERROR, None, Some(payload)),
+                        );
+                        context.stop();
+                    }
+                }
+                fut::ok(())
+            })
+            .wait(ctx);
+    }
+
+    fn on_start(
+        &mut self,
+        ctx: &mut <Self as Actor>::Context,
+        id: String,
+        payload: Option<serde_json::Value>,
+    ) {
+        let user_id = match self.user_id {
+            None => return self.send_error(ctx, Some(id), "connection not initialized"),
+            Some(val) => val,
+        };
+        let payload = payload.unwrap_or(serde_json::Value::Null);
+        let document = payload["query"].as_str().unwrap_or("");
+        let query = match subscription_to_query(document) {
+            None => {
+                let message = "single subscription operation without fragments expected";
+                return self.send_error(ctx, Some(id), message);
+            }
+            Some(val) => val,
+        };
+        let topics: HashSet<Topic> = top_level_fields(document)
+            .iter()
+            .filter_map(|field| Topic::from_field(field, user_id))
+            .collect();
+        if topics.is_empty() {
+            return self.send_error(ctx, Some(id), "no subscribable field");
+        }
+
+        let operation = Operation {
+            query: query,
+            variables: payload.get("variables").cloned(),
+            operation_name: payload["operationName"].as_str().map(|s| s.to_owned()),
+            topics: topics,
+        };
+        self.operations.insert(id.clone(), operation);
+        self.update_topics();
+        self.execute(ctx, id);
+    }
+
+    fn on_stop(&mut self, ctx: &mut <Self as Actor>::Context, id: String) {
+        if self.operations.remove(&id).is_some() {
+            self.update_topics();
+        }
+        self.send_message(ctx, OperationMessage::new(GQL_COMPLETE, Some(id), None));
+    }
+
+    /// execute operation as query, result is sent as data message
+    fn execute(&mut self, ctx: &mut <Self as Actor>::Context, id: String) {
+        let operation = match self.operations.get(&id) {
+            None => return,
+            Some(val) => val,
+        };
+        let request_json = serde_json::json!({
+            "query": operation.query,
+            "variables": operation.variables,
+            "operationName": operation.operation_name,
+        });
+        let request = match serde_json::from_value::<GraphQLRequest>(request_json) {
+            Err(err) => {
+                info!("invalid subscription request: {:?}", err);
+                return self.send_error(ctx, Some(id), "invalid request");
+            }
+            Ok(val) => val,
+        };
+
+        let msg = api::GraphQLData {
+            request: request,
+            authorization: self.authorization.clone(),
+        };
+        api::api_executor()
+            .send(msg)
+            .into_actor(self)
+            .then(move |res, act, context| {
+                // operation may be stopped while executing
+                if !act.operations.contains_key(&id) {
+                    return fut::ok(());
+                }
+                let result = match res {
+                    Ok(Ok(text)) => serde_json::from_str::<serde_json::Value>(text.as_str()).ok(),
+                    _ => None,
+                };
+                match result {
+                    None => act.send_error(context, Some(id), "execute subscription failure"),
+                    Some(data) => act.send_message(
+                        context,
+                        OperationMessage::new(GQL_DATA, Some(id), Some(data)),
+                    ),
+                }
+                fut::ok(())
+            })
+            .spawn(ctx);
+    }
+}
+
+// topic changes from hub
+impl Handler<MsgTopicChanged> for SubscriptionSession {
+    type Result = ();
+
+    fn handle(&mut self, msg: MsgTopicChanged, ctx: &mut Self::Context) -> Self::Result {
+        let ids: Vec<String> = self
+            .operations
+            .iter()
+            .filter(|(_, op)| op.topics.contains(&msg.0))
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in ids {
+            self.execute(ctx, id);
+        }
+    }
+}
@@ -13,11 +13,17 @@ extern crate log;
 extern crate failure;
 #[macro_use]
 extern crate lazy_static;
+#[macro_use]
+extern crate prometheus;
 
 pub mod app;
+pub mod cluster;
 pub mod db;
 pub mod graphql;
+pub mod metrics;
+pub mod recording;
 pub mod room;
+pub mod webhook;
 
 fn main() -> failure::Fallible<()> {
     app::launch()
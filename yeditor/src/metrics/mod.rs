@@ -0,0 +1,193 @@
+// Prometheus metrics, exposed by app::server at /metrics
+
+use failure::Fallible;
+use prometheus::{Encoder, HistogramVec, IntCounterVec, IntGauge, TextEncoder};
+use std::time::Duration;
+
+use crate::db;
+
+lazy_static! {
+    static ref ROOMS_ACTIVE: IntGauge =
+        register_int_gauge!("yeditor_rooms_active", "Number of live rooms").unwrap();
+    static ref CLIENTS_ACTIVE: IntGauge = register_int_gauge!(
+        "yeditor_room_clients_active",
+        "Number of clients joined to rooms"
+    )
+    .unwrap();
+    static ref WS_MESSAGES: IntCounterVec = register_int_counter_vec!(
+        "yeditor_ws_messages_total",
+        "Realtime websocket messages by direction and type",
+        &["direction", "type"]
+    )
+    .unwrap();
+    static ref WS_PACKETS: IntCounterVec = register_int_counter_vec!(
+        "yeditor_ws_packets_total",
+        "Realtime websocket packets by direction and encoding",
+        &["direction", "encoding"]
+    )
+    .unwrap();
+    static ref RUNNER_AGENT_EVENTS: IntCounterVec = register_int_counter_vec!(
+        "yeditor_runner_agent_events_total",
+        "Runner agent connects, reconnects and failures",
+        &["event"]
+    )
+    .unwrap();
+    static ref RUN_DURATION: HistogramVec = register_histogram_vec!(
+        "yeditor_run_duration_seconds",
+        "Code run duration reported by runner",
+        &["language"],
+        vec![0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0]
+    )
+    .unwrap();
+    static ref GRAPHQL_DURATION: HistogramVec = register_histogram_vec!(
+        "yeditor_graphql_request_duration_seconds",
+        "GraphQL request latency by top level field",
+        &["field"],
+        vec![0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5]
+    )
+    .unwrap();
+    static ref GRAPHQL_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "yeditor_graphql_errors_total",
+        "GraphQL errors by field path",
+        &["field"]
+    )
+    .unwrap();
+    static ref DB_POOL_CONNECTIONS: IntGauge = register_int_gauge!(
+        "yeditor_db_pool_connections",
+        "Database connections in pool"
+    )
+    .unwrap();
+    static ref DB_POOL_IDLE_CONNECTIONS: IntGauge = register_int_gauge!(
+        "yeditor_db_pool_idle_connections",
+        "Idle database connections in pool"
+    )
+    .unwrap();
+    static ref DB_POOL_MAX_SIZE: IntGauge = register_int_gauge!(
+        "yeditor_db_pool_max_size",
+        "Maximum database connections of pool"
+    )
+    .unwrap();
+}
+
+pub enum Direction {
+    In,
+    Out,
+}
+
+impl Direction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Direction::In => "in",
+            Direction::Out => "out",
+        }
+    }
+}
+
+pub enum RunnerAgentEvent {
+    Connect,
+    Reconnect,
+    Failure,
+    ProtocolError,
+    Failover,
+}
+
+impl RunnerAgentEvent {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RunnerAgentEvent::Connect => "connect",
+            RunnerAgentEvent::Reconnect => "reconnect",
+            RunnerAgentEvent::Failure => "failure",
+            RunnerAgentEvent::ProtocolError => "protocol_error",
+            RunnerAgentEvent::Failover => "failover",
+        }
+    }
+}
+
+pub fn room_started() {
+    ROOMS_ACTIVE.inc();
+}
+
+pub fn room_stopped() {
+    ROOMS_ACTIVE.dec();
+}
+
+pub fn client_joined() {
+    CLIENTS_ACTIVE.inc();
+}
+
+pub fn client_left() {
+    CLIENTS_ACTIVE.dec();
+}
+
+pub fn ws_message(direction: Direction, msg_type: &str) {
+    WS_MESSAGES
+        .with_label_values(&[direction.as_str(), msg_type])
+        .inc();
+}
+
+pub fn ws_packet(direction: Direction, encoding: &str) {
+    WS_PACKETS
+        .with_label_values(&[direction.as_str(), encoding])
+        .inc();
+}
+
+pub fn runner_agent_event(event: RunnerAgentEvent) {
+    RUNNER_AGENT_EVENTS
+        .with_label_values(&[event.as_str()])
+        .inc();
+}
+
+pub fn run_finished(language: &str, duration_ms: f64) {
+    RUN_DURATION
+        .with_label_values(&[language])
+        .observe(duration_ms / 1000.0);
+}
+
+/// Observe request latency for each top level field of response data,
+/// and count errors by field path
+pub fn graphql_request(elapsed: Duration, response: &serde_json::Value) {
+    let seconds = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+    match response["data"].as_object() {
+        Some(data) if !data.is_empty() => {
+            for field in data.keys() {
+                GRAPHQL_DURATION
+                    .with_label_values(&[field.as_str()])
+                    .observe(seconds);
+            }
+        }
+        _ => GRAPHQL_DURATION
+            .with_label_values(&["_request"])
+            .observe(seconds),
+    }
+
+    let errors = match response["errors"].as_array() {
+        None => return,
+        Some(val) => val,
+    };
+    for error in errors {
+        // e.g. ["pads", "create"], arguments errors have no path
+        let path: Vec<&str> = match error["path"].as_array() {
+            None => vec![],
+            Some(path) => path.iter().filter_map(|p| p.as_str()).collect(),
+        };
+        let field = if path.is_empty() {
+            "_request".to_owned()
+        } else {
+            path.join(".")
+        };
+        GRAPHQL_ERRORS.with_label_values(&[field.as_str()]).inc();
+    }
+}
+
+/// Text exposition of all metrics
+pub fn render(pool: &db::DBPool) -> Fallible<String> {
+    let state = pool.state();
+    DB_POOL_CONNECTIONS.set(state.connections as i64);
+    DB_POOL_IDLE_CONNECTIONS.set(state.idle_connections as i64);
+    DB_POOL_MAX_SIZE.set(pool.max_size() as i64);
+
+    let encoder = TextEncoder::new();
+    let mut buffer = vec![];
+    encoder.encode(&prometheus::gather(), &mut buffer)?;
+    Ok(String::from_utf8(buffer)?)
+}
@@ -0,0 +1,223 @@
+// Terminal recording of pads, exported as asciicast v2
+// https://github.com/asciinema/asciinema/blob/develop/doc/asciicast-v2.md
+
+use chrono::prelude::*;
+use failure::Fallible;
+
+use crate::{db, room::StdoutDecoder};
+
+pub const EVENT_OUTPUT: &str = "o";
+pub const EVENT_RESIZE: &str = "r";
+pub const EVENT_MARKER: &str = "m";
+// room opened for pad, stored only, rendered as a marker
+pub const EVENT_SESSION: &str = "s";
+
+const DEFAULT_WIDTH: u16 = 80;
+const DEFAULT_HEIGHT: u16 = 24;
+// rooms of a pad are played back to back, longer gaps between them are cut to this
+const SESSION_GAP_MICROS: i64 = 1_000_000;
+
+/// Events of a room waiting to be saved
+pub struct Recorder {
+    pad_id: i32,
+    events: Vec<db::NewPadRecordingEvent>,
+}
+
+impl Recorder {
+    pub fn new(pad_id: i32) -> Self {
+        let mut recorder = Self {
+            pad_id,
+            events: vec![],
+        };
+        recorder.push(EVENT_SESSION, vec![]);
+        recorder
+    }
+
+    fn push(&mut self, event_type: &str, data: Vec<u8>) {
+        self.events.push(db::NewPadRecordingEvent {
+            pad_id: self.pad_id,
+            event_type: event_type.to_owned(),
+            data,
+            created_at: Utc::now().naive_utc(),
+        });
+    }
+
+    pub fn output(&mut self, data: &[u8]) {
+        self.push(EVENT_OUTPUT, data.to_vec());
+    }
+
+    pub fn resize(&mut self, row: u16, col: u16) {
+        self.push(EVENT_RESIZE, format!("{}x{}", col, row).into_bytes());
+    }
+
+    pub fn take(&mut self) -> Vec<db::NewPadRecordingEvent> {
+        self.events.split_off(0)
+    }
+
+    /// Put back events not saved, older events are dropped beyond limit
+    pub fn restore(&mut self, mut events: Vec<db::NewPadRecordingEvent>, limit: usize) {
+        events.append(&mut self.events);
+        let dropped = events.len().saturating_sub(limit);
+        if dropped > 0 {
+            warn!(
+                "recording events dropped: pad_id = {}, count = {}",
+                self.pad_id, dropped
+            );
+        }
+        self.events = events.split_off(dropped);
+    }
+}
+
+// "{cols}x{rows}"
+fn parse_size(data: &[u8]) -> Option<(u16, u16)> {
+    let text = std::str::from_utf8(data).ok()?;
+    let mut parts = text.splitn(2, 'x');
+    let width = parts.next()?.parse().ok()?;
+    let height = parts.next()?.parse().ok()?;
+    Some((width, height))
+}
+
+// size in effect when the first output is recorded
+fn initial_size(events: &[db::PadRecordingEvent]) -> (u16, u16) {
+    let mut size = (DEFAULT_WIDTH, DEFAULT_HEIGHT);
+    for event in events {
+        match event.event_type.as_str() {
+            EVENT_OUTPUT => break,
+            EVENT_RESIZE => size = parse_size(&event.data).unwrap_or(size),
+            _ => {}
+        }
+    }
+    size
+}
+
+/// Render events as asciicast v2, one json document per line.
+/// Sessions after the first start with a marker, idle time between them is cut.
+pub fn render_asciicast(title: &str, events: &[db::PadRecordingEvent]) -> Fallible<String> {
+    let (width, height) = initial_size(events);
+
+    let mut header = serde_json::json!({
+        "version": 2,
+        "width": width,
+        "height": height,
+        "title": title,
+    });
+    let started_at = events.first().map(|event| event.created_at);
+    if let Some(started_at) = started_at.as_ref() {
+        header["timestamp"] = serde_json::json!(started_at.timestamp());
+    }
+
+    let mut cast = serde_json::to_string(&header)?;
+    cast.push('\n');
+
+    // output chunks may split multibyte characters
+    let mut decoder = StdoutDecoder::default();
+    // time cut from gaps between sessions, and time of previous event
+    let mut cut = 0;
+    let mut previous = 0;
+    for (index, event) in events.iter().enumerate() {
+        let at = started_at
+            .map(|t| event.created_at - t)
+            .and_then(|d| d.num_microseconds())
+            .unwrap_or(0);
+        let (event_type, data) = match event.event_type.as_str() {
+            EVENT_OUTPUT => (EVENT_OUTPUT, decoder.decode(&event.data)),
+            EVENT_RESIZE => (
+                EVENT_RESIZE,
+                String::from_utf8_lossy(&event.data).into_owned(),
+            ),
+            EVENT_SESSION if index > 0 => {
+                cut += (at - previous - SESSION_GAP_MICROS).max(0);
+                let label = format!(
+                    "session {}",
+                    event.created_at.format("%Y-%m-%d %H:%M:%S UTC")
+                );
+                (EVENT_MARKER, label)
+            }
+            _ => continue,
+        };
+        previous = at;
+        if data.is_empty() {
+            continue;
+        }
+        let elapsed = at - cut;
+        let line = serde_json::json!([elapsed as f64 / 1e6, event_type, data]);
+        cast.push_str(serde_json::to_string(&line)?.as_str());
+        cast.push('\n');
+    }
+    Ok(cast)
+}
+
+/// Recording of a pad owned by user, none if pad is not found
+pub fn query_asciicast(dao: &db::ApiDao, user_id: i32, hash: &str) -> Fallible<Option<String>> {
+    let pad = match dao.pads.query_pad_by_hash(hash)? {
+        Some(pad) if pad.user_id == user_id => pad,
+        _ => return Ok(None),
+    };
+    let events = dao.pads.query_recording_events(pad.id)?;
+    Ok(Some(render_asciicast(pad.title.as_str(), &events)?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn event(event_type: &str, data: &[u8], millis: i64) -> db::PadRecordingEvent {
+        db::PadRecordingEvent {
+            id: 0,
+            pad_id: 1,
+            event_type: event_type.to_owned(),
+            data: data.to_vec(),
+            created_at: NaiveDateTime::from_timestamp(1560000000, 0)
+                + chrono::Duration::milliseconds(millis),
+        }
+    }
+
+    #[test]
+    fn asciicast_render() {
+        let events = vec![
+            event(EVENT_RESIZE, b"100x30", 0),
+            event(EVENT_OUTPUT, b"hi \xe4\xb8", 500),
+            event(EVENT_OUTPUT, b"\xad\r\n", 1250),
+        ];
+        let cast = render_asciicast("demo", &events).unwrap();
+        let lines: Vec<&str> = cast.lines().collect();
+        assert_eq!(
+            r#"{"height":30,"timestamp":1560000000,"title":"demo","version":2,"width":100}"#,
+            lines[0]
+        );
+        assert_eq!(r#"[0.0,"r","100x30"]"#, lines[1]);
+        assert_eq!(r#"[0.5,"o","hi "]"#, lines[2]);
+        assert_eq!(r#"[1.25,"o","中\r\n"]"#, lines[3]);
+    }
+
+    #[test]
+    fn asciicast_render_size_of_first_output() {
+        let events = vec![
+            event(EVENT_RESIZE, b"100x30", 0),
+            event(EVENT_RESIZE, b"120x40", 100),
+            event(EVENT_OUTPUT, b"hi", 500),
+            event(EVENT_RESIZE, b"90x20", 1000),
+        ];
+        let cast = render_asciicast("demo", &events).unwrap();
+        let header: serde_json::Value = serde_json::from_str(cast.lines().next().unwrap()).unwrap();
+        assert_eq!(120, header["width"]);
+        assert_eq!(40, header["height"]);
+    }
+
+    #[test]
+    fn asciicast_render_sessions_back_to_back() {
+        let events = vec![
+            event(EVENT_SESSION, b"", 0),
+            event(EVENT_OUTPUT, b"one", 500),
+            // room reopened an hour later
+            event(EVENT_SESSION, b"", 3600500),
+            event(EVENT_OUTPUT, b"two", 3601000),
+        ];
+        let cast = render_asciicast("demo", &events).unwrap();
+        let lines: Vec<&str> = cast.lines().collect();
+        assert_eq!(4, lines.len());
+        assert_eq!(r#"[0.5,"o","one"]"#, lines[1]);
+        assert_eq!(r#"[1.5,"m","session 2019-06-08 14:20:00 UTC"]"#, lines[2]);
+        assert_eq!(r#"[2.0,"o","two"]"#, lines[3]);
+    }
+}
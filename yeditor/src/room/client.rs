@@ -2,13 +2,15 @@ use actix::fut;
 use actix::prelude::*;
 use actix::Actor;
 use actix_web::ws;
-use failure::{Fallible, ResultExt};
+use failure::Fallible;
 use std::time;
 
 use super::client_proto::*;
 use super::manager;
 use super::room;
-use crate::app;
+use super::upstream::{self, RoomUpstream, UpstreamEvents, UpstreamFrame};
+use super::wire::{WireFormat, WireFrame};
+use crate::{app, cluster, metrics};
 
 // client session
 
@@ -18,6 +20,19 @@ pub struct RoomClientSession {
     room_key: String,
     room_addr: Option<Addr<super::Room>>,
     keep_alive_time: time::Instant,
+    // connection proxied from another node
+    proxied: bool,
+    // proxy to node owning the room
+    upstream: Option<Addr<RoomUpstream>>,
+    upstream_retries: u32,
+    // resolving room owner after upstream closed
+    migrating: bool,
+    // client protocol version, see client_proto::PROTOCOL_VERSION
+    proto_version: u32,
+    // negotiated by hello, or implied by proto_version
+    hello: HelloParams,
+    stdout_decoder: StdoutDecoder,
+    wire: WireFormat,
 }
 
 impl RoomClientSession {
@@ -28,6 +43,14 @@ impl RoomClientSession {
             room_key: "".to_owned(),
             room_addr: None,
             keep_alive_time: time::Instant::now(),
+            proxied: false,
+            upstream: None,
+            upstream_retries: 0,
+            migrating: false,
+            proto_version: 1,
+            hello: HelloParams::implied(1),
+            stdout_decoder: StdoutDecoder::default(),
+            wire: WireFormat::default(),
         }
     }
 }
@@ -39,10 +62,20 @@ impl Actor for RoomClientSession {
         let req = ctx.request();
         let params = req.match_info();
         self.room_key = params.get("room_key").unwrap_or("").to_owned();
+        self.proxied = req.headers().contains_key(cluster::PROXIED_BY_HEADER);
+        self.proto_version = req
+            .query()
+            .get("proto")
+            .and_then(|v| v.parse::<u32>().ok())
+            .map(|v| std::cmp::min(v, PROTOCOL_VERSION))
+            .unwrap_or(1);
+        self.hello = HelloParams::implied(self.proto_version);
+        self.wire = WireFormat::from_request(&req);
         info!(
-            "client started! {:?}, room_key = {:?}",
+            "client started! {:?}, room_key = {:?}, wire = {:?}",
             req.peer_addr(),
-            self.room_key
+            self.room_key,
+            self.wire
         );
         self.start_keep_alive(ctx);
         self.get_or_create_room(ctx);
@@ -51,6 +84,9 @@ impl Actor for RoomClientSession {
     fn stopping(&mut self, ctx: &mut Self::Context) -> Running {
         info!("client stopping!");
         let _ = self.leave_room(ctx);
+        if let Some(upstream) = self.upstream.take() {
+            upstream.do_send(upstream::ReqStop);
+        }
         Running::Stop
     }
 
@@ -85,18 +121,35 @@ impl RoomClientSession {
     }
 
     fn get_or_create_room(&mut self, ctx: &mut <Self as Actor>::Context) {
-        let msg = manager::MsgGetOrCreateRoom::new(self.room_key.clone());
+        let msg = manager::MsgGetOrCreateRoom::new(self.room_key.clone(), self.proxied);
         manager::RoomManager::from_registry()
             .send(msg)
             .into_actor(self)
             .then(|res, act, ctx| {
+                act.migrating = false;
                 let val = res.expect("call should not fail");
-                if let Err(err) = val {
-                    info!("get or create room failure: {:?}", err);
-                    return fut::ok(());
-                }
+                let room_info: manager::RoomInfo = match val {
+                    Err(err) => {
+                        info!("get or create room failure: {:?}", err);
+                        ctx.stop();
+                        return fut::ok(());
+                    }
+                    Ok(manager::RoomLocation::Remote(owner)) => {
+                        info!("room {} owned by node {}, proxying", act.room_key, owner.id);
+                        let listener = ctx.address().recipient();
+                        let upstream = RoomUpstream::new(
+                            &owner,
+                            &act.room_key,
+                            act.upstream_query(),
+                            listener,
+                        )
+                        .start();
+                        act.upstream = Some(upstream);
+                        return fut::ok(());
+                    }
+                    Ok(manager::RoomLocation::Local(room_info)) => room_info,
+                };
 
-                let room_info: manager::RoomInfo = val.unwrap();
                 act.room_addr = Some(room_info.addr.clone());
                 act.room_id = room_info.id;
                 info!("room created: room_info {:?}", room_info.id);
@@ -129,6 +182,12 @@ impl RoomClientSession {
                         info!("join room failure: {:?}", e);
                         ctx.stop();
                     }
+                    Ok(0) => {
+                        // room is stopping, client reconnects to its next instance
+                        info!("join room rejected, room is closing: {:?}", act.room_id);
+                        act.room_addr = None;
+                        ctx.stop();
+                    }
                     Ok(client_id) => {
                         info!("client join: id = {}", client_id);
                         act.client_id = client_id;
@@ -166,24 +225,39 @@ impl StreamHandler<ws::Message, ws::ProtocolError> for RoomClientSession {
                 self.keep_alive_time = time::Instant::now();
             }
             ws::Message::Text(text) => {
-                if let Err(err) = self.on_message(&text, ctx) {
+                metrics::ws_packet(metrics::Direction::In, self.wire.text_label());
+                if let Some(upstream) = self.upstream.as_ref() {
+                    upstream.do_send(UpstreamFrame::Text(text));
+                    return;
+                }
+                if self.migrating {
+                    debug!("room owner resolving, dropping client packet");
+                    return;
+                }
+                let result = self
+                    .wire
+                    .decode_text(&text)
+                    .and_then(|request| self.on_message(request, ctx));
+                if let Err(err) = result {
                     warn!("on message error = {:?}", err);
                     ctx.stop()
                 }
             }
             ws::Message::Binary(bin) => {
-                use flate2::read::GzDecoder;
-                use std::io::Read;
-
-                info!("received binary from client, try decompress");
-                let mut decoder = GzDecoder::new(bin.as_ref());
-                let mut text = String::new();
-                if let Err(err) = decoder.read_to_string(&mut text) {
-                    warn!("binary decompression failure: {:?}", err);
-                    ctx.stop();
+                metrics::ws_packet(metrics::Direction::In, self.wire.binary_label(bin.as_ref()));
+                if let Some(upstream) = self.upstream.as_ref() {
+                    upstream.do_send(UpstreamFrame::Binary(bin));
+                    return;
+                }
+                if self.migrating {
+                    debug!("room owner resolving, dropping client packet");
                     return;
                 }
-                if let Err(err) = self.on_message(&text, ctx) {
+                let result = self
+                    .wire
+                    .decode_binary(bin.as_ref())
+                    .and_then(|request| self.on_message(request, ctx));
+                if let Err(err) = result {
                     warn!("on message error = {:?}", err);
                     ctx.stop();
                 }
@@ -197,10 +271,137 @@ impl StreamHandler<ws::Message, ws::ProtocolError> for RoomClientSession {
 }
 
 impl RoomClientSession {
-    fn on_message(&mut self, text: &String, ctx: &mut <Self as Actor>::Context) -> Fallible<()> {
-        info!("on client message: {}", text);
-        let client_request =
-            serde_json::from_str::<ClientRequests>(text.as_str()).context("client packet error")?;
+    // convert or drop packets not supported by client capabilities
+    fn downgrade(&mut self, packet: ClientResponses) -> Option<ClientResponses> {
+        match packet {
+            ClientResponses::Terminal(TerminalResponseParams::StdoutBytes(data))
+                if !self.hello.has_capability(CAP_STDOUT_BYTES) =>
+            {
+                let text = self.stdout_decoder.decode(&data);
+                Some(ClientResponses::Terminal(TerminalResponseParams::Stdout(
+                    text,
+                )))
+            }
+            ClientResponses::Command(CommandResponseParams::SaveState(_))
+                if !self.hello.has_capability(CAP_SAVE_STATE) =>
+            {
+                None
+            }
+            ClientResponses::Editor(EditorSyncParams::InputChanged(_))
+            | ClientResponses::Editor(EditorSyncParams::InputText(_))
+                if !self.hello.has_capability(CAP_PROGRAM_INPUT) =>
+            {
+                None
+            }
+            ClientResponses::Command(CommandResponseParams::SetRunOptions(_))
+                if !self.hello.has_capability(CAP_RUN_OPTIONS) =>
+            {
+                None
+            }
+            ClientResponses::Command(CommandResponseParams::SetDependencies(_))
+                if !self.hello.has_capability(CAP_DEPENDENCIES) =>
+            {
+                None
+            }
+            ClientResponses::Command(CommandResponseParams::SetDatabaseSeed(_))
+                if !self.hello.has_capability(CAP_DATABASE) =>
+            {
+                None
+            }
+            ClientResponses::Command(CommandResponseParams::Diagnostics(_))
+                if !self.hello.has_capability(CAP_CODE_TOOLS) =>
+            {
+                None
+            }
+            ClientResponses::Command(CommandResponseParams::Lsp(_))
+                if !self.hello.has_capability(CAP_LSP) =>
+            {
+                None
+            }
+            ClientResponses::Command(CommandResponseParams::Artifact(_))
+            | ClientResponses::Command(CommandResponseParams::ClearArtifacts())
+                if !self.hello.has_capability(CAP_ARTIFACT) =>
+            {
+                None
+            }
+            ClientResponses::Command(CommandResponseParams::RunFiles(_))
+                if !self.hello.has_capability(CAP_RUN_FILES) =>
+            {
+                None
+            }
+            ClientResponses::Editor(EditorSyncParams::Breakpoints(_))
+            | ClientResponses::Editor(EditorSyncParams::DebugStopped(_))
+                if !self.hello.has_capability(CAP_DEBUG) =>
+            {
+                None
+            }
+            packet => Some(packet),
+        }
+    }
+
+    fn send_packet(&mut self, packet: ClientResponses, ctx: &mut <Self as Actor>::Context) {
+        let packet = match self.downgrade(packet) {
+            Some(packet) => packet,
+            None => return,
+        };
+        match self.wire.encode(&packet) {
+            Err(e) => {
+                info!("serialize response failure: {:?}", e);
+                ctx.stop();
+            }
+            Ok(WireFrame::Text(text)) => {
+                metrics::ws_message(metrics::Direction::Out, packet.msg_type());
+                metrics::ws_packet(metrics::Direction::Out, self.wire.text_label());
+                ctx.text(text);
+            }
+            Ok(WireFrame::Binary(bin)) => {
+                metrics::ws_message(metrics::Direction::Out, packet.msg_type());
+                metrics::ws_packet(
+                    metrics::Direction::Out,
+                    self.wire.binary_label(bin.as_ref()),
+                );
+                ctx.binary(bin);
+            }
+        }
+    }
+
+    fn on_hello(&mut self, client_hello: HelloParams, ctx: &mut <Self as Actor>::Context) {
+        self.hello = HelloParams::current().negotiate(&client_hello);
+        self.proto_version = self.hello.version;
+        info!(
+            "client hello: room_key = {}, client = {:?}, negotiated = {:?}",
+            self.room_key, client_hello, self.hello
+        );
+        self.send_packet(ClientResponses::Hello(self.hello.clone()), ctx);
+    }
+
+    // query of the owner node connection, same protocol and wire format as client
+    fn upstream_query(&self) -> String {
+        let mut query = format!("proto={}", self.proto_version);
+        let wire_query = self.wire.to_query();
+        if !wire_query.is_empty() {
+            query.push('&');
+            query.push_str(wire_query.as_str());
+        }
+        query
+    }
+
+    fn on_message(
+        &mut self,
+        client_request: ClientRequests,
+        ctx: &mut <Self as Actor>::Context,
+    ) -> Fallible<()> {
+        info!("on client message: {:?}", client_request);
+        metrics::ws_message(metrics::Direction::In, client_request.msg_type());
+
+        // handshake does not need room
+        let client_request = match client_request {
+            ClientRequests::Hello(client_hello) => {
+                self.on_hello(client_hello, ctx);
+                return Ok(());
+            }
+            client_request => client_request,
+        };
 
         let room_packet = room::MsgRoomClientRequest {
             client_id: self.client_id,
@@ -230,33 +431,13 @@ impl Handler<room::ClientEvents> for RoomClientSession {
         info!("received msg event: {:?}", msg);
 
         match msg {
-            room::ClientEvents::Packet(packet) => match serde_json::to_string(&packet) {
-                Err(e) => {
-                    info!("serialize response failure: {:?}", e);
-                    ctx.stop();
-                }
-                Ok(json_text) => {
-                    if json_text.len() <= 128 {
-                        ctx.text(json_text)
-                    } else {
-                        use flate2::{write::GzEncoder, Compression};
-                        use std::io::Write;
-                        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
-                        if encoder.write_all(json_text.as_bytes()).is_err() {
-                            ctx.text(json_text);
-                        } else if let Ok(v) = encoder.finish() {
-                            ctx.binary(v);
-                        } else {
-                            ctx.text(json_text);
-                        }
-                    }
-                }
-            },
+            room::ClientEvents::Packet(packet) => self.send_packet(packet, ctx),
             room::ClientEvents::Destroy => {
                 info!(
                     "client received destropy command: room = {}, client = {}",
                     self.room_key, self.client_id
                 );
+                ctx.close(None);
                 ctx.stop();
             }
         }
@@ -264,3 +445,58 @@ impl Handler<room::ClientEvents> for RoomClientSession {
         MessageResult(())
     }
 }
+
+// frames from node owning the room
+impl Handler<UpstreamEvents> for RoomClientSession {
+    type Result = ();
+
+    fn handle(&mut self, msg: UpstreamEvents, ctx: &mut Self::Context) -> Self::Result {
+        match msg {
+            UpstreamEvents::Connected => self.upstream_retries = 0,
+            UpstreamEvents::Text(text) => {
+                metrics::ws_packet(metrics::Direction::Out, self.wire.text_label());
+                ctx.text(text);
+            }
+            UpstreamEvents::Binary(bin) => {
+                metrics::ws_packet(
+                    metrics::Direction::Out,
+                    self.wire.binary_label(bin.as_ref()),
+                );
+                ctx.binary(bin);
+            }
+            UpstreamEvents::Closed(true) => {
+                info!("room upstream closed by owner, stop client session");
+                self.upstream.take();
+                ctx.stop();
+            }
+            UpstreamEvents::Closed(false) => {
+                self.upstream.take();
+                self.reconnect_upstream(ctx);
+            }
+        }
+    }
+}
+
+impl RoomClientSession {
+    /// Owner node may be gone, resolve the owner again once its lease could have expired
+    fn reconnect_upstream(&mut self, ctx: &mut <Self as Actor>::Context) {
+        let cluster_config = &app::config().cluster;
+        let max_retries = cluster_config.room_ttl_ms / cluster_config.renew_interval_ms + 2;
+        if self.upstream_retries as u64 >= max_retries {
+            info!("room upstream retries exceeded, stop client session");
+            ctx.stop();
+            return;
+        }
+        self.upstream_retries += 1;
+        self.migrating = true;
+
+        let delay = time::Duration::from_millis(cluster_config.renew_interval_ms);
+        info!(
+            "room upstream closed, resolving owner in {}ms: {}",
+            cluster_config.renew_interval_ms, self.room_key
+        );
+        ctx.run_later(delay, |this: &mut Self, context| {
+            this.get_or_create_room(context);
+        });
+    }
+}
@@ -1,13 +1,64 @@
 use actix::prelude::*;
+use schemars::JsonSchema;
+use ycommon::runner_proto::{base64_bytes, Dependency, Diagnostic, RunOptions};
 
 // client protocol
 // Terminal messages only for xterm frontend
 // Editor message only for editor synchronization frontend
 // Command messages only for other functions, e.g. Run, Reset, SetLang
 
-#[derive(Debug, Clone, Deserialize, Serialize, Message)]
+/// Client protocol version, requested by client with `proto` query parameter of realtime url.
+/// Clients of version 1 receive terminal output as text, version 2 as raw bytes,
+/// clients of version 3 send `hello` with capabilities after connected.
+pub const PROTOCOL_VERSION: u32 = 3;
+
+/// Terminal output as `stdout_bytes`
+pub const CAP_STDOUT_BYTES: &str = "stdout_bytes";
+/// Save state of pad as `save_state` command
+pub const CAP_SAVE_STATE: &str = "save_state";
+/// Program input buffer as `input_text` and `input_changed` editor messages
+pub const CAP_PROGRAM_INPUT: &str = "program_input";
+/// Run options of room as `set_run_options` command
+pub const CAP_RUN_OPTIONS: &str = "run_options";
+/// Dependency manifest of pad as `set_dependencies` command
+pub const CAP_DEPENDENCIES: &str = "dependencies";
+/// Format and lint commands, lint results as `diagnostics` command
+pub const CAP_CODE_TOOLS: &str = "code_tools";
+/// Messages of language server of room as `lsp` command
+pub const CAP_LSP: &str = "lsp";
+/// Debug runs as `debug_code` command, `breakpoints` and `debug_stopped` editor messages
+pub const CAP_DEBUG: &str = "debug";
+/// Seed of database of room as `set_database_seed` command
+pub const CAP_DATABASE: &str = "database";
+/// Rich output of runs as `artifact` and `clear_artifacts` commands
+pub const CAP_ARTIFACT: &str = "artifact";
+/// Files of the latest run as `list_run_files` and `run_files` commands, downloaded over http
+pub const CAP_RUN_FILES: &str = "run_files";
+/// Runs of a range of the code buffer as `run_selection` command
+pub const CAP_RUN_SELECTION: &str = "run_selection";
+
+/// Capabilities supported by server
+pub const CAPABILITIES: &[&str] = &[
+    CAP_STDOUT_BYTES,
+    CAP_SAVE_STATE,
+    CAP_PROGRAM_INPUT,
+    CAP_RUN_OPTIONS,
+    CAP_DEPENDENCIES,
+    CAP_CODE_TOOLS,
+    CAP_LSP,
+    CAP_DEBUG,
+    CAP_DATABASE,
+    CAP_ARTIFACT,
+    CAP_RUN_FILES,
+    CAP_RUN_SELECTION,
+];
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema, Message)]
 #[serde(tag = "t", content = "c")]
 pub enum ClientRequests {
+    #[serde(rename = "h")]
+    Hello(HelloParams),
+
     #[serde(rename = "e")]
     Editor(EditorSyncParams),
 
@@ -18,9 +69,12 @@ pub enum ClientRequests {
     Terminal(TerminalRequestParams),
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize, Message)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema, Message)]
 #[serde(tag = "t", content = "c")]
 pub enum ClientResponses {
+    #[serde(rename = "h")]
+    Hello(HelloParams),
+
     #[serde(rename = "e")]
     Editor(EditorSyncParams),
 
@@ -31,7 +85,76 @@ pub enum ClientResponses {
     Terminal(TerminalResponseParams),
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+impl ClientRequests {
+    /// message type for metrics
+    pub fn msg_type(&self) -> &'static str {
+        match self {
+            ClientRequests::Hello(_) => "hello",
+            ClientRequests::Editor(_) => "editor",
+            ClientRequests::Command(_) => "command",
+            ClientRequests::Terminal(_) => "terminal",
+        }
+    }
+}
+
+impl ClientResponses {
+    /// message type for metrics
+    pub fn msg_type(&self) -> &'static str {
+        match self {
+            ClientResponses::Hello(_) => "hello",
+            ClientResponses::Editor(_) => "editor",
+            ClientResponses::Command(_) => "command",
+            ClientResponses::Terminal(_) => "terminal",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
+pub struct HelloParams {
+    pub version: u32,
+    pub capabilities: Vec<String>,
+}
+
+impl HelloParams {
+    /// Hello of server
+    pub fn current() -> Self {
+        Self::with_capabilities(PROTOCOL_VERSION, CAPABILITIES)
+    }
+
+    /// Capabilities implied by version, for clients sending no hello
+    pub fn implied(version: u32) -> Self {
+        match version {
+            0..=1 => Self::with_capabilities(version, &[CAP_SAVE_STATE]),
+            _ => Self::with_capabilities(version, &[CAP_STDOUT_BYTES, CAP_SAVE_STATE]),
+        }
+    }
+
+    fn with_capabilities(version: u32, capabilities: &[&str]) -> Self {
+        Self {
+            version,
+            capabilities: capabilities.iter().map(|cap| (*cap).to_owned()).collect(),
+        }
+    }
+
+    /// Lower version and common capabilities of both sides
+    pub fn negotiate(&self, peer: &HelloParams) -> HelloParams {
+        HelloParams {
+            version: std::cmp::min(self.version, peer.version),
+            capabilities: self
+                .capabilities
+                .iter()
+                .filter(|cap| peer.has_capability(cap))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    pub fn has_capability(&self, cap: &str) -> bool {
+        self.capabilities.iter().any(|c| c == cap)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub enum EditorSyncParams {
     #[serde(rename = "changed")]
     Changed(EditorChangedEvent),
@@ -41,34 +164,49 @@ pub enum EditorSyncParams {
 
     #[serde(rename = "cursor")]
     Cursor(CursorChangedEvent),
+
+    // program input buffer, piped to stdin of runs
+    #[serde(rename = "input_changed")]
+    InputChanged(EditorChangedEvent),
+
+    #[serde(rename = "input_text")]
+    InputText(String),
+
+    // 1-based lines of code buffer, sorted, moved by edits of the buffer
+    #[serde(rename = "breakpoints")]
+    Breakpoints(Vec<u32>),
+
+    // line where debugger stopped, none when stopped outside the code or not debugging
+    #[serde(rename = "debug_stopped")]
+    DebugStopped(Option<u32>),
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct CursorChangedEvent {
     pub peer_id: u32,
     pub position: Option<CursorPosition>,
     pub secondary_positions: Vec<CursorPosition>,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct CursorPosition {
     pub line: i32,
     pub column: i32,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct EditorChangedEvent {
     pub version: i64,
     pub changes: Vec<TextChange>,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct TextChange {
     pub range: TextRange,
     pub text: String,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct TextRange {
     pub start_line: i32,
     pub start_column: i32,
@@ -76,7 +214,7 @@ pub struct TextRange {
     pub end_column: i32,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub enum CommandRequestParams {
     #[serde(rename = "reset")]
     Reset(),
@@ -84,17 +222,134 @@ pub enum CommandRequestParams {
     #[serde(rename = "run_code")]
     RunCode(String),
 
+    // run a range of code buffer on its own, languages of `run_selection` only
+    #[serde(rename = "run_selection")]
+    RunSelection(TextRange),
+
     #[serde(rename = "set_lang")]
     SetLang(String),
+
+    // compiler flags, args, version and env of runs, cleared by set_lang
+    #[serde(rename = "set_run_options")]
+    SetRunOptions(RunOptions),
+
+    // packages of pad, saved with content and cleared by set_lang
+    #[serde(rename = "set_dependencies")]
+    SetDependencies(Vec<Dependency>),
+
+    // sql run in database of room on next reset, saved with content and cleared by set_lang
+    #[serde(rename = "set_database_seed")]
+    SetDatabaseSeed(String),
+
+    // formatted code is applied to the buffer as a single edit
+    #[serde(rename = "format")]
+    Format(),
+
+    #[serde(rename = "lint")]
+    Lint(),
+
+    // run code under debugger with breakpoints of room, commands are typed in terminal
+    #[serde(rename = "debug_code")]
+    DebugCode(String),
+
+    // json-rpc request or notification to language server of room
+    #[serde(rename = "lsp")]
+    Lsp(serde_json::Value),
+
+    // files of the latest run, answered to requester only
+    #[serde(rename = "list_run_files")]
+    ListRunFiles(),
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub enum CommandResponseParams {
     #[serde(rename = "set_lang")]
     SetLang(String),
+
+    #[serde(rename = "set_run_options")]
+    SetRunOptions(RunOptions),
+
+    #[serde(rename = "set_dependencies")]
+    SetDependencies(Vec<Dependency>),
+
+    #[serde(rename = "set_database_seed")]
+    SetDatabaseSeed(String),
+
+    // lint result of code buffer, markers of editor
+    #[serde(rename = "diagnostics")]
+    Diagnostics(Vec<Diagnostic>),
+
+    // json-rpc response to requester, or diagnostics notification of code buffer
+    #[serde(rename = "lsp")]
+    Lsp(serde_json::Value),
+
+    // file written by the latest run, shown next to terminal
+    #[serde(rename = "artifact")]
+    Artifact(ArtifactParams),
+
+    // a run started, artifacts of the previous one are gone
+    #[serde(rename = "clear_artifacts")]
+    ClearArtifacts(),
+
+    #[serde(rename = "run_files")]
+    RunFiles(RunFilesParams),
+
+    #[serde(rename = "notice")]
+    Notice(String),
+
+    // server is going away, client should reconnect after delay
+    #[serde(rename = "reconnect")]
+    Reconnect(u64), // delay_ms
+
+    #[serde(rename = "save_state")]
+    SaveState(SaveStateParams),
+}
+
+/// Markdown, html or image by mime type, data as base64
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct ArtifactParams {
+    pub name: String,
+    pub mime: String,
+    #[serde(with = "base64_bytes")]
+    #[schemars(with = "String")]
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct RunFilesParams {
+    pub files: Vec<RunFileLink>,
+}
+
+/// File is downloaded from `/pads/{hash}/files/{name}?token={token}`, the token is signed
+/// for the requester and expires after `run_file_token_ttl_ms` or when a run starts
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct RunFileLink {
+    pub name: String,
+    pub size: u64,
+    pub token: String,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
+pub enum SaveState {
+    #[serde(rename = "saving")]
+    Saving,
+
+    #[serde(rename = "saved")]
+    Saved,
+
+    #[serde(rename = "failed")]
+    Failed,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct SaveStateParams {
+    pub state: SaveState,
+    pub version: u64,          // room buffer version
+    pub saved_at: Option<i64>, // unix ms of last successful save
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub enum TerminalRequestParams {
     #[serde(rename = "set_size")]
     SetSize(u16, u16), // row, col
@@ -103,10 +358,51 @@ pub enum TerminalRequestParams {
     Stdin(String),
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub enum TerminalResponseParams {
     #[serde(rename = "stdout")]
     Stdout(String),
+
+    // raw output as base64, decoded by client
+    #[serde(rename = "stdout_bytes", with = "base64_bytes")]
+    #[schemars(with = "String")]
+    StdoutBytes(Vec<u8>),
+}
+
+/// Decodes raw terminal output for clients of protocol version 1,
+/// an incomplete utf-8 sequence at the end of a chunk is kept for next chunk
+#[derive(Debug, Default)]
+pub struct StdoutDecoder {
+    pending: Vec<u8>,
+}
+
+impl StdoutDecoder {
+    pub fn decode(&mut self, data: &[u8]) -> String {
+        self.pending.extend_from_slice(data);
+        let complete = self.pending.len() - Self::incomplete_tail_len(&self.pending);
+        let tail = self.pending.split_off(complete);
+        let text = String::from_utf8_lossy(&self.pending).into_owned();
+        self.pending = tail;
+        text
+    }
+
+    fn incomplete_tail_len(data: &[u8]) -> usize {
+        // find lead byte of last sequence, sequences are at most 4 bytes
+        for i in 1..=std::cmp::min(3, data.len()) {
+            let b = data[data.len() - i];
+            if b & 0xc0 == 0x80 {
+                continue; // continuation byte
+            }
+            let seq_len = match b {
+                0xf0..=0xff => 4,
+                0xe0..=0xef => 3,
+                0xc0..=0xdf => 2,
+                _ => 1,
+            };
+            return if seq_len > i { i } else { 0 };
+        }
+        0
+    }
 }
 
 #[cfg(test)]
@@ -122,7 +418,7 @@ mod test {
             }
             Ok(json) => {
                 println!("client req json = {}", json);
-                let expected = r#"{"t":"tm","c":{"set_size":[25,80]}}"#;
+                let expected = r#"{"t":"t","c":{"set_size":[25,80]}}"#;
                 assert_eq!(expected, json);
             }
         }
@@ -137,7 +433,7 @@ mod test {
             }
             Ok(json) => {
                 println!("client req json = {}", json);
-                let expected = r#"{"t":"tm","c":{"stdin":"hello"}}"#;
+                let expected = r#"{"t":"t","c":{"stdin":"hello"}}"#;
                 assert_eq!(expected, json);
             }
         }
@@ -152,9 +448,55 @@ mod test {
             }
             Ok(json) => {
                 println!("client req json = {}", json);
-                let expected = r#"{"t":"tm","c":{"reset":[]}}"#;
+                let expected = r#"{"t":"c","c":{"reset":[]}}"#;
                 assert_eq!(expected, json);
             }
         }
     }
+
+    #[test]
+    fn stdout_decoder_split_multibyte() {
+        let data = "a中b".as_bytes();
+        let mut decoder = StdoutDecoder::default();
+        assert_eq!("a", decoder.decode(&data[..2]));
+        assert_eq!("", decoder.decode(&data[2..3]));
+        assert_eq!("中b", decoder.decode(&data[3..]));
+        assert_eq!("\u{fffd}x", decoder.decode(&[0xff, b'x']));
+    }
+
+    #[test]
+    fn serde_client_response_terminal_stdout_bytes() {
+        let resp = ClientResponses::Terminal(TerminalResponseParams::StdoutBytes(vec![0xff, b'o']));
+        let json = serde_json::to_string(&resp).unwrap();
+        assert_eq!(r#"{"t":"t","c":{"stdout_bytes":"/28="}}"#, json);
+    }
+
+    #[test]
+    fn hello_negotiate() {
+        let client = HelloParams {
+            version: 4,
+            capabilities: vec![CAP_SAVE_STATE.to_owned(), "future".to_owned()],
+        };
+        let hello = HelloParams::current().negotiate(&client);
+        assert_eq!(PROTOCOL_VERSION, hello.version);
+        assert_eq!(vec![CAP_SAVE_STATE.to_owned()], hello.capabilities);
+        assert!(!HelloParams::implied(1).has_capability(CAP_STDOUT_BYTES));
+        assert!(!HelloParams::implied(2).has_capability(CAP_PROGRAM_INPUT));
+    }
+
+    // messages shared with angular client, see docs/protocol
+    #[test]
+    fn serde_round_trip_fixtures() {
+        let fixtures: serde_json::Value =
+            serde_json::from_str(include_str!("../../../docs/protocol/client_messages.json"))
+                .unwrap();
+        for expected in fixtures["requests"].as_array().unwrap() {
+            let req: ClientRequests = serde_json::from_value(expected.clone()).unwrap();
+            assert_eq!(expected, &serde_json::to_value(&req).unwrap());
+        }
+        for expected in fixtures["responses"].as_array().unwrap() {
+            let resp: ClientResponses = serde_json::from_value(expected.clone()).unwrap();
+            assert_eq!(expected, &serde_json::to_value(&resp).unwrap());
+        }
+    }
 }
@@ -0,0 +1,164 @@
+use super::TextChange;
+
+// Debug runs of room. Breakpoints are lines of code buffer shared by participants, moved by
+// edits like markers of editors. Debuggers of yscript write stop locations to terminal output
+// as an OSC sequence, ignored by terminals.
+
+const STOP_MARKER_PREFIX: &[u8] = b"\x1b]5379;ye-debug-stop=";
+const STOP_MARKER_SUFFIX: u8 = 0x07;
+// prefix, line of u32 and suffix
+const MAX_MARKER_LEN: usize = 32;
+
+pub const MAX_BREAKPOINTS: usize = 256;
+
+/// Sorted lines of breakpoints without duplicates
+pub fn normalize_breakpoints(mut breakpoints: Vec<u32>) -> Vec<u32> {
+    breakpoints.retain(|line| *line > 0);
+    breakpoints.sort();
+    breakpoints.dedup();
+    breakpoints.truncate(MAX_BREAKPOINTS);
+    breakpoints
+}
+
+/// Moves breakpoints with edits of code buffer, true if any of them moved.
+/// Breakpoints in removed lines move to the first line of the edit.
+pub fn shift_breakpoints(breakpoints: &mut Vec<u32>, changes: &[TextChange]) -> bool {
+    let mut shifted = false;
+    for change in changes {
+        let r = &change.range;
+        let (start, end) = (r.start_line.max(1) as u32, r.end_line.max(1) as u32);
+        let inserted = change.text.matches('\n').count() as i64;
+        let delta = inserted - i64::from(end - start.min(end));
+        // a line inserted before the first column pushes the line down
+        let pushed = r.start_column <= 1 && start == end && r.start_column == r.end_column;
+        for line in breakpoints.iter_mut() {
+            let moved = if *line > end || (pushed && *line == start) {
+                (i64::from(*line) + delta).max(1) as u32
+            } else if *line > start {
+                start
+            } else {
+                *line
+            };
+            if moved != *line {
+                *line = moved;
+                shifted = true;
+            }
+        }
+    }
+    if shifted {
+        *breakpoints = normalize_breakpoints(breakpoints.clone());
+    }
+    shifted
+}
+
+/// Stop locations in output of a debug run, markers may be split across chunks
+#[derive(Debug, Default)]
+pub struct StopScanner {
+    pending: Vec<u8>,
+}
+
+impl StopScanner {
+    /// Last stop location in output, none inside when stopped outside the code
+    pub fn scan(&mut self, data: &[u8]) -> Option<Option<u32>> {
+        let mut buf = std::mem::replace(&mut self.pending, vec![]);
+        buf.extend_from_slice(data);
+
+        let mut stop = None;
+        let mut pos = 0;
+        while let Some(start) = find(&buf[pos..], STOP_MARKER_PREFIX).map(|i| pos + i) {
+            let value_start = start + STOP_MARKER_PREFIX.len();
+            match buf[value_start..]
+                .iter()
+                .position(|b| *b == STOP_MARKER_SUFFIX)
+            {
+                Some(len) => {
+                    let value = String::from_utf8_lossy(&buf[value_start..value_start + len]);
+                    stop = Some(value.trim().parse::<u32>().ok());
+                    pos = value_start + len + 1;
+                }
+                None => {
+                    if buf.len() - start < MAX_MARKER_LEN {
+                        self.pending = buf[start..].to_vec();
+                    }
+                    return stop;
+                }
+            }
+        }
+
+        // a marker may start at the end of chunk
+        let tail = &buf[pos..];
+        for len in (1..std::cmp::min(tail.len() + 1, STOP_MARKER_PREFIX.len())).rev() {
+            if tail[tail.len() - len..] == STOP_MARKER_PREFIX[..len] {
+                self.pending = tail[tail.len() - len..].to_vec();
+                break;
+            }
+        }
+        stop
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::room::TextRange;
+
+    fn change(start: (i32, i32), end: (i32, i32), text: &str) -> TextChange {
+        TextChange {
+            range: TextRange {
+                start_line: start.0,
+                start_column: start.1,
+                end_line: end.0,
+                end_column: end.1,
+            },
+            text: text.to_owned(),
+        }
+    }
+
+    #[test]
+    fn shift_breakpoints_with_edits() {
+        let mut breakpoints = vec![2, 5, 9];
+        assert!(shift_breakpoints(
+            &mut breakpoints,
+            &[change((3, 1), (3, 1), "a\nb\n")]
+        ));
+        assert_eq!(vec![2, 7, 11], breakpoints);
+
+        // lines 6..8 joined into line 6
+        assert!(shift_breakpoints(
+            &mut breakpoints,
+            &[change((6, 3), (8, 1), "")]
+        ));
+        assert_eq!(vec![2, 6, 9], breakpoints);
+
+        assert!(!shift_breakpoints(
+            &mut breakpoints,
+            &[change((2, 4), (2, 4), "x")]
+        ));
+        assert!(shift_breakpoints(
+            &mut breakpoints,
+            &[change((2, 1), (2, 1), "\n")]
+        ));
+        assert_eq!(vec![3, 7, 10], breakpoints);
+    }
+
+    #[test]
+    fn scan_split_markers() {
+        let mut scanner = StopScanner::default();
+        assert_eq!(None, scanner.scan(b"(gdb) \x1b]5379;ye-de"));
+        assert_eq!(
+            Some(Some(12)),
+            scanner.scan(b"bug-stop=12\x07\r\n12\t  x += 1;\r\n")
+        );
+        assert_eq!(None, scanner.scan(b"plain output\x1b"));
+        assert_eq!(
+            Some(None),
+            scanner.scan(b"]5379;ye-debug-stop=3\x07\x1b]5379;ye-debug-stop=\x07")
+        );
+    }
+}
@@ -0,0 +1,1084 @@
+use actix::prelude::*;
+use failure::{err_msg, Fallible};
+use nix::libc;
+use nix::pty;
+use nix::sys::signal::{self, Signal};
+use nix::unistd::Pid;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, ExitStatus, Output, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::runner_backend::{
+    EventListener, Events, ReqStop, RunnerBackend, RunnerConnection, BACKEND_LOCAL,
+};
+use crate::app::{self, config::RunnerConfig};
+use ycommon::artifact::ArtifactScanner;
+use ycommon::runner_proto as proto;
+
+// Runs yscript of this host in a pty, optionally sandboxed by bubblewrap.
+// There is no repl, stdin goes to the running code only.
+// Language servers run in the same sandbox with the code in work_dir/lsp.
+// Databases of sql rooms are created in servers of this host, dropped when the session stops.
+// Code runs in work_dir/run, kept after the run for files_ttl_ms so its files can be fetched.
+
+const REQUEST_FILE: &str = "request.json";
+const READ_BUFFER_SIZE: usize = 4096;
+const LSP_DIR: &str = "lsp";
+const RUN_DIR: &str = "run";
+const MAX_RUN_FILES: usize = 256;
+const DB_RESET_FILE: &str = "db_reset.json";
+const DB_DROP_FILE: &str = "db_drop.json";
+// json-rpc message of language server
+const LSP_MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+const DEFAULT_ROWS: u16 = 24;
+const DEFAULT_COLS: u16 = 80;
+
+pub struct LocalRunnerBackend {
+    config: RunnerConfig,
+}
+
+impl LocalRunnerBackend {
+    pub fn new(config: RunnerConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl RunnerBackend for LocalRunnerBackend {
+    fn name(&self) -> &'static str {
+        BACKEND_LOCAL
+    }
+
+    fn connect(&self, room_key: &str, listener: EventListener) -> RunnerConnection {
+        let session = LocalRunnerSession::new(room_key, self.config.clone(), listener);
+        RunnerConnection::from_addr(session.start(), BACKEND_LOCAL.to_owned())
+    }
+}
+
+struct LocalProcess {
+    run_seq: u64,
+    // leader of process group
+    pid: Pid,
+    master: File,
+}
+
+struct LocalLanguageServer {
+    id: proto::CodeId,
+    pid: Pid,
+    // framed messages, written by a thread so a busy server does not block the session
+    input: mpsc::Sender<Vec<u8>>,
+}
+
+struct LocalDatabase {
+    language: String,
+    params: proto::DatabaseParams,
+}
+
+pub struct LocalRunnerSession {
+    room_key: String,
+    config: RunnerConfig,
+    listener: EventListener,
+    win_size: proto::WinSize,
+    process: Option<LocalProcess>,
+    run_seq: u64,
+    // formatters and linters, group leader by sequence
+    tools: HashMap<u64, Pid>,
+    tool_seq: u64,
+    lsp: Option<LocalLanguageServer>,
+    database: Option<LocalDatabase>,
+}
+
+#[derive(Message)]
+struct MsgProcessExited {
+    run_seq: u64,
+    resp: proto::RunResp,
+}
+
+#[derive(Message)]
+struct MsgToolExited {
+    tool_seq: u64,
+    resp: proto::ServiceResponses,
+}
+
+#[derive(Message)]
+struct MsgDatabaseReset {
+    tool_seq: u64,
+    output: Option<Output>,
+}
+
+#[derive(Message)]
+struct MsgLspExited {
+    id: proto::CodeId,
+}
+
+#[derive(Clone, Copy)]
+enum ToolAction {
+    Format,
+    Lint,
+}
+
+impl ToolAction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ToolAction::Format => "format",
+            ToolAction::Lint => "lint",
+        }
+    }
+}
+
+impl LocalRunnerSession {
+    pub fn new(room_key: &str, config: RunnerConfig, listener: EventListener) -> Self {
+        Self {
+            room_key: room_key.to_owned(),
+            config,
+            listener,
+            win_size: proto::WinSize {
+                row: DEFAULT_ROWS,
+                col: DEFAULT_COLS,
+            },
+            process: None,
+            run_seq: 0,
+            tools: HashMap::new(),
+            tool_seq: 0,
+            lsp: None,
+            database: None,
+        }
+    }
+
+    fn respond(&self, resp: proto::ServiceResponses) {
+        let _ = self.listener.do_send(Events::Response(resp));
+    }
+
+    fn write_notice(&self, text: &str) {
+        self.respond(proto::ServiceResponses::StdoutRaw(
+            Ok(proto::StdoutRawResp {
+                id: 0,
+                data: text.as_bytes().to_vec(),
+            })
+            .into(),
+        ));
+    }
+
+    fn work_dir(&self) -> Fallible<PathBuf> {
+        let valid = self
+            .room_key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if self.room_key.is_empty() || !valid {
+            return Err(format_err!("invalid room key: {}", self.room_key));
+        }
+        let base = if self.config.work_dir.is_empty() {
+            std::env::temp_dir().join("yeditor")
+        } else {
+            PathBuf::from(&self.config.work_dir)
+        };
+        Ok(base.join(&self.room_key))
+    }
+
+    fn build_command(&self, work_dir: &Path, request_file: &str) -> Command {
+        let mut yscript_args = vec!["-f", request_file];
+        let deps_dir = self.config.deps_dir.as_str();
+        if !deps_dir.is_empty() {
+            yscript_args.extend(&["--deps-dir", deps_dir]);
+        }
+        let cache_dir = self.config.cache_dir.as_str();
+        if !cache_dir.is_empty() {
+            yscript_args.extend(&["--cache-dir", cache_dir]);
+        }
+        self.sandbox_command(work_dir, &self.config.yscript_path, &yscript_args)
+    }
+
+    fn sandbox_command(&self, work_dir: &Path, program: &str, args: &[&str]) -> Command {
+        if !self.config.bubblewrap {
+            let mut command = Command::new(program);
+            command.args(args);
+            return command;
+        }
+
+        // program must be inside one of ro binds
+        let deps_dir = self.config.deps_dir.as_str();
+        let cache_dir = self.config.cache_dir.as_str();
+        let work_dir = work_dir.to_string_lossy().into_owned();
+        let mut command = Command::new(&self.config.bubblewrap_path);
+        for dir in &self.config.bubblewrap_ro_binds {
+            if Path::new(dir).exists() {
+                command.args(&["--ro-bind", dir, dir]);
+            }
+        }
+        command
+            .args(&["--proc", "/proc", "--dev", "/dev", "--tmpfs", "/tmp"])
+            .args(&["--bind", &work_dir, &work_dir, "--chdir", &work_dir]);
+        // environments of dependencies and compiled programs are kept across runs
+        for dir in &[deps_dir, cache_dir] {
+            if !dir.is_empty() && Path::new(dir).exists() {
+                command.args(&["--bind", dir, dir]);
+            }
+        }
+        command.arg("--unshare-all");
+        // database server of room is reached over network
+        if self.database.is_some() {
+            command.arg("--share-net");
+        }
+        command
+            .args(&["--die-with-parent", "--"])
+            .arg(program)
+            .args(args);
+        command
+    }
+
+    fn start_process(
+        &mut self,
+        code: proto::Code,
+        ctx: &mut <Self as Actor>::Context,
+    ) -> Fallible<()> {
+        self.kill_process();
+        // exit of the killed run is stale from here, even if this run fails to start
+        self.run_seq += 1;
+        let run_seq = self.run_seq;
+
+        let work_dir = self.work_dir()?;
+        fs::create_dir_all(&work_dir)?;
+        // files of the previous run
+        let run_dir = work_dir.join(RUN_DIR);
+        if run_dir.exists() {
+            fs::remove_dir_all(&run_dir)?;
+        }
+        fs::create_dir_all(&run_dir)?;
+        let mut payload = match code.debug {
+            Some(ref debug) => {
+                let mut payload = payload(&code, "debug");
+                payload["breakpoints"] = serde_json::json!(debug.breakpoints);
+                payload
+            }
+            None => payload(&code, "run"),
+        };
+        let timeout_ms = if code.debug.is_some() {
+            self.config.debug_timeout_ms
+        } else {
+            self.config.run_timeout_ms
+        };
+        if let Some(database) = self.database.as_ref() {
+            payload["database"] = serde_json::json!(database.params.name);
+        }
+        fs::write(work_dir.join(REQUEST_FILE), serde_json::to_vec(&payload)?)?;
+
+        let pty = pty::openpty(Some(&winsize(&self.win_size)), None)?;
+        // fds owned by files, closed on drop
+        let master = unsafe { File::from_raw_fd(pty.master) };
+        let slave = unsafe { File::from_raw_fd(pty.slave) };
+
+        let mut command = self.build_command(&work_dir, REQUEST_FILE);
+        command
+            .current_dir(&work_dir)
+            .env("TERM", "xterm-256color")
+            .env("YSCRIPT_WORK_DIR", &run_dir)
+            .stdin(Stdio::from(slave.try_clone()?))
+            .stdout(Stdio::from(slave.try_clone()?))
+            .stderr(Stdio::from(slave));
+        unsafe {
+            command.pre_exec(|| {
+                // new session with the pty as controlling terminal
+                if libc::setsid() < 0 || libc::ioctl(0, libc::TIOCSCTTY as _, 0) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let mut child = command.spawn()?;
+        // slave is kept open by command otherwise, reader would never see eof
+        drop(command);
+
+        self.process = Some(LocalProcess {
+            run_seq,
+            pid: Pid::from_raw(child.id() as i32),
+            master: master.try_clone()?,
+        });
+
+        let mut reader = master;
+        let listener = self.listener.clone();
+        let session = ctx.address();
+        let code_id = code.id;
+        let started_at = Instant::now();
+        thread::spawn(move || {
+            let send_stdout = |data: Vec<u8>| {
+                let resp = proto::ServiceResponses::StdoutRaw(
+                    Ok(proto::StdoutRawResp { id: code_id, data }).into(),
+                );
+                listener.do_send(Events::Response(resp))
+            };
+            let mut buf = [0u8; READ_BUFFER_SIZE];
+            let mut scanner = ArtifactScanner::new(code_id);
+            loop {
+                // EIO when all slave fds are closed
+                let size = match reader.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(size) => size,
+                    Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(_) => break,
+                };
+                let (output, artifacts) = scanner.scan(&buf[..size]);
+                if !output.is_empty() && send_stdout(output).is_err() {
+                    break;
+                }
+                for artifact in artifacts {
+                    let resp = proto::ServiceResponses::Artifact(Ok(artifact).into());
+                    let _ = listener.do_send(Events::Response(resp));
+                }
+            }
+            let rest = scanner.finish();
+            if !rest.is_empty() {
+                let _ = send_stdout(rest);
+            }
+            let exit_status = match child.wait() {
+                Ok(status) => status
+                    .code()
+                    .or_else(|| status.signal().map(|sig| 128 + sig))
+                    .unwrap_or(-1),
+                Err(_) => -1,
+            };
+            let resp = proto::RunResp {
+                id: code_id,
+                exit_status,
+                duration_ms: started_at.elapsed().as_micros() as f64 / 1000.0,
+            };
+            session.do_send(MsgProcessExited { run_seq, resp });
+        });
+
+        let timeout = Duration::from_millis(timeout_ms);
+        ctx.run_later(timeout, move |act: &mut Self, _| {
+            if act.process.as_ref().map(|p| p.run_seq) == Some(run_seq) {
+                act.write_notice("\r\n[ execution timeout ]\r\n");
+                act.kill_process();
+            }
+        });
+        Ok(())
+    }
+
+    // formatter or linter with captured output, runs beside the code
+    fn start_tool(
+        &mut self,
+        action: ToolAction,
+        code: proto::Code,
+        ctx: &mut <Self as Actor>::Context,
+    ) -> Fallible<()> {
+        let work_dir = self.work_dir()?;
+        fs::create_dir_all(&work_dir)?;
+        let request_file = format!("{}.json", action.as_str());
+        let payload = payload(&code, action.as_str());
+        fs::write(work_dir.join(&request_file), serde_json::to_vec(&payload)?)?;
+
+        let mut command = self.build_command(&work_dir, &request_file);
+        command
+            .current_dir(&work_dir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        own_process_group(&mut command);
+        let child = command.spawn()?;
+
+        self.tool_seq += 1;
+        let tool_seq = self.tool_seq;
+        self.tools
+            .insert(tool_seq, Pid::from_raw(child.id() as i32));
+
+        let session = ctx.address();
+        let code_id = code.id;
+        thread::spawn(move || {
+            let resp = match child.wait_with_output() {
+                Ok(output) => tool_response(action, code_id, output),
+                Err(err) => {
+                    warn!("local runner tool failure: {:?}", err);
+                    tool_error(action, proto::ServiceError::ErrServiceInternal)
+                }
+            };
+            session.do_send(MsgToolExited { tool_seq, resp });
+        });
+
+        let timeout = Duration::from_millis(self.config.run_timeout_ms);
+        ctx.run_later(timeout, move |act: &mut Self, _| {
+            if let Some(pid) = act.tools.remove(&tool_seq) {
+                kill_group(pid);
+            }
+        });
+        Ok(())
+    }
+
+    // seed of database runs before reset is answered, killed on timeout like tools
+    fn start_database_reset(&mut self, ctx: &mut <Self as Actor>::Context) -> Fallible<()> {
+        let database = self.database.as_ref().ok_or(err_msg("database not set"))?;
+        let work_dir = self.work_dir()?;
+        fs::create_dir_all(&work_dir)?;
+        let payload = database_payload(database, "db_reset");
+        fs::write(work_dir.join(DB_RESET_FILE), serde_json::to_vec(&payload)?)?;
+
+        let mut command = self.build_command(&work_dir, DB_RESET_FILE);
+        command
+            .current_dir(&work_dir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped());
+        own_process_group(&mut command);
+        let child = command.spawn()?;
+
+        self.tool_seq += 1;
+        let tool_seq = self.tool_seq;
+        self.tools
+            .insert(tool_seq, Pid::from_raw(child.id() as i32));
+
+        let session = ctx.address();
+        thread::spawn(move || {
+            let output = match child.wait_with_output() {
+                Ok(output) => Some(output),
+                Err(err) => {
+                    warn!("local runner database failure: {:?}", err);
+                    None
+                }
+            };
+            session.do_send(MsgDatabaseReset { tool_seq, output });
+        });
+
+        let timeout = Duration::from_millis(self.config.run_timeout_ms);
+        ctx.run_later(timeout, move |act: &mut Self, _| {
+            if let Some(pid) = act.tools.remove(&tool_seq) {
+                kill_group(pid);
+            }
+        });
+        Ok(())
+    }
+
+    // waits for the drop, work_dir is removed after it on stopping
+    fn drop_database(&mut self) {
+        let name = match self.database.as_ref() {
+            None => return,
+            Some(database) => database.params.name.clone(),
+        };
+        match self.run_database_drop() {
+            Ok(status) if status.success() => info!("local runner database dropped: {}", name),
+            Ok(status) => warn!("local runner database drop failure: {}, {}", name, status),
+            Err(err) => warn!(
+                "local runner database drop failure: {}, err = {:?}",
+                name, err
+            ),
+        }
+        self.database = None;
+    }
+
+    fn run_database_drop(&self) -> Fallible<ExitStatus> {
+        let database = self.database.as_ref().ok_or(err_msg("database not set"))?;
+        let work_dir = self.work_dir()?;
+        fs::create_dir_all(&work_dir)?;
+        let payload = database_payload(database, "db_drop");
+        fs::write(work_dir.join(DB_DROP_FILE), serde_json::to_vec(&payload)?)?;
+
+        let status = self
+            .build_command(&work_dir, DB_DROP_FILE)
+            .current_dir(&work_dir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()?;
+        Ok(status)
+    }
+
+    // language server with the code as only source file, messages are framed by threads
+    fn start_lsp(
+        &mut self,
+        req: proto::LspStart,
+        ctx: &mut <Self as Actor>::Context,
+    ) -> Fallible<proto::LspStartResp> {
+        self.stop_lsp();
+
+        let lang = app::config().languages.get(&req.language);
+        let args: Vec<&str> = lang
+            .map(|lang| lang.lsp.iter().map(|arg| arg.as_str()).collect())
+            .unwrap_or_default();
+        let ext = lang.map(|lang| lang.ext.as_str()).unwrap_or_default();
+        let work_dir = self.work_dir()?;
+        let lsp_dir = work_dir.join(LSP_DIR);
+        fs::create_dir_all(&lsp_dir)?;
+        let file_path = lsp_dir.join(format!("{}{}", req.filename, ext));
+        fs::write(&file_path, req.content.as_bytes())?;
+
+        let mut command = self.sandbox_command(&work_dir, args[0], &args[1..]);
+        command
+            .current_dir(&lsp_dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null());
+        unsafe {
+            command.pre_exec(|| {
+                // own process group, killed as a whole when stopped
+                if libc::setsid() < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let mut child = command.spawn()?;
+        let pid = Pid::from_raw(child.id() as i32);
+        let mut stdin = child.stdin.take().ok_or(err_msg("lsp stdin not piped"))?;
+        let stdout = child.stdout.take().ok_or(err_msg("lsp stdout not piped"))?;
+
+        let (input, frames) = mpsc::channel::<Vec<u8>>();
+        thread::spawn(move || {
+            for frame in frames {
+                if stdin.write_all(&frame).and_then(|_| stdin.flush()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let id = req.id;
+        let listener = self.listener.clone();
+        let session = ctx.address();
+        thread::spawn(move || {
+            let mut reader = BufReader::new(stdout);
+            loop {
+                let body = match read_lsp_frame(&mut reader) {
+                    Ok(Some(body)) => body,
+                    Ok(None) => break,
+                    Err(err) => {
+                        debug!("language server output failure: {:?}", err);
+                        break;
+                    }
+                };
+                let message = match serde_json::from_slice(&body) {
+                    Ok(message) => message,
+                    Err(_) => continue,
+                };
+                let resp =
+                    proto::ServiceResponses::Lsp(Ok(proto::LspMessage { id, message }).into());
+                if listener.do_send(Events::Response(resp)).is_err() {
+                    break;
+                }
+            }
+            let _ = child.wait();
+            session.do_send(MsgLspExited { id });
+        });
+
+        self.lsp = Some(LocalLanguageServer { id, pid, input });
+        Ok(proto::LspStartResp {
+            id,
+            root_uri: file_uri(&lsp_dir),
+            uri: file_uri(&file_path),
+        })
+    }
+
+    fn stop_lsp(&mut self) {
+        if let Some(lsp) = self.lsp.take() {
+            kill_group(lsp.pid);
+        }
+    }
+
+    // files of run_seq are removed after files_ttl_ms, unless another run started
+    fn retain_run_files(&mut self, run_seq: u64, ctx: &mut <Self as Actor>::Context) {
+        let timeout = Duration::from_millis(self.config.files_ttl_ms);
+        ctx.run_later(timeout, move |act: &mut Self, _| {
+            if act.run_seq == run_seq && act.process.is_none() {
+                act.remove_run_files();
+            }
+        });
+    }
+
+    fn remove_run_files(&self) {
+        if let Ok(work_dir) = self.work_dir() {
+            let _ = fs::remove_dir_all(work_dir.join(RUN_DIR));
+        }
+    }
+
+    fn list_files(&self) -> Fallible<Vec<proto::RunFile>> {
+        let run_dir = self.work_dir()?.join(RUN_DIR);
+        let mut files = Vec::new();
+        if run_dir.exists() {
+            collect_files(&run_dir, "", &mut files)?;
+        }
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(files)
+    }
+
+    fn fetch_file(&self, name: &str, max_size: u64) -> Result<Vec<u8>, proto::ServiceError> {
+        // names of list_files only, nothing outside of run directory
+        let valid = !name.is_empty()
+            && Path::new(name).components().all(|c| match c {
+                Component::Normal(_) => true,
+                _ => false,
+            });
+        let path = match self.work_dir() {
+            Ok(work_dir) if valid => work_dir.join(RUN_DIR).join(name),
+            _ => return Err(proto::ServiceError::ErrFileNotFound),
+        };
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.is_file() => {
+                if metadata.len() > max_size {
+                    return Err(proto::ServiceError::ErrFileTooLarge);
+                }
+            }
+            _ => return Err(proto::ServiceError::ErrFileNotFound),
+        }
+        fs::read(&path).map_err(|err| {
+            warn!(
+                "local runner fetch failure: {}, err = {:?}",
+                self.room_key, err
+            );
+            proto::ServiceError::ErrServiceInternal
+        })
+    }
+
+    fn kill_process(&mut self) {
+        if let Some(process) = self.process.take() {
+            kill_group(process.pid);
+        }
+    }
+
+    fn set_win_size(&mut self, win_size: proto::WinSize) {
+        if win_size.row == 0 || win_size.col == 0 {
+            return;
+        }
+        if let Some(process) = self.process.as_ref() {
+            let size = winsize(&win_size);
+            unsafe {
+                libc::ioctl(process.master.as_raw_fd(), libc::TIOCSWINSZ as _, &size);
+            }
+        }
+        self.win_size = win_size;
+    }
+}
+
+fn kill_group(pid: Pid) {
+    // negative pid for the whole group
+    let group = Pid::from_raw(-pid.as_raw());
+    if let Err(err) = signal::kill(group, Signal::SIGKILL) {
+        debug!("kill process group failure: {:?}, err = {:?}", group, err);
+    }
+}
+
+// own process group, killed as a whole on timeout
+fn own_process_group(command: &mut Command) {
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+// yscript payload of database action, the seed is the only file
+// regular files under dir, symbolic links are not followed
+fn collect_files(dir: &Path, prefix: &str, files: &mut Vec<proto::RunFile>) -> Fallible<()> {
+    for entry in fs::read_dir(dir)? {
+        if files.len() >= MAX_RUN_FILES {
+            break;
+        }
+        let entry = entry?;
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_files(&entry.path(), &format!("{}/", name), files)?;
+        } else if file_type.is_file() {
+            files.push(proto::RunFile {
+                name,
+                size: entry.metadata()?.len(),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn database_payload(database: &LocalDatabase, action: &str) -> serde_json::Value {
+    let ext = app::config()
+        .languages
+        .get(&database.language)
+        .map(|lang| lang.ext.clone())
+        .unwrap_or_default();
+    serde_json::json!({
+        "language": database.language,
+        "files": [{
+            "name": format!("seed{}", ext),
+            "content": database.params.seed,
+        }],
+        "database": database.params.name,
+        "action": action,
+    })
+}
+
+// yscript payload of code, action is run, format or lint
+fn payload(code: &proto::Code, action: &str) -> serde_json::Value {
+    let ext = app::config()
+        .languages
+        .get(&code.language)
+        .map(|lang| lang.ext.clone())
+        .unwrap_or_default();
+    serde_json::json!({
+        "language": code.language,
+        "files": [{
+            "name": format!("{}{}", code.filename, ext),
+            "content": code.content,
+        }],
+        "stdin": code.stdin,
+        "options": code.options,
+        "dependencies": code.dependencies,
+        "action": action,
+    })
+}
+
+fn file_uri(path: &Path) -> String {
+    format!("file://{}", path.to_string_lossy())
+}
+
+/// Body of a message framed by Content-Length header, none at end of output
+fn read_lsp_frame<R: BufRead>(reader: &mut R) -> Fallible<Option<Vec<u8>>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        let mut parts = line.splitn(2, ':');
+        let name = parts.next().unwrap_or_default();
+        if name.eq_ignore_ascii_case("content-length") {
+            let value = parts.next().unwrap_or_default().trim();
+            length = Some(value.parse::<usize>()?);
+        }
+    }
+    match length {
+        Some(length) if length <= LSP_MAX_MESSAGE_SIZE => {
+            let mut body = vec![0u8; length];
+            reader.read_exact(&mut body)?;
+            Ok(Some(body))
+        }
+        _ => Err(format_err!("invalid content length: {:?}", length)),
+    }
+}
+
+fn lsp_frame(message: &serde_json::Value) -> Vec<u8> {
+    let body = message.to_string();
+    let mut frame = format!("Content-Length: {}\r\n\r\n", body.len()).into_bytes();
+    frame.extend_from_slice(body.as_bytes());
+    frame
+}
+
+fn tool_error(action: ToolAction, err: proto::ServiceError) -> proto::ServiceResponses {
+    match action {
+        ToolAction::Format => proto::ServiceResponses::Format(Err(err).into()),
+        ToolAction::Lint => proto::ServiceResponses::Lint(Err(err).into()),
+    }
+}
+
+// formatted code or diagnostics on stdout, error report of yscript on stderr
+fn tool_response(action: ToolAction, id: proto::CodeId, output: Output) -> proto::ServiceResponses {
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    let error = if output.status.success() {
+        None
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let message = stderr.trim().trim_start_matches("Error: ");
+        if message.is_empty() {
+            Some(format!("{} failure", action.as_str()))
+        } else {
+            Some(message.to_owned())
+        }
+    };
+    match action {
+        ToolAction::Format => proto::ServiceResponses::Format(
+            Ok(proto::FormatResp {
+                id,
+                content: if error.is_none() {
+                    stdout
+                } else {
+                    String::new()
+                },
+                error,
+            })
+            .into(),
+        ),
+        ToolAction::Lint => {
+            let (diagnostics, error) = match error {
+                Some(error) => (vec![], Some(error)),
+                None => match serde_json::from_str(stdout.as_str()) {
+                    Ok(diagnostics) => (diagnostics, None),
+                    Err(_) => (vec![], Some("invalid linter output".to_owned())),
+                },
+            };
+            proto::ServiceResponses::Lint(
+                Ok(proto::LintResp {
+                    id,
+                    diagnostics,
+                    error,
+                })
+                .into(),
+            )
+        }
+    }
+}
+
+fn winsize(win_size: &proto::WinSize) -> pty::Winsize {
+    pty::Winsize {
+        ws_row: win_size.row,
+        ws_col: win_size.col,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    }
+}
+
+impl Actor for LocalRunnerSession {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        info!("local runner started: {}", self.room_key);
+        let _ = self.listener.do_send(Events::Connected);
+    }
+
+    fn stopping(&mut self, ctx: &mut Self::Context) -> Running {
+        info!("local runner stopping: {}", self.room_key);
+        self.kill_process();
+        self.stop_lsp();
+        for (_, pid) in self.tools.drain() {
+            kill_group(pid);
+        }
+        self.drop_database();
+        if let Ok(work_dir) = self.work_dir() {
+            let _ = fs::remove_dir_all(work_dir);
+        }
+        let _ = self.listener.do_send(Events::Closed);
+        Running::Stop
+    }
+}
+
+impl Handler<proto::ServiceRequests> for LocalRunnerSession {
+    type Result = MessageResult<proto::ServiceRequests>;
+
+    fn handle(&mut self, msg: proto::ServiceRequests, ctx: &mut Self::Context) -> Self::Result {
+        debug!("local runner received request: {:?}", msg);
+        match msg {
+            proto::ServiceRequests::Hello(_) => {}
+            proto::ServiceRequests::Reset(run_env) => {
+                self.kill_process();
+                self.stop_lsp();
+                self.remove_run_files();
+                self.set_win_size(run_env.win_size);
+                let language = run_env.language;
+                let database = run_env
+                    .database
+                    .map(|params| LocalDatabase { language, params });
+                // database of previous language
+                if database.is_none() {
+                    self.drop_database();
+                }
+                self.database = database;
+                if self.database.is_none() {
+                    self.respond(proto::ServiceResponses::Reset(
+                        Ok(proto::ResetResp {}).into(),
+                    ));
+                } else if let Err(err) = self.start_database_reset(ctx) {
+                    warn!(
+                        "local runner database failure: {}, err = {:?}",
+                        self.room_key, err
+                    );
+                    self.write_notice(&format!("\r\n[ database failure: {} ]\r\n", err));
+                    self.respond(proto::ServiceResponses::Reset(
+                        Ok(proto::ResetResp {}).into(),
+                    ));
+                }
+            }
+            proto::ServiceRequests::Run(code) => {
+                let code_id = code.id;
+                if let Err(err) = self.start_process(code, ctx) {
+                    warn!(
+                        "local runner start failure: {}, err = {:?}",
+                        self.room_key, err
+                    );
+                    self.write_notice(&format!("\r\n[ run failure: {} ]\r\n", err));
+                    self.respond(proto::ServiceResponses::Run(
+                        Ok(proto::RunResp {
+                            id: code_id,
+                            exit_status: -1,
+                            duration_ms: 0.0,
+                        })
+                        .into(),
+                    ));
+                }
+            }
+            proto::ServiceRequests::Format(code) => {
+                if let Err(err) = self.start_tool(ToolAction::Format, code, ctx) {
+                    warn!(
+                        "local runner format failure: {}, err = {:?}",
+                        self.room_key, err
+                    );
+                    self.respond(tool_error(
+                        ToolAction::Format,
+                        proto::ServiceError::ErrServiceInternal,
+                    ));
+                }
+            }
+            proto::ServiceRequests::Lint(code) => {
+                if let Err(err) = self.start_tool(ToolAction::Lint, code, ctx) {
+                    warn!(
+                        "local runner lint failure: {}, err = {:?}",
+                        self.room_key, err
+                    );
+                    self.respond(tool_error(
+                        ToolAction::Lint,
+                        proto::ServiceError::ErrServiceInternal,
+                    ));
+                }
+            }
+            proto::ServiceRequests::LspStart(req) => {
+                let supported = app::config()
+                    .languages
+                    .get(&req.language)
+                    .map(|lang| !lang.lsp.is_empty())
+                    .unwrap_or(false);
+                let resp = if !supported {
+                    self.stop_lsp();
+                    Err(proto::ServiceError::ErrNotSupported)
+                } else {
+                    self.start_lsp(req, ctx).map_err(|err| {
+                        warn!(
+                            "local runner lsp failure: {}, err = {:?}",
+                            self.room_key, err
+                        );
+                        proto::ServiceError::ErrServiceInternal
+                    })
+                };
+                self.respond(proto::ServiceResponses::LspStart(resp.into()));
+            }
+            proto::ServiceRequests::Lsp(msg) => {
+                if let Some(lsp) = self.lsp.as_ref().filter(|lsp| lsp.id == msg.id) {
+                    let _ = lsp.input.send(lsp_frame(&msg.message));
+                }
+            }
+            proto::ServiceRequests::LspStop(msg) => {
+                if self.lsp.as_ref().map(|lsp| lsp.id) == Some(msg.id) {
+                    self.stop_lsp();
+                }
+            }
+            proto::ServiceRequests::ListFiles(req) => {
+                let (files, error) = match self.list_files() {
+                    Ok(files) => (files, None),
+                    Err(err) => {
+                        warn!(
+                            "local runner list failure: {}, err = {:?}",
+                            self.room_key, err
+                        );
+                        (vec![], Some(proto::ServiceError::ErrServiceInternal))
+                    }
+                };
+                self.respond(proto::ServiceResponses::ListFiles(
+                    Ok(proto::FileList {
+                        id: req.id,
+                        files,
+                        error,
+                    })
+                    .into(),
+                ));
+            }
+            proto::ServiceRequests::FetchFile(req) => {
+                let (data, error) = match self.fetch_file(&req.name, req.max_size) {
+                    Ok(data) => (data, None),
+                    Err(err) => (vec![], Some(err)),
+                };
+                self.respond(proto::ServiceResponses::FetchFile(
+                    Ok(proto::FileData {
+                        id: req.id,
+                        name: req.name,
+                        data,
+                        error,
+                    })
+                    .into(),
+                ));
+            }
+            proto::ServiceRequests::WinSize(win_size) => self.set_win_size(win_size),
+            proto::ServiceRequests::Stdin(input) => {
+                if let Some(process) = self.process.as_mut() {
+                    if let Err(err) = process.master.write_all(input.as_bytes()) {
+                        debug!("local runner stdin failure: {:?}", err);
+                    }
+                }
+            }
+        }
+        MessageResult(())
+    }
+}
+
+impl Handler<MsgProcessExited> for LocalRunnerSession {
+    type Result = ();
+
+    fn handle(&mut self, msg: MsgProcessExited, ctx: &mut Self::Context) -> Self::Result {
+        // runs killed for a newer run are not reported, room is running the newer one
+        if msg.run_seq != self.run_seq {
+            debug!(
+                "local runner stale run exited: {}, run_seq = {}",
+                self.room_key, msg.run_seq
+            );
+            return;
+        }
+        if self.process.as_ref().map(|p| p.run_seq) == Some(msg.run_seq) {
+            self.process = None;
+        }
+        self.retain_run_files(msg.run_seq, ctx);
+        self.respond(proto::ServiceResponses::Run(Ok(msg.resp).into()));
+    }
+}
+
+impl Handler<MsgToolExited> for LocalRunnerSession {
+    type Result = ();
+
+    fn handle(&mut self, msg: MsgToolExited, ctx: &mut Self::Context) -> Self::Result {
+        self.tools.remove(&msg.tool_seq);
+        self.respond(msg.resp);
+    }
+}
+
+impl Handler<MsgDatabaseReset> for LocalRunnerSession {
+    type Result = ();
+
+    fn handle(&mut self, msg: MsgDatabaseReset, ctx: &mut Self::Context) -> Self::Result {
+        self.tools.remove(&msg.tool_seq);
+        match msg.output {
+            Some(ref output) if output.status.success() => {}
+            Some(output) => {
+                // error report of yscript and the client
+                let stderr = String::from_utf8_lossy(&output.stderr).replace('\n', "\r\n");
+                self.write_notice(&format!("\r\n[ database seed failure ]\r\n{}", stderr));
+            }
+            None => self.write_notice("\r\n[ database not created ]\r\n"),
+        }
+        self.respond(proto::ServiceResponses::Reset(
+            Ok(proto::ResetResp {}).into(),
+        ));
+    }
+}
+
+impl Handler<MsgLspExited> for LocalRunnerSession {
+    type Result = ();
+
+    fn handle(&mut self, msg: MsgLspExited, ctx: &mut Self::Context) -> Self::Result {
+        if self.lsp.as_ref().map(|lsp| lsp.id) == Some(msg.id) {
+            self.lsp = None;
+        }
+        self.respond(proto::ServiceResponses::LspStop(
+            Ok(proto::LspStop { id: msg.id }).into(),
+        ));
+    }
+}
+
+impl Handler<ReqStop> for LocalRunnerSession {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReqStop, ctx: &mut Self::Context) -> Self::Result {
+        ctx.stop();
+    }
+}
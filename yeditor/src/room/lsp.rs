@@ -0,0 +1,372 @@
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use super::TextBuffer;
+use ycommon::runner_proto as proto;
+
+// Language server of room, started by runner in the environment of the room.
+// Room is the only client of the server: it initializes the server and syncs the code buffer
+// as the one open document, so every participant gets results of the same text.
+// Requests of participants are forwarded with ids of room and answered to the requester.
+
+// json-rpc error codes
+const ERR_INVALID_REQUEST: i64 = -32600;
+const ERR_METHOD_NOT_FOUND: i64 = -32601;
+const ERR_SERVER_NOT_INITIALIZED: i64 = -32002;
+const ERR_REQUEST_CANCELLED: i64 = -32800;
+
+// requests of participants are numbered after initialize
+const INITIALIZE_ID: i64 = 0;
+// requests a server did not answer yet
+const MAX_PENDING_REQUESTS: usize = 256;
+
+// document sync is owned by room
+const SYNC_METHODS: &[&str] = &[
+    "textDocument/didOpen",
+    "textDocument/didChange",
+    "textDocument/didSave",
+    "textDocument/didClose",
+    "textDocument/willSave",
+    "textDocument/willSaveWaitUntil",
+];
+
+#[derive(Debug, PartialEq)]
+pub enum LspOutput {
+    // message to language server
+    Server(Value),
+    // response to requester
+    Reply(u32, Value),
+    // notification to all participants
+    Broadcast(Value),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LspState {
+    Starting,
+    Initializing,
+    Ready,
+}
+
+struct PendingRequest {
+    client_id: u32,
+    // id of participant request
+    id: Value,
+}
+
+pub struct LspBridge {
+    // id of lsp_start, messages of other servers are dropped
+    id: proto::CodeId,
+    language: String,
+    state: LspState,
+    uri: String,
+    next_id: i64,
+    doc_version: i64,
+    pending: HashMap<i64, PendingRequest>,
+    // last diagnostics of code buffer, for late joiners
+    diagnostics: Option<Value>,
+}
+
+impl LspBridge {
+    pub fn new(id: proto::CodeId, language: &str) -> Self {
+        Self {
+            id,
+            language: language.to_owned(),
+            state: LspState::Starting,
+            uri: String::new(),
+            next_id: INITIALIZE_ID + 1,
+            doc_version: 0,
+            pending: HashMap::new(),
+            diagnostics: None,
+        }
+    }
+
+    pub fn id(&self) -> proto::CodeId {
+        self.id
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.state == LspState::Ready
+    }
+
+    pub fn diagnostics(&self) -> Option<&Value> {
+        self.diagnostics.as_ref()
+    }
+
+    /// Initialize request of the server started by runner
+    pub fn on_started(&mut self, resp: &proto::LspStartResp) -> Value {
+        self.uri = resp.uri.clone();
+        self.state = LspState::Initializing;
+        json!({
+            "jsonrpc": "2.0",
+            "id": INITIALIZE_ID,
+            "method": "initialize",
+            "params": {
+                "processId": null,
+                "rootUri": resp.root_uri,
+                "workspaceFolders": [{ "uri": resp.root_uri, "name": "workspace" }],
+                "capabilities": client_capabilities(),
+            },
+        })
+    }
+
+    /// Message of participant to server, or error response to the participant
+    pub fn on_client(
+        &mut self,
+        client_id: u32,
+        mut message: Value,
+    ) -> Result<Option<Value>, Value> {
+        let method = message["method"].as_str().map(|m| m.to_owned());
+        let id = message.get("id").cloned();
+        let (method, id) = match (method, id) {
+            (Some(method), Some(id)) => (method, id),
+            (Some(ref method), None) if method == "$/cancelRequest" => {
+                return Ok(self.cancel(client_id, &message["params"]["id"]));
+            }
+            // other notifications and responses of participants are dropped
+            _ => return Ok(None),
+        };
+
+        if !is_forwarded(method.as_str()) {
+            let error = format!("method not supported: {}", method);
+            return Err(error_response(id, ERR_METHOD_NOT_FOUND, error.as_str()));
+        }
+        if self.state != LspState::Ready {
+            let error = "language server is starting";
+            return Err(error_response(id, ERR_SERVER_NOT_INITIALIZED, error));
+        }
+        if self.pending.len() >= MAX_PENDING_REQUESTS {
+            let error = "too many pending requests";
+            return Err(error_response(id, ERR_INVALID_REQUEST, error));
+        }
+
+        // participants may name the buffer anything, the server knows one document
+        if let Some(doc) = message
+            .pointer_mut("/params/textDocument")
+            .and_then(Value::as_object_mut)
+        {
+            doc.insert("uri".to_owned(), json!(self.uri));
+        }
+        let room_id = self.next_id;
+        self.next_id += 1;
+        self.pending
+            .insert(room_id, PendingRequest { client_id, id });
+        message["id"] = json!(room_id);
+        message["jsonrpc"] = json!("2.0");
+        Ok(Some(message))
+    }
+
+    fn cancel(&mut self, client_id: u32, id: &Value) -> Option<Value> {
+        let room_id = self
+            .pending
+            .iter()
+            .find(|(_, p)| p.client_id == client_id && p.id == *id)
+            .map(|(room_id, _)| *room_id)?;
+        Some(notification("$/cancelRequest", json!({ "id": room_id })))
+    }
+
+    /// Message of server, the buffer is opened once the server is initialized
+    pub fn on_server(&mut self, mut message: Value, buffer: &TextBuffer) -> Vec<LspOutput> {
+        let method = message["method"].as_str().map(|m| m.to_owned());
+        let id = message.get("id").cloned();
+        match (method, id) {
+            (None, Some(id)) => {
+                if id == json!(INITIALIZE_ID) && self.state == LspState::Initializing {
+                    return self.on_initialized(buffer);
+                }
+                let pending = match id.as_i64().and_then(|id| self.pending.remove(&id)) {
+                    None => return vec![],
+                    Some(pending) => pending,
+                };
+                message["id"] = pending.id;
+                vec![LspOutput::Reply(pending.client_id, message)]
+            }
+            // requests of server are answered by room
+            (Some(method), Some(id)) => {
+                let result = match method.as_str() {
+                    "workspace/configuration" => {
+                        let items = message["params"]["items"].as_array().map_or(0, |i| i.len());
+                        json!(vec![Value::Null; items])
+                    }
+                    _ => Value::Null,
+                };
+                let resp = json!({ "jsonrpc": "2.0", "id": id, "result": result });
+                vec![LspOutput::Server(resp)]
+            }
+            (Some(ref method), None) if method == "textDocument/publishDiagnostics" => {
+                // diagnostics of other files in workspace, e.g. build manifest
+                if message["params"]["uri"].as_str() != Some(self.uri.as_str()) {
+                    return vec![];
+                }
+                self.diagnostics = Some(message.clone());
+                vec![LspOutput::Broadcast(message)]
+            }
+            _ => vec![],
+        }
+    }
+
+    fn on_initialized(&mut self, buffer: &TextBuffer) -> Vec<LspOutput> {
+        self.state = LspState::Ready;
+        self.doc_version = 1;
+        let open = json!({
+            "textDocument": {
+                "uri": self.uri,
+                "languageId": language_id(self.language.as_str()),
+                "version": self.doc_version,
+                "text": buffer.text(),
+            }
+        });
+        vec![
+            LspOutput::Server(notification("initialized", json!({}))),
+            LspOutput::Server(notification("textDocument/didOpen", open)),
+        ]
+    }
+
+    /// Full text of buffer as a change of the document, none until the server is ready
+    pub fn did_change(&mut self, buffer: &TextBuffer) -> Option<Value> {
+        if self.state != LspState::Ready {
+            return None;
+        }
+        self.doc_version += 1;
+        let params = json!({
+            "textDocument": { "uri": self.uri, "version": self.doc_version },
+            "contentChanges": [{ "text": buffer.text() }],
+        });
+        Some(notification("textDocument/didChange", params))
+    }
+
+    /// Server is gone, pending requests fail and diagnostics are cleared
+    pub fn close(&mut self) -> Vec<LspOutput> {
+        let mut outputs: Vec<LspOutput> = self
+            .pending
+            .drain()
+            .map(|(_, p)| {
+                let error = "language server stopped";
+                LspOutput::Reply(
+                    p.client_id,
+                    error_response(p.id, ERR_REQUEST_CANCELLED, error),
+                )
+            })
+            .collect();
+        if self.diagnostics.take().is_some() {
+            let params = json!({ "uri": self.uri, "diagnostics": [] });
+            outputs.push(LspOutput::Broadcast(notification(
+                "textDocument/publishDiagnostics",
+                params,
+            )));
+        }
+        outputs
+    }
+}
+
+/// Error response to a request of participant when there is no language server
+pub fn not_available(message: &Value) -> Option<Value> {
+    let id = message.get("id")?.clone();
+    message["method"].as_str()?;
+    let error = "language server not available";
+    Some(error_response(id, ERR_SERVER_NOT_INITIALIZED, error))
+}
+
+fn is_forwarded(method: &str) -> bool {
+    (method.starts_with("textDocument/") && !SYNC_METHODS.contains(&method))
+        || method == "completionItem/resolve"
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+fn language_id(language: &str) -> &str {
+    match language {
+        "python2" | "python3" => "python",
+        "objc" => "objective-c",
+        language => language,
+    }
+}
+
+// features of editor clients, positions are utf-16 like monaco
+fn client_capabilities() -> Value {
+    json!({
+        "textDocument": {
+            "synchronization": { "dynamicRegistration": false },
+            "hover": { "contentFormat": ["markdown", "plaintext"] },
+            "completion": {
+                "completionItem": {
+                    "snippetSupport": false,
+                    "documentationFormat": ["markdown", "plaintext"],
+                },
+            },
+            "signatureHelp": {
+                "signatureInformation": { "documentationFormat": ["markdown", "plaintext"] },
+            },
+            "definition": {},
+            "references": {},
+            "documentHighlight": {},
+            "publishDiagnostics": { "relatedInformation": false },
+        },
+        "workspace": { "configuration": true, "workspaceFolders": true },
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn started_bridge(buffer: &TextBuffer) -> LspBridge {
+        let mut bridge = LspBridge::new(1, "python3");
+        let init = bridge.on_started(&proto::LspStartResp {
+            id: 1,
+            root_uri: "file:///ws".to_owned(),
+            uri: "file:///ws/source.py".to_owned(),
+        });
+        assert_eq!("initialize", init["method"]);
+        let outputs = bridge.on_server(json!({ "jsonrpc": "2.0", "id": 0, "result": {} }), buffer);
+        assert_eq!(2, outputs.len());
+        bridge
+    }
+
+    #[test]
+    fn forward_request_and_reply() {
+        let mut buffer = TextBuffer::new();
+        buffer.set_text("import os\n");
+        let mut bridge = started_bridge(&buffer);
+        assert!(bridge.is_ready());
+
+        let req = json!({
+            "id": "a",
+            "method": "textDocument/hover",
+            "params": { "textDocument": { "uri": "inmemory://model/1" } },
+        });
+        let forwarded = bridge.on_client(7, req).unwrap().unwrap();
+        assert_eq!(1, forwarded["id"]);
+        assert_eq!(
+            "file:///ws/source.py",
+            forwarded["params"]["textDocument"]["uri"]
+        );
+
+        let resp = json!({ "jsonrpc": "2.0", "id": 1, "result": null });
+        match bridge.on_server(resp, &buffer).as_slice() {
+            [LspOutput::Reply(7, reply)] => assert_eq!("a", reply["id"]),
+            other => panic!("unexpected outputs: {:?}", other),
+        }
+        assert!(bridge.close().is_empty());
+    }
+
+    #[test]
+    fn reject_sync_and_early_requests() {
+        let buffer = TextBuffer::new();
+        let mut bridge = LspBridge::new(1, "rust");
+        let req = json!({ "id": 1, "method": "textDocument/completion", "params": {} });
+        let err = bridge.on_client(2, req.clone()).unwrap_err();
+        assert_eq!(ERR_SERVER_NOT_INITIALIZED, err["error"]["code"]);
+
+        let mut bridge = started_bridge(&buffer);
+        let sync = json!({ "id": 2, "method": "textDocument/didOpen", "params": {} });
+        let err = bridge.on_client(2, sync).unwrap_err();
+        assert_eq!(ERR_METHOD_NOT_FOUND, err["error"]["code"]);
+        assert_eq!(Ok(None), bridge.on_client(2, json!({ "method": "exit" })));
+    }
+}
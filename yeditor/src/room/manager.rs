@@ -2,7 +2,15 @@ use super::room;
 
 use actix::prelude::*;
 use failure::Fallible;
+use futures::{future, Future};
 use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::{
+    app::{self, drain},
+    cluster,
+    graphql::subscription,
+};
 
 #[derive(Clone)]
 pub struct RoomInfo {
@@ -11,6 +19,12 @@ pub struct RoomInfo {
     pub room_key: String,
 }
 
+#[derive(Clone)]
+pub enum RoomLocation {
+    Local(RoomInfo),
+    Remote(cluster::NodeInfo), // owner node
+}
+
 pub struct RoomManager {
     rooms: HashMap<String, RoomInfo>,
     next_room_id: u32,
@@ -28,6 +42,54 @@ impl RoomManager {
         self.next_room_id += 1;
         self.next_room_id
     }
+
+    fn get_or_create_local_room(
+        &mut self,
+        room_key: String,
+        ctx: &mut <Self as Actor>::Context,
+    ) -> RoomInfo {
+        if let Some(room) = self.rooms.get(&room_key) {
+            return room.clone();
+        }
+        let listener = ctx.address().recipient();
+        let room_addr = room::Room::new(&room_key, listener).start();
+        let room_info = RoomInfo {
+            id: self.next_room_id(),
+            room_key: room_key.clone(),
+            addr: room_addr,
+        };
+        self.rooms.insert(room_key, room_info.clone());
+        room_info
+    }
+
+    /// extend leases of local rooms, close rooms taken over by other nodes
+    fn renew_rooms(&mut self, ctx: &mut <Self as Actor>::Context) {
+        if self.rooms.is_empty() {
+            return;
+        }
+        let msg = cluster::MsgRenewRooms {
+            room_keys: self.rooms.keys().cloned().collect(),
+        };
+        cluster::cluster_executor()
+            .send(msg)
+            .into_actor(self)
+            .then(|res, act, context| {
+                match res {
+                    Ok(Ok(lost_rooms)) => {
+                        for room_key in lost_rooms {
+                            if let Some(room) = act.rooms.get(&room_key) {
+                                warn!("room ownership lost, closing room: {}", room_key);
+                                room.addr.do_send(room::MsgCloseRoom);
+                            }
+                        }
+                    }
+                    Ok(Err(err)) => warn!("renew rooms failure: {:?}", err),
+                    Err(err) => warn!("renew rooms executor error: {:?}", err),
+                }
+                fut::ok(())
+            })
+            .spawn(ctx);
+    }
 }
 
 // System Service
@@ -40,6 +102,10 @@ impl Default for RoomManager {
 impl SystemService for RoomManager {
     fn service_started(&mut self, ctx: &mut Context<Self>) {
         info!("RoomManager Service started");
+        let renew_interval = Duration::from_millis(app::config().cluster.renew_interval_ms);
+        ctx.run_interval(renew_interval, |this: &mut Self, context| {
+            this.renew_rooms(context);
+        });
     }
 }
 
@@ -59,16 +125,17 @@ impl Actor for RoomManager {
 #[derive(Debug)]
 pub struct MsgGetOrCreateRoom {
     pub room_key: String,
+    pub proxied: bool, // proxied connections are never proxied again
 }
 
 impl MsgGetOrCreateRoom {
-    pub fn new(room_key: String) -> Self {
-        Self { room_key }
+    pub fn new(room_key: String, proxied: bool) -> Self {
+        Self { room_key, proxied }
     }
 }
 
 impl Message for MsgGetOrCreateRoom {
-    type Result = Fallible<RoomInfo>;
+    type Result = Fallible<RoomLocation>;
 }
 
 #[derive(Debug)]
@@ -83,25 +150,247 @@ pub struct MsgDestroyRoom {
     pub room_key: String,
 }
 
-// Get or create room
+/// Details of live rooms of current node, rooms not answering in time are skipped
+pub struct MsgQueryRoomDetails {
+    pub timeout: Duration,
+}
+
+impl Message for MsgQueryRoomDetails {
+    type Result = Vec<room::RoomDetail>;
+}
+
+/// Kick client of room, rooms owned by other nodes kick through cluster events
+pub struct MsgKickRoomClient {
+    pub room_key: String,
+    pub client_id: u32,
+}
+
+impl Message for MsgKickRoomClient {
+    // none if room is not live, otherwise client found, always true for remote rooms
+    type Result = Fallible<Option<bool>>;
+}
+
+/// Drain all rooms, see app::drain
+#[derive(Message)]
+pub struct MsgDrainRooms;
+
+/// Locate live room of cluster, never creates or claims one
+#[derive(Debug)]
+pub struct MsgLocateRoom {
+    pub room_key: String,
+}
+
+impl Message for MsgLocateRoom {
+    type Result = Fallible<Option<RoomLocation>>;
+}
+
+#[derive(Debug)]
+pub struct MsgGetRoom {
+    pub room_key: String,
+}
+
+impl Message for MsgGetRoom {
+    type Result = Option<RoomInfo>;
+}
+
+// Get or create room, rooms owned by other nodes are returned as remote
 impl Handler<MsgGetOrCreateRoom> for RoomManager {
-    type Result = MessageResult<MsgGetOrCreateRoom>;
+    type Result = ResponseActFuture<Self, RoomLocation, failure::Error>;
 
     fn handle(&mut self, msg: MsgGetOrCreateRoom, ctx: &mut Self::Context) -> Self::Result {
-        let room_option = self.rooms.get(&msg.room_key);
-        let listener = ctx.address().recipient();
-        match room_option {
-            None => {
-                let room_addr = room::Room::new(&msg.room_key, listener).start();
-                let room_info = RoomInfo {
-                    id: self.next_room_id(),
-                    room_key: msg.room_key.clone(),
-                    addr: room_addr,
+        if let Some(room) = self.rooms.get(&msg.room_key) {
+            return Box::new(fut::ok(RoomLocation::Local(room.clone())));
+        }
+        if drain::is_draining() {
+            return Box::new(fut::err(format_err!("node is draining")));
+        }
+
+        let claim = cluster::MsgClaimRoom {
+            room_key: msg.room_key.clone(),
+        };
+        let fut = cluster::cluster_executor()
+            .send(claim)
+            .into_actor(self)
+            .then(move |res, act, context| {
+                let owner = match res {
+                    Err(err) => {
+                        return fut::err(format_err!("cluster executor error: {:?}", err));
+                    }
+                    Ok(Err(err)) => return fut::err(err),
+                    Ok(Ok(owner)) => owner,
                 };
-                self.rooms.insert(msg.room_key, room_info.clone());
-                MessageResult(Ok(room_info.clone()))
+                if owner.id == cluster::node().id {
+                    let room_info = act.get_or_create_local_room(msg.room_key, context);
+                    fut::ok(RoomLocation::Local(room_info))
+                } else if msg.proxied {
+                    fut::err(format_err!(
+                        "room {} is owned by node {}",
+                        msg.room_key,
+                        owner.id
+                    ))
+                } else {
+                    fut::ok(RoomLocation::Remote(owner))
+                }
+            });
+        Box::new(fut)
+    }
+}
+
+// List live rooms
+impl Handler<MsgListRooms> for RoomManager {
+    type Result = MessageResult<MsgListRooms>;
+
+    fn handle(&mut self, msg: MsgListRooms, ctx: &mut Self::Context) -> Self::Result {
+        let mut rooms: Vec<RoomInfo> = self.rooms.values().cloned().collect();
+        rooms.sort_by_key(|room| room.id);
+        MessageResult(Ok(rooms))
+    }
+}
+
+// Get live room, never creates one
+impl Handler<MsgGetRoom> for RoomManager {
+    type Result = MessageResult<MsgGetRoom>;
+
+    fn handle(&mut self, msg: MsgGetRoom, ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.rooms.get(&msg.room_key).cloned())
+    }
+}
+
+// Local room, otherwise the owner of room in cluster
+impl Handler<MsgLocateRoom> for RoomManager {
+    type Result = ResponseFuture<Option<RoomLocation>, failure::Error>;
+
+    fn handle(&mut self, msg: MsgLocateRoom, ctx: &mut Self::Context) -> Self::Result {
+        if let Some(room) = self.rooms.get(&msg.room_key) {
+            return Box::new(future::ok(Some(RoomLocation::Local(room.clone()))));
+        }
+        let lookup = cluster::MsgGetRoomOwner {
+            room_key: msg.room_key,
+        };
+        let fut = cluster::cluster_executor()
+            .send(lookup)
+            .map_err(|err| format_err!("cluster executor error: {:?}", err))
+            .and_then(|res| match res? {
+                // rooms of current node are local, its lease may outlive the room
+                Some(ref owner) if owner.id != cluster::node().id => {
+                    Ok(Some(RoomLocation::Remote(owner.clone())))
+                }
+                _ => Ok(None),
+            });
+        Box::new(fut)
+    }
+}
+
+// Rooms are queried together, one slow room does not hold the others
+impl Handler<MsgQueryRoomDetails> for RoomManager {
+    type Result = ResponseFuture<Vec<room::RoomDetail>, ()>;
+
+    fn handle(&mut self, msg: MsgQueryRoomDetails, ctx: &mut Self::Context) -> Self::Result {
+        let mut rooms: Vec<RoomInfo> = self.rooms.values().cloned().collect();
+        rooms.sort_by_key(|room| room.id);
+        let queries = rooms.into_iter().map(move |room| {
+            room.addr
+                .send(room::MsgQueryRoomDetail)
+                .timeout(msg.timeout)
+                .then(|res| Ok::<_, ()>(res.ok()))
+        });
+        Box::new(future::join_all(queries).map(|details| details.into_iter().flatten().collect()))
+    }
+}
+
+impl Handler<MsgKickRoomClient> for RoomManager {
+    type Result = ResponseFuture<Option<bool>, failure::Error>;
+
+    fn handle(&mut self, msg: MsgKickRoomClient, ctx: &mut Self::Context) -> Self::Result {
+        if let Some(room) = self.rooms.get(&msg.room_key) {
+            let kick = room::MsgKickClient {
+                client_id: msg.client_id,
+            };
+            let fut = room
+                .addr
+                .send(kick)
+                .map(Some)
+                .map_err(|err| format_err!("room unavailable: {:?}", err));
+            return Box::new(fut);
+        }
+        let lookup = cluster::MsgGetRoomOwner {
+            room_key: msg.room_key.clone(),
+        };
+        let fut = cluster::cluster_executor()
+            .send(lookup)
+            .map_err(|err| format_err!("cluster executor error: {:?}", err))
+            .and_then(move |res| match res? {
+                Some(ref owner) if owner.id != cluster::node().id => {
+                    let event = cluster::ClusterEvent::KickClient(msg.room_key, msg.client_id);
+                    cluster::publish(event);
+                    Ok(Some(true))
+                }
+                _ => Ok(None),
+            });
+        Box::new(fut)
+    }
+}
+
+// Force close room, removed from rooms after RoomEvents::Closed
+impl Handler<MsgDestroyRoom> for RoomManager {
+    type Result = ();
+
+    fn handle(&mut self, msg: MsgDestroyRoom, ctx: &mut Self::Context) -> Self::Result {
+        match self.rooms.get(&msg.room_key) {
+            None => cluster::publish(cluster::ClusterEvent::DestroyRoom(msg.room_key)),
+            Some(room) => room.addr.do_send(room::MsgCloseRoom),
+        }
+    }
+}
+
+// Save and close all rooms
+impl Handler<MsgDrainRooms> for RoomManager {
+    type Result = ();
+
+    fn handle(&mut self, msg: MsgDrainRooms, ctx: &mut Self::Context) -> Self::Result {
+        info!("draining {} rooms", self.rooms.len());
+        for room in self.rooms.values() {
+            room.addr.do_send(room::MsgDrainRoom);
+        }
+    }
+}
+
+// Broadcast notice to rooms of all nodes
+impl Handler<room::MsgBroadcastNotice> for RoomManager {
+    type Result = ();
+
+    fn handle(&mut self, msg: room::MsgBroadcastNotice, ctx: &mut Self::Context) -> Self::Result {
+        cluster::publish(cluster::ClusterEvent::BroadcastNotice(msg.message));
+    }
+}
+
+// Events from other nodes, and current node
+impl Handler<cluster::ClusterEvent> for RoomManager {
+    type Result = ();
+
+    fn handle(&mut self, msg: cluster::ClusterEvent, ctx: &mut Self::Context) -> Self::Result {
+        debug!("received cluster event: {:?}", msg);
+        match msg {
+            cluster::ClusterEvent::BroadcastNotice(message) => {
+                info!("broadcasting notice to {} rooms", self.rooms.len());
+                for room in self.rooms.values() {
+                    room.addr.do_send(room::MsgBroadcastNotice {
+                        message: message.clone(),
+                    });
+                }
+            }
+            cluster::ClusterEvent::DestroyRoom(room_key) => {
+                if let Some(room) = self.rooms.get(&room_key) {
+                    room.addr.do_send(room::MsgCloseRoom);
+                }
+            }
+            cluster::ClusterEvent::KickClient(room_key, client_id) => {
+                if let Some(room) = self.rooms.get(&room_key) {
+                    room.addr.do_send(room::MsgKickClient { client_id });
+                }
             }
-            Some(room) => MessageResult(Ok(room.clone())),
+            // manager is the listener of cluster events
+            cluster::ClusterEvent::Subscription(event) => subscription::deliver(event),
         }
     }
 }
@@ -115,6 +404,7 @@ impl Handler<room::RoomEvents> for RoomManager {
         match msg {
             room::RoomEvents::Closed(room_key) => {
                 self.rooms.remove(&room_key);
+                cluster::cluster_executor().do_send(cluster::MsgReleaseRoom { room_key });
             }
         }
         MessageResult(())
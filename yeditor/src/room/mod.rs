@@ -11,9 +11,23 @@ pub use self::manager::*;
 mod room;
 pub use self::room::*;
 
+mod debugger;
+mod local_runner;
+mod lsp;
 mod runner_agent;
+pub mod runner_backend;
+pub use self::runner_backend::{runner_backend, RunnerBackend, RunnerConnection};
+mod runner_pool;
 mod runner_proxy;
 pub use self::runner_proxy::*;
 
+mod terminal;
+pub use self::terminal::*;
+
 mod text_buffer;
 pub use self::text_buffer::*;
+
+mod upstream;
+
+mod wire;
+pub use self::wire::*;
@@ -1,16 +1,25 @@
 use actix::prelude::*;
 use failure::{err_msg, Fallible};
-use std::collections::{HashMap, VecDeque};
-use std::time::Duration;
+use futures::{future, sync::oneshot, Future};
+use sha2::{Digest, Sha256};
+use std::cmp;
+use std::collections::HashMap;
+use std::fs;
+use std::time::{Duration, Instant};
 
 use ycommon::runner_proto as proto;
 
 use super::client_proto::*;
+use super::debugger::{self, StopScanner};
+use super::lsp::{self, LspBridge, LspOutput};
+use super::runner_backend;
 use super::runner_proxy::{self, MsgRunnerEvent, RunnerProxy};
 use super::*;
 use crate::{
     app::{self, api},
     db,
+    graphql::subscription::{self, SubscriptionEvent},
+    metrics, recording, webhook,
 };
 
 pub type RoomClientListener = Recipient<ClientEvents>;
@@ -34,6 +43,46 @@ pub enum RoomEvents {
 
 pub type RoomEventListener = Recipient<RoomEvents>;
 
+const RECORDING_FLUSH_INTERVAL: Duration = Duration::from_secs(2);
+// events kept for retry when database is unavailable
+const RECORDING_MAX_PENDING: usize = 10000;
+// packages of dependency manifest, same limit as yscript
+const MAX_DEPENDENCIES: usize = 64;
+const MAX_DATABASE_SEED_SIZE: usize = 1024 * 1024;
+// artifacts of the latest run kept for joining clients, later ones are only broadcast
+const MAX_ARTIFACTS_SIZE: usize = 16 * 1024 * 1024;
+// requests of run files are dropped by runners while resetting
+pub const RUN_FILES_TIMEOUT: Duration = Duration::from_secs(10);
+
+// debug run in runner, stop locations are read from its output
+struct DebugSession {
+    client_id: u32,
+    scanner: StopScanner,
+    stopped: Option<u32>,
+}
+
+// format or lint request in flight, result applies to the content it was requested for
+struct PendingTool {
+    id: proto::CodeId,
+    client_id: u32,
+    content: String,
+    editor_version: i64,
+}
+
+// list of run files for a client, or fetch of a file for a download
+enum PendingFiles {
+    List(u32), // client_id
+    Fetch(oneshot::Sender<Result<Vec<u8>, RunFileError>>),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RoomStats {
+    pub room_key: String,
+    pub user_id: i32, // pad owner
+    pub participants: u32,
+    pub running: bool,
+}
+
 pub struct Room {
     room_key: String,
     room_run_env: Option<proto::RunEnv>,
@@ -41,14 +90,61 @@ pub struct Room {
     clients: HashMap<u32, RoomClient>,
     next_client_id: u32,
     runner_proxy_addr: Option<Addr<RunnerProxy>>,
-    // cache output
-    terminal_stdout: VecDeque<String>,
+    // terminal screen shared by clients
+    terminal: TerminalState,
     // room stop handler
     stop_handle: Option<SpawnHandle>,
     // current code buffer
     code_buffer: TextBuffer,
+    // program input, piped to stdin of runs
+    input_buffer: TextBuffer,
+    // dependency manifest of pad, saved with content
+    dependencies: Vec<proto::Dependency>,
+    // sql run in database of room on reset, template seed of language if empty
+    database_seed: String,
+    // rich output of the latest run
+    artifacts: Vec<ArtifactParams>,
     // pad with content
     pad: Option<db::Pad>,
+    // code is running in runner
+    running: bool,
+    // runner agent is connected
+    runner_connected: bool,
+    created_at: Instant,
+    // buffer version, bumped by every applied edit
+    version: u64,
+    // editor version of the latest change applied to code buffer, as sent by its client
+    editor_version: i64,
+    // version persisted to database
+    saved_version: u64,
+    // version of in-flight save
+    saving_version: Option<u64>,
+    // first unsaved edit, bounds the save debounce
+    dirty_since: Option<Instant>,
+    save_handle: Option<SpawnHandle>,
+    save_failures: u32,
+    saved_at: Option<i64>, // unix ms
+    // room stops after pending content is saved
+    closing: bool,
+    // closing because the room is empty, resumed by a join
+    idle_closing: bool,
+    // terminal recording of pad
+    recorder: Option<recording::Recorder>,
+    // latest format and lint requests, results of older ones are dropped
+    pending_format: Option<PendingTool>,
+    pending_lint: Option<PendingTool>,
+    tool_seq: proto::CodeId,
+    // list and fetch requests of run files by id, sequenced with tools
+    pending_files: HashMap<proto::CodeId, PendingFiles>,
+    // signs download tokens of run files, changed by every run
+    files_secret: String,
+    // language server of room, restarted on every reset
+    lsp: Option<LspBridge>,
+    lsp_seq: proto::CodeId,
+    lsp_sync_handle: Option<SpawnHandle>,
+    // breakpoints of code buffer, shared by participants
+    breakpoints: Vec<u32>,
+    debug: Option<DebugSession>,
 }
 
 impl Room {
@@ -60,10 +156,38 @@ impl Room {
             clients: HashMap::new(),
             next_client_id: 1,
             runner_proxy_addr: None,
-            terminal_stdout: VecDeque::new(),
+            terminal: TerminalState::new(Self::room_config().scrollback_lines),
             stop_handle: None,
             code_buffer: TextBuffer::new(),
+            input_buffer: TextBuffer::new(),
+            dependencies: vec![],
+            database_seed: String::new(),
+            artifacts: vec![],
             pad: None,
+            running: false,
+            runner_connected: false,
+            created_at: Instant::now(),
+            version: 0,
+            editor_version: 0,
+            saved_version: 0,
+            saving_version: None,
+            dirty_since: None,
+            save_handle: None,
+            save_failures: 0,
+            saved_at: None,
+            closing: false,
+            idle_closing: false,
+            recorder: None,
+            pending_format: None,
+            pending_lint: None,
+            tool_seq: 0,
+            pending_files: HashMap::new(),
+            files_secret: new_files_secret(),
+            lsp: None,
+            lsp_seq: 0,
+            lsp_sync_handle: None,
+            breakpoints: vec![],
+            debug: None,
         }
     }
 
@@ -76,7 +200,8 @@ impl Actor for Room {
     type Context = Context<Self>;
 
     fn started(&mut self, ctx: &mut <Self as Actor>::Context) {
-        let runner_service_url = Self::room_config().runner_service_url.to_owned();
+        metrics::room_started();
+        let backend = runner_backend::runner_backend();
         let recipient = ctx.address().recipient();
         let room_key = self.room_key.to_owned();
 
@@ -119,42 +244,76 @@ impl Actor for Room {
                 let pad_language = curr_pad.language.to_owned();
                 info!("pad loaded: {}, language = {}", curr_pad.hash, pad_language);
 
+                if Self::room_config().record_terminal {
+                    this.recorder = Some(recording::Recorder::new(curr_pad.id));
+                }
                 this.pad = data.pad;
                 if let Some(content) = data.content {
                     this.code_buffer.set_text(content.code.as_str());
+                    this.input_buffer.set_text(content.stdin.as_str());
+                    match serde_json::from_str(content.dependencies.as_str()) {
+                        Ok(dependencies) => this.dependencies = dependencies,
+                        Err(err) => warn!("invalid pad dependencies: {}, err = {}", room_key, err),
+                    }
+                    this.database_seed = content.database_seed;
                 }
 
                 let run_env = proto::RunEnv {
                     win_size: proto::WinSize { row: 0, col: 0 },
+                    database: this.database_params(&pad_language),
                     language: pad_language,
                     boot: None,
+                    options: proto::RunOptions::default(),
                 };
 
+                this.publish_stats();
+
                 this.room_run_env = Some(run_env.clone());
-                let proxy_addr =
-                    RunnerProxy::new(room_key, run_env, recipient, runner_service_url).start();
+                let proxy_addr = RunnerProxy::new(room_key, run_env, recipient, backend).start();
                 this.runner_proxy_addr = Some(proxy_addr);
 
                 fut::ok(())
             })
             .wait(ctx);
 
-        // start auto save
-        let cfg = Self::room_config();
-        if cfg.auto_save_seconds > 0 {
-            let save_interval = Duration::from_secs(cfg.auto_save_seconds);
-            ctx.run_interval(save_interval, |this: &mut Self, context| {
-                this.save_pad_content(context);
-            });
-        }
+        ctx.run_interval(RECORDING_FLUSH_INTERVAL, |this: &mut Self, context| {
+            this.flush_recording(context);
+        });
     }
 
     fn stopping(&mut self, ctx: &mut Self::Context) -> Running {
         info!("room stopping: {}", self.room_key);
 
-        self.save_pad_content(ctx);
+        // last chance for content not flushed, room is gone when it completes
+        if let Some(req) = self.save_request() {
+            let room_key = self.room_key.clone();
+            Arbiter::spawn(api::api_executor().send(req).then(move |res| {
+                match res {
+                    Ok(Ok(_)) => info!("room content saved on stopping: {}", room_key),
+                    Ok(Err(err)) => {
+                        warn!("save on stopping failure: {}, err = {:?}", room_key, err)
+                    }
+                    Err(err) => warn!("save on stopping failure: {}, err = {:?}", room_key, err),
+                }
+                Ok(())
+            }));
+        }
+        if let Some(req) = self.recording_request() {
+            Arbiter::spawn(api::api_executor().send(req).then(|res| {
+                if !res.map(|r| r.is_ok()).unwrap_or(false) {
+                    warn!("save recording on stopping failure");
+                }
+                Ok(())
+            }));
+        }
         self.destroy_clients(ctx);
+        for _ in self.clients.drain() {
+            metrics::client_left();
+        }
         self.destroy_proxy(ctx);
+        if let Some(stats) = self.stats() {
+            subscription::publish(SubscriptionEvent::RoomClosed(stats));
+        }
 
         let _ = self
             .listener
@@ -164,6 +323,7 @@ impl Actor for Room {
 
     fn stopped(&mut self, ctx: &mut Self::Context) {
         info!("room stopped: {}", self.room_key);
+        metrics::room_stopped();
     }
 }
 
@@ -196,25 +356,256 @@ impl Room {
         }
     }
 
-    fn save_pad_content(&mut self, ctx: &mut <Self as Actor>::Context) {
+    fn is_dirty(&self) -> bool {
+        self.version != self.saved_version
+    }
+
+    /// request saving current buffer, none if there is nothing to save
+    fn save_request(&self) -> Option<api::ReqSavePadContent> {
+        if !self.is_dirty() {
+            return None;
+        }
         let pad: &db::Pad = match self.pad.as_ref() {
             None => {
                 info!("pad not found, skip saving content: {}", self.room_key);
-                return;
+                return None;
             }
             Some(val) => val,
         };
-
-        let code = self.code_buffer.text();
-        let req = api::ReqSavePadContent {
+        Some(api::ReqSavePadContent {
             content: db::NewPadContent {
                 pad_id: pad.id,
-                code: code,
+                code: self.code_buffer.text(),
+                stdin: self.input_buffer.text(),
+                dependencies: serde_json::to_string(&self.dependencies)
+                    .unwrap_or_else(|_| "[]".to_owned()),
+                database_seed: self.database_seed.clone(),
             },
+        })
+    }
+
+    fn recording_request(&mut self) -> Option<api::ReqSavePadRecording> {
+        let events = self.recorder.as_mut()?.take();
+        if events.is_empty() {
+            return None;
+        }
+        Some(api::ReqSavePadRecording { events })
+    }
+
+    fn flush_recording(&mut self, ctx: &mut <Self as Actor>::Context) {
+        let req = match self.recording_request() {
+            None => return,
+            Some(req) => req,
         };
+        // events are put back on failure
+        let events = req.events.clone();
+        api::api_executor()
+            .send(req)
+            .into_actor(self)
+            .then(move |res, act, context| {
+                if !res.map(|r| r.is_ok()).unwrap_or(false) {
+                    warn!("save recording failure: {}", act.room_key);
+                    if let Some(recorder) = act.recorder.as_mut() {
+                        recorder.restore(events, RECORDING_MAX_PENDING);
+                    }
+                }
+                fut::ok(())
+            })
+            .spawn(ctx);
+    }
 
-        let api_executor = api::api_executor();
-        api_executor.do_send(req);
+    fn mark_dirty(&mut self, ctx: &mut <Self as Actor>::Context) {
+        self.version += 1;
+        if self.dirty_since.is_none() {
+            self.dirty_since = Some(Instant::now());
+        }
+        self.schedule_save(ctx);
+    }
+
+    // save after edits are quiet, but no later than max staleness of first unsaved edit
+    fn schedule_save(&mut self, ctx: &mut <Self as Actor>::Context) {
+        let cfg = Self::room_config();
+        let quiet = Duration::from_millis(cfg.save_quiet_ms);
+        let max_stale = Duration::from_millis(cfg.save_max_stale_ms);
+        let stale = self.dirty_since.map(|t| t.elapsed()).unwrap_or_default();
+        let delay = cmp::min(quiet, max_stale.checked_sub(stale).unwrap_or_default());
+        self.schedule_save_after(ctx, delay);
+    }
+
+    fn schedule_save_after(&mut self, ctx: &mut <Self as Actor>::Context, delay: Duration) {
+        if let Some(save_handle) = self.save_handle.take() {
+            ctx.cancel_future(save_handle);
+        }
+        let save_handle = ctx.run_later(delay, |this: &mut Self, context| {
+            this.save_handle = None;
+            this.save_pad_content(context);
+        });
+        self.save_handle = Some(save_handle);
+    }
+
+    fn save_pad_content(&mut self, ctx: &mut <Self as Actor>::Context) {
+        // edits during an in-flight save are scheduled again when it completes
+        if self.saving_version.is_some() || self.closing {
+            return;
+        }
+        let req = match self.save_request() {
+            None => return,
+            Some(req) => req,
+        };
+
+        let version = self.version;
+        self.saving_version = Some(version);
+        self.broadcast_save_state(SaveState::Saving, version, None);
+
+        api::api_executor()
+            .send(req)
+            .into_actor(self)
+            .then(move |res, act, context| {
+                act.saving_version = None;
+                let res: Fallible<()> = match res {
+                    Err(err) => Err(err.into()),
+                    Ok(Err(err)) => Err(format_err!("{}", err)),
+                    Ok(Ok(_)) => Ok(()),
+                };
+                match res {
+                    Ok(_) => act.on_pad_content_saved(context, version),
+                    Err(err) => act.on_pad_content_save_failure(context, version, err),
+                }
+                fut::ok(())
+            })
+            .spawn(ctx);
+    }
+
+    fn on_pad_content_saved(&mut self, ctx: &mut <Self as Actor>::Context, version: u64) {
+        debug!(
+            "room content saved: {}, version = {}",
+            self.room_key, version
+        );
+        self.saved_version = version;
+        self.saved_at = Some(chrono::Utc::now().timestamp_millis());
+        self.save_failures = 0;
+        self.broadcast_save_state(SaveState::Saved, version, None);
+
+        if self.closing {
+            self.flush_and_stop(ctx);
+        } else if self.is_dirty() {
+            // edits arrived while saving
+            self.dirty_since = Some(Instant::now());
+            self.schedule_save(ctx);
+        } else {
+            self.dirty_since = None;
+        }
+    }
+
+    fn on_pad_content_save_failure(
+        &mut self,
+        ctx: &mut <Self as Actor>::Context,
+        version: u64,
+        err: failure::Error,
+    ) {
+        self.save_failures += 1;
+        warn!(
+            "room content save failure: {}, version = {}, failures = {}, err = {:?}",
+            self.room_key, version, self.save_failures, err
+        );
+        let max_retries = Self::room_config().save_max_retries;
+        let error = if self.save_failures > max_retries {
+            // retried on next edit or when room closes
+            "save failure, changes are not saved yet".to_owned()
+        } else {
+            "save failure, retrying".to_owned()
+        };
+        self.broadcast_save_state(SaveState::Failed, version, Some(error));
+
+        if self.closing {
+            self.flush_and_stop(ctx);
+        } else if self.save_failures <= max_retries {
+            let retry_delay_ms =
+                Self::room_config().save_retry_delay_ms * self.save_failures as u64;
+            self.schedule_save_after(ctx, Duration::from_millis(retry_delay_ms));
+        }
+    }
+
+    /// Save pending content, then stop room
+    fn flush_and_stop(&mut self, ctx: &mut <Self as Actor>::Context) {
+        self.closing = true;
+        if let Some(save_handle) = self.save_handle.take() {
+            ctx.cancel_future(save_handle);
+        }
+        // continued when in-flight save completes
+        if self.saving_version.is_some() {
+            return;
+        }
+        let req = match self.save_request() {
+            None => {
+                ctx.stop();
+                return;
+            }
+            Some(req) => req,
+        };
+
+        let version = self.version;
+        api::api_executor()
+            .send(req)
+            .into_actor(self)
+            .then(move |res, act, context| {
+                match res {
+                    Ok(Ok(_)) => {
+                        info!("room content flushed: {}", act.room_key);
+                        act.saved_version = version;
+                    }
+                    _ => warn!("room content flush failure: {}", act.room_key),
+                }
+                context.stop();
+                fut::ok(())
+            })
+            .wait(ctx);
+    }
+
+    fn save_state(&self, state: SaveState, version: u64, error: Option<String>) -> ClientResponses {
+        ClientResponses::Command(CommandResponseParams::SaveState(SaveStateParams {
+            state,
+            version,
+            saved_at: self.saved_at,
+            error,
+        }))
+    }
+
+    fn broadcast_save_state(&self, state: SaveState, version: u64, error: Option<String>) {
+        let msg = self.save_state(state, version, error);
+        self.broadcast_all(ClientEvents::Packet(msg));
+    }
+
+    fn stats(&self) -> Option<RoomStats> {
+        self.pad.as_ref().map(|pad| RoomStats {
+            room_key: self.room_key.clone(),
+            user_id: pad.user_id,
+            participants: self.clients.len() as u32,
+            running: self.running,
+        })
+    }
+
+    fn publish_stats(&self) {
+        if let Some(stats) = self.stats() {
+            subscription::publish(SubscriptionEvent::RoomChanged(stats));
+        }
+    }
+
+    fn set_running(&mut self, running: bool) {
+        if !running {
+            self.end_debug();
+        }
+        if self.running != running {
+            self.running = running;
+            self.publish_stats();
+        }
+    }
+
+    fn notify_webhook(&self, event: webhook::WebhookEvent, mut data: serde_json::Value) {
+        if let Some(pad) = self.pad.as_ref() {
+            data["pad"] = webhook::pad_event_data(pad);
+            webhook::notify(pad.user_id, event, data);
+        }
     }
 }
 
@@ -224,12 +615,24 @@ pub struct MsgJoinRoom {
 }
 
 impl Message for MsgJoinRoom {
-    type Result = u32; // client id
+    type Result = u32; // client id, 0 if room is closing
 }
 
 impl Handler<MsgJoinRoom> for Room {
     type Result = MessageResult<MsgJoinRoom>;
     fn handle(&mut self, msg: MsgJoinRoom, ctx: &mut Self::Context) -> Self::Result {
+        // closing room is waiting for an in-flight save, then stops
+        if self.closing {
+            if !self.idle_closing {
+                info!("client rejected, room is closing: {}", self.room_key);
+                return MessageResult(0);
+            }
+            // pending stop is cancelled, the save completes as a regular one
+            info!("client joins idle closing room, resumed: {}", self.room_key);
+            self.closing = false;
+            self.idle_closing = false;
+        }
+
         let client_id = self.get_next_client_id();
         let room_client = RoomClient {
             id: client_id,
@@ -255,9 +658,95 @@ impl Handler<MsgJoinRoom> for Room {
             .recipient
             .do_send(ClientEvents::Packet(msg_code));
 
-        // send cached output to client
-        for line in self.terminal_stdout.iter() {
-            let value = TerminalResponseParams::Stdout(line.to_owned());
+        // send program input to client
+        let curr_input = self.input_buffer.text();
+        let msg_input = ClientResponses::Editor(EditorSyncParams::InputText(curr_input));
+        let _ = room_client
+            .recipient
+            .do_send(ClientEvents::Packet(msg_input));
+
+        // send run options to client
+        if let Some(run_env) = self.room_run_env.as_ref() {
+            if !run_env.options.is_empty() {
+                let options = run_env.options.clone();
+                let msg_options =
+                    ClientResponses::Command(CommandResponseParams::SetRunOptions(options));
+                let _ = room_client
+                    .recipient
+                    .do_send(ClientEvents::Packet(msg_options));
+            }
+        }
+
+        // send dependency manifest to client
+        if !self.dependencies.is_empty() {
+            let dependencies = self.dependencies.clone();
+            let msg_dependencies =
+                ClientResponses::Command(CommandResponseParams::SetDependencies(dependencies));
+            let _ = room_client
+                .recipient
+                .do_send(ClientEvents::Packet(msg_dependencies));
+        }
+
+        // send database seed to client
+        if !self.database_seed.is_empty() {
+            let seed = self.database_seed.clone();
+            let msg_seed = ClientResponses::Command(CommandResponseParams::SetDatabaseSeed(seed));
+            let _ = room_client
+                .recipient
+                .do_send(ClientEvents::Packet(msg_seed));
+        }
+
+        // send artifacts of the latest run to client
+        for artifact in self.artifacts.iter() {
+            let msg_artifact =
+                ClientResponses::Command(CommandResponseParams::Artifact(artifact.clone()));
+            let _ = room_client
+                .recipient
+                .do_send(ClientEvents::Packet(msg_artifact));
+        }
+
+        // send save state to client
+        let msg_save_state = match self.saving_version {
+            Some(version) => self.save_state(SaveState::Saving, version, None),
+            None if self.save_failures > 0 => self.save_state(
+                SaveState::Failed,
+                self.version,
+                Some("save failure, retrying".to_owned()),
+            ),
+            None => self.save_state(SaveState::Saved, self.saved_version, None),
+        };
+        let _ = room_client
+            .recipient
+            .do_send(ClientEvents::Packet(msg_save_state));
+
+        // send diagnostics of language server to client
+        if let Some(diagnostics) = self.lsp.as_ref().and_then(|lsp| lsp.diagnostics()) {
+            let msg_diagnostics =
+                ClientResponses::Command(CommandResponseParams::Lsp(diagnostics.clone()));
+            let _ = room_client
+                .recipient
+                .do_send(ClientEvents::Packet(msg_diagnostics));
+        }
+
+        // send breakpoints and stop location of debug run to client
+        if !self.breakpoints.is_empty() {
+            let breakpoints = self.breakpoints.clone();
+            let msg_breakpoints =
+                ClientResponses::Editor(EditorSyncParams::Breakpoints(breakpoints));
+            let _ = room_client
+                .recipient
+                .do_send(ClientEvents::Packet(msg_breakpoints));
+        }
+        if let Some(line) = self.debug.as_ref().and_then(|debug| debug.stopped) {
+            let msg_stopped = ClientResponses::Editor(EditorSyncParams::DebugStopped(Some(line)));
+            let _ = room_client
+                .recipient
+                .do_send(ClientEvents::Packet(msg_stopped));
+        }
+
+        // send terminal snapshot to client
+        if let Some(snapshot) = self.terminal.snapshot() {
+            let value = TerminalResponseParams::StdoutBytes(snapshot);
             let msg_stdout = ClientResponses::Terminal(value);
             let _ = room_client
                 .recipient
@@ -265,6 +754,8 @@ impl Handler<MsgJoinRoom> for Room {
         }
 
         self.clients.insert(client_id, room_client);
+        metrics::client_joined();
+        self.publish_stats();
 
         // check stop handle
         if let Some(stop_handle) = self.stop_handle.take() {
@@ -300,6 +791,10 @@ impl Handler<MsgLeaveRoom> for Room {
     fn handle(&mut self, msg: MsgLeaveRoom, ctx: &mut Self::Context) -> Self::Result {
         info!("client({}) leaves room: {}", msg.client_id, self.room_key);
         let client_option = self.clients.remove(&msg.client_id);
+        if client_option.is_some() {
+            metrics::client_left();
+        }
+        self.publish_stats();
 
         if self.clients.is_empty() {
             // if room is empty, destory room after a few seconds
@@ -309,7 +804,8 @@ impl Handler<MsgLeaveRoom> for Room {
                 Duration::from_millis(delay_ms),
                 |this: &mut Self, context: &mut Self::Context| {
                     info!("stop delay timeout, call stop room now: {}", this.room_key);
-                    context.stop();
+                    this.idle_closing = true;
+                    this.flush_and_stop(context);
                 },
             );
             self.stop_handle = Some(stop_handle);
@@ -348,6 +844,8 @@ impl Handler<MsgRoomClientRequest> for Room {
         info!("room: {}, client msg: {:?}", self.room_key, msg);
         let packet = msg.client_request;
         match packet {
+            // answered by client session
+            ClientRequests::Hello(_) => {}
             ClientRequests::Editor(payload) => {
                 if let Err(err) = self.on_editor(ctx, msg.client_id, payload) {
                     warn!("on_editor err = {:?}", err);
@@ -379,32 +877,86 @@ impl Room {
 
         // sync to code buffer
         // TODO: port teletype algorithms here
+        let mut breakpoints_shifted = false;
         match &mut payload {
             EditorSyncParams::Text(text) => {
                 warn!("not supported text input from client");
             }
             EditorSyncParams::Changed(changed) => {
-                for change in changed.changes.iter() {
-                    let r = &change.range;
-                    let start = TextPosition::new(r.start_line as usize, r.start_column as usize);
-                    let end = TextPosition::new(r.end_line as usize, r.end_column as usize);
-                    let res = self.code_buffer.edit(&start, &end, change.text.as_str());
-                    if let Err(err) = res {
-                        warn!("invalid edit: err = {:?}, change = {:?}", err, change);
-                    }
+                if apply_changes(&mut self.code_buffer, changed) {
+                    self.editor_version = changed.version;
+                    self.mark_dirty(ctx);
+                    self.schedule_lsp_sync(ctx);
+                    breakpoints_shifted =
+                        debugger::shift_breakpoints(&mut self.breakpoints, &changed.changes);
                 }
             }
             EditorSyncParams::Cursor(cursor) => {
                 cursor.peer_id = client_id;
             }
+            EditorSyncParams::InputText(text) => {
+                warn!("not supported input text from client");
+            }
+            EditorSyncParams::InputChanged(changed) => {
+                if apply_changes(&mut self.input_buffer, changed) {
+                    self.mark_dirty(ctx);
+                }
+            }
+            EditorSyncParams::Breakpoints(breakpoints) => {
+                // normalized list to every client, including the sender
+                self.breakpoints = debugger::normalize_breakpoints(breakpoints.clone());
+                self.broadcast_breakpoints();
+                return Ok(());
+            }
+            EditorSyncParams::DebugStopped(_) => {
+                warn!("not supported debug stop from client");
+                return Ok(());
+            }
         };
 
         let msg = ClientResponses::Editor(payload);
         self.broadcast_excludes(ClientEvents::Packet(msg), vec![client_id]);
 
+        // after the edit, editors move their breakpoints with it too
+        if breakpoints_shifted {
+            self.broadcast_breakpoints();
+        }
+
         Ok(())
     }
 
+    fn broadcast_breakpoints(&self) {
+        let breakpoints = self.breakpoints.clone();
+        let msg = ClientResponses::Editor(EditorSyncParams::Breakpoints(breakpoints));
+        self.broadcast_all(ClientEvents::Packet(msg));
+    }
+
+    /// Debug run is gone, clients clear the stop location
+    fn end_debug(&mut self) {
+        if let Some(DebugSession {
+            stopped: Some(_), ..
+        }) = self.debug.take()
+        {
+            let msg = ClientResponses::Editor(EditorSyncParams::DebugStopped(None));
+            self.broadcast_all(ClientEvents::Packet(msg));
+        }
+    }
+
+    fn on_debug_output(&mut self, data: &[u8]) {
+        let debug = match self.debug.as_mut() {
+            None => return,
+            Some(debug) => debug,
+        };
+        match debug.scanner.scan(data) {
+            Some(stopped) if stopped != debug.stopped => {
+                debug.stopped = stopped;
+                let msg = ClientResponses::Editor(EditorSyncParams::DebugStopped(stopped));
+                self.broadcast_all(ClientEvents::Packet(msg));
+            }
+            _ => {}
+        }
+    }
+
     pub fn on_command(
         &mut self,
         ctx: &mut <Self as Actor>::Context,
@@ -427,19 +979,37 @@ impl Room {
                 }
                 Some(run_env) => proto::ServiceRequests::Reset(run_env.clone()),
             },
-            CommandRequestParams::RunCode(content) => match self.room_run_env.as_ref() {
-                None => {
-                    warn!("room run env not found: {}", self.room_key);
-                    return Err(err_msg("run_env not set"));
+            CommandRequestParams::RunCode(content) => {
+                proto::ServiceRequests::Run(self.run_code(content, None)?)
+            }
+            CommandRequestParams::RunSelection(range) => match self.selection_code(&range) {
+                Ok(content) => proto::ServiceRequests::Run(self.run_code(content, None)?),
+                Err(notice) => {
+                    self.send_notice(client_id, notice.to_owned());
+                    return Ok(());
                 }
-                Some(run_env) => proto::ServiceRequests::Run(proto::Code {
-                    id: 1,
-                    language: run_env.language.clone(),
-                    filename: "source".to_owned(),
-                    content: content,
-                }),
             },
+            CommandRequestParams::DebugCode(content) => {
+                let debug = proto::DebugParams {
+                    breakpoints: self.breakpoints.clone(),
+                };
+                let code = self.run_code(content, Some(debug))?;
+                // stop location of previous run is cleared by set_running below
+                self.end_debug();
+                self.debug = Some(DebugSession {
+                    client_id,
+                    scanner: StopScanner::default(),
+                    stopped: None,
+                });
+                proto::ServiceRequests::Run(code)
+            }
             CommandRequestParams::SetLang(lang) => {
+                // packages and seed are specific to language
+                if !self.dependencies.is_empty() || !self.database_seed.is_empty() {
+                    self.dependencies.clear();
+                    self.database_seed.clear();
+                    self.mark_dirty(ctx);
+                }
                 self.update_room_language(lang.as_str());
 
                 // broadcast to clients
@@ -449,6 +1019,113 @@ impl Room {
 
                 proto::ServiceRequests::Reset(self.room_run_env.as_ref().unwrap().clone())
             }
+            CommandRequestParams::SetRunOptions(options) => {
+                let run_env = match self.room_run_env.as_mut() {
+                    None => {
+                        warn!("room run env not found: {}", self.room_key);
+                        return Err(err_msg("run_env not set"));
+                    }
+                    Some(run_env) => {
+                        run_env.options = options.clone();
+                        run_env.clone()
+                    }
+                };
+
+                // broadcast to clients
+                let msg_options =
+                    ClientResponses::Command(CommandResponseParams::SetRunOptions(options));
+                self.broadcast_all(ClientEvents::Packet(msg_options));
+
+                proto::ServiceRequests::Reset(run_env)
+            }
+            CommandRequestParams::SetDependencies(dependencies) => {
+                if dependencies.len() > MAX_DEPENDENCIES {
+                    return Err(err_msg("too many dependencies"));
+                }
+                if dependencies != self.dependencies {
+                    self.dependencies = dependencies.clone();
+                    self.mark_dirty(ctx);
+                }
+
+                // broadcast to clients, resolved by runner on next run
+                let msg_dependencies =
+                    ClientResponses::Command(CommandResponseParams::SetDependencies(dependencies));
+                self.broadcast_all(ClientEvents::Packet(msg_dependencies));
+                return Ok(());
+            }
+            CommandRequestParams::SetDatabaseSeed(seed) => {
+                if seed.len() > MAX_DATABASE_SEED_SIZE {
+                    return Err(err_msg("database seed too large"));
+                }
+                if seed != self.database_seed {
+                    self.database_seed = seed.clone();
+                    self.mark_dirty(ctx);
+                }
+                self.update_database();
+
+                // broadcast to clients, run in database on next reset
+                let msg_seed =
+                    ClientResponses::Command(CommandResponseParams::SetDatabaseSeed(seed));
+                self.broadcast_all(ClientEvents::Packet(msg_seed));
+                return Ok(());
+            }
+            CommandRequestParams::Format() => {
+                let code = self.tool_code()?;
+                self.pending_format = Some(PendingTool {
+                    id: code.id,
+                    client_id,
+                    content: code.content.clone(),
+                    editor_version: self.editor_version,
+                });
+                proto::ServiceRequests::Format(code)
+            }
+            CommandRequestParams::Lint() => {
+                let code = self.tool_code()?;
+                self.pending_lint = Some(PendingTool {
+                    id: code.id,
+                    client_id,
+                    content: code.content.clone(),
+                    editor_version: self.editor_version,
+                });
+                proto::ServiceRequests::Lint(code)
+            }
+            CommandRequestParams::Lsp(message) => {
+                self.on_lsp_request(ctx, client_id, message);
+                return Ok(());
+            }
+            CommandRequestParams::ListRunFiles() => {
+                self.tool_seq += 1;
+                let id = self.tool_seq;
+                self.pending_files.insert(id, PendingFiles::List(client_id));
+                self.expire_pending_files(ctx, id);
+                proto::ServiceRequests::ListFiles(proto::ListFiles { id })
+            }
+        };
+
+        let reset = match msg {
+            proto::ServiceRequests::Run(ref code) => {
+                // runner stops the previous run, debug or not
+                if code.debug.is_none() {
+                    self.end_debug();
+                }
+                self.set_running(true);
+                self.clear_artifacts();
+                // links of previous files are not valid for files of this run
+                self.files_secret = new_files_secret();
+                false
+            }
+            // tools do not stop running code
+            proto::ServiceRequests::Format(_)
+            | proto::ServiceRequests::Lint(_)
+            | proto::ServiceRequests::ListFiles(_) => false,
+            proto::ServiceRequests::Reset(_) => {
+                self.set_running(false);
+                true
+            }
+            _ => {
+                self.set_running(false);
+                false
+            }
         };
 
         runner_proxy_addr
@@ -460,14 +1137,455 @@ impl Room {
             })
             .spawn(ctx);
 
+        // reset stops language server of runner, started again after it
+        if reset {
+            self.start_lsp(ctx);
+        }
+
         Ok(())
     }
 
+    fn run_code(
+        &self,
+        content: String,
+        debug: Option<proto::DebugParams>,
+    ) -> Fallible<proto::Code> {
+        let run_env = match self.room_run_env.as_ref() {
+            None => {
+                warn!("room run env not found: {}", self.room_key);
+                return Err(err_msg("run_env not set"));
+            }
+            Some(run_env) => run_env,
+        };
+        let input = self.input_buffer.text();
+        Ok(proto::Code {
+            id: 1,
+            language: run_env.language.clone(),
+            filename: "source".to_owned(),
+            content: content,
+            // empty input keeps stdin of runner unchanged
+            stdin: if input.is_empty() { None } else { Some(input) },
+            options: run_env.options.clone(),
+            dependencies: self.dependencies.clone(),
+            debug,
+        })
+    }
+
+    /// selected code for languages running snippets, compiled languages need the whole program
+    fn selection_code(&self, range: &TextRange) -> Result<String, &'static str> {
+        let supported = self
+            .room_run_env
+            .as_ref()
+            .and_then(|run_env| app::config().languages.get(&run_env.language))
+            .map(|lang| lang.run_selection)
+            .unwrap_or(false);
+        if !supported {
+            return Err("running a selection is not supported by the language, run the whole code");
+        }
+        let start = TextPosition::new(range.start_line as usize, range.start_column as usize);
+        let end = TextPosition::new(range.end_line as usize, range.end_column as usize);
+        match self.code_buffer.slice(&start, &end) {
+            Ok(ref content) if content.trim().is_empty() => Err("selection is empty"),
+            Ok(content) => Ok(content),
+            Err(err) => {
+                warn!("invalid selection: err = {:?}, range = {:?}", err, range);
+                Err("selection is out of code, try again")
+            }
+        }
+    }
+
+    /// code buffer as input of formatter and linter
+    fn tool_code(&mut self) -> Fallible<proto::Code> {
+        self.tool_seq += 1;
+        let run_env = self
+            .room_run_env
+            .as_ref()
+            .ok_or(err_msg("run_env not set"))?;
+        Ok(proto::Code {
+            id: self.tool_seq,
+            language: run_env.language.clone(),
+            filename: "source".to_owned(),
+            content: self.code_buffer.text(),
+            stdin: None,
+            options: run_env.options.clone(),
+            dependencies: vec![],
+            debug: None,
+        })
+    }
+
+    fn send_notice(&self, client_id: u32, message: String) {
+        if let Some(client) = self.clients.get(&client_id) {
+            let notice = ClientResponses::Command(CommandResponseParams::Notice(message));
+            let _ = client.recipient.do_send(ClientEvents::Packet(notice));
+        }
+    }
+
+    fn on_formatted(
+        &mut self,
+        ctx: &mut <Self as Actor>::Context,
+        resp: Result<proto::FormatResp, proto::ServiceError>,
+    ) {
+        let pending = match take_pending(&mut self.pending_format, &resp, |r| r.id) {
+            None => return,
+            Some(pending) => pending,
+        };
+        let content = match resp {
+            Err(err) => return self.send_notice(pending.client_id, tool_notice("format", err)),
+            Ok(proto::FormatResp {
+                error: Some(error), ..
+            }) => return self.send_notice(pending.client_id, error),
+            Ok(resp) => resp.content,
+        };
+        if self.editor_version != pending.editor_version
+            || self.code_buffer.text() != pending.content
+        {
+            let notice = "code changed while formatting, format again".to_owned();
+            return self.send_notice(pending.client_id, notice);
+        }
+        let (start, end, text) = match self.code_buffer.diff(content.as_str()) {
+            None => return,
+            Some(edit) => edit,
+        };
+        if let Err(err) = self.code_buffer.edit(&start, &end, text.as_str()) {
+            warn!("apply format failure: {}, err = {:?}", self.room_key, err);
+            return;
+        }
+        self.mark_dirty(ctx);
+        self.schedule_lsp_sync(ctx);
+
+        // one edit for every client, including the requester
+        let change = TextChange {
+            range: TextRange {
+                start_line: start.line as i32,
+                start_column: start.column as i32,
+                end_line: end.line as i32,
+                end_column: end.column as i32,
+            },
+            text,
+        };
+        let shifted = debugger::shift_breakpoints(&mut self.breakpoints, &[change.clone()]);
+        // edit of the editor version format was requested for, like edits of clients
+        let msg = ClientResponses::Editor(EditorSyncParams::Changed(EditorChangedEvent {
+            version: pending.editor_version,
+            changes: vec![change],
+        }));
+        self.broadcast_all(ClientEvents::Packet(msg));
+        if shifted {
+            self.broadcast_breakpoints();
+        }
+    }
+
+    fn on_artifact(&mut self, artifact: proto::Artifact) {
+        info!(
+            "room: {}, artifact: {}, mime = {}, size = {}",
+            self.room_key,
+            artifact.name,
+            artifact.mime,
+            artifact.data.len()
+        );
+        let params = ArtifactParams {
+            name: artifact.name,
+            mime: artifact.mime,
+            data: artifact.data,
+        };
+        let kept_size: usize = self.artifacts.iter().map(|a| a.data.len()).sum();
+        if kept_size + params.data.len() <= MAX_ARTIFACTS_SIZE {
+            self.artifacts.push(params.clone());
+        }
+        let msg = ClientResponses::Command(CommandResponseParams::Artifact(params));
+        self.broadcast_all(ClientEvents::Packet(msg));
+    }
+
+    fn clear_artifacts(&mut self) {
+        if self.artifacts.is_empty() {
+            return;
+        }
+        self.artifacts.clear();
+        let msg = ClientResponses::Command(CommandResponseParams::ClearArtifacts());
+        self.broadcast_all(ClientEvents::Packet(msg));
+    }
+
+    // requests unanswered by runner are failed
+    fn expire_pending_files(&mut self, ctx: &mut <Self as Actor>::Context, id: proto::CodeId) {
+        ctx.run_later(RUN_FILES_TIMEOUT, move |act: &mut Self, _| {
+            match act.pending_files.remove(&id) {
+                Some(PendingFiles::List(client_id)) => {
+                    let notice = "listing run files timed out, try again later".to_owned();
+                    act.send_notice(client_id, notice);
+                }
+                // receiver sees the sender dropped
+                Some(PendingFiles::Fetch(_)) | None => {}
+            }
+        });
+    }
+
+    fn on_file_list(&mut self, resp: proto::FileList) {
+        let client_id = match self.pending_files.remove(&resp.id) {
+            Some(PendingFiles::List(client_id)) => client_id,
+            _ => return,
+        };
+        if let Some(err) = resp.error {
+            return self.send_notice(client_id, tool_notice("listing run files", err));
+        }
+        if let Some(client) = self.clients.get(&client_id) {
+            let expires_at = chrono::Utc::now().timestamp_millis()
+                + Self::room_config().run_file_token_ttl_ms as i64;
+            let files = resp
+                .files
+                .into_iter()
+                .map(|file| RunFileLink {
+                    token: sign_run_file(
+                        &self.files_secret,
+                        &self.room_key,
+                        &file.name,
+                        client_id,
+                        expires_at,
+                    ),
+                    name: file.name,
+                    size: file.size,
+                })
+                .collect();
+            let params = RunFilesParams { files };
+            let msg = ClientResponses::Command(CommandResponseParams::RunFiles(params));
+            let _ = client.recipient.do_send(ClientEvents::Packet(msg));
+        }
+    }
+
+    fn on_file_data(&mut self, resp: proto::FileData) {
+        let sender = match self.pending_files.remove(&resp.id) {
+            Some(PendingFiles::Fetch(sender)) => sender,
+            _ => return,
+        };
+        let result = match resp.error {
+            None => Ok(resp.data),
+            Some(err) => Err(RunFileError::from(err)),
+        };
+        let _ = sender.send(result);
+    }
+
+    fn on_linted(&mut self, resp: Result<proto::LintResp, proto::ServiceError>) {
+        let pending = match take_pending(&mut self.pending_lint, &resp, |r| r.id) {
+            None => return,
+            Some(pending) => pending,
+        };
+        match resp {
+            Err(err) => self.send_notice(pending.client_id, tool_notice("lint", err)),
+            Ok(proto::LintResp {
+                error: Some(error), ..
+            }) => self.send_notice(pending.client_id, error),
+            Ok(resp) => {
+                let msg =
+                    ClientResponses::Command(CommandResponseParams::Diagnostics(resp.diagnostics));
+                self.broadcast_all(ClientEvents::Packet(msg));
+            }
+        }
+    }
+
+    fn send_runner(&self, msg: proto::ServiceRequests) {
+        match self.runner_proxy_addr.as_ref() {
+            None => warn!("runner proxy not found: {}", self.room_key),
+            Some(addr) => addr.do_send(msg),
+        }
+    }
+
+    /// Start language server of room language, replacing the running one
+    fn start_lsp(&mut self, ctx: &mut <Self as Actor>::Context) {
+        self.stop_lsp(ctx);
+        if !Self::room_config().language_server || !self.runner_connected {
+            return;
+        }
+        let language = match self.room_run_env.as_ref() {
+            None => return,
+            Some(run_env) => run_env.language.clone(),
+        };
+        self.lsp_seq += 1;
+        self.lsp = Some(LspBridge::new(self.lsp_seq, language.as_str()));
+        self.send_runner(proto::ServiceRequests::LspStart(proto::LspStart {
+            id: self.lsp_seq,
+            language,
+            filename: "source".to_owned(),
+            content: self.code_buffer.text(),
+        }));
+    }
+
+    fn stop_lsp(&mut self, ctx: &mut <Self as Actor>::Context) {
+        if let Some(handle) = self.lsp_sync_handle.take() {
+            ctx.cancel_future(handle);
+        }
+        if let Some(mut bridge) = self.lsp.take() {
+            if self.runner_connected {
+                let id = bridge.id();
+                self.send_runner(proto::ServiceRequests::LspStop(proto::LspStop { id }));
+            }
+            let outputs = bridge.close();
+            self.send_lsp_outputs(bridge.id(), outputs);
+        }
+    }
+
+    fn send_lsp_outputs(&self, id: proto::CodeId, outputs: Vec<LspOutput>) {
+        for output in outputs {
+            match output {
+                LspOutput::Server(message) => {
+                    let msg = proto::LspMessage { id, message };
+                    self.send_runner(proto::ServiceRequests::Lsp(msg));
+                }
+                LspOutput::Reply(client_id, message) => {
+                    if let Some(client) = self.clients.get(&client_id) {
+                        let msg = ClientResponses::Command(CommandResponseParams::Lsp(message));
+                        let _ = client.recipient.do_send(ClientEvents::Packet(msg));
+                    }
+                }
+                LspOutput::Broadcast(message) => {
+                    let msg = ClientResponses::Command(CommandResponseParams::Lsp(message));
+                    self.broadcast_all(ClientEvents::Packet(msg));
+                }
+            }
+        }
+    }
+
+    fn on_lsp_request(
+        &mut self,
+        ctx: &mut <Self as Actor>::Context,
+        client_id: u32,
+        message: serde_json::Value,
+    ) {
+        // positions of request are in the text the client has seen
+        self.flush_lsp_sync(ctx);
+        let (id, result) = match self.lsp.as_mut() {
+            None => (0, Err(lsp::not_available(&message))),
+            Some(bridge) => (
+                bridge.id(),
+                bridge.on_client(client_id, message).map_err(Some),
+            ),
+        };
+        let output = match result {
+            Ok(None) | Err(None) => return,
+            Ok(Some(message)) => LspOutput::Server(message),
+            Err(Some(error)) => LspOutput::Reply(client_id, error),
+        };
+        self.send_lsp_outputs(id, vec![output]);
+    }
+
+    fn on_lsp_started(&mut self, resp: Result<proto::LspStartResp, proto::ServiceError>) {
+        let bridge = match self.lsp.as_mut() {
+            None => return,
+            Some(bridge) => bridge,
+        };
+        match resp {
+            Ok(resp) => {
+                if resp.id != bridge.id() {
+                    return;
+                }
+                let message = bridge.on_started(&resp);
+                let id = bridge.id();
+                self.send_lsp_outputs(id, vec![LspOutput::Server(message)]);
+            }
+            // errors carry no id and answer the latest start
+            Err(err) => {
+                info!(
+                    "language server not started: {}, err = {:?}",
+                    self.room_key, err
+                );
+                self.lsp = None;
+            }
+        }
+    }
+
+    fn on_lsp_message(&mut self, msg: proto::LspMessage) {
+        let outputs = match self.lsp.as_mut() {
+            Some(bridge) if bridge.id() == msg.id => {
+                bridge.on_server(msg.message, &self.code_buffer)
+            }
+            _ => return,
+        };
+        self.send_lsp_outputs(msg.id, outputs);
+    }
+
+    fn on_lsp_stopped(&mut self, ctx: &mut <Self as Actor>::Context, id: proto::CodeId) {
+        if self.lsp.as_ref().map(|bridge| bridge.id()) != Some(id) {
+            return;
+        }
+        info!("language server exited: {}, id = {}", self.room_key, id);
+        if let Some(handle) = self.lsp_sync_handle.take() {
+            ctx.cancel_future(handle);
+        }
+        if let Some(mut bridge) = self.lsp.take() {
+            let outputs = bridge.close();
+            self.send_lsp_outputs(id, outputs);
+        }
+    }
+
+    // full text is sent to language server at most once in a sync period
+    fn schedule_lsp_sync(&mut self, ctx: &mut <Self as Actor>::Context) {
+        if self.lsp.is_none() || self.lsp_sync_handle.is_some() {
+            return;
+        }
+        let delay = Duration::from_millis(Self::room_config().language_server_sync_ms);
+        let handle = ctx.run_later(delay, |this: &mut Self, context| {
+            this.lsp_sync_handle = None;
+            this.sync_lsp();
+        });
+        self.lsp_sync_handle = Some(handle);
+    }
+
+    fn flush_lsp_sync(&mut self, ctx: &mut <Self as Actor>::Context) {
+        if let Some(handle) = self.lsp_sync_handle.take() {
+            ctx.cancel_future(handle);
+            self.sync_lsp();
+        }
+    }
+
+    fn sync_lsp(&mut self) {
+        let (id, message) = match self.lsp.as_mut() {
+            None => return,
+            Some(bridge) => match bridge.did_change(&self.code_buffer) {
+                None => return,
+                Some(message) => (bridge.id(), message),
+            },
+        };
+        self.send_lsp_outputs(id, vec![LspOutput::Server(message)]);
+    }
+
+    // database of room for sql languages, seeded by pad or template seed of language
+    fn database_params(&self, language: &str) -> Option<proto::DatabaseParams> {
+        let lang = app::config()
+            .languages
+            .get(language)
+            .filter(|lang| lang.database)?;
+        let seed = if !self.database_seed.is_empty() || lang.seed.is_empty() {
+            self.database_seed.clone()
+        } else {
+            fs::read_to_string(&lang.seed).unwrap_or_else(|err| {
+                warn!("read database seed failure: {}, err = {}", lang.seed, err);
+                String::new()
+            })
+        };
+        Some(proto::DatabaseParams {
+            name: database_name(&self.room_key),
+            seed,
+        })
+    }
+
+    fn update_database(&mut self) {
+        let language = match self.room_run_env.as_ref() {
+            None => return,
+            Some(run_env) => run_env.language.clone(),
+        };
+        let database = self.database_params(&language);
+        if let Some(run_env) = self.room_run_env.as_mut() {
+            run_env.database = database;
+        }
+    }
+
     fn update_room_language(&mut self, language: &str) {
+        let database = self.database_params(language);
         // save run_env
         if let Some(run_env) = self.room_run_env.as_mut() {
             run_env.language = language.to_owned();
             run_env.boot = None;
+            // options are specific to language
+            run_env.options = proto::RunOptions::default();
+            run_env.database = database;
         }
         // save language to pad
         if let Some(pad) = self.pad.as_mut() {
@@ -498,10 +1616,15 @@ impl Room {
         let runner_proxy_addr = self
             .runner_proxy_addr
             .as_ref()
-            .ok_or(err_msg("runner not ready"))?;
+            .ok_or(err_msg("runner not ready"))?
+            .clone();
 
         let msg = match payload {
             TerminalRequestParams::SetSize(row, col) => {
+                self.terminal.set_size(row, col);
+                if let Some(recorder) = self.recorder.as_mut() {
+                    recorder.resize(row, col);
+                }
                 proto::ServiceRequests::WinSize(proto::WinSize { row, col })
             }
 
@@ -521,42 +1644,349 @@ impl Room {
     }
 }
 
+/// Pending request answered by result, errors of runner carry no id and answer the latest one
+fn take_pending<T, F>(
+    pending: &mut Option<PendingTool>,
+    resp: &Result<T, proto::ServiceError>,
+    id_of: F,
+) -> Option<PendingTool>
+where
+    F: Fn(&T) -> proto::CodeId,
+{
+    match resp {
+        Ok(val) if pending.as_ref().map(|p| p.id) != Some(id_of(val)) => None,
+        _ => pending.take(),
+    }
+}
+
+fn tool_notice(action: &str, err: proto::ServiceError) -> String {
+    match err {
+        proto::ServiceError::ErrNotSupported => format!("{} is not supported by runner", action),
+        _ => format!("{} failure, try again later", action),
+    }
+}
+
+fn new_files_secret() -> String {
+    uuid::Uuid::new_v4().to_string().replace("-", "")
+}
+
+/// Download token of a run file for a client, "<client_id>.<expires_at unix ms>.<hmac>"
+fn sign_run_file(
+    secret: &str,
+    room_key: &str,
+    name: &str,
+    client_id: u32,
+    expires_at: i64,
+) -> String {
+    let payload = format!("{}\n{}\n{}\n{}", room_key, name, client_id, expires_at);
+    let hex = webhook::hmac_sha256_hex(secret, payload.as_bytes());
+    format!("{}.{}.{}", client_id, expires_at, hex)
+}
+
+/// Client id of a valid token of file
+fn verify_run_file(secret: &str, room_key: &str, name: &str, token: &str, now: i64) -> Option<u32> {
+    let mut parts = token.splitn(3, '.');
+    let client_id = parts.next()?.parse::<u32>().ok()?;
+    let expires_at = parts.next()?.parse::<i64>().ok()?;
+    if expires_at < now {
+        return None;
+    }
+    let expected = sign_run_file(secret, room_key, name, client_id, expires_at);
+    if webhook::signature_eq(expected.as_str(), token) {
+        Some(client_id)
+    } else {
+        None
+    }
+}
+
+/// Apply editor changes to buffer, true if any change is applied
+fn apply_changes(buffer: &mut TextBuffer, changed: &EditorChangedEvent) -> bool {
+    let mut edited = false;
+    for change in changed.changes.iter() {
+        let r = &change.range;
+        let start = TextPosition::new(r.start_line as usize, r.start_column as usize);
+        let end = TextPosition::new(r.end_line as usize, r.end_column as usize);
+        match buffer.edit(&start, &end, change.text.as_str()) {
+            Err(err) => warn!("invalid edit: err = {:?}, change = {:?}", err, change),
+            Ok(_) => edited = true,
+        }
+    }
+    edited
+}
+
 impl Handler<MsgRunnerEvent> for Room {
     type Result = MessageResult<MsgRunnerEvent>;
 
     fn handle(&mut self, msg: MsgRunnerEvent, ctx: &mut Self::Context) -> Self::Result {
         match msg {
             MsgRunnerEvent::Stdout(payload) => {
-                self.push_terminal_stdout(&payload);
-                let value = TerminalResponseParams::Stdout(payload);
+                self.on_debug_output(&payload);
+                self.terminal.process(&payload);
+                if let Some(recorder) = self.recorder.as_mut() {
+                    recorder.output(&payload);
+                }
+                let value = TerminalResponseParams::StdoutBytes(payload);
                 let msg = ClientResponses::Terminal(value);
                 self.broadcast_all(ClientEvents::Packet(msg));
             }
+            MsgRunnerEvent::RunFinished(resp) => {
+                info!(
+                    "room: {}, run finished: exit_status = {}, duration = {}ms",
+                    self.room_key, resp.exit_status, resp.duration_ms
+                );
+                let data = serde_json::json!({
+                    "run": {
+                        "exit_status": resp.exit_status,
+                        "duration_ms": resp.duration_ms,
+                    }
+                });
+                self.notify_webhook(webhook::WebhookEvent::RunFinished, data);
+                if let Some(run_env) = self.room_run_env.as_ref() {
+                    metrics::run_finished(run_env.language.as_str(), resp.duration_ms);
+                }
+                self.set_running(false);
+            }
+            MsgRunnerEvent::RunFailed(err) => {
+                if let Some(debug) = self.debug.as_ref() {
+                    self.send_notice(debug.client_id, tool_notice("debugging", err));
+                }
+                self.set_running(false);
+            }
+            MsgRunnerEvent::Artifact(artifact) => self.on_artifact(artifact),
+            MsgRunnerEvent::FileList(resp) => self.on_file_list(resp),
+            MsgRunnerEvent::FileData(resp) => self.on_file_data(resp),
+            MsgRunnerEvent::Formatted(resp) => self.on_formatted(ctx, resp),
+            MsgRunnerEvent::Linted(resp) => self.on_linted(resp),
+            MsgRunnerEvent::LspStarted(resp) => self.on_lsp_started(resp),
+            MsgRunnerEvent::LspMessage(msg) => self.on_lsp_message(msg),
+            MsgRunnerEvent::LspStopped(id) => self.on_lsp_stopped(ctx, id),
+            MsgRunnerEvent::Connection(connected) => {
+                self.runner_connected = connected;
+                // runner resets environment on connect, server of the old one is gone
+                if connected {
+                    self.start_lsp(ctx);
+                } else {
+                    self.stop_lsp(ctx);
+                }
+            }
         }
         MessageResult(())
     }
 }
 
-impl Room {
-    fn push_terminal_stdout(&mut self, payload: &String) {
-        match self.terminal_stdout.back_mut() {
-            None => {
-                self.terminal_stdout.push_back(payload.clone());
-            }
-            Some(back_line) => {
-                let line: &mut String = back_line;
-                if line.len() >= 256 {
-                    self.terminal_stdout.push_back(payload.clone());
-                } else if line.ends_with("\n") {
-                    self.terminal_stdout.push_back(payload.clone());
-                } else {
-                    line.push_str(payload.as_str());
-                }
-            }
+// Run files
+
+/// Failure of a run file download
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RunFileError {
+    Unauthorized,
+    NotFound,
+    TooLarge,
+    NotSupported,
+    Timeout,
+    Internal,
+}
+
+impl From<proto::ServiceError> for RunFileError {
+    fn from(err: proto::ServiceError) -> Self {
+        match err {
+            proto::ServiceError::ErrFileNotFound => RunFileError::NotFound,
+            proto::ServiceError::ErrFileTooLarge => RunFileError::TooLarge,
+            proto::ServiceError::ErrNotSupported => RunFileError::NotSupported,
+            _ => RunFileError::Internal,
         }
-        let cache_lines = Self::room_config().cache_lines;
-        if self.terminal_stdout.len() > cache_lines {
-            self.terminal_stdout.pop_front();
+    }
+}
+
+/// Fetches a file of the latest run from runner, authorized by the token of the file in
+/// `run_files`, only while the client it was signed for is in the room
+pub struct MsgFetchRunFile {
+    pub token: String,
+    pub name: String,
+}
+
+impl Message for MsgFetchRunFile {
+    type Result = Result<Vec<u8>, RunFileError>;
+}
+
+impl Handler<MsgFetchRunFile> for Room {
+    type Result = ResponseFuture<Vec<u8>, RunFileError>;
+
+    fn handle(&mut self, msg: MsgFetchRunFile, ctx: &mut Self::Context) -> Self::Result {
+        let now = chrono::Utc::now().timestamp_millis();
+        match verify_run_file(
+            &self.files_secret,
+            &self.room_key,
+            &msg.name,
+            &msg.token,
+            now,
+        ) {
+            Some(client_id) if self.clients.contains_key(&client_id) => {}
+            _ => return Box::new(future::err(RunFileError::Unauthorized)),
+        }
+        // files are gone with the environment of runner
+        if !self.runner_connected {
+            return Box::new(future::err(RunFileError::NotFound));
+        }
+        self.tool_seq += 1;
+        let id = self.tool_seq;
+        let (sender, receiver) = oneshot::channel();
+        self.pending_files.insert(id, PendingFiles::Fetch(sender));
+        self.expire_pending_files(ctx, id);
+        self.send_runner(proto::ServiceRequests::FetchFile(proto::FetchFile {
+            id,
+            name: msg.name,
+            max_size: Self::room_config().run_file_max_size_mb * 1024 * 1024,
+        }));
+        Box::new(receiver.then(|res| match res {
+            Ok(result) => result,
+            Err(_) => Err(RunFileError::Timeout),
+        }))
+    }
+}
+
+// Admin
+
+#[derive(Debug, Clone)]
+pub struct RoomClientDetail {
+    pub id: u32,
+    pub name: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct RoomDetail {
+    pub room_key: String,
+    pub pad_id: Option<i32>,
+    pub language: Option<String>,
+    pub clients: Vec<RoomClientDetail>,
+    pub runner_connected: bool,
+    pub running: bool,
+    pub uptime: Duration,
+    pub buffer_size: usize, // bytes
+}
+
+pub struct MsgQueryRoomDetail;
+
+impl Message for MsgQueryRoomDetail {
+    type Result = RoomDetail;
+}
+
+impl Handler<MsgQueryRoomDetail> for Room {
+    type Result = MessageResult<MsgQueryRoomDetail>;
+
+    fn handle(&mut self, msg: MsgQueryRoomDetail, ctx: &mut Self::Context) -> Self::Result {
+        let mut clients: Vec<RoomClientDetail> = self
+            .clients
+            .values()
+            .map(|client| RoomClientDetail {
+                id: client.id,
+                name: client.name.clone(),
+            })
+            .collect();
+        clients.sort_by_key(|client| client.id);
+
+        MessageResult(RoomDetail {
+            room_key: self.room_key.clone(),
+            pad_id: self.pad.as_ref().map(|pad| pad.id),
+            language: self.pad.as_ref().map(|pad| pad.language.clone()),
+            clients,
+            runner_connected: self.runner_connected,
+            running: self.running,
+            uptime: self.created_at.elapsed(),
+            buffer_size: self.code_buffer.len_bytes(),
+        })
+    }
+}
+
+#[derive(Message)]
+pub struct MsgCloseRoom;
+
+impl Handler<MsgCloseRoom> for Room {
+    type Result = ();
+
+    fn handle(&mut self, msg: MsgCloseRoom, ctx: &mut Self::Context) -> Self::Result {
+        info!("closing room by admin: {}", self.room_key);
+        self.idle_closing = false;
+        self.flush_and_stop(ctx);
+    }
+}
+
+pub struct MsgKickClient {
+    pub client_id: u32,
+}
+
+impl Message for MsgKickClient {
+    type Result = bool; // client found
+}
+
+impl Handler<MsgKickClient> for Room {
+    type Result = MessageResult<MsgKickClient>;
+
+    fn handle(&mut self, msg: MsgKickClient, ctx: &mut Self::Context) -> Self::Result {
+        match self.clients.get(&msg.client_id) {
+            None => MessageResult(false),
+            Some(client) => {
+                info!("kicking client({}) from room: {}", client.id, self.room_key);
+                // client session leaves room when stopping
+                let _ = client.recipient.do_send(ClientEvents::Destroy);
+                MessageResult(true)
+            }
         }
     }
 }
+
+#[derive(Message)]
+pub struct MsgBroadcastNotice {
+    pub message: String,
+}
+
+impl Handler<MsgBroadcastNotice> for Room {
+    type Result = ();
+
+    fn handle(&mut self, msg: MsgBroadcastNotice, ctx: &mut Self::Context) -> Self::Result {
+        let notice = ClientResponses::Command(CommandResponseParams::Notice(msg.message.clone()));
+        self.broadcast_all(ClientEvents::Packet(notice));
+
+        // also shown in terminal for clients without notice support, not cached
+        let line = format!("\r\n\x1b[33m[notice] {}\x1b[0m\r\n", msg.message);
+        let stdout = ClientResponses::Terminal(TerminalResponseParams::Stdout(line));
+        self.broadcast_all(ClientEvents::Packet(stdout));
+    }
+}
+
+// Drain
+
+const DRAIN_RECONNECT_DELAY_MS: u64 = 1000;
+
+/// Ask clients to reconnect, save pad content and stop room
+#[derive(Message)]
+pub struct MsgDrainRoom;
+
+impl Handler<MsgDrainRoom> for Room {
+    type Result = ();
+
+    fn handle(&mut self, msg: MsgDrainRoom, ctx: &mut Self::Context) -> Self::Result {
+        info!("draining room: {}", self.room_key);
+
+        let reconnect = CommandResponseParams::Reconnect(DRAIN_RECONNECT_DELAY_MS);
+        self.broadcast_all(ClientEvents::Packet(ClientResponses::Command(reconnect)));
+        let line = "\r\n\x1b[33m[notice] server restarting, reconnecting...\x1b[0m\r\n";
+        let stdout = TerminalResponseParams::Stdout(line.to_owned());
+        self.broadcast_all(ClientEvents::Packet(ClientResponses::Terminal(stdout)));
+        // no more edits after clients are closed
+        self.destroy_clients(ctx);
+        self.idle_closing = false;
+        self.flush_and_stop(ctx);
+    }
+}
+
+// unique per room, valid name of database and its user for mysql and postgres
+fn database_name(room_key: &str) -> String {
+    let digest = Sha256::digest(room_key.as_bytes());
+    let hex: String = digest
+        .iter()
+        .take(8)
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("ye_{}", hex)
+}
@@ -1,18 +1,18 @@
 use actix::prelude::*;
 use actix_web::ws;
+use std::cmp;
 use std::time::Duration;
 
 use super::room;
-use ycommon::runner_proto;
+use super::runner_backend::{EventListener, Events, ReqStop};
+use super::runner_pool::RunnerLease;
+use crate::metrics::{self, RunnerAgentEvent};
+use ycommon::{artifact, runner_proto};
 
-#[derive(Debug, Message)]
-pub enum Events {
-    Response(runner_proto::ServiceResponses),
-    Closed,
-    Connected,
-}
-
-pub type EventListener = Recipient<Events>;
+// runners of protocol v3 send hello right after connected
+const HELLO_WAIT: Duration = Duration::from_secs(2);
+// json of a response besides its base64 data
+const MAX_FRAME_OVERHEAD: usize = 64 * 1024;
 
 pub enum AgentState {
     None,
@@ -23,18 +23,148 @@ pub enum AgentState {
 
 pub struct RunnerAgent {
     service_uri: String,
+    // placement of room in runner pool, released when agent is dropped
+    lease: RunnerLease,
     state: AgentState,
     listener: EventListener,
     client_writer: Option<ws::ClientWriter>, // send to upstream yrunner
+    // negotiated with runners of protocol v3, older runners send no hello
+    hello: Option<runner_proto::Hello>,
+    // lsp_start received before hello, capability of runner is not known yet
+    pending_lsp_start: Option<runner_proto::ServiceRequests>,
 }
 
 impl RunnerAgent {
-    pub fn new(service_uri: String, listener: EventListener) -> Self {
+    pub fn new(lease: RunnerLease, listener: EventListener) -> Self {
         Self {
-            service_uri,
+            service_uri: lease.url.clone(),
+            lease,
             listener,
             state: AgentState::None,
             client_writer: None,
+            hello: None,
+            pending_lsp_start: None,
+        }
+    }
+
+    fn send_request(&mut self, msg: &runner_proto::ServiceRequests) {
+        let writer = match self.client_writer.as_mut() {
+            Some(w) => w,
+            None => {
+                info!("runner agent writer not ready");
+                return;
+            }
+        };
+        match serde_json::to_string(msg) {
+            Ok(text) => writer.text(text),
+            Err(err) => info!("runner agent serialize msg failure: {:?}", err),
+        }
+    }
+
+    fn on_hello(
+        &mut self,
+        resp: runner_proto::ServiceResult<runner_proto::Hello>,
+        ctx: &mut <Self as Actor>::Context,
+    ) {
+        match resp.into() {
+            Ok(runner_hello) => {
+                let hello = runner_proto::Hello::current().negotiate(&runner_hello);
+                info!(
+                    "runner hello: {}, runner = {:?}, negotiated = {:?}",
+                    self.service_uri, runner_hello, hello
+                );
+                self.send_request(&runner_proto::ServiceRequests::Hello(hello.clone()));
+                self.hello = Some(hello);
+                self.flush_pending_lsp_start();
+            }
+            Err(err) => {
+                error!(
+                    "runner rejected hello: {}, err = {:?}",
+                    self.service_uri, err
+                );
+                metrics::runner_agent_event(RunnerAgentEvent::ProtocolError);
+                ctx.stop();
+            }
+        }
+    }
+
+    fn flush_pending_lsp_start(&mut self) {
+        if let Some(msg) = self.pending_lsp_start.take() {
+            self.forward_request(msg);
+        }
+    }
+
+    fn has_capability(&self, cap: &str) -> bool {
+        self.hello
+            .as_ref()
+            .map(|hello| hello.has_capability(cap))
+            .unwrap_or(false)
+    }
+
+    /// Send request to runner, or answer it here when runner lacks the capability,
+    /// runners would not reply
+    fn forward_request(&mut self, msg: runner_proto::ServiceRequests) {
+        let code_tools = self.has_capability(runner_proto::CAP_CODE_TOOLS);
+        let lsp = self.has_capability(runner_proto::CAP_LSP);
+        let debug = self.has_capability(runner_proto::CAP_DEBUG);
+        let run_files = self.has_capability(runner_proto::CAP_RUN_FILES);
+        let not_supported = match msg {
+            runner_proto::ServiceRequests::Format(_) if !code_tools => {
+                Some(runner_proto::ServiceResponses::Format(
+                    Err(runner_proto::ServiceError::ErrNotSupported).into(),
+                ))
+            }
+            runner_proto::ServiceRequests::Lint(_) if !code_tools => {
+                Some(runner_proto::ServiceResponses::Lint(
+                    Err(runner_proto::ServiceError::ErrNotSupported).into(),
+                ))
+            }
+            // older runners would run the code without debugger
+            runner_proto::ServiceRequests::Run(ref code) if code.debug.is_some() && !debug => {
+                Some(runner_proto::ServiceResponses::Run(
+                    Err(runner_proto::ServiceError::ErrNotSupported).into(),
+                ))
+            }
+            runner_proto::ServiceRequests::LspStart(_) if !lsp => {
+                Some(runner_proto::ServiceResponses::LspStart(
+                    Err(runner_proto::ServiceError::ErrNotSupported).into(),
+                ))
+            }
+            // errors of files are in payload, matched to requests by id
+            runner_proto::ServiceRequests::ListFiles(ref req) if !run_files => {
+                Some(runner_proto::ServiceResponses::ListFiles(
+                    Ok(runner_proto::FileList {
+                        id: req.id,
+                        files: vec![],
+                        error: Some(runner_proto::ServiceError::ErrNotSupported),
+                    })
+                    .into(),
+                ))
+            }
+            runner_proto::ServiceRequests::FetchFile(ref req) if !run_files => {
+                Some(runner_proto::ServiceResponses::FetchFile(
+                    Ok(runner_proto::FileData {
+                        id: req.id,
+                        name: req.name.clone(),
+                        data: vec![],
+                        error: Some(runner_proto::ServiceError::ErrNotSupported),
+                    })
+                    .into(),
+                ))
+            }
+            // no server was started
+            runner_proto::ServiceRequests::Lsp(_) | runner_proto::ServiceRequests::LspStop(_)
+                if !lsp =>
+            {
+                return;
+            }
+            _ => None,
+        };
+        match not_supported {
+            Some(resp) => {
+                let _ = self.listener.do_send(Events::Response(resp));
+            }
+            None => self.send_request(&msg),
         }
     }
 
@@ -58,6 +188,13 @@ impl RunnerAgent {
     }
 }
 
+// largest response is a fetched run file or an artifact, base64 in json
+fn max_frame_size() -> usize {
+    let run_file_max_size = room::Room::room_config().run_file_max_size_mb as usize * 1024 * 1024;
+    let max_size = cmp::max(run_file_max_size, artifact::MAX_ARTIFACT_SIZE);
+    (max_size + 2) / 3 * 4 + MAX_FRAME_OVERHEAD
+}
+
 impl Actor for RunnerAgent {
     type Context = Context<Self>;
 
@@ -65,6 +202,11 @@ impl Actor for RunnerAgent {
         info!("agent started");
         self.state = AgentState::Connecting;
         ws::Client::new(&self.service_uri)
+            .max_frame_size(max_frame_size())
+            .header(
+                runner_proto::PROTOCOL_VERSION_HEADER,
+                runner_proto::PROTOCOL_VERSION.to_string(),
+            )
             .connect()
             .into_actor(self)
             .then(|res, act: &mut RunnerAgent, context: &mut Self::Context| {
@@ -73,6 +215,7 @@ impl Actor for RunnerAgent {
                     Ok((r, w)) => (r, w),
                     Err(err) => {
                         warn!("runner agent res error = {:?}", err);
+                        act.lease.mark_failed();
                         context.stop();
                         return fut::ok(());
                     }
@@ -113,21 +256,16 @@ impl Handler<runner_proto::ServiceRequests> for RunnerAgent {
         ctx: &mut Self::Context,
     ) -> Self::Result {
         debug!("agent received service request: {:?}", msg);
-        let writer = match self.client_writer.as_mut() {
-            Some(w) => w,
-            None => {
-                info!("runner agent writer not ready");
-                return MessageResult(());
-            }
-        };
-        let data = match serde_json::to_string(&msg) {
-            Ok(text) => text,
-            Err(err) => {
-                info!("runner agent serialize msg failure: {:?}", err);
-                return MessageResult(());
+        match msg {
+            runner_proto::ServiceRequests::LspStart(_) if self.hello.is_none() => {
+                self.pending_lsp_start = Some(msg);
+                // older runners send no hello
+                ctx.run_later(HELLO_WAIT, |act: &mut Self, _| {
+                    act.flush_pending_lsp_start();
+                });
             }
-        };
-        writer.text(data);
+            msg => self.forward_request(msg),
+        }
         MessageResult(())
     }
 }
@@ -140,11 +278,16 @@ impl StreamHandler<ws::Message, ws::ProtocolError> for RunnerAgent {
 
     fn error(&mut self, err: ws::ProtocolError, ctx: &mut Self::Context) -> Running {
         info!("stream error: {}, err = {:?}", self.service_uri, err);
+        self.lease.mark_failed();
         Running::Stop
     }
 
     fn finished(&mut self, ctx: &mut Self::Context) {
         info!("stream finished: {}", self.service_uri);
+        // runner went away, unless the room closed the connection
+        if let AgentState::Connected = self.state {
+            self.lease.mark_failed();
+        }
         ctx.stop();
     }
 
@@ -154,11 +297,17 @@ impl StreamHandler<ws::Message, ws::ProtocolError> for RunnerAgent {
                 let packet_result =
                     serde_json::from_str::<runner_proto::ServiceResponses>(&payload);
                 match packet_result {
+                    Ok(runner_proto::ServiceResponses::Hello(resp)) => self.on_hello(resp, ctx),
                     Ok(packet) => {
                         let _ = self.listener.do_send(Events::Response(packet));
                     }
                     Err(err) => {
-                        error!("invalid response, payload = {}, err = {:?}", payload, err);
+                        // usually a runner speaking a newer protocol
+                        error!(
+                            "invalid response, payload = {}, hello = {:?}, err = {:?}",
+                            payload, self.hello, err
+                        );
+                        metrics::runner_agent_event(RunnerAgentEvent::ProtocolError);
                     }
                 }
             }
@@ -180,14 +329,12 @@ impl StreamHandler<ws::Message, ws::ProtocolError> for RunnerAgent {
     }
 }
 
-#[derive(Message)]
-pub struct ReqStop;
-
 impl Handler<ReqStop> for RunnerAgent {
     type Result = ();
 
     fn handle(&mut self, msg: ReqStop, ctx: &mut Self::Context) -> Self::Result {
         info!("request agent stop");
+        self.state = AgentState::Disconnecting;
         ctx.stop();
     }
 }
@@ -0,0 +1,131 @@
+use actix::prelude::*;
+use failure::{err_msg, Fallible, ResultExt};
+use std::sync::Arc;
+
+use super::local_runner::LocalRunnerBackend;
+use super::runner_agent::RunnerAgent;
+use super::runner_pool::{RunnerHealthChecker, RunnerPool};
+use crate::app;
+use ycommon::runner_proto;
+
+// Backends running code of rooms, selected by `runner.backend` of config:
+//   websocket: external yrunner services, code runs in docker containers,
+//              rooms are placed on runner.endpoints by RunnerPool
+//   local: yscript in a local pty, for single binary setups
+
+pub const BACKEND_WEBSOCKET: &str = "websocket";
+pub const BACKEND_LOCAL: &str = "local";
+
+#[derive(Debug, Message)]
+pub enum Events {
+    Response(runner_proto::ServiceResponses),
+    Closed,
+    Connected,
+}
+
+pub type EventListener = Recipient<Events>;
+
+#[derive(Message)]
+pub struct ReqStop;
+
+/// Connection of a room to backend, closed by ReqStop
+pub struct RunnerConnection {
+    pub requests: Recipient<runner_proto::ServiceRequests>,
+    pub stop: Recipient<ReqStop>,
+    // name of runner endpoint, changes when failing over
+    pub endpoint: String,
+}
+
+impl RunnerConnection {
+    pub fn from_addr<A>(addr: Addr<A>, endpoint: String) -> Self
+    where
+        A: Actor + Handler<runner_proto::ServiceRequests> + Handler<ReqStop>,
+        A::Context: dev::ToEnvelope<A, runner_proto::ServiceRequests> + dev::ToEnvelope<A, ReqStop>,
+    {
+        Self {
+            requests: addr.clone().recipient(),
+            stop: addr.recipient(),
+            endpoint,
+        }
+    }
+}
+
+pub trait RunnerBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Connect room to backend, responses and connection events are sent to listener
+    fn connect(&self, room_key: &str, listener: EventListener) -> RunnerConnection;
+
+    /// Readiness of backend, None if there is nothing to probe
+    fn check_ready(&self) -> Option<Fallible<()>> {
+        None
+    }
+}
+
+lazy_static! {
+    static ref BACKEND: Arc<dyn RunnerBackend> = create_backend(app::config());
+}
+
+pub fn runner_backend() -> Arc<dyn RunnerBackend> {
+    BACKEND.clone()
+}
+
+fn create_backend(config: &app::config::Config) -> Arc<dyn RunnerBackend> {
+    match config.runner.backend.as_str() {
+        BACKEND_LOCAL => Arc::new(LocalRunnerBackend::new(config.runner.clone())),
+        BACKEND_WEBSOCKET => Arc::new(create_websocket_backend(config)),
+        other => {
+            warn!("unknown runner backend: {}, using websocket", other);
+            Arc::new(create_websocket_backend(config))
+        }
+    }
+}
+
+// invalid endpoints of config fail the first use of backend, at server start
+fn create_websocket_backend(config: &app::config::Config) -> WebsocketRunnerBackend {
+    WebsocketRunnerBackend::new(config)
+        .context(err_msg("create websocket runner backend failure"))
+        .unwrap()
+}
+
+/// yrunner services over websocket, one connection per room
+pub struct WebsocketRunnerBackend {
+    pool: Arc<RunnerPool>,
+}
+
+impl WebsocketRunnerBackend {
+    /// Starts health checks of endpoints, requires a running system
+    pub fn new(config: &app::config::Config) -> Fallible<Self> {
+        let pool = Arc::new(RunnerPool::new(
+            &config.runner,
+            &config.room.runner_service_url,
+        )?);
+        RunnerHealthChecker::new(pool.clone(), &config.runner).start();
+        Ok(Self { pool })
+    }
+}
+
+impl RunnerBackend for WebsocketRunnerBackend {
+    fn name(&self) -> &'static str {
+        BACKEND_WEBSOCKET
+    }
+
+    fn connect(&self, room_key: &str, listener: EventListener) -> RunnerConnection {
+        let lease = RunnerPool::acquire(&self.pool, room_key);
+        info!(
+            "connecting to agent: {}, endpoint = {}",
+            lease.url, lease.name
+        );
+        let endpoint = lease.name.clone();
+        RunnerConnection::from_addr(RunnerAgent::new(lease, listener).start(), endpoint)
+    }
+
+    // rooms are placed on unhealthy endpoints too, but they are unlikely to connect
+    fn check_ready(&self) -> Option<Fallible<()>> {
+        if self.pool.healthy_endpoints() > 0 {
+            Some(Ok(()))
+        } else {
+            Some(Err(format_err!("no healthy runner endpoints")))
+        }
+    }
+}
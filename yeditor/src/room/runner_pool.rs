@@ -0,0 +1,349 @@
+use actix::prelude::*;
+use actix_web::client;
+use failure::Fallible;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::app::config::{RunnerConfig, RunnerEndpointConfig};
+
+// Runner endpoints of websocket backend. Rooms are placed on healthy endpoints by
+// least load relative to weight, or by weighted rendezvous hashing of room_key,
+// which keeps rooms on the same endpoint while it is healthy.
+// Endpoints are marked unhealthy by failed connections and health checks,
+// and healthy again by health checks.
+
+pub const PLACEMENT_LEAST_LOAD: &str = "least_load";
+pub const PLACEMENT_HASH: &str = "hash";
+
+struct EndpointState {
+    name: String,
+    url_tpl: String,
+    health_url: String,
+    weight: u32,
+    healthy: bool,
+    // leases by room, a room holds two while reconnecting
+    rooms: HashMap<String, usize>,
+}
+
+pub struct RunnerPool {
+    hash_placement: bool,
+    endpoints: Mutex<Vec<EndpointState>>,
+}
+
+impl RunnerPool {
+    /// Endpoints of config, or the default url, pool without endpoints is rejected
+    pub fn new(config: &RunnerConfig, default_url_tpl: &str) -> Fallible<Self> {
+        let endpoints = if config.endpoints.is_empty() {
+            vec![RunnerEndpointConfig {
+                url: default_url_tpl.to_owned(),
+                weight: 1,
+                health_url: "".to_owned(),
+                name: "".to_owned(),
+            }]
+        } else {
+            config.endpoints.clone()
+        };
+        if endpoints.iter().any(|endpoint| endpoint.url.is_empty()) {
+            return Err(format_err!("runner endpoint without url"));
+        }
+        let endpoints = endpoints
+            .into_iter()
+            .map(|endpoint| EndpointState {
+                name: if endpoint.name.is_empty() {
+                    endpoint_host(&endpoint.url)
+                } else {
+                    endpoint.name.clone()
+                },
+                health_url: if endpoint.health_url.is_empty() {
+                    default_health_url(&endpoint.url)
+                } else {
+                    endpoint.health_url.clone()
+                },
+                url_tpl: endpoint.url,
+                weight: endpoint.weight,
+                healthy: true,
+                rooms: HashMap::new(),
+            })
+            .collect();
+        Ok(Self {
+            hash_placement: config.placement == PLACEMENT_HASH,
+            endpoints: Mutex::new(endpoints),
+        })
+    }
+
+    /// Place room on an endpoint, unhealthy endpoints are used only if all are unhealthy
+    pub fn acquire(pool: &Arc<RunnerPool>, room_key: &str) -> RunnerLease {
+        let mut endpoints = pool.endpoints.lock().unwrap();
+        // pool is never empty, see new
+        let index = pool.pick(&endpoints, room_key).unwrap_or(0);
+        let endpoint = &mut endpoints[index];
+        *endpoint.rooms.entry(room_key.to_owned()).or_insert(0) += 1;
+        RunnerLease {
+            pool: pool.clone(),
+            index,
+            room_key: room_key.to_owned(),
+            name: endpoint.name.clone(),
+            url: endpoint.url_tpl.replace("{room_key}", room_key),
+        }
+    }
+
+    fn pick(&self, endpoints: &[EndpointState], room_key: &str) -> Option<usize> {
+        // healthy endpoints, then any weighted endpoint, then any endpoint
+        let tiers: [&dyn Fn(&EndpointState) -> bool; 3] =
+            [&|e| e.weight > 0 && e.healthy, &|e| e.weight > 0, &|_| true];
+        let candidates = tiers
+            .iter()
+            .map(|accept| {
+                endpoints
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, e)| accept(e))
+                    .collect::<Vec<_>>()
+            })
+            .find(|candidates| !candidates.is_empty())?;
+        let weight = |e: &EndpointState| f64::from(e.weight.max(1));
+        if self.hash_placement {
+            candidates
+                .into_iter()
+                .map(|(index, e)| (index, hash_score(&e.url_tpl, room_key, weight(e))))
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(index, _)| index)
+        } else {
+            candidates
+                .into_iter()
+                .map(|(index, e)| (index, (e.rooms.len() + 1) as f64 / weight(e)))
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(index, _)| index)
+        }
+    }
+
+    fn release(&self, index: usize, room_key: &str) {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        let rooms = &mut endpoints[index].rooms;
+        if let Some(leases) = rooms.get_mut(room_key) {
+            *leases -= 1;
+            if *leases == 0 {
+                rooms.remove(room_key);
+            }
+        }
+    }
+
+    fn set_healthy(&self, index: usize, healthy: bool) {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        let endpoint = &mut endpoints[index];
+        if endpoint.healthy != healthy {
+            info!(
+                "runner endpoint {} is {}, rooms = {}",
+                endpoint.name,
+                if healthy { "healthy" } else { "unhealthy" },
+                endpoint.rooms.len()
+            );
+        }
+        endpoint.healthy = healthy;
+    }
+
+    /// Endpoints passing health checks, all until the first check
+    pub fn healthy_endpoints(&self) -> usize {
+        let endpoints = self.endpoints.lock().unwrap();
+        endpoints.iter().filter(|e| e.healthy).count()
+    }
+
+    fn health_urls(&self) -> Vec<(usize, String)> {
+        let endpoints = self.endpoints.lock().unwrap();
+        endpoints
+            .iter()
+            .enumerate()
+            .map(|(index, e)| (index, e.health_url.clone()))
+            .collect()
+    }
+}
+
+/// Room placed on endpoint, released on drop
+pub struct RunnerLease {
+    pool: Arc<RunnerPool>,
+    index: usize,
+    room_key: String,
+    pub name: String,
+    pub url: String,
+}
+
+impl RunnerLease {
+    /// Connection failed or lost, rooms are placed elsewhere until health check passes
+    pub fn mark_failed(&self) {
+        self.pool.set_healthy(self.index, false);
+    }
+}
+
+impl Drop for RunnerLease {
+    fn drop(&mut self) {
+        self.pool.release(self.index, &self.room_key);
+    }
+}
+
+fn endpoint_host(url: &str) -> String {
+    match url::Url::parse(url) {
+        Ok(url) => match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_owned(),
+            _ => url.to_string(),
+        },
+        Err(_) => url.to_owned(),
+    }
+}
+
+fn default_health_url(url: &str) -> String {
+    match url::Url::parse(url) {
+        Ok(mut url) => {
+            url.set_path("/health");
+            url.set_query(None);
+            url.to_string()
+        }
+        Err(_) => "".to_owned(),
+    }
+}
+
+// weighted rendezvous hashing, higher is preferred
+fn hash_score(url_tpl: &str, room_key: &str, weight: f64) -> f64 {
+    let digest = Sha256::new()
+        .chain(url_tpl.as_bytes())
+        .chain(b"|")
+        .chain(room_key.as_bytes())
+        .result();
+    let mut value = 0u64;
+    for b in digest.iter().take(8) {
+        value = (value << 8) | u64::from(*b);
+    }
+    // uniform in (0, 1), 53 bits fit in f64 exactly
+    let unit = ((value >> 11) as f64 + 0.5) / (1u64 << 53) as f64;
+    weight / -unit.ln()
+}
+
+/// Polls health urls of endpoints, any http response means the runner is up
+pub struct RunnerHealthChecker {
+    pool: Arc<RunnerPool>,
+    interval: Duration,
+    timeout: Duration,
+}
+
+impl RunnerHealthChecker {
+    pub fn new(pool: Arc<RunnerPool>, config: &RunnerConfig) -> Self {
+        Self {
+            pool,
+            interval: Duration::from_millis(config.health_check_interval_ms),
+            timeout: Duration::from_millis(config.health_check_timeout_ms),
+        }
+    }
+
+    fn check_all(&mut self, ctx: &mut <Self as Actor>::Context) {
+        for (index, health_url) in self.pool.health_urls() {
+            if health_url.is_empty() {
+                continue;
+            }
+            let request = match client::get(health_url.as_str())
+                .timeout(self.timeout)
+                .finish()
+            {
+                Ok(request) => request,
+                Err(err) => {
+                    warn!("invalid runner health url: {}, err = {}", health_url, err);
+                    continue;
+                }
+            };
+            request
+                .send()
+                .into_actor(self)
+                .then(move |res, act, _| {
+                    let healthy = match res {
+                        Ok(resp) => !resp.status().is_server_error(),
+                        Err(err) => {
+                            debug!("runner health check failure: {}, err = {}", health_url, err);
+                            false
+                        }
+                    };
+                    act.pool.set_healthy(index, healthy);
+                    fut::ok(())
+                })
+                .spawn(ctx);
+        }
+    }
+}
+
+impl Actor for RunnerHealthChecker {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.check_all(ctx);
+        ctx.run_interval(self.interval, |act, context| act.check_all(context));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pool(placement: &str, weights: &[u32]) -> Arc<RunnerPool> {
+        let mut config = RunnerConfig::default();
+        config.placement = placement.to_owned();
+        config.endpoints = weights
+            .iter()
+            .enumerate()
+            .map(|(i, weight)| RunnerEndpointConfig {
+                url: format!("http://127.0.0.1:{}/ws/{{room_key}}", 4500 + i),
+                weight: *weight,
+                health_url: "".to_owned(),
+                name: "".to_owned(),
+            })
+            .collect();
+        Arc::new(RunnerPool::new(&config, "").unwrap())
+    }
+
+    #[test]
+    fn runner_pool_least_load_by_weight() {
+        let pool = pool(PLACEMENT_LEAST_LOAD, &[2, 1]);
+        let leases: Vec<RunnerLease> = (0..6)
+            .map(|i| RunnerPool::acquire(&pool, &format!("room{}", i)))
+            .collect();
+        let on_first = leases.iter().filter(|l| l.index == 0).count();
+        assert_eq!(4, on_first);
+        assert_eq!("127.0.0.1:4500", leases[0].name);
+        assert_eq!("http://127.0.0.1:4500/ws/room0", leases[0].url);
+
+        drop(leases);
+        let endpoints = pool.endpoints.lock().unwrap();
+        assert!(endpoints.iter().all(|e| e.rooms.is_empty()));
+    }
+
+    #[test]
+    fn runner_pool_without_endpoints_rejected() {
+        assert!(RunnerPool::new(&RunnerConfig::default(), "").is_err());
+    }
+
+    #[test]
+    fn runner_pool_room_with_two_leases() {
+        let pool = pool(PLACEMENT_HASH, &[1]);
+        let old = RunnerPool::acquire(&pool, "abc");
+        let new = RunnerPool::acquire(&pool, "abc");
+        drop(old);
+        assert_eq!(1, pool.endpoints.lock().unwrap()[new.index].rooms.len());
+        drop(new);
+        assert!(pool.endpoints.lock().unwrap()[0].rooms.is_empty());
+    }
+
+    #[test]
+    fn runner_pool_hash_fails_over() {
+        let pool = pool(PLACEMENT_HASH, &[1, 1, 1]);
+        let first = RunnerPool::acquire(&pool, "abc");
+        let again = RunnerPool::acquire(&pool, "abc");
+        assert_eq!(first.index, again.index);
+
+        first.mark_failed();
+        let moved = RunnerPool::acquire(&pool, "abc");
+        assert_ne!(first.index, moved.index);
+
+        assert_eq!(2, pool.healthy_endpoints());
+        pool.set_healthy(first.index, true);
+        assert_eq!(first.index, RunnerPool::acquire(&pool, "abc").index);
+    }
+}
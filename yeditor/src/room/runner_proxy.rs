@@ -1,13 +1,31 @@
 use actix::prelude::*;
 use std::ops::Add;
+use std::sync::Arc;
 use std::time::Duration;
 
-use super::runner_agent as agent;
+use super::runner_backend::{self as backend, RunnerBackend, RunnerConnection};
+use crate::metrics::{self, RunnerAgentEvent};
 use ycommon::runner_proto as proto;
 
 #[derive(Message)]
 pub enum MsgRunnerEvent {
-    Stdout(String),
+    Stdout(Vec<u8>),
+    RunFinished(proto::RunResp),
+    // run not started, e.g. debug runs on runners without debugger
+    RunFailed(proto::ServiceError),
+    // result of format and lint requests, errors when runner has no code tools
+    Formatted(Result<proto::FormatResp, proto::ServiceError>),
+    Linted(Result<proto::LintResp, proto::ServiceError>),
+    // language server of room, errors when runner has none for the language
+    LspStarted(Result<proto::LspStartResp, proto::ServiceError>),
+    LspMessage(proto::LspMessage),
+    LspStopped(proto::CodeId),
+    // rich output of run
+    Artifact(proto::Artifact),
+    // files of the latest run, errors are in payload
+    FileList(proto::FileList),
+    FileData(proto::FileData),
+    Connection(bool), // runner agent connected or not
 }
 
 pub type RunnerEventRecipient = Recipient<MsgRunnerEvent>;
@@ -16,8 +34,11 @@ pub struct RunnerProxy {
     room_key: String,
     reset_params: proto::RunEnv,
     recipient: RunnerEventRecipient,
-    service_uri_tpl: String,
-    agent: Option<Addr<agent::RunnerAgent>>,
+    backend: Arc<dyn RunnerBackend>,
+    agent: Option<RunnerConnection>,
+    agent_connected: bool,
+    // endpoint of last connected agent, to notice failover
+    last_endpoint: Option<String>,
     reconnect_delay: Duration,
 }
 
@@ -26,28 +47,26 @@ impl RunnerProxy {
         room_key: String,
         reset_params: proto::RunEnv,
         recipient: RunnerEventRecipient,
-        runner_service_url: String,
+        backend: Arc<dyn RunnerBackend>,
     ) -> RunnerProxy {
         RunnerProxy {
             room_key,
             reset_params,
             recipient,
-            service_uri_tpl: runner_service_url,
+            backend,
             agent: None,
+            agent_connected: false,
+            last_endpoint: None,
             reconnect_delay: Duration::from_millis(0),
         }
     }
 
-    fn service_full_uri(&self) -> String {
-        self.service_uri_tpl
-            .replace("{room_key}", self.room_key.as_str())
-    }
-
     fn agent_reconnect(&mut self, ctx: &mut <Self as Actor>::Context) {
         if self.agent.is_some() {
             warn!("agent is running, do not need to reconnect");
             return;
         }
+        metrics::runner_agent_event(RunnerAgentEvent::Reconnect);
         let prefix = if self.reconnect_delay.as_millis() == 0 {
             "\r\n"
         } else {
@@ -67,10 +86,9 @@ impl RunnerProxy {
         );
         info!("{}", waiting_str);
 
-        let _ = self.recipient.do_send(MsgRunnerEvent::Stdout(format!(
-            "{}{}\r\n",
-            prefix, waiting_str
-        )));
+        let _ = self.recipient.do_send(MsgRunnerEvent::Stdout(
+            format!("{}{}\r\n", prefix, waiting_str).into_bytes(),
+        ));
 
         ctx.run_later(self.reconnect_delay.clone(), |this: &mut Self, context| {
             this.agent_connect(context);
@@ -78,12 +96,17 @@ impl RunnerProxy {
     }
 
     fn agent_connect(&mut self, ctx: &mut <Self as Actor>::Context) {
-        let service_full_uri = self.service_full_uri();
-        info!("connecting to agent: {}", service_full_uri);
+        info!(
+            "connecting to runner: {}, backend = {}",
+            self.room_key,
+            self.backend.name()
+        );
 
         let listener = ctx.address().recipient();
-        let agent = agent::RunnerAgent::new(service_full_uri, listener).start();
+        let agent = self.backend.connect(&self.room_key, listener);
         self.agent = Some(agent);
+        self.agent_connected = false;
+        metrics::runner_agent_event(RunnerAgentEvent::Connect);
 
         // launch
         let run_env = self.reset_params.clone();
@@ -93,7 +116,7 @@ impl RunnerProxy {
     fn agent_stop(&mut self, ctx: &mut <Self as Actor>::Context) {
         if let Some(agent) = self.agent.as_ref() {
             info!("sending stop signal to runner_agent");
-            agent.do_send(agent::ReqStop);
+            let _ = agent.stop.do_send(backend::ReqStop);
         }
     }
 }
@@ -121,6 +144,8 @@ impl Handler<proto::ServiceRequests> for RunnerProxy {
             proto::ServiceRequests::Reset(params) => {
                 self.reset_params.language = params.language.clone();
                 self.reset_params.boot = params.boot.clone();
+                self.reset_params.options = params.options.clone();
+                self.reset_params.database = params.database.clone();
                 if params.win_size.col > 0 && params.win_size.row > 0 {
                     self.reset_params.win_size = params.win_size.clone();
                 } else {
@@ -141,46 +166,147 @@ impl Handler<proto::ServiceRequests> for RunnerProxy {
 }
 
 impl RunnerProxy {
+    // environment is reset on every connect, tell users when state was lost on another runner
+    fn on_agent_endpoint(&mut self) {
+        let endpoint = match self.agent.as_ref() {
+            Some(agent) => agent.endpoint.clone(),
+            None => return,
+        };
+        if let Some(last_endpoint) = self.last_endpoint.as_ref() {
+            if *last_endpoint != endpoint {
+                info!(
+                    "runner failover: {}, from = {}, to = {}",
+                    self.room_key, last_endpoint, endpoint
+                );
+                metrics::runner_agent_event(RunnerAgentEvent::Failover);
+                let notice = format!(
+                    "\r\n[ runner {} unavailable, moved to {}, environment reset ]\r\n",
+                    last_endpoint, endpoint
+                );
+                let _ = self
+                    .recipient
+                    .do_send(MsgRunnerEvent::Stdout(notice.into_bytes()));
+            }
+        }
+        self.last_endpoint = Some(endpoint);
+    }
+
     fn send_agent_message(&mut self, msg: proto::ServiceRequests) {
         match self.agent.as_ref() {
             None => {
                 warn!("agent not found: {}, msg = {:?}", self.room_key, msg);
             }
             Some(agent) => {
-                agent.do_send(msg);
+                let _ = agent.requests.do_send(msg);
             }
         }
     }
 }
 
 // Handle listener events from RunnerAgent
-impl Handler<agent::Events> for RunnerProxy {
-    type Result = MessageResult<agent::Events>;
+impl Handler<backend::Events> for RunnerProxy {
+    type Result = MessageResult<backend::Events>;
 
-    fn handle(&mut self, msg: agent::Events, ctx: &mut Self::Context) -> Self::Result {
+    fn handle(&mut self, msg: backend::Events, ctx: &mut Self::Context) -> Self::Result {
         debug!("events from agent: {:?}", msg);
         match msg {
-            agent::Events::Connected => {
+            backend::Events::Connected => {
                 info!("runner agent connected successfully: {}", self.room_key);
                 self.reconnect_delay = Duration::from_secs(0);
+                self.agent_connected = true;
+                self.on_agent_endpoint();
+                let _ = self.recipient.do_send(MsgRunnerEvent::Connection(true));
             }
-            agent::Events::Closed => {
+            backend::Events::Closed => {
                 // agent closed
                 info!("runner agent closed: {}", self.room_key);
+                if !self.agent_connected {
+                    metrics::runner_agent_event(RunnerAgentEvent::Failure);
+                }
+                self.agent_connected = false;
                 self.agent.take();
+                let _ = self.recipient.do_send(MsgRunnerEvent::Connection(false));
                 self.agent_reconnect(ctx);
             }
-            agent::Events::Response(packet) => match packet {
+            backend::Events::Response(packet) => match packet {
+                // handshake is handled by agent
+                proto::ServiceResponses::Hello(resp) => {}
                 proto::ServiceResponses::Init(resp) => {}
                 proto::ServiceResponses::Reset(resp) => {}
-                proto::ServiceResponses::Run(resp) => {}
+                proto::ServiceResponses::Run(resp) => match resp.into() {
+                    Err(err) => {
+                        info!("runner run failure: {}, err = {:?}", self.room_key, err);
+                        let _ = self.recipient.do_send(MsgRunnerEvent::RunFailed(err));
+                    }
+                    Ok(val) => {
+                        let _ = self.recipient.do_send(MsgRunnerEvent::RunFinished(val));
+                    }
+                },
+                // runner of protocol v1
                 proto::ServiceResponses::Stdout(resp) => match resp.into() {
+                    Err(err) => {}
+                    Ok(val) => {
+                        let data = val.data.into_bytes();
+                        let _ = self.recipient.do_send(MsgRunnerEvent::Stdout(data));
+                    }
+                },
+                proto::ServiceResponses::StdoutRaw(resp) => match resp.into() {
                     Err(err) => {}
                     Ok(val) => {
                         let _ = self.recipient.do_send(MsgRunnerEvent::Stdout(val.data));
                     }
                 },
                 proto::ServiceResponses::WinSize(resp) => {}
+                proto::ServiceResponses::Format(resp) => {
+                    let _ = self
+                        .recipient
+                        .do_send(MsgRunnerEvent::Formatted(resp.into()));
+                }
+                proto::ServiceResponses::Lint(resp) => {
+                    let _ = self.recipient.do_send(MsgRunnerEvent::Linted(resp.into()));
+                }
+                proto::ServiceResponses::LspStart(resp) => {
+                    let _ = self
+                        .recipient
+                        .do_send(MsgRunnerEvent::LspStarted(resp.into()));
+                }
+                proto::ServiceResponses::Lsp(resp) => match resp.into() {
+                    Err(err) => {
+                        info!("runner lsp failure: {}, err = {:?}", self.room_key, err);
+                    }
+                    Ok(val) => {
+                        let _ = self.recipient.do_send(MsgRunnerEvent::LspMessage(val));
+                    }
+                },
+                proto::ServiceResponses::LspStop(resp) => match resp.into() {
+                    Err(err) => {}
+                    Ok(val) => {
+                        let _ = self.recipient.do_send(MsgRunnerEvent::LspStopped(val.id));
+                    }
+                },
+                proto::ServiceResponses::Artifact(resp) => match resp.into() {
+                    Err(err) => {}
+                    Ok(val) => {
+                        let _ = self.recipient.do_send(MsgRunnerEvent::Artifact(val));
+                    }
+                },
+                // requests of errors without id time out in room
+                proto::ServiceResponses::ListFiles(resp) => match resp.into() {
+                    Err(err) => {
+                        info!("runner list files failure: {}, err = {:?}", self.room_key, err);
+                    }
+                    Ok(val) => {
+                        let _ = self.recipient.do_send(MsgRunnerEvent::FileList(val));
+                    }
+                },
+                proto::ServiceResponses::FetchFile(resp) => match resp.into() {
+                    Err(err) => {
+                        info!("runner fetch file failure: {}, err = {:?}", self.room_key, err);
+                    }
+                    Ok(val) => {
+                        let _ = self.recipient.do_send(MsgRunnerEvent::FileData(val));
+                    }
+                },
             },
         }
         MessageResult(())
@@ -0,0 +1,87 @@
+// Server side terminal emulator of a room, late joiners get the same screen
+// as everyone else instead of replayed output chunks
+
+const DEFAULT_ROWS: u16 = 24;
+const DEFAULT_COLS: u16 = 80;
+
+pub struct TerminalState {
+    parser: vt100::Parser,
+    // nothing written yet, joiners keep their own welcome screen
+    empty: bool,
+}
+
+impl TerminalState {
+    pub fn new(scrollback_lines: usize) -> Self {
+        Self {
+            parser: vt100::Parser::new(DEFAULT_ROWS, DEFAULT_COLS, scrollback_lines),
+            empty: true,
+        }
+    }
+
+    pub fn process(&mut self, data: &[u8]) {
+        self.parser.process(data);
+        self.empty = false;
+    }
+
+    pub fn set_size(&mut self, row: u16, col: u16) {
+        if row > 0 && col > 0 {
+            self.parser.set_size(row, col);
+        }
+    }
+
+    /// Output reproducing scrollback and screen in a fresh terminal
+    pub fn snapshot(&mut self) -> Option<Vec<u8>> {
+        if self.empty {
+            return None;
+        }
+        let (rows, cols) = self.parser.screen().size();
+
+        // scrollback offset is clamped to the lines available
+        self.parser.set_scrollback(usize::max_value());
+        let scrollback = self.parser.screen().scrollback();
+
+        // oldest first, the first row of view scrolled back by offset
+        let mut data = vec![];
+        for offset in (1..=scrollback).rev() {
+            self.parser.set_scrollback(offset);
+            if let Some(row) = self.parser.screen().rows_formatted(0, cols).next() {
+                data.extend_from_slice(&row);
+            }
+            data.extend_from_slice(b"\x1b[m\r\n");
+        }
+        self.parser.set_scrollback(0);
+
+        // scroll history out of view, screen is cleared before it is drawn
+        if scrollback > 0 {
+            for _ in 1..rows {
+                data.extend_from_slice(b"\r\n");
+            }
+        }
+        data.extend_from_slice(&self.parser.screen().contents_formatted());
+        Some(data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn terminal_snapshot_replays_screen() {
+        let mut terminal = TerminalState::new(10);
+        assert!(terminal.snapshot().is_none());
+
+        terminal.set_size(2, 20);
+        terminal.process(b"line 1\r\nline 2\r\n\x1b[31mred");
+
+        let mut replayed = vt100::Parser::new(2, 20, 10);
+        replayed.process(&terminal.snapshot().unwrap());
+        assert_eq!(
+            terminal.parser.screen().contents_formatted(),
+            replayed.screen().contents_formatted()
+        );
+
+        replayed.set_scrollback(10);
+        assert_eq!("line 1\nline 2", replayed.screen().contents());
+    }
+}
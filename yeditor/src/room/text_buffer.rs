@@ -1,4 +1,5 @@
 use ropey::Rope;
+use std::cmp;
 use std::ops::Range;
 
 #[derive(Debug)]
@@ -39,6 +40,10 @@ impl TextBuffer {
         s
     }
 
+    pub fn len_bytes(&self) -> usize {
+        self.buffer.len_bytes()
+    }
+
     pub fn set_text(&mut self, text: &str) {
         self.buffer.remove(..);
         self.buffer.insert(0, text);
@@ -60,6 +65,47 @@ impl TextBuffer {
         Ok(())
     }
 
+    /// Text between start and end, positions as in `edit`
+    pub fn slice(&self, start: &TextPosition, end: &TextPosition) -> Result<String, EditError> {
+        let char_range = self.get_char_range(start, end)?;
+        if char_range.start > char_range.end {
+            return Err(EditError::InvalidPosition);
+        }
+        Ok(self.buffer.slice(char_range).to_string())
+    }
+
+    /// Smallest single edit turning buffer into text, none if unchanged.
+    /// Common prefix and suffix are kept, cursors outside of the edit stay in place.
+    pub fn diff(&self, text: &str) -> Option<(TextPosition, TextPosition, String)> {
+        let old: Vec<char> = self.buffer.chars().collect();
+        let new: Vec<char> = text.chars().collect();
+        let prefix = old
+            .iter()
+            .zip(new.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        if prefix == old.len() && prefix == new.len() {
+            return None;
+        }
+        let suffix = old
+            .iter()
+            .rev()
+            .zip(new.iter().rev())
+            .take(cmp::min(old.len(), new.len()) - prefix)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let start = self.get_position(prefix);
+        let end = self.get_position(old.len() - suffix);
+        let replaced = new[prefix..new.len() - suffix].iter().collect();
+        Some((start, end, replaced))
+    }
+
+    fn get_position(&self, char_idx: usize) -> TextPosition {
+        let line_idx = self.buffer.char_to_line(char_idx);
+        let column = char_idx - self.buffer.line_to_char(line_idx);
+        TextPosition::new(line_idx + 1, column + 1)
+    }
+
     fn get_char_index(&self, pos: &TextPosition) -> Result<usize, EditError> {
         let total_lines = self.buffer.len_lines();
         if pos.line < 1 || pos.line > total_lines + 1 {
@@ -96,3 +142,36 @@ impl TextBuffer {
         Ok(char_start..char_end)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn diff_single_edit() {
+        let mut buffer = TextBuffer::new();
+        buffer.set_text("fn main(){\n  let x=1;\n}\n");
+        let formatted = "fn main() {\n    let x = 1;\n}\n";
+
+        let (start, end, text) = buffer.diff(formatted).unwrap();
+        assert_eq!((1, 10), (start.line, start.column));
+        assert_eq!((2, 9), (end.line, end.column));
+        buffer.edit(&start, &end, text.as_str()).unwrap();
+        assert_eq!(formatted, buffer.text());
+        assert!(buffer.diff(formatted).is_none());
+    }
+
+    #[test]
+    fn slice_range() {
+        let mut buffer = TextBuffer::new();
+        buffer.set_text("x = 1\nprint(x)\ny = 2\n");
+
+        let start = TextPosition::new(2, 1);
+        let end = TextPosition::new(3, 1);
+        assert_eq!("print(x)\n", buffer.slice(&start, &end).unwrap());
+        let end = TextPosition::new(4, 1);
+        assert_eq!("print(x)\ny = 2\n", buffer.slice(&start, &end).unwrap());
+        assert!(buffer.slice(&end, &start).is_err());
+        assert!(buffer.slice(&start, &TextPosition::new(2, 11)).is_err());
+    }
+}
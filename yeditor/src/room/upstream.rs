@@ -0,0 +1,177 @@
+use actix::prelude::*;
+use actix_web::{ws, Binary};
+use std::time::Duration;
+
+use super::room;
+use crate::cluster;
+
+// Proxies a client session to the node owning the room, frames are forwarded as is
+
+#[derive(Debug, Message)]
+pub enum UpstreamEvents {
+    Connected,
+    Text(String),
+    Binary(Binary),
+    Closed(bool), // closed by owner node, otherwise connection lost
+}
+
+pub type UpstreamListener = Recipient<UpstreamEvents>;
+
+#[derive(Debug, Message)]
+pub enum UpstreamFrame {
+    Text(String),
+    Binary(Binary),
+}
+
+pub struct RoomUpstream {
+    room_uri: String,
+    listener: UpstreamListener,
+    client_writer: Option<ws::ClientWriter>,
+    // frames received before connected
+    pending: Vec<UpstreamFrame>,
+    closed_by_owner: bool,
+}
+
+impl RoomUpstream {
+    pub fn new(
+        owner: &cluster::NodeInfo,
+        room_key: &str,
+        query: String,
+        listener: UpstreamListener,
+    ) -> Self {
+        let room_uri = format!(
+            "{}/realtime/{}?{}",
+            owner.url.trim_end_matches('/'),
+            room_key,
+            query
+        );
+        Self {
+            room_uri,
+            listener,
+            client_writer: None,
+            pending: vec![],
+            closed_by_owner: false,
+        }
+    }
+
+    fn heart_beat(&mut self, ctx: &mut <Self as Actor>::Context) {
+        let keep_alive_ms = room::Room::room_config().client_keep_alive_ms;
+        ctx.run_later(
+            Duration::from_millis(keep_alive_ms),
+            |act: &mut Self, context| {
+                if let Some(writer) = act.client_writer.as_mut() {
+                    writer.ping("");
+                    act.heart_beat(context);
+                }
+            },
+        );
+    }
+
+    fn write_frame(writer: &mut ws::ClientWriter, frame: UpstreamFrame) {
+        match frame {
+            UpstreamFrame::Text(text) => writer.text(text),
+            UpstreamFrame::Binary(bin) => writer.binary(bin),
+        }
+    }
+}
+
+impl Actor for RoomUpstream {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        info!("room upstream connecting: {}", self.room_uri);
+        ws::Client::new(&self.room_uri)
+            .header(cluster::PROXIED_BY_HEADER, cluster::node().id.as_str())
+            .connect()
+            .into_actor(self)
+            .then(|res, act: &mut Self, context: &mut Self::Context| {
+                let (reader, mut writer) = match res {
+                    Ok((r, w)) => (r, w),
+                    Err(err) => {
+                        warn!("room upstream connect error = {:?}", err);
+                        context.stop();
+                        return fut::ok(());
+                    }
+                };
+                info!("room upstream connected: {}", act.room_uri);
+                Self::add_stream(reader, context);
+                for frame in act.pending.drain(..) {
+                    Self::write_frame(&mut writer, frame);
+                }
+                act.client_writer = Some(writer);
+                act.heart_beat(context);
+                let _ = act.listener.do_send(UpstreamEvents::Connected);
+                fut::ok(())
+            })
+            .wait(ctx);
+    }
+
+    fn stopping(&mut self, ctx: &mut Self::Context) -> Running {
+        info!("room upstream stopping: {}", self.room_uri);
+        if let Some(w) = self.client_writer.take().as_mut() {
+            w.close(None);
+        }
+        let _ = self
+            .listener
+            .do_send(UpstreamEvents::Closed(self.closed_by_owner));
+        Running::Stop
+    }
+}
+
+// client to owner node
+impl Handler<UpstreamFrame> for RoomUpstream {
+    type Result = ();
+
+    fn handle(&mut self, msg: UpstreamFrame, ctx: &mut Self::Context) -> Self::Result {
+        match self.client_writer.as_mut() {
+            None => self.pending.push(msg),
+            Some(writer) => Self::write_frame(writer, msg),
+        }
+    }
+}
+
+// owner node to client
+impl StreamHandler<ws::Message, ws::ProtocolError> for RoomUpstream {
+    fn error(&mut self, err: ws::ProtocolError, ctx: &mut Self::Context) -> Running {
+        info!("room upstream error: {}, err = {:?}", self.room_uri, err);
+        Running::Stop
+    }
+
+    fn finished(&mut self, ctx: &mut Self::Context) {
+        info!("room upstream finished: {}", self.room_uri);
+        ctx.stop();
+    }
+
+    fn handle(&mut self, msg: ws::Message, ctx: &mut Self::Context) {
+        match msg {
+            ws::Message::Text(text) => {
+                let _ = self.listener.do_send(UpstreamEvents::Text(text));
+            }
+            ws::Message::Binary(bin) => {
+                let _ = self.listener.do_send(UpstreamEvents::Binary(bin));
+            }
+            ws::Message::Ping(ping) => {
+                if let Some(writer) = self.client_writer.as_mut() {
+                    writer.pong(&ping);
+                }
+            }
+            ws::Message::Pong(_) => {}
+            ws::Message::Close(reason) => {
+                info!("room upstream closed: reason = {:?}", reason);
+                self.closed_by_owner = true;
+                ctx.stop();
+            }
+        }
+    }
+}
+
+#[derive(Message)]
+pub struct ReqStop;
+
+impl Handler<ReqStop> for RoomUpstream {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReqStop, ctx: &mut Self::Context) -> Self::Result {
+        ctx.stop();
+    }
+}
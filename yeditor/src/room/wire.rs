@@ -0,0 +1,250 @@
+use actix_web::{http, HttpRequest};
+use failure::{Fallible, ResultExt};
+use flate2::{read::GzDecoder, write::GzEncoder};
+use std::io::{Read, Write};
+
+use super::client_proto::*;
+
+// Wire format of room websocket, negotiated by subprotocol or query parameters:
+//   subprotocol `yeditor.json` or `yeditor.msgpack`, or `encoding=json|msgpack`
+//   `compress=gzip` compresses large packets
+//
+// Binary frames start with a flag byte, FLAG_RAW or FLAG_GZIP, followed by the
+// encoded packet. Uncompressed json packets are sent as text frames.
+//
+// Clients negotiating nothing use the legacy format: json text frames,
+// gzipped json in binary frames when larger than GZIP_THRESHOLD.
+
+pub const SUBPROTOCOL_JSON: &str = "yeditor.json";
+pub const SUBPROTOCOL_MSGPACK: &str = "yeditor.msgpack";
+
+const FLAG_RAW: u8 = 0;
+const FLAG_GZIP: u8 = 1;
+
+const GZIP_THRESHOLD: usize = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Json,
+    MsgPack,
+}
+
+impl Encoding {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(Encoding::Json),
+            "msgpack" => Some(Encoding::MsgPack),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Json => "json",
+            Encoding::MsgPack => "msgpack",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum WireFrame {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WireFormat {
+    pub encoding: Encoding,
+    pub gzip: bool,
+    pub legacy: bool,
+    // accepted subprotocol, echoed in handshake
+    pub subprotocol: Option<&'static str>,
+}
+
+impl Default for WireFormat {
+    fn default() -> Self {
+        WireFormat {
+            encoding: Encoding::Json,
+            gzip: true,
+            legacy: true,
+            subprotocol: None,
+        }
+    }
+}
+
+impl WireFormat {
+    pub fn from_request<S>(req: &HttpRequest<S>) -> Self {
+        let protocols = req
+            .headers()
+            .get(http::header::SEC_WEBSOCKET_PROTOCOL)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("");
+        let query = req.query();
+        Self::negotiate(
+            protocols,
+            query.get("encoding").map(|v| v.as_str()),
+            query.get("compress").map(|v| v.as_str()),
+        )
+    }
+
+    fn negotiate(protocols: &str, encoding: Option<&str>, compress: Option<&str>) -> Self {
+        let subprotocol = protocols
+            .split(',')
+            .map(|p| p.trim())
+            .find_map(|p| match p {
+                SUBPROTOCOL_JSON => Some(SUBPROTOCOL_JSON),
+                SUBPROTOCOL_MSGPACK => Some(SUBPROTOCOL_MSGPACK),
+                _ => None,
+            });
+        let encoding = match subprotocol {
+            Some(SUBPROTOCOL_MSGPACK) => Some(Encoding::MsgPack),
+            Some(_) => Some(Encoding::Json),
+            None => encoding.and_then(Encoding::from_name),
+        };
+        match encoding {
+            None if compress.is_none() => WireFormat::default(),
+            _ => WireFormat {
+                encoding: encoding.unwrap_or(Encoding::Json),
+                gzip: compress == Some("gzip"),
+                legacy: false,
+                subprotocol,
+            },
+        }
+    }
+
+    /// Query parameters selecting the same format, used by proxied connections
+    pub fn to_query(&self) -> String {
+        if self.legacy {
+            return "".to_owned();
+        }
+        let compress = if self.gzip { "gzip" } else { "none" };
+        format!("encoding={}&compress={}", self.encoding.as_str(), compress)
+    }
+
+    /// Metrics label of text frames
+    pub fn text_label(&self) -> &'static str {
+        "plain"
+    }
+
+    /// Metrics label of binary frames
+    pub fn binary_label(&self, frame: &[u8]) -> &'static str {
+        match (self.legacy, self.encoding, frame.first()) {
+            (true, _, _) => "gzip",
+            (false, Encoding::MsgPack, Some(&FLAG_GZIP)) => "msgpack+gzip",
+            (false, Encoding::MsgPack, _) => "msgpack",
+            (false, Encoding::Json, Some(&FLAG_GZIP)) => "gzip",
+            (false, Encoding::Json, _) => "plain",
+        }
+    }
+
+    pub fn encode(&self, packet: &ClientResponses) -> Fallible<WireFrame> {
+        if self.legacy {
+            let text = serde_json::to_string(packet)?;
+            if text.len() <= GZIP_THRESHOLD {
+                return Ok(WireFrame::Text(text));
+            }
+            return Ok(WireFrame::Binary(gzip(text.as_bytes())?));
+        }
+
+        let data = match self.encoding {
+            Encoding::Json => serde_json::to_vec(packet)?,
+            Encoding::MsgPack => rmp_serde::to_vec_named(packet)?,
+        };
+        if self.gzip && data.len() > GZIP_THRESHOLD {
+            let mut frame = vec![FLAG_GZIP];
+            frame.append(&mut gzip(&data)?);
+            return Ok(WireFrame::Binary(frame));
+        }
+        match self.encoding {
+            Encoding::Json => Ok(WireFrame::Text(String::from_utf8(data)?)),
+            Encoding::MsgPack => {
+                let mut frame = vec![FLAG_RAW];
+                frame.extend_from_slice(&data);
+                Ok(WireFrame::Binary(frame))
+            }
+        }
+    }
+
+    pub fn decode_text(&self, text: &str) -> Fallible<ClientRequests> {
+        let packet = serde_json::from_str::<ClientRequests>(text).context("client packet error")?;
+        Ok(packet)
+    }
+
+    pub fn decode_binary(&self, frame: &[u8]) -> Fallible<ClientRequests> {
+        if self.legacy {
+            let text = String::from_utf8(gunzip(frame)?)?;
+            return self.decode_text(text.as_str());
+        }
+
+        let (flag, data) = frame
+            .split_first()
+            .ok_or_else(|| format_err!("empty binary frame"))?;
+        let data = match *flag {
+            FLAG_RAW => data.to_vec(),
+            FLAG_GZIP => gunzip(data)?,
+            _ => return Err(format_err!("unknown frame flag: {}", flag)),
+        };
+        let packet = match self.encoding {
+            Encoding::Json => serde_json::from_slice::<ClientRequests>(&data)?,
+            Encoding::MsgPack => rmp_serde::from_slice::<ClientRequests>(&data)?,
+        };
+        Ok(packet)
+    }
+}
+
+fn gzip(data: &[u8]) -> Fallible<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+fn gunzip(data: &[u8]) -> Fallible<Vec<u8>> {
+    let mut decoder = GzDecoder::new(data);
+    let mut decoded = vec![];
+    decoder
+        .read_to_end(&mut decoded)
+        .context("binary decompression failure")?;
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn wire_format_negotiate() {
+        assert_eq!(WireFormat::default(), WireFormat::negotiate("", None, None));
+
+        let format = WireFormat::negotiate("graphql-ws, yeditor.msgpack", None, Some("gzip"));
+        assert_eq!(Encoding::MsgPack, format.encoding);
+        assert_eq!(Some(SUBPROTOCOL_MSGPACK), format.subprotocol);
+        assert!(format.gzip && !format.legacy);
+        assert_eq!("encoding=msgpack&compress=gzip", format.to_query());
+
+        let format = WireFormat::negotiate("", Some("json"), None);
+        assert_eq!(Encoding::Json, format.encoding);
+        assert!(!format.gzip && !format.legacy);
+    }
+
+    #[test]
+    fn wire_format_msgpack_round_trip() {
+        let format = WireFormat::negotiate(SUBPROTOCOL_MSGPACK, None, Some("gzip"));
+        let req = ClientRequests::Terminal(TerminalRequestParams::Stdin("x".repeat(200)));
+        let data = rmp_serde::to_vec_named(&req).unwrap();
+        let mut frame = vec![FLAG_GZIP];
+        frame.append(&mut gzip(&data).unwrap());
+
+        match format.decode_binary(&frame).unwrap() {
+            ClientRequests::Terminal(TerminalRequestParams::Stdin(input)) => {
+                assert_eq!("x".repeat(200), input)
+            }
+            other => panic!("unexpected request: {:?}", other),
+        }
+
+        let resp = ClientResponses::Command(CommandResponseParams::Notice("hi".to_owned()));
+        match format.encode(&resp).unwrap() {
+            WireFrame::Binary(frame) => assert_eq!(FLAG_RAW, frame[0]),
+            other => panic!("unexpected frame: {:?}", other),
+        }
+    }
+}
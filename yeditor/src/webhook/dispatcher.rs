@@ -0,0 +1,310 @@
+use actix::prelude::*;
+use actix_web::client;
+use chrono::Utc;
+use failure::Fallible;
+use std::time::Duration;
+
+use super::event::{self, WebhookEvent};
+use super::signature;
+use crate::{
+    app::{self, api},
+    db::{self, dao::webhooks::DeliveryStatus},
+};
+
+pub const HEADER_EVENT: &'static str = "X-Yeditor-Event";
+pub const HEADER_DELIVERY: &'static str = "X-Yeditor-Delivery";
+pub const HEADER_SIGNATURE: &'static str = "X-Yeditor-Signature";
+
+// deliveries resumed by a scan
+const RESUME_BATCH_SIZE: i64 = 100;
+
+/// Send an event to webhooks subscribed by user, delivery runs in background
+pub fn notify(user_id: i32, event: WebhookEvent, data: serde_json::Value) {
+    if user_id <= 0 {
+        return;
+    }
+    WebhookDispatcher::from_registry().do_send(MsgWebhookEvent {
+        user_id,
+        event,
+        data,
+    });
+}
+
+/// Send the event of a pad status change, if any
+pub fn notify_pad_status(previous_status: &str, pad: &db::Pad) {
+    if let Some(event) = event::pad_status_event(previous_status, pad.status.as_str()) {
+        notify(
+            pad.user_id,
+            event,
+            serde_json::json!({ "pad": event::pad_event_data(pad) }),
+        );
+    }
+}
+
+struct DeliveryJob {
+    delivery_id: i32,
+    webhook_id: i32,
+    url: String,
+    secret: String,
+    event: String,
+    payload: String,
+    attempts: u32,
+}
+
+impl From<api::QueuedWebhookDelivery> for DeliveryJob {
+    fn from(queued: api::QueuedWebhookDelivery) -> Self {
+        DeliveryJob {
+            delivery_id: queued.delivery.id,
+            webhook_id: queued.webhook.id,
+            url: queued.webhook.url,
+            secret: queued.webhook.secret,
+            event: queued.delivery.event,
+            payload: queued.delivery.payload,
+            attempts: queued.delivery.attempts as u32,
+        }
+    }
+}
+
+pub struct WebhookDispatcher {}
+
+impl WebhookDispatcher {
+    pub fn new() -> Self {
+        WebhookDispatcher {}
+    }
+
+    pub fn webhook_config() -> &'static app::config::WebhookConfig {
+        &app::config().webhook
+    }
+
+    fn retry_delay(attempts: u32) -> Duration {
+        let cfg = Self::webhook_config();
+        let exp = attempts.saturating_sub(1).min(16);
+        let delay_ms = cfg.retry_delay_ms.saturating_mul(1 << exp);
+        Duration::from_millis(delay_ms.min(cfg.max_retry_delay_ms))
+    }
+
+    fn build_request(job: &DeliveryJob) -> Fallible<client::ClientRequest> {
+        let cfg = Self::webhook_config();
+        let signature = signature::sign_payload(job.secret.as_str(), job.payload.as_bytes());
+        let request = client::post(job.url.as_str())
+            .header(HEADER_EVENT, job.event.as_str())
+            .header(HEADER_DELIVERY, job.delivery_id.to_string())
+            .header(HEADER_SIGNATURE, signature)
+            .content_type("application/json")
+            .timeout(Duration::from_millis(cfg.timeout_ms))
+            .body(job.payload.clone())
+            .map_err(|err| format_err!("build webhook request failure: {}", err))?;
+        Ok(request)
+    }
+
+    fn deliver(&mut self, mut job: DeliveryJob, ctx: &mut <Self as Actor>::Context) {
+        job.attempts += 1;
+        debug!(
+            "webhook delivery: id = {}, webhook_id = {}, attempt = {}",
+            job.delivery_id, job.webhook_id, job.attempts
+        );
+
+        let request = match Self::build_request(&job) {
+            Err(err) => {
+                // invalid request never succeeds, do not retry
+                job.attempts = Self::webhook_config().max_attempts;
+                self.on_delivery_failed(job, None, format!("{}", err), ctx);
+                return;
+            }
+            Ok(val) => val,
+        };
+
+        request
+            .send()
+            .into_actor(self)
+            .then(move |res, act, context| {
+                match res {
+                    Err(err) => {
+                        act.on_delivery_failed(job, None, format!("{}", err), context);
+                    }
+                    Ok(resp) => {
+                        let status = resp.status();
+                        let code = status.as_u16() as i32;
+                        if status.is_success() {
+                            act.on_delivery_succeeded(job, code);
+                        } else {
+                            let reason = format!("unexpected response status: {}", code);
+                            act.on_delivery_failed(job, Some(code), reason, context);
+                        }
+                    }
+                }
+                fut::ok(())
+            })
+            .spawn(ctx);
+    }
+
+    fn on_delivery_succeeded(&mut self, job: DeliveryJob, response_code: i32) {
+        info!(
+            "webhook delivered: id = {}, url = {}, status = {}",
+            job.delivery_id, job.url, response_code
+        );
+        Self::update_delivery(
+            job.delivery_id,
+            db::WebhookDeliveryChangeset {
+                status: Some(DeliveryStatus::Succeeded.as_str().to_owned()),
+                attempts: Some(job.attempts as i32),
+                response_code: Some(response_code),
+                last_error: None,
+            },
+        );
+    }
+
+    fn on_delivery_failed(
+        &mut self,
+        job: DeliveryJob,
+        response_code: Option<i32>,
+        reason: String,
+        ctx: &mut <Self as Actor>::Context,
+    ) {
+        let max_attempts = Self::webhook_config().max_attempts;
+        let status = if job.attempts < max_attempts {
+            DeliveryStatus::Pending
+        } else {
+            DeliveryStatus::Failed
+        };
+        warn!(
+            "webhook delivery failure: id = {}, url = {}, attempt = {}/{}, err = {}",
+            job.delivery_id, job.url, job.attempts, max_attempts, reason
+        );
+        Self::update_delivery(
+            job.delivery_id,
+            db::WebhookDeliveryChangeset {
+                status: Some(status.as_str().to_owned()),
+                attempts: Some(job.attempts as i32),
+                response_code: response_code,
+                last_error: Some(reason),
+            },
+        );
+
+        if let DeliveryStatus::Pending = status {
+            let delay = Self::retry_delay(job.attempts);
+            info!(
+                "webhook delivery retry: id = {}, waiting for {}ms",
+                job.delivery_id,
+                delay.as_millis()
+            );
+            ctx.run_later(delay, move |this: &mut Self, context| {
+                this.deliver(job, context);
+            });
+        }
+    }
+
+    // pending deliveries are touched by every attempt, retries of a live node are never later
+    // than max_retry_delay_ms plus timeout_ms. Older ones lost their retries by a restart.
+    fn stale_delivery_ms() -> u64 {
+        let cfg = Self::webhook_config();
+        2 * cfg.max_retry_delay_ms + cfg.timeout_ms
+    }
+
+    fn resume_deliveries(&mut self, ctx: &mut <Self as Actor>::Context) {
+        let req = api::ReqClaimStaleWebhookDeliveries {
+            stale_ms: Self::stale_delivery_ms() as i64,
+            limit: RESUME_BATCH_SIZE,
+        };
+        api::api_executor()
+            .send(req)
+            .into_actor(self)
+            .then(|res, act, context| {
+                match res {
+                    Err(err) => warn!("resume webhook deliveries error = {:?}", err),
+                    Ok(Err(err)) => warn!("resume webhook deliveries database err = {:?}", err),
+                    Ok(Ok(claimed)) => {
+                        if !claimed.is_empty() {
+                            info!("webhook deliveries resumed: {}", claimed.len());
+                        }
+                        for item in claimed {
+                            act.deliver(item.into(), context);
+                        }
+                    }
+                }
+                fut::ok(())
+            })
+            .spawn(ctx);
+    }
+
+    fn update_delivery(delivery_id: i32, changeset: db::WebhookDeliveryChangeset) {
+        let req = api::ReqUpdateWebhookDelivery {
+            delivery_id,
+            changeset,
+        };
+        api::api_executor().do_send(req);
+    }
+}
+
+// System Service
+impl Default for WebhookDispatcher {
+    fn default() -> Self {
+        WebhookDispatcher::new()
+    }
+}
+
+impl SystemService for WebhookDispatcher {
+    fn service_started(&mut self, ctx: &mut Context<Self>) {
+        info!("WebhookDispatcher Service started");
+        // retries are timers of this actor, lost by restarts of this or other nodes
+        self.resume_deliveries(ctx);
+        let interval = Duration::from_millis(Self::webhook_config().max_retry_delay_ms);
+        ctx.run_interval(interval, |act, context| act.resume_deliveries(context));
+    }
+}
+
+impl Supervised for WebhookDispatcher {
+    fn restarting(&mut self, ctx: &mut <Self as Actor>::Context) {
+        info!("WebhookDispatcher Service restarting");
+    }
+}
+
+// Actor
+
+impl Actor for WebhookDispatcher {
+    type Context = Context<Self>;
+}
+
+// Messages
+
+#[derive(Debug, Message)]
+pub struct MsgWebhookEvent {
+    pub user_id: i32,
+    pub event: WebhookEvent,
+    pub data: serde_json::Value,
+}
+
+impl Handler<MsgWebhookEvent> for WebhookDispatcher {
+    type Result = ();
+
+    fn handle(&mut self, msg: MsgWebhookEvent, ctx: &mut Self::Context) -> Self::Result {
+        debug!("webhook event: {:?}", msg);
+        let payload = serde_json::json!({
+            "event": msg.event.as_str(),
+            "timestamp": Utc::now().to_rfc3339(),
+            "data": msg.data,
+        });
+        let req = api::ReqQueueWebhookDeliveries {
+            user_id: msg.user_id,
+            event: msg.event.as_str().to_owned(),
+            payload: payload.to_string(),
+        };
+
+        api::api_executor()
+            .send(req)
+            .into_actor(self)
+            .then(|res, act, context| {
+                match res {
+                    Err(err) => warn!("queue webhook deliveries error = {:?}", err),
+                    Ok(Err(err)) => warn!("queue webhook deliveries database err = {:?}", err),
+                    Ok(Ok(queued)) => {
+                        for item in queued {
+                            act.deliver(item.into(), context);
+                        }
+                    }
+                }
+                fut::ok(())
+            })
+            .spawn(ctx);
+    }
+}
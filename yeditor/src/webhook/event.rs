@@ -0,0 +1,90 @@
+use std::str;
+
+use crate::db::{self, dao::pads::PadStatus};
+
+const EVENT_PAD_CREATED: &'static str = "pad_created";
+const EVENT_INTERVIEW_STARTED: &'static str = "interview_started";
+const EVENT_RUN_FINISHED: &'static str = "run_finished";
+const EVENT_PAD_ENDED: &'static str = "pad_ended";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WebhookEvent {
+    PadCreated,
+    InterviewStarted,
+    RunFinished,
+    PadEnded,
+}
+
+impl str::FromStr for WebhookEvent {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            EVENT_PAD_CREATED => Ok(WebhookEvent::PadCreated),
+            EVENT_INTERVIEW_STARTED => Ok(WebhookEvent::InterviewStarted),
+            EVENT_RUN_FINISHED => Ok(WebhookEvent::RunFinished),
+            EVENT_PAD_ENDED => Ok(WebhookEvent::PadEnded),
+            _ => Err("unknown event"),
+        }
+    }
+}
+
+impl WebhookEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::PadCreated => EVENT_PAD_CREATED,
+            WebhookEvent::InterviewStarted => EVENT_INTERVIEW_STARTED,
+            WebhookEvent::RunFinished => EVENT_RUN_FINISHED,
+            WebhookEvent::PadEnded => EVENT_PAD_ENDED,
+        }
+    }
+}
+
+/// Event of a pad status change, interviews start once from unused and end once
+pub fn pad_status_event(previous: &str, current: &str) -> Option<WebhookEvent> {
+    let previous: PadStatus = previous.parse().ok()?;
+    let current: PadStatus = current.parse().ok()?;
+    match (previous, current) {
+        (PadStatus::Unused, PadStatus::Processing) => Some(WebhookEvent::InterviewStarted),
+        (PadStatus::Ended, PadStatus::Ended) => None,
+        (_, PadStatus::Ended) => Some(WebhookEvent::PadEnded),
+        _ => None,
+    }
+}
+
+/// pad fields shared by all event payloads
+pub fn pad_event_data(pad: &db::Pad) -> serde_json::Value {
+    serde_json::json!({
+        "hash": pad.hash,
+        "title": pad.title,
+        "status": pad.status,
+        "creator": pad.creator,
+        "language": pad.language,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pad_status_event_on_changes_only() {
+        assert_eq!(
+            Some(WebhookEvent::InterviewStarted),
+            pad_status_event("unused", "processing")
+        );
+        assert_eq!(
+            Some(WebhookEvent::PadEnded),
+            pad_status_event("processing", "ended")
+        );
+        assert_eq!(
+            Some(WebhookEvent::PadEnded),
+            pad_status_event("unused", "ended")
+        );
+        // rooms closed when idle or drained keep pad status, nothing ends
+        assert_eq!(None, pad_status_event("processing", "processing"));
+        assert_eq!(None, pad_status_event("ended", "ended"));
+        assert_eq!(None, pad_status_event("ended", "processing"));
+        assert_eq!(None, pad_status_event("unused", "unknown"));
+    }
+}
@@ -0,0 +1,8 @@
+mod dispatcher;
+pub use self::dispatcher::*;
+
+mod event;
+pub use self::event::*;
+
+mod signature;
+pub use self::signature::*;
@@ -0,0 +1,53 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const SIGNATURE_PREFIX: &'static str = "sha256=";
+
+/// Sign payload with webhook secret, result is "sha256=<hex digest>"
+pub fn sign_payload(secret: &str, payload: &[u8]) -> String {
+    format!("{}{}", SIGNATURE_PREFIX, hmac_sha256_hex(secret, payload))
+}
+
+/// Hex digest of HMAC-SHA256, shared by signatures of webhooks and download tokens
+pub fn hmac_sha256_hex(key: &str, payload: &[u8]) -> String {
+    // hmac accepts keys of any size, new_varkey never fails here
+    let mut mac = HmacSha256::new_varkey(key.as_bytes()).expect("hmac key");
+    mac.input(payload);
+    mac.result()
+        .code()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Compare signatures in constant time of their length
+pub fn signature_eq(a: &str, b: &str) -> bool {
+    let diff = a
+        .bytes()
+        .zip(b.bytes())
+        .fold(0, |acc, (x, y)| acc | (x ^ y));
+    a.len() == b.len() && diff == 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sign_payload_hmac_sha256() {
+        let signature = sign_payload("key", b"The quick brown fox jumps over the lazy dog");
+        assert_eq!(
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8",
+            signature
+        );
+    }
+
+    #[test]
+    fn signature_eq_of_same_bytes() {
+        assert!(signature_eq("sha256=00ff", "sha256=00ff"));
+        assert!(!signature_eq("sha256=00ff", "sha256=00fe"));
+        assert!(!signature_eq("sha256=00ff", "sha256=00ff00"));
+    }
+}
@@ -2,6 +2,7 @@ import { ChangeDetectionStrategy, ChangeDetectorRef, Component, EventEmitter, In
 import { MatDialog } from '@angular/material';
 import { Language, Languages } from 'app/website/shared/models/languages.model';
 import { LoggerService } from 'app/website/shared/services/common/logger.service';
+import { ISaveStateParams } from 'app/website/shared/services/codepad/codepad.service';
 import { CodepadLanguageDialogComponent, LanguageDialogResult } from '../codepad-language-dialog/codepad-language-dialog.component';
 import { CodepadLangcodeDialogComponent } from '../codepad-langcode-dialog/codepad-langcode-dialog.component';
 
@@ -19,14 +20,34 @@ export class CodepadEditorHeaderComponent implements OnInit {
   ) { }
 
   @Output() runCode = new EventEmitter<any>();
+  @Output() runSelection = new EventEmitter<any>();
+  @Output() debugCode = new EventEmitter<any>();
+  @Output() formatCode = new EventEmitter<any>();
+  @Output() lintCode = new EventEmitter<any>();
   @Output() languageChange = new EventEmitter<Language>();
 
   @Input() language: Language = Languages.typescript;
 
+  saveState: ISaveStateParams = null;
+
   get languageName(): string {
     return this.language ? this.language.name : '';
   }
 
+  get saveStateText(): string {
+    if (!this.saveState) {
+      return '';
+    }
+    switch (this.saveState.state) {
+      case 'saving':
+        return 'Saving...';
+      case 'failed':
+        return this.saveState.error || 'Save failed';
+      default:
+        return this.saveState.saved_at ? 'Saved at ' + new Date(this.saveState.saved_at).toLocaleTimeString() : 'Saved';
+    }
+  }
+
   ngOnInit() {
   }
 
@@ -34,6 +55,22 @@ export class CodepadEditorHeaderComponent implements OnInit {
     this.runCode.emit();
   }
 
+  onRunSelectionClick() {
+    this.runSelection.emit();
+  }
+
+  onDebugClick() {
+    this.debugCode.emit();
+  }
+
+  onFormatClick() {
+    this.formatCode.emit();
+  }
+
+  onLintClick() {
+    this.lintCode.emit();
+  }
+
   showLanguageDialog() {
     const dialogRef = this.dialog.open(CodepadLanguageDialogComponent, {
       position: { left: '350px', top: '65px' },
@@ -56,6 +93,11 @@ export class CodepadEditorHeaderComponent implements OnInit {
     }
   }
 
+  changeSaveState(saveState: ISaveStateParams) {
+    this.saveState = saveState;
+    this.changeDetectorRef.markForCheck();
+  }
+
   private onLanguageDialogResult(it: LanguageDialogResult) {
     if (!it) {
       return;
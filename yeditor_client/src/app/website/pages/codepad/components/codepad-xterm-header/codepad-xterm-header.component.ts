@@ -10,10 +10,15 @@ export class CodepadXtermHeaderComponent implements OnInit {
   constructor() { }
 
   @Output() reset = new EventEmitter<any>();
+  @Output() listFiles = new EventEmitter<any>();
 
   ngOnInit() { }
 
   onResetClick() {
     this.reset.emit();
   }
+
+  onListFilesClick() {
+    this.listFiles.emit();
+  }
 }
@@ -1,4 +1,5 @@
-import { ChangeDetectionStrategy, Component, OnDestroy, OnInit, ViewChild } from '@angular/core';
+import { ChangeDetectionStrategy, ChangeDetectorRef, Component, OnDestroy, OnInit, ViewChild } from '@angular/core';
+import { DomSanitizer, SafeHtml, SafeUrl } from '@angular/platform-browser';
 import { ActivatedRoute, Router } from '@angular/router';
 import { Store } from '@ngrx/store';
 import { MonacoEditorComponent } from 'app/website/shared-ui/monaco-editor/components/monaco-editor.component';
@@ -7,8 +8,8 @@ import { XtermComponent } from 'app/website/shared-ui/xterm/components/xterm.com
 import { XTermSocketChannel } from 'app/website/shared-ui/xterm/components/xterm.socket.channel';
 import { Language, Languages } from 'app/website/shared/models/languages.model';
 import { //
-  CodepadClient, CodepadService, ICommandResponseParams, ICursorChangedEvent, //
-  ICursorPosition, IEditorChangedEvent, IEditorSyncParams, ITextChange, ITextRange
+  CodepadClient, CodepadService, IArtifact, ICommandResponseParams, ICursorChangedEvent, //
+  ICursorPosition, IDiagnostic, IEditorChangedEvent, IEditorSyncParams, IRunFiles, ITextChange, ITextRange
 } from 'app/website/shared/services/codepad/codepad.service';
 import { LoggerService } from 'app/website/shared/services/common/logger.service';
 import * as codepad from 'app/website/store/codepad';
@@ -31,6 +32,8 @@ export class CodepadEditComponent implements OnInit, OnDestroy {
     private logger: LoggerService,
     private codepadService: CodepadService,
     private store$: Store<WebsiteState>,
+    private changeDetector: ChangeDetectorRef,
+    private sanitizer: DomSanitizer,
   ) { }
 
   private subscriptions: Subscription[] = [];
@@ -42,11 +45,22 @@ export class CodepadEditComponent implements OnInit, OnDestroy {
     wordWrap: 'off',
     minimap: { enabled: true, renderCharacters: false },
     scrollBeyondLastLine: false,
+    glyphMargin: true,
+  };
+
+  inputEditorOptions: monaco.editor.IEditorConstructionOptions = {
+    theme: 'vs-dark',
+    lineNumbers: 'on',
+    language: 'plaintext',
+    wordWrap: 'off',
+    minimap: { enabled: false },
+    scrollBeyondLastLine: false,
   };
 
   @ViewChild('rootPane') rootPane: SplitPaneComponent;
   @ViewChild('editorHeader') editorHeader: CodepadEditorHeaderComponent;
   @ViewChild('editor') editor: MonacoEditorComponent;
+  @ViewChild('inputEditor') inputEditor: MonacoEditorComponent;
   @ViewChild('xterm') xterm: XtermComponent;
 
   codepadClientState = this.store$.select(codepad.selectCodepadClientState);
@@ -54,12 +68,30 @@ export class CodepadEditComponent implements OnInit, OnDestroy {
 
   private isRemoteEdits: boolean;
   private editorMounted = false;
+  private inputEditorMounted = false;
   private terminalMounted = false;
   private roomKey: string;
+  // server asked to reconnect after delay (ms), e.g. on deploy
+  private reconnectDelay: number = null;
 
   // TODO: move peer state to store
   private peerCursors = new PeerCursors(); // peer_id => decorations
 
+  // requests to language server of room, id => resolve of response
+  private lspRequests = new Map<number, (response: any) => void>();
+  private lspNextId = 1;
+  private lspProviders: monaco.IDisposable[] = [];
+
+  // breakpoints of room and stop line of debug run
+  private breakpoints: number[] = [];
+  private breakpointDecorations: string[] = [];
+  private debugStoppedDecorations: string[] = [];
+
+  // rich output of the latest run, shown below terminal
+  artifacts: IRenderedArtifact[] = [];
+  // download links of files of the latest run, listed on demand
+  runFiles: IRunFileLink[] = [];
+
   ngOnInit() {
     const routeUrl = this.activatedRoute.snapshot.parent.url[0];
     this.roomKey = routeUrl.path;
@@ -70,10 +102,13 @@ export class CodepadEditComponent implements OnInit, OnDestroy {
     this.logger.log('editor destroyed.');
     this.subscriptions.forEach(it => it && it.unsubscribe());
     this.subscriptions = [];
+    this.lspProviders.forEach(it => it.dispose());
+    this.lspProviders = [];
   }
 
   doLayout(event: DragEventData) {
     this.editor.layout();
+    this.inputEditor.layout();
     this.xterm.layout();
   }
 
@@ -87,6 +122,11 @@ export class CodepadEditComponent implements OnInit, OnDestroy {
     this.onDidMount();
   }
 
+  onInputEditorDidMount() {
+    this.inputEditorMounted = true;
+    this.onDidMount();
+  }
+
   onTerminalDidMount() {
     this.terminalMounted = true;
     this.onDidMount();
@@ -96,6 +136,9 @@ export class CodepadEditComponent implements OnInit, OnDestroy {
     if (!this.editorMounted) {
       return;
     }
+    if (!this.inputEditorMounted) {
+      return;
+    }
     if (!this.terminalMounted) {
       return;
     }
@@ -110,6 +153,8 @@ export class CodepadEditComponent implements OnInit, OnDestroy {
     this.editor.codeEditor.getModel().updateOptions({ tabSize: 2 });
     this.editor.registerOnChange((e) => this.onEditorChanged(e));
     this.editor.codeEditor.onDidChangeCursorPosition((e) => this.onCursorPositionChanged(e));
+    this.editor.codeEditor.onMouseDown((e) => this.onEditorMouseDown(e));
+    this.inputEditor.registerOnChange((e) => this.onInputEditorChanged(e));
     this.isRemoteEdits = false;
 
     this.connectCodepadService();
@@ -121,6 +166,11 @@ export class CodepadEditComponent implements OnInit, OnDestroy {
       const client = this.codepadService.currentClient;
       const channel = new XTermSocketChannel(client);
       this.xterm.attachSocketChannel(channel);
+    } else if (clientState.connectStatus === ConnectStatus.closed && this.reconnectDelay != null) {
+      const delay = this.reconnectDelay;
+      this.reconnectDelay = null;
+      this.logger.log('reconnecting in', delay, 'ms');
+      setTimeout(() => this.connectCodepadService(), delay);
     }
   }
 
@@ -130,6 +180,7 @@ export class CodepadEditComponent implements OnInit, OnDestroy {
     if (language) {
       this.editor.changeLanguage(language.editorLanguage);
       this.editorHeader.changeLanguage(language);
+      this.registerLspProviders(language.editorLanguage);
     } else {
       this.logger.log('unsupported language:', languageId);
     }
@@ -178,6 +229,36 @@ export class CodepadEditComponent implements OnInit, OnDestroy {
     client.sendCommandReset();
   }
 
+  onFormatCode() {
+    this.logger.log('format button clicked');
+    const client = this.codepadService.currentClient;
+    if (!client) {
+      this.logger.log('format command: client not connected');
+      return;
+    }
+    client.sendCommandFormat();
+  }
+
+  onLintCode() {
+    this.logger.log('lint button clicked');
+    const client = this.codepadService.currentClient;
+    if (!client) {
+      this.logger.log('lint command: client not connected');
+      return;
+    }
+    client.sendCommandLint();
+  }
+
+  onListRunFiles() {
+    this.logger.log('files button clicked');
+    const client = this.codepadService.currentClient;
+    if (!client) {
+      this.logger.log('list run files command: client not connected');
+      return;
+    }
+    client.sendCommandListRunFiles();
+  }
+
   onRunCode() {
     this.logger.log('run button clicked');
     const client = this.codepadService.currentClient;
@@ -190,6 +271,64 @@ export class CodepadEditComponent implements OnInit, OnDestroy {
     client.sendCommandRunCode(code);
   }
 
+  onRunSelection() {
+    this.logger.log('run selection button clicked');
+    const client = this.codepadService.currentClient;
+    if (!client) {
+      this.logger.log('run selection command: client not connected');
+      return;
+    }
+    if (!client.hello || client.hello.capabilities.indexOf('run_selection') < 0) {
+      this.xterm.writeln('[notice] running a selection is not supported by the server');
+      return;
+    }
+
+    const selection = this.editor.codeEditor.getSelection();
+    if (!selection || selection.isEmpty()) {
+      this.xterm.writeln('[notice] select code to run');
+      return;
+    }
+    client.sendCommandRunSelection({
+      start_line: selection.startLineNumber,
+      start_column: selection.startColumn,
+      end_line: selection.endLineNumber,
+      end_column: selection.endColumn,
+    });
+  }
+
+  onDebugCode() {
+    this.logger.log('debug button clicked');
+    const client = this.codepadService.currentClient;
+    if (!client) {
+      this.logger.log('debug code command: client not connected');
+      return;
+    }
+    if (!client.hello || client.hello.capabilities.indexOf('debug') < 0) {
+      this.xterm.writeln('[notice] debugging is not supported by the server');
+      return;
+    }
+
+    let code = this.editor.codeEditor.getValue();
+    client.sendCommandDebugCode(code);
+  }
+
+  // click in glyph margin toggles breakpoint of the line, the room broadcasts the list to everyone
+  private onEditorMouseDown(event: monaco.editor.IEditorMouseEvent) {
+    if (event.target.type !== monaco.editor.MouseTargetType.GUTTER_GLYPH_MARGIN || !event.target.position) {
+      return;
+    }
+    const client = this.codepadService.currentClient;
+    if (!client) {
+      this.logger.log('breakpoint toggle: client not connected');
+      return;
+    }
+    const line = event.target.position.lineNumber;
+    let breakpoints = this.breakpoints.indexOf(line) < 0
+      ? [...this.breakpoints, line]
+      : this.breakpoints.filter(it => it !== line);
+    client.sendEditorBreakpoints(breakpoints.sort((a, b) => a - b));
+  }
+
   private onEditorChanged(event: monaco.editor.IModelContentChangedEvent) {
     if (this.isRemoteEdits) {
       return;
@@ -200,26 +339,29 @@ export class CodepadEditComponent implements OnInit, OnDestroy {
       return;
     }
     // console.log('change event = ', event);
-    let changed = <IEditorChangedEvent>{
-      version: event.versionId,
-      changes: event.changes.map((item) => <ITextChange>{
-        text: item.text,
-        range: <ITextRange>{
-          start_line: item.range.startLineNumber,
-          start_column: item.range.startColumn,
-          end_line: item.range.endLineNumber,
-          end_column: item.range.endColumn,
-        }
-      })
-    };
     let syncParams = <IEditorSyncParams>{
-      changed: changed,
+      changed: toChangedEvent(event),
     };
     // this.logger.log('[', (this.isRemoteEdits ? 'remote' : 'local'), ']', ' editor change: ', JSON.stringify(changed));
     client.sendEditor(syncParams);
     this.renderAllPeerCursors();
   }
 
+  private onInputEditorChanged(event: monaco.editor.IModelContentChangedEvent) {
+    if (this.isRemoteEdits) {
+      return;
+    }
+    const client = this.codepadService.currentClient;
+    if (!client) {
+      this.logger.log('input editor change: client not connected');
+      return;
+    }
+    let syncParams = <IEditorSyncParams>{
+      input_changed: toChangedEvent(event),
+    };
+    client.sendEditor(syncParams);
+  }
+
   private onCursorPositionChanged(event: monaco.editor.ICursorPositionChangedEvent) {
     // send to remote
     if (this.isRemoteEdits) {
@@ -257,9 +399,164 @@ export class CodepadEditComponent implements OnInit, OnDestroy {
       if (language) {
         this.store$.dispatch(new codepad.CodepadActionLanguageChanged({ language: langId }));
       }
+    } else if (cmdParams.reconnect != null) {
+      this.reconnectDelay = cmdParams.reconnect;
+    } else if (cmdParams.save_state) {
+      this.editorHeader.changeSaveState(cmdParams.save_state);
+    } else if (cmdParams.diagnostics) {
+      this.renderDiagnostics(cmdParams.diagnostics);
+    } else if (cmdParams.lsp) {
+      this.onLspMessage(cmdParams.lsp);
+    } else if (cmdParams.artifact) {
+      this.addArtifact(cmdParams.artifact);
+    } else if (cmdParams.clear_artifacts) {
+      this.setArtifacts([]);
+      this.setRunFiles(null);
+    } else if (cmdParams.run_files) {
+      this.setRunFiles(cmdParams.run_files);
+    } else if (cmdParams.notice != null) {
+      this.xterm.writeln('[notice] ' + cmdParams.notice);
     }
   }
 
+  private addArtifact(artifact: IArtifact) {
+    let rendered: IRenderedArtifact = { name: artifact.name, mime: artifact.mime };
+    if (artifact.mime.startsWith('image/')) {
+      // svg in img does not run scripts
+      rendered.url = this.sanitizer.bypassSecurityTrustUrl('data:' + artifact.mime + ';base64,' + artifact.data);
+    } else if (artifact.mime === 'text/html') {
+      // shown in a sandboxed frame without scripts
+      rendered.html = this.sanitizer.bypassSecurityTrustHtml(decodeBase64Text(artifact.data));
+    } else {
+      rendered.text = decodeBase64Text(artifact.data);
+    }
+    this.setArtifacts([...this.artifacts, rendered]);
+  }
+
+  private setArtifacts(artifacts: IRenderedArtifact[]) {
+    if (artifacts.length === 0 && this.artifacts.length === 0) {
+      return;
+    }
+    this.artifacts = artifacts;
+    this.changeDetector.markForCheck();
+    // terminal is resized with the artifacts pane
+    setTimeout(() => this.xterm.layout());
+  }
+
+  private setRunFiles(runFiles: IRunFiles) {
+    if (runFiles && runFiles.files.length === 0) {
+      this.xterm.writeln('[notice] no files of the latest run');
+    }
+    const links = (runFiles ? runFiles.files : []).map(file => <IRunFileLink>{
+      name: file.name,
+      size: file.size,
+      url: `/pads/${this.roomKey}/files/${file.name.split('/').map(encodeURIComponent).join('/')}?token=${encodeURIComponent(file.token)}`,
+    });
+    if (links.length === 0 && this.runFiles.length === 0) {
+      return;
+    }
+    this.runFiles = links;
+    this.changeDetector.markForCheck();
+    setTimeout(() => this.xterm.layout());
+  }
+
+  private renderDiagnostics(diagnostics: IDiagnostic[]) {
+    const model = this.editor.codeEditor.getModel();
+    const markers = diagnostics.map(it => <monaco.editor.IMarkerData>{
+      severity: toMarkerSeverity(it.severity),
+      message: it.message,
+      startLineNumber: it.line,
+      startColumn: it.column,
+      endLineNumber: it.line,
+      endColumn: model.getLineMaxColumn(Math.min(it.line, model.getLineCount())),
+    });
+    monaco.editor.setModelMarkers(model, 'lint', markers);
+  }
+
+  // providers of editor language ask language server of room, results are of the shared buffer
+  private registerLspProviders(editorLanguage: string) {
+    this.lspProviders.forEach(it => it.dispose());
+    this.lspProviders = [
+      monaco.languages.registerHoverProvider(editorLanguage, {
+        provideHover: (model, position) => this.requestLsp('textDocument/hover', position)
+          .then(result => result && result.contents ? <monaco.languages.Hover>{
+            contents: toMarkdownStrings(result.contents),
+            range: result.range ? fromLspRange(result.range) : undefined,
+          } : null),
+      }),
+      monaco.languages.registerCompletionItemProvider(editorLanguage, {
+        triggerCharacters: ['.', ':', '>'],
+        provideCompletionItems: (model, position) => this.requestLsp('textDocument/completion', position)
+          .then(result => {
+            let items: any[] = (result && (Array.isArray(result) ? result : result.items)) || [];
+            return items.map(item => <monaco.languages.CompletionItem>{
+              label: item.label,
+              kind: fromLspCompletionKind(item.kind),
+              detail: item.detail,
+              documentation: item.documentation,
+              insertText: item.textEdit ? item.textEdit.newText : (item.insertText || item.label),
+              sortText: item.sortText,
+              filterText: item.filterText,
+            });
+          }),
+      }),
+      monaco.languages.registerDefinitionProvider(editorLanguage, {
+        provideDefinition: (model, position) => this.requestLsp('textDocument/definition', position)
+          .then(result => {
+            let locations: any[] = result ? (Array.isArray(result) ? result : [result]) : [];
+            // the server knows one document, locations in other files are not shown
+            return locations
+              .filter(it => it.range)
+              .map(it => <monaco.languages.Location>{ uri: model.uri, range: fromLspRange(it.range) });
+          }),
+      }),
+    ];
+  }
+
+  private requestLsp(method: string, position: monaco.Position): Promise<any> {
+    const client = this.codepadService.currentClient;
+    if (!client || !client.hello || client.hello.capabilities.indexOf('lsp') < 0) {
+      return Promise.resolve(null);
+    }
+    const id = this.lspNextId++;
+    client.sendCommandLsp({
+      jsonrpc: '2.0',
+      id: id,
+      method: method,
+      params: {
+        textDocument: { uri: this.editor.codeEditor.getModel().uri.toString() },
+        position: { line: position.lineNumber - 1, character: position.column - 1 },
+      },
+    });
+    return new Promise(resolve => this.lspRequests.set(id, resolve));
+  }
+
+  private onLspMessage(message: any) {
+    if (message.method === 'textDocument/publishDiagnostics') {
+      this.renderLspDiagnostics(message.params.diagnostics || []);
+      return;
+    }
+    const resolve = this.lspRequests.get(message.id);
+    if (resolve) {
+      this.lspRequests.delete(message.id);
+      if (message.error) {
+        this.logger.log('lsp error:', message.error.message);
+      }
+      resolve(message.result);
+    }
+  }
+
+  private renderLspDiagnostics(diagnostics: any[]) {
+    const model = this.editor.codeEditor.getModel();
+    const markers = diagnostics.map(it => <monaco.editor.IMarkerData>{
+      severity: fromLspSeverity(it.severity),
+      message: it.message,
+      source: it.source,
+      ...fromLspRange(it.range),
+    });
+    monaco.editor.setModelMarkers(model, 'lsp', markers);
+  }
+
   private onEditorSync(event: MessageEvent) {
     this.isRemoteEdits = true;
     try {
@@ -271,6 +568,15 @@ export class CodepadEditComponent implements OnInit, OnDestroy {
         this.onEditorSyncEdit(syncParams.changed);
       } else if (syncParams.cursor != null) {
         this.onEditorSyncCursor(syncParams.cursor);
+      } else if (syncParams.input_text != null) {
+        this.inputEditor.codeEditor.setValue(syncParams.input_text);
+      } else if (syncParams.input_changed != null) {
+        let inputEditor = this.inputEditor.codeEditor;
+        inputEditor.executeEdits('remote', toEditOperations(syncParams.input_changed), inputEditor.getSelections());
+      } else if (syncParams.breakpoints != null) {
+        this.renderBreakpoints(syncParams.breakpoints);
+      } else if ('debug_stopped' in syncParams) {
+        this.renderDebugStopped(syncParams.debug_stopped);
       }
     } finally {
       this.isRemoteEdits = false;
@@ -282,19 +588,7 @@ export class CodepadEditComponent implements OnInit, OnDestroy {
   }
 
   private onEditorSyncEdit(changedEvent: IEditorChangedEvent) {
-    let edits = changedEvent.changes.map((item, idx) => <monaco.editor.IIdentifiedSingleEditOperation>{
-      identifier: <monaco.editor.ISingleEditOperationIdentifier>{
-        major: changedEvent.version,
-        minor: idx,
-      },
-      text: item.text,
-      range: <monaco.IRange>{
-        startLineNumber: item.range.start_line,
-        startColumn: item.range.start_column,
-        endLineNumber: item.range.end_line,
-        endColumn: item.range.end_column,
-      }
-    });
+    let edits = toEditOperations(changedEvent);
     let currentSelections = this.editor.codeEditor.getSelections();
     this.editor.codeEditor.executeEdits('remote', edits, currentSelections);
     this.renderAllPeerCursors();
@@ -330,6 +624,27 @@ export class CodepadEditComponent implements OnInit, OnDestroy {
     peer.decorations = this.editor.codeEditor.deltaDecorations(oldDecorations, newDecorations);
   }
 
+  private renderBreakpoints(breakpoints: number[]) {
+    this.breakpoints = breakpoints;
+    let newDecorations = breakpoints.map(line => <monaco.editor.IModelDeltaDecoration>{
+      range: new monaco.Range(line, 1, line, 1),
+      options: { glyphMarginClassName: 'debug-breakpoint' },
+    });
+    this.breakpointDecorations = this.editor.codeEditor.deltaDecorations(this.breakpointDecorations, newDecorations);
+  }
+
+  private renderDebugStopped(line: number) {
+    let newDecorations = [];
+    if (line != null) {
+      newDecorations.push(<monaco.editor.IModelDeltaDecoration>{
+        range: new monaco.Range(line, 1, line, 1),
+        options: { isWholeLine: true, className: 'debug-stopped-line', glyphMarginClassName: 'debug-stopped-glyph' },
+      });
+      this.editor.codeEditor.revealLineInCenterIfOutsideViewport(line);
+    }
+    this.debugStoppedDecorations = this.editor.codeEditor.deltaDecorations(this.debugStoppedDecorations, newDecorations);
+  }
+
   private renderAllPeerCursors() {
     this.peerCursors.data.forEach(peer => {
       this.renderPeerCursors(peer);
@@ -337,6 +652,29 @@ export class CodepadEditComponent implements OnInit, OnDestroy {
   }
 }
 
+interface IRenderedArtifact {
+  name: string;
+  mime: string;
+  url?: SafeUrl; // images
+  html?: SafeHtml;
+  text?: string; // markdown and text
+}
+
+interface IRunFileLink {
+  name: string;
+  size: number;
+  url: string;
+}
+
+function decodeBase64Text(data: string): string {
+  let binary = atob(data);
+  let bytes = new Uint8Array(binary.length);
+  for (let i = 0; i < binary.length; i++) {
+    bytes[i] = binary.charCodeAt(i);
+  }
+  return new TextDecoder('utf-8').decode(bytes);
+}
+
 interface IPeerCursor {
   peer_id: number;
   decorations: string[];
@@ -368,3 +706,88 @@ class PeerCursors {
     return peer;
   }
 }
+
+// MarkerSeverity values of monaco-editor 0.17, typings in @types are of an older version
+function toMarkerSeverity(severity: string): monaco.Severity {
+  switch (severity) {
+    case 'error':
+      return <any>8;
+    case 'warning':
+      return <any>4;
+    default:
+      return <any>2;
+  }
+}
+
+// DiagnosticSeverity of lsp, error = 1, warning = 2, information = 3, hint = 4
+function fromLspSeverity(severity: number): monaco.Severity {
+  switch (severity) {
+    case 1:
+      return <any>8;
+    case 2:
+      return <any>4;
+    default:
+      return <any>2;
+  }
+}
+
+// CompletionItemKind of lsp => CompletionItemKind of monaco-editor 0.17
+const LSP_COMPLETION_KINDS = [18, 18, 0, 1, 2, 3, 4, 5, 7, 8, 9, 12, 13, 15, 17, 25, 19, 20, 21, 23, 16, 14, 6, 10, 11, 24];
+
+function fromLspCompletionKind(kind: number): monaco.languages.CompletionItemKind {
+  return <any>(LSP_COMPLETION_KINDS[kind] != null ? LSP_COMPLETION_KINDS[kind] : 18);
+}
+
+// lsp positions are 0-based, monaco positions 1-based, both count utf-16 code units
+function fromLspRange(range: any): monaco.IRange {
+  return <monaco.IRange>{
+    startLineNumber: range.start.line + 1,
+    startColumn: range.start.character + 1,
+    endLineNumber: range.end.line + 1,
+    endColumn: range.end.character + 1,
+  };
+}
+
+// MarkedString | MarkedString[] | MarkupContent of hover
+function toMarkdownStrings(contents: any): monaco.IMarkdownString[] {
+  let items: any[] = Array.isArray(contents) ? contents : [contents];
+  return items.map(it => {
+    if (typeof it === 'string') {
+      return { value: it };
+    } else if (it.language) {
+      return { value: '```' + it.language + '\n' + it.value + '\n```' };
+    }
+    return { value: it.value };
+  });
+}
+
+function toChangedEvent(event: monaco.editor.IModelContentChangedEvent): IEditorChangedEvent {
+  return <IEditorChangedEvent>{
+    version: event.versionId,
+    changes: event.changes.map((item) => <ITextChange>{
+      text: item.text,
+      range: <ITextRange>{
+        start_line: item.range.startLineNumber,
+        start_column: item.range.startColumn,
+        end_line: item.range.endLineNumber,
+        end_column: item.range.endColumn,
+      }
+    })
+  };
+}
+
+function toEditOperations(changedEvent: IEditorChangedEvent): monaco.editor.IIdentifiedSingleEditOperation[] {
+  return changedEvent.changes.map((item, idx) => <monaco.editor.IIdentifiedSingleEditOperation>{
+    identifier: <monaco.editor.ISingleEditOperationIdentifier>{
+      major: changedEvent.version,
+      minor: idx,
+    },
+    text: item.text,
+    range: <monaco.IRange>{
+      startLineNumber: item.range.start_line,
+      startColumn: item.range.start_column,
+      endLineNumber: item.range.end_line,
+      endColumn: item.range.end_column,
+    }
+  });
+}
@@ -5,6 +5,9 @@ type MessageEventListener = (event: MessageEvent) => void;
 
 export class XTermSocketChannel implements ISocketChannel {
 
+  // keeps multibyte characters split across stdout chunks
+  private stdoutDecoder = new TextDecoder('utf-8');
+
   constructor(
     public client: CodepadClient
   ) {
@@ -44,8 +47,19 @@ export class XTermSocketChannel implements ISocketChannel {
     this.client.sendTerminal(content);
   }
 
+  private decode_stdout_bytes(data: string): string {
+    let binary = atob(data);
+    let bytes = new Uint8Array(binary.length);
+    for (let i = 0; i < binary.length; i++) {
+      bytes[i] = binary.charCodeAt(i);
+    }
+    return this.stdoutDecoder.decode(bytes, { stream: true });
+  }
+
   private convert_to_xterm(content: any): string {
-    if (content.stdout !== undefined && content.stdout !== null) {
+    if (content.stdout_bytes !== undefined && content.stdout_bytes !== null) {
+      return JSON.stringify(['stdout', this.decode_stdout_bytes(content.stdout_bytes)]);
+    } else if (content.stdout !== undefined && content.stdout !== null) {
       return JSON.stringify(['stdout', content.stdout]);
     } else if (content.stderr !== undefined && content.stdout !== null) {
       return JSON.stringify(['stderr', content.stderr]);
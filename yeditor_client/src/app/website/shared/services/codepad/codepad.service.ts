@@ -2,6 +2,16 @@ import { Injectable } from '@angular/core';
 import { gzip, ungzip } from 'pako';
 import { LoggerService } from '../common/logger.service';
 
+// realtime protocol version, terminal output is received as raw bytes since version 2,
+// hello with capabilities is sent after connected since version 3
+export const CODEPAD_PROTOCOL_VERSION = 3;
+export const CODEPAD_CAPABILITIES = ['stdout_bytes', 'save_state', 'program_input', 'code_tools', 'lsp', 'debug', 'database', 'artifact', 'run_files', 'run_selection'];
+
+// json packets, binary frames start with a flag byte telling whether the rest is gzipped
+const CODEPAD_WIRE_FORMAT = 'encoding=json&compress=gzip';
+const FRAME_FLAG_RAW = 0;
+const FRAME_FLAG_GZIP = 1;
+
 @Injectable()
 export class CodepadService {
 
@@ -33,7 +43,7 @@ export class CodepadService {
       }
     }
 
-    let endpointUrl = this.makeEndPointUrl('/realtime/' + roomKey);
+    let endpointUrl = this.makeEndPointUrl('/realtime/' + roomKey + '?proto=' + CODEPAD_PROTOCOL_VERSION + '&' + CODEPAD_WIRE_FORMAT);
     this._currentClient = new CodepadClient(roomKey, endpointUrl);
 
     let onClientClosed = () => {
@@ -61,6 +71,10 @@ export class CodepadClient {
 
   private socket: WebSocket;
 
+  // negotiated with server
+  private _hello: IHelloParams;
+  get hello(): IHelloParams { return this._hello; }
+
   // 'message' listeners, Array<[listener, translatedListener]>
   private messageListeners: Array<EventListenerOrEventListenerObject[]>;
 
@@ -71,6 +85,12 @@ export class CodepadClient {
     this.socket = new WebSocket(endpointUrl);
     this.socket.binaryType = 'arraybuffer';
     this.messageListeners = [];
+    this.socket.addEventListener('open', () => {
+      this.sendMessage('h', { version: CODEPAD_PROTOCOL_VERSION, capabilities: CODEPAD_CAPABILITIES });
+    });
+    this.socket.addEventListener('message', this.createMessageAdaptor((event: MessageEvent) => {
+      this._hello = event.data.c;
+    }, 'hello'));
   }
 
   get readyState(): number {
@@ -87,7 +107,7 @@ export class CodepadClient {
 
   addEventListener<
     K extends keyof WebSocketEventMap,
-    C extends 'terminal' | 'editor' | 'command' | undefined
+    C extends 'hello' | 'terminal' | 'editor' | 'command' | undefined
   >(
     type: K, listener: MessageEventListener, msgCmd?: C, payloadConverter?: PayloadConverter
   ): void {
@@ -119,6 +139,7 @@ export class CodepadClient {
 
   private getMsgTag(msgCmd?: string): string {
     switch (msgCmd) {
+      case 'hello': return 'h';
       case 'terminal': return 't';
       case 'editor': return 'e';
       case 'command': return 'c';
@@ -135,7 +156,12 @@ export class CodepadClient {
     return (event: MessageEvent) => {
       let data = event.data;
       if (data instanceof ArrayBuffer) {
-        data = ungzip(data, { to: 'string' });
+        let frame = new Uint8Array(data);
+        if (frame[0] === FRAME_FLAG_RAW) {
+          data = new TextDecoder().decode(frame.subarray(1));
+        } else {
+          data = ungzip(frame.subarray(1), { to: 'string' });
+        }
       }
       // console.log('data = ', data);
       let payload = JSON.parse(data);
@@ -156,18 +182,23 @@ export class CodepadClient {
   }
 
   // packet = { t: tag, c: content }
-  // tag = e (editor), c (command), t (terminal)
+  // tag = h (hello), e (editor), c (command), t (terminal)
   // content = { name: args }
   //   command = [{'reset': []}, {'run_code', code_content}, {'set_lang': 'lang_name'}]
   //   terminal = [{'set_size': [row, col]}, {'stdin': input_string}]
-  //   editor = []
+  //   editor = [{'changed': changed_event}, {'cursor': cursor_event}, {'input_changed': changed_event}]
   private sendMessage(tag: string, content: any) {
     let packet = { t: tag, c: content };
     let payload = JSON.stringify(packet);
     if (payload.length > 128) {
-      payload = gzip(payload);
+      let compressed = gzip(payload);
+      let frame = new Uint8Array(compressed.length + 1);
+      frame[0] = FRAME_FLAG_GZIP;
+      frame.set(compressed, 1);
+      this.socket.send(frame);
+    } else {
+      this.socket.send(payload);
     }
-    this.socket.send(payload);
   }
 
   sendTerminal(content: any) {
@@ -198,15 +229,67 @@ export class CodepadClient {
     this.sendCommand({ 'run_code': code });
   }
 
+  sendCommandRunSelection(range: ITextRange) {
+    this.sendCommand({ 'run_selection': range });
+  }
+
   sendCommandSetLang(lang: string) {
     this.sendCommand({ 'set_lang': lang });
   }
+
+  sendCommandSetRunOptions(options: IRunOptions) {
+    this.sendCommand({ 'set_run_options': options });
+  }
+
+  sendCommandSetDependencies(dependencies: IDependency[]) {
+    this.sendCommand({ 'set_dependencies': dependencies });
+  }
+
+  sendCommandSetDatabaseSeed(seed: string) {
+    this.sendCommand({ 'set_database_seed': seed });
+  }
+
+  sendCommandFormat() {
+    this.sendCommand({ 'format': [] });
+  }
+
+  sendCommandLint() {
+    this.sendCommand({ 'lint': [] });
+  }
+
+  sendCommandLsp(message: any) {
+    this.sendCommand({ 'lsp': message });
+  }
+
+  sendCommandDebugCode(code: string) {
+    this.sendCommand({ 'debug_code': code });
+  }
+
+  sendCommandListRunFiles() {
+    this.sendCommand({ 'list_run_files': [] });
+  }
+
+  sendEditorBreakpoints(breakpoints: number[]) {
+    this.sendEditor({ 'breakpoints': breakpoints });
+  }
+}
+
+// see docs/protocol for all messages
+export interface IHelloParams {
+  version: number;
+  capabilities: string[];
 }
 
 export interface IEditorSyncParams {
   changed?: IEditorChangedEvent;
   text?: string;
   cursor?: ICursorChangedEvent;
+  // program input, piped to stdin of runs
+  input_changed?: IEditorChangedEvent;
+  input_text?: string;
+  // lines of breakpoints shared by participants, stop line of debug run
+  breakpoints?: number[];
+  debug_stopped?: number; // null when resumed or finished
 }
 export interface IEditorChangedEvent {
   version: number;
@@ -237,9 +320,77 @@ export interface ICursorPosition {
 export interface ICommandRequestParams {
   reset?: any;
   run_code?: string;
+  run_selection?: ITextRange; // range of the code buffer, interpreted languages only
+  debug_code?: string;
   set_lang?: string;
+  set_run_options?: IRunOptions;
+  set_dependencies?: IDependency[];
+  set_database_seed?: string; // sql run in the room database on reset
+  format?: any;
+  lint?: any;
+  lsp?: any; // json-rpc message to language server of room
+  list_run_files?: any;
 }
 
 export interface ICommandResponseParams {
   set_lang?: string;
+  set_run_options?: IRunOptions;
+  set_dependencies?: IDependency[];
+  set_database_seed?: string;
+  diagnostics?: IDiagnostic[];
+  notice?: string;
+  reconnect?: number; // delay in ms
+  save_state?: ISaveStateParams;
+  lsp?: any; // json-rpc response or diagnostics of language server
+  artifact?: IArtifact; // rich output of the latest run
+  clear_artifacts?: any; // a run started
+  run_files?: IRunFiles; // reply of list_run_files
+}
+
+// validated by yscript against allow list of the language
+export interface IRunOptions {
+  compiler_flags?: string[];
+  args?: string[];
+  version?: string;
+  env?: { [name: string]: string };
+}
+
+// crate, npm package or python distribution, resolved offline by yscript
+export interface IDependency {
+  name: string;
+  version?: string; // any version if empty
+}
+
+// linter message, line and column are 1-based
+export interface IDiagnostic {
+  line: number;
+  column: number;
+  severity: 'error' | 'warning' | 'info';
+  message: string;
+}
+
+// file written by code to output/, markdown, html or image by mime type
+export interface IArtifact {
+  name: string;
+  mime: string;
+  data: string; // base64
+}
+
+// files of the latest run
+export interface IRunFiles {
+  files: IRunFile[];
+}
+
+// downloaded from /pads/{hash}/files/{name}?token={token}
+export interface IRunFile {
+  name: string; // relative to the run directory
+  size: number;
+  token: string; // signed for this client, expires in minutes or when a run starts
+}
+
+export interface ISaveStateParams {
+  state: 'saving' | 'saved' | 'failed';
+  version: number;
+  saved_at?: number; // unix ms
+  error?: string;
 }
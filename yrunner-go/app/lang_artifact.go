@@ -0,0 +1,128 @@
+package app
+
+import (
+	"bytes"
+	"encoding/base64"
+	"io"
+)
+
+// Artifacts of runs, files written by code to output/. yscript writes them to terminal output
+// as ESC ] 5379 ; ye-artifact=<name>;<mime>;<base64> BEL at the end of run, the writer strips
+// them from stdout and sends them as artifact responses.
+
+var (
+	artifactMarkerPrefix = []byte("\x1b]5379;ye-artifact=")
+	artifactMarkerSuffix = byte(0x07)
+)
+
+const (
+	maxArtifactSize = 4 * 1024 * 1024
+	// prefix, name and mime, base64 of data
+	maxArtifactMarkerLen = 1024 + (maxArtifactSize+2)/3*4
+)
+
+// ArtifactWriter passes output without markers to writer, markers may be split across writes
+type ArtifactWriter struct {
+	id       CodeID
+	listener LangRuntimeListener
+	writer   io.Writer
+	pending  []byte
+	searched int // bytes of pending marker searched for suffix
+}
+
+var _ io.Writer = (*ArtifactWriter)(nil)
+
+func NewArtifactWriter(id CodeID, listener LangRuntimeListener, writer io.Writer) *ArtifactWriter {
+	return &ArtifactWriter{
+		id:       id,
+		listener: listener,
+		writer:   writer,
+	}
+}
+
+func (w *ArtifactWriter) Write(data []byte) (n int, err error) {
+	buf := append(w.pending, data...)
+	searched := w.searched
+	w.pending = nil
+	w.searched = 0
+
+	var output []byte
+	pos := 0
+	for {
+		i := bytes.Index(buf[pos:], artifactMarkerPrefix)
+		if i < 0 {
+			break
+		}
+		start := pos + i
+		output = append(output, buf[pos:start]...)
+		valueStart := start + len(artifactMarkerPrefix)
+		searchStart := valueStart
+		if start == 0 && searched > searchStart {
+			searchStart = searched
+		}
+		end := bytes.IndexByte(buf[searchStart:], artifactMarkerSuffix)
+		if end < 0 {
+			if len(buf)-start < maxArtifactMarkerLen {
+				// buf is owned by the writer, data is copied by append
+				w.pending = buf[start:]
+				w.searched = len(w.pending)
+				return len(data), w.writeOutput(output)
+			}
+			// too large for an artifact, not a marker of yscript
+			output = append(output, buf[start:]...)
+			return len(data), w.writeOutput(output)
+		}
+		valueEnd := searchStart + end
+		w.writeArtifact(buf[valueStart:valueEnd])
+		pos = valueEnd + 1
+	}
+
+	// a marker may start at the end of write
+	tail := buf[pos:]
+	keep := 0
+	for l := len(artifactMarkerPrefix) - 1; l > 0; l-- {
+		if l <= len(tail) && bytes.Equal(tail[len(tail)-l:], artifactMarkerPrefix[:l]) {
+			keep = l
+			break
+		}
+	}
+	output = append(output, tail[:len(tail)-keep]...)
+	w.pending = append([]byte(nil), tail[len(tail)-keep:]...)
+	return len(data), w.writeOutput(output)
+}
+
+// Flush writes the rest of output at the end of run, an incomplete marker is written as is
+func (w *ArtifactWriter) Flush() error {
+	rest := w.pending
+	w.pending = nil
+	w.searched = 0
+	return w.writeOutput(rest)
+}
+
+func (w *ArtifactWriter) writeOutput(output []byte) error {
+	if len(output) == 0 {
+		return nil
+	}
+	_, err := w.writer.Write(output)
+	return err
+}
+
+// invalid markers are dropped
+func (w *ArtifactWriter) writeArtifact(value []byte) {
+	parts := bytes.SplitN(value, []byte(";"), 3)
+	if len(parts) != 3 || len(parts[0]) == 0 || len(parts[1]) == 0 {
+		return
+	}
+	data, err := base64.StdEncoding.DecodeString(string(parts[2]))
+	if err != nil {
+		return
+	}
+	if w.listener != nil {
+		w.listener.WriteArtifact(&Artifact{
+			ID:   w.id,
+			Name: string(parts[0]),
+			Mime: string(parts[1]),
+			Data: data,
+		})
+	}
+}
@@ -14,10 +14,14 @@ type LangConfigData struct {
 }
 
 type DockerConfig struct {
-	MemoryMB      int `yaml:"memory_mb"`
-	CPUPeriodMS   int `yaml:"cpu_period_ms"`
-	CPUQuotaMS    int `yaml:"cpu_quota_ms"`
-	CodeTimeoutMS int `yaml:"code_timeout_ms"`
+	MemoryMB       int    `yaml:"memory_mb"`
+	CPUPeriodMS    int    `yaml:"cpu_period_ms"`
+	CPUQuotaMS     int    `yaml:"cpu_quota_ms"`
+	CodeTimeoutMS  int    `yaml:"code_timeout_ms"`
+	DebugTimeoutMS int    `yaml:"debug_timeout_ms"` // code_timeout_ms if zero
+	DepsDir        string `yaml:"deps_dir"`         // host directory of pad dependencies, see yscript
+	CacheDir       string `yaml:"cache_dir"`        // host directory of yscript compile cache
+	FilesTTLMS     int    `yaml:"files_ttl_ms"`     // files of the latest run are kept, removed after run if zero
 }
 
 type LangConfig struct {
@@ -32,6 +36,7 @@ type LangEnv struct {
 	Service *CmdLine `yaml:"service"`
 	Repl    *CmdLine `yaml:"repl"`
 	Run     *CmdLine `yaml:"run"`
+	Lsp     *CmdLine `yaml:"lsp"` // language server, json-rpc on stdio
 }
 
 type CmdLine struct {
@@ -0,0 +1,42 @@
+package app
+
+import (
+	"context"
+	"fmt"
+	"log"
+)
+
+// Database of room for sql languages. yscript creates it with a user of the same name in the
+// service container of the runtime and runs the seed, runs of the code connect to it.
+// The database is gone with the service container when the runtime stops.
+
+// resetDatabase recreates database of the runtime, failures are written to terminal
+func (rt *LangRuntime) resetDatabase(ctx context.Context) {
+	database := rt.runEnv.Database
+	if database == nil || rt.serviceContainer == "" {
+		return
+	}
+
+	seed := &Code{
+		ID:       0,
+		Language: rt.runEnv.Language,
+		Filename: "seed",
+		Content:  database.Seed,
+	}
+	output, err := rt.RunTool(ctx, "db_reset", seed)
+	if err != nil {
+		log.Printf("reset database failure: %s, err = %v", database.Name, err)
+		rt.writeStdout(0, []byte(termColor(TcRed, "[ database not created ]")+"\r\n"))
+		return
+	}
+	if output.ExitCode != 0 {
+		log.Printf("reset database failure: %s, exit code = %d", database.Name, output.ExitCode)
+		message := fmt.Sprintf("[ database seed failure, exit code %d ]", output.ExitCode)
+		rt.writeStdout(0, []byte(termColor(TcRed, message)+"\r\n"))
+		if rt.listener != nil {
+			NewListenerStdoutWriter(0, rt.listener, true).Write(output.Stderr)
+		}
+		return
+	}
+	log.Printf("database reset: %s", database.Name)
+}
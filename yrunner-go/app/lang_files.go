@@ -0,0 +1,204 @@
+package app
+
+import (
+	"archive/tar"
+	"bytes"
+	"context"
+	"io"
+	"io/ioutil"
+	"log"
+	"path"
+	"strings"
+	"time"
+
+	types "github.com/docker/docker/api/types"
+	docker "github.com/docker/docker/client"
+)
+
+// Files of runs. yscript runs code in containerRunDir, the stopped container of the latest run
+// is kept for FilesTTLMS so its files, e.g. compiled binaries and output/, can be listed and
+// fetched. It is removed by the next run or the reset of the runtime.
+
+const (
+	containerRunDir = "/home/ye/run"
+	maxRunFiles     = 256
+)
+
+// nextCodeSeq numbers a new run, files of the previous run are removed
+func (rt *LangRuntime) nextCodeSeq(dockerClient *docker.Client) int {
+	rt.filesMutex.Lock()
+	defer rt.filesMutex.Unlock()
+	rt.codeSeq++
+	rt.dropFilesLocked(dockerClient)
+	return rt.codeSeq
+}
+
+// retainFiles keeps the stopped container of run seq, containers of older runs are removed
+func (rt *LangRuntime) retainFiles(dockerClient *docker.Client, seq int, containerID string) {
+	rt.filesMutex.Lock()
+	defer rt.filesMutex.Unlock()
+
+	if seq != rt.codeSeq || rt.status == RtStatusStopping {
+		removeContainer(dockerClient, containerID)
+		return
+	}
+	ttl := time.Duration(rt.listener.GetLangConfig().Docker.FilesTTLMS) * time.Millisecond
+	if ttl <= 0 {
+		removeContainer(dockerClient, containerID)
+		return
+	}
+	rt.dropFilesLocked(dockerClient)
+	rt.filesContainer = containerID
+	rt.filesTimer = time.AfterFunc(ttl, func() {
+		rt.filesMutex.Lock()
+		defer rt.filesMutex.Unlock()
+		if rt.filesContainer == containerID {
+			log.Printf("run files expired: %s", containerID)
+			rt.dropFilesLocked(dockerClient)
+		}
+	})
+}
+
+// dropFiles removes files of the latest run, the runtime is stopping
+func (rt *LangRuntime) dropFiles(dockerClient *docker.Client) {
+	rt.filesMutex.Lock()
+	defer rt.filesMutex.Unlock()
+	rt.dropFilesLocked(dockerClient)
+}
+
+func (rt *LangRuntime) dropFilesLocked(dockerClient *docker.Client) {
+	if rt.filesTimer != nil {
+		rt.filesTimer.Stop()
+		rt.filesTimer = nil
+	}
+	if rt.filesContainer != "" {
+		removeContainer(dockerClient, rt.filesContainer)
+		rt.filesContainer = ""
+	}
+}
+
+func (rt *LangRuntime) getFilesContainer() string {
+	rt.filesMutex.Lock()
+	defer rt.filesMutex.Unlock()
+	return rt.filesContainer
+}
+
+// ListFiles lists regular files in run directory of the latest run, none if it expired
+func (rt *LangRuntime) ListFiles(ctx context.Context, req *ListFiles) *FileList {
+	resp := &FileList{ID: req.ID, Files: []RunFile{}}
+	containerID := rt.getFilesContainer()
+	if containerID == "" {
+		return resp
+	}
+
+	dockerClient := rt.listener.GetDockerClient()
+	reader, _, err := dockerClient.CopyFromContainer(ctx, containerID, containerRunDir+"/")
+	if err != nil {
+		if !docker.IsErrNotFound(err) {
+			log.Printf("list run files %s, err = %v", containerID, err)
+			resp.Error = ErrServiceInternal
+		}
+		return resp
+	}
+	defer reader.Close()
+
+	// entries are prefixed by the base name of run directory
+	prefix := path.Base(containerRunDir) + "/"
+	tarReader := tar.NewReader(reader)
+	for len(resp.Files) < maxRunFiles {
+		header, err := tarReader.Next()
+		if err == io.EOF {
+			break
+		}
+		if err != nil {
+			log.Printf("read run files %s, err = %v", containerID, err)
+			resp.Error = ErrServiceInternal
+			break
+		}
+		// symbolic links and directories are not listed
+		if header.Typeflag != tar.TypeReg || !strings.HasPrefix(header.Name, prefix) {
+			continue
+		}
+		resp.Files = append(resp.Files, RunFile{
+			Name: strings.TrimPrefix(header.Name, prefix),
+			Size: header.Size,
+		})
+	}
+	return resp
+}
+
+// FetchFile reads a regular file of the latest run by its name in ListFiles
+func (rt *LangRuntime) FetchFile(ctx context.Context, req *FetchFile) *FileData {
+	resp := &FileData{ID: req.ID, Name: req.Name, Data: []byte{}}
+	name := path.Clean(req.Name)
+	containerID := rt.getFilesContainer()
+	if containerID == "" || name != req.Name || path.IsAbs(name) || strings.HasPrefix(name, "..") {
+		resp.Error = ErrFileNotFound
+		return resp
+	}
+
+	dockerClient := rt.listener.GetDockerClient()
+	reader, stat, err := dockerClient.CopyFromContainer(ctx, containerID, path.Join(containerRunDir, name))
+	if err != nil {
+		if docker.IsErrNotFound(err) {
+			resp.Error = ErrFileNotFound
+		} else {
+			log.Printf("fetch run file %s, err = %v", containerID, err)
+			resp.Error = ErrServiceInternal
+		}
+		return resp
+	}
+	defer reader.Close()
+	if !stat.Mode.IsRegular() {
+		resp.Error = ErrFileNotFound
+		return resp
+	}
+	if stat.Size > req.MaxSize {
+		resp.Error = ErrFileTooLarge
+		return resp
+	}
+
+	// a single entry of the file
+	tarReader := tar.NewReader(reader)
+	data := []byte{}
+	_, err = tarReader.Next()
+	if err == nil {
+		data, err = ioutil.ReadAll(io.LimitReader(tarReader, req.MaxSize+1))
+	}
+	if err != nil {
+		log.Printf("read run file %s, err = %v", containerID, err)
+		resp.Error = ErrServiceInternal
+		return resp
+	}
+	if int64(len(data)) > req.MaxSize {
+		resp.Error = ErrFileTooLarge
+		return resp
+	}
+	resp.Data = data
+	return resp
+}
+
+// copyDir creates a directory in home directory of container, writable by the user of yscript
+func (rt *LangRuntime) copyDir(ctx context.Context, containerID string, name string) error {
+	tarBuffer := bytes.NewBuffer(nil)
+	tarWriter := tar.NewWriter(tarBuffer)
+	tarWriter.WriteHeader(&tar.Header{
+		Typeflag: tar.TypeDir,
+		Name:     name + "/",
+		Mode:     0777,
+		Uname:    "ye",
+		Gname:    "ye",
+		ModTime:  time.Now(),
+	})
+	tarWriter.Close()
+
+	dockerClient := rt.listener.GetDockerClient()
+	return dockerClient.CopyToContainer(ctx, containerID, "/home/ye/", tarBuffer, types.CopyToContainerOptions{})
+}
+
+func removeContainer(dockerClient *docker.Client, containerID string) {
+	err := dockerClient.ContainerRemove(context.Background(), containerID, types.ContainerRemoveOptions{Force: true})
+	if err != nil && !docker.IsErrNotFound(err) {
+		log.Printf("remove container %s, err = %v", containerID, err)
+	}
+}
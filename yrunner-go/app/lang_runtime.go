@@ -9,8 +9,10 @@ import (
 	"fmt"
 	"io"
 	"log"
+	"path"
 	"regexp"
 	"strings"
+	"sync"
 	"time"
 
 	types "github.com/docker/docker/api/types"
@@ -33,6 +35,8 @@ const (
 	RunTypeRepl    = "repl"
 	RunTypeCode    = "code"
 	RunTypeNetwork = "network"
+	RunTypeTool    = "tool"
+	RunTypeLsp     = "lsp"
 )
 
 const (
@@ -41,6 +45,12 @@ const (
 	KwYeRequestName = "<YE_REQUEST_FILE>"
 )
 
+// mount points of DockerConfig.DepsDir and DockerConfig.CacheDir in code containers
+const (
+	containerDepsDir  = "/opt/yscript/deps"
+	containerCacheDir = "/opt/yscript/cache"
+)
+
 const (
 	RtStatusCreating = "rt_status_creating"
 	RtStatusRunning  = "rt_status_running"
@@ -59,6 +69,10 @@ type LangRuntimeListener interface {
 	GetDockerClient() *docker.Client
 	GetLangConfig() *LangConfig
 	WriteStdout(id CodeID, buf []byte)
+	WriteRunResult(id CodeID, exitStatus int32, durationMillis float64)
+	WriteLspMessage(id CodeID, message []byte)
+	WriteLspStop(id CodeID)
+	WriteArtifact(artifact *Artifact)
 }
 
 // Writer adapter
@@ -113,7 +127,17 @@ type LangRuntime struct {
 	codeName      string
 	codeContainer string
 	codeStdio     *types.HijackedResponse
+	codeDebug     bool // stdin of room goes to debugger
+	codeSeq       int  // containers of runs are named by sequence, see retainFiles
 	requestName   string
+	// stopped container of the latest run, kept for its files
+	filesMutex     *sync.Mutex
+	filesContainer string
+	filesTimer     *time.Timer
+	// language server
+	lspContainer string
+	lspStdio     *types.HijackedResponse
+	lspID        CodeID
 }
 
 func NewLangRuntime(seqNo int, runEnv *RunEnv, listener LangRuntimeListener) (*LangRuntime, error) {
@@ -123,11 +147,12 @@ func NewLangRuntime(seqNo int, runEnv *RunEnv, listener LangRuntimeListener) (*L
 		return nil, fmt.Errorf("lang not found: %s", runEnv.Language)
 	}
 	rt := &LangRuntime{
-		seqNo:    seqNo,
-		listener: listener,
-		runEnv:   runEnv,
-		langEnv:  langEnv,
-		status:   RtStatusCreating,
+		seqNo:      seqNo,
+		listener:   listener,
+		runEnv:     runEnv,
+		langEnv:    langEnv,
+		status:     RtStatusCreating,
+		filesMutex: &sync.Mutex{},
 	}
 	log.Printf("NewLangRuntime: rt = %+v", rt.langEnv)
 	rt.networkName = rt.makeUniqueName(RunTypeNetwork)
@@ -156,6 +181,7 @@ func (rt *LangRuntime) Start(ctx context.Context) {
 		time.Sleep(10 * time.Second)
 	}
 
+	rt.resetDatabase(ctx)
 	rt.startServiceBootProcess(ctx)
 	rt.startReplProcess(ctx)
 }
@@ -166,6 +192,8 @@ func (rt *LangRuntime) Stop(ctx context.Context) {
 	// kill all processes
 	rt.stopReplProcess(ctx)
 	rt.stopCodeProcess(ctx)
+	rt.dropFiles(rt.listener.GetDockerClient())
+	rt.stopLspProcess(ctx)
 	rt.stopServiceProcess(ctx)
 	rt.stopServiceNetwork(ctx)
 
@@ -185,7 +213,9 @@ func (rt *LangRuntime) SetReplWinSize(ctx context.Context, winSize *WinSize) {
 }
 
 func (rt *LangRuntime) SendReplStdin(stdin string) {
-	if rt.replStdio != nil {
+	if rt.codeDebug && rt.codeStdio != nil {
+		rt.codeStdio.Conn.Write([]byte(stdin))
+	} else if rt.replStdio != nil {
 		rt.replStdio.Conn.Write([]byte(stdin))
 	}
 }
@@ -513,26 +543,42 @@ func (rt *LangRuntime) startCodeProcess(ctx context.Context, code *Code) error {
 	// TODO: refactor, code cleanup
 	// start new
 
+	dockerClient := rt.listener.GetDockerClient()
+	seq := rt.nextCodeSeq(dockerClient)
+	debug := code.Debug != nil
 	labels := rt.buildCommonLabels(RunTypeCode)
 	config := types.ContainerCreateConfig{
-		Name: rt.codeName,
+		Name: fmt.Sprintf("%s.%d", rt.codeName, seq),
 		Config: &container.Config{
 			Labels:       labels,
 			Image:        rt.langEnv.Image,
 			Cmd:          rt.replaceParams(rt.langEnv.Run.Cmd),
 			Env:          rt.replaceParams(rt.langEnv.Run.Env),
 			Tty:          true,
-			AttachStdin:  false,
+			AttachStdin:  debug,
 			AttachStdout: true,
 			AttachStderr: true,
-			OpenStdin:    false,
+			OpenStdin:    debug,
 			StdinOnce:    false,
 		},
 		HostConfig:       rt.defaultHostConfig(),
 		NetworkingConfig: rt.defaultNetworkingConfig(),
 	}
+	// files of the run are kept with the stopped container, removed by retainFiles
+	config.HostConfig.AutoRemove = false
+	config.Config.Env = append(config.Config.Env, "YSCRIPT_WORK_DIR="+containerRunDir)
+
+	// offline packages and environments of pad dependencies, shared by code containers
+	if depsDir := rt.listener.GetLangConfig().Docker.DepsDir; depsDir != "" {
+		config.HostConfig.Binds = append(config.HostConfig.Binds, depsDir+":"+containerDepsDir)
+		config.Config.Env = append(config.Config.Env, "YSCRIPT_DEPS_DIR="+containerDepsDir)
+	}
+	// compiled programs, shared by code containers
+	if cacheDir := rt.listener.GetLangConfig().Docker.CacheDir; cacheDir != "" {
+		config.HostConfig.Binds = append(config.HostConfig.Binds, cacheDir+":"+containerCacheDir)
+		config.Config.Env = append(config.Config.Env, "YSCRIPT_CACHE_DIR="+containerCacheDir)
+	}
 
-	dockerClient := rt.listener.GetDockerClient()
 	createResult, err := dockerClient.ContainerCreate(
 		ctx,
 		config.Config,
@@ -552,41 +598,11 @@ func (rt *LangRuntime) startCodeProcess(ctx context.Context, code *Code) error {
 	// 	}
 	// }
 
-	// write code payload
-	reqPayload := &ReqRunCode{
-		Language: code.Language,
-		Files: []CodeFile{
-			CodeFile{
-				// TODO: filename with extesion should be passed from web client
-				Name:    code.Filename + rt.langEnv.Ext,
-				Content: code.Content,
-			},
-		},
-	}
-	reqContent, err := json.Marshal(reqPayload)
-	if err != nil {
-		log.Printf("marshal req code run err = %v, req = %+v", err, reqPayload)
-		return nil
+	// write code payload, code runs in its own directory
+	if err := rt.copyDir(ctx, createResult.ID, path.Base(containerRunDir)); err != nil {
+		log.Printf("create run directory in container failure: %v", err)
 	}
-
-	// TODO: query WorkingDir and User from container inspect
-
-	tarBuffer := bytes.NewBuffer(nil)
-	tarWriter := tar.NewWriter(tarBuffer)
-	tarWriter.WriteHeader(&tar.Header{
-		Typeflag:   tar.TypeReg,
-		Name:       rt.requestName,
-		Mode:       0644,
-		Size:       int64(len(reqContent)),
-		Uname:      "ye",
-		Gname:      "ye",
-		ModTime:    time.Now(),
-		AccessTime: time.Now(),
-		ChangeTime: time.Now(),
-	})
-	tarWriter.Write(reqContent)
-
-	err = dockerClient.CopyToContainer(ctx, createResult.ID, "/home/ye/", tarBuffer, types.CopyToContainerOptions{})
+	err = rt.copyRequest(ctx, createResult.ID, rt.codeRequest(code))
 	if err != nil {
 		log.Printf("copy file to container failure: %v", err)
 	}
@@ -595,7 +611,7 @@ func (rt *LangRuntime) startCodeProcess(ctx context.Context, code *Code) error {
 	// attach
 	attachOptions := types.ContainerAttachOptions{
 		Stream:     true,
-		Stdin:      false,
+		Stdin:      debug,
 		Stdout:     true,
 		Stderr:     true,
 		Logs:       false,
@@ -605,10 +621,13 @@ func (rt *LangRuntime) startCodeProcess(ctx context.Context, code *Code) error {
 	attachResp, err := dockerClient.ContainerAttach(ctx, createResult.ID, attachOptions)
 	if err != nil {
 		log.Printf("attach to container %s, err = %v", createResult.ID, err)
+		rt.stopCodeProcess(ctx)
+		removeContainer(dockerClient, createResult.ID)
 		return fmt.Errorf("attach container %s, err = %v", createResult.ID, err)
 	}
 
 	rt.codeStdio = &attachResp
+	rt.codeDebug = debug
 
 	// start copy stream
 	outputDone := make(chan error)
@@ -620,14 +639,16 @@ func (rt *LangRuntime) startCodeProcess(ctx context.Context, code *Code) error {
 		var copyError error
 
 		if config.Config.Tty {
-			writer := NewListenerStdoutWriter(code.ID, rt.listener, false)
+			writer := NewArtifactWriter(code.ID, rt.listener, NewListenerStdoutWriter(code.ID, rt.listener, false))
 			written, copyError = io.Copy(writer, reader)
+			writer.Flush()
 		} else {
-			writer := NewListenerStdoutWriter(code.ID, rt.listener, true)
+			writer := NewArtifactWriter(code.ID, rt.listener, NewListenerStdoutWriter(code.ID, rt.listener, true))
 			written, copyError = stdcopy.StdCopy(writer, writer, reader)
 			if copyError != nil {
 				log.Printf("copy from resp.reader err = %v", copyError)
 			}
+			writer.Flush()
 		}
 
 		log.Printf("stop reading code stdout: written = %d", written)
@@ -637,10 +658,13 @@ func (rt *LangRuntime) startCodeProcess(ctx context.Context, code *Code) error {
 	log.Printf("code container attach success: %s", createResult.ID)
 
 	// process end waiter
-	okWaiter, errWaiter := dockerClient.ContainerWait(ctx, createResult.ID, container.WaitConditionRemoved)
+	okWaiter, errWaiter := dockerClient.ContainerWait(ctx, createResult.ID, container.WaitConditionNotRunning)
 
 	langConfig := rt.listener.GetLangConfig()
 	codeTimeout := time.Duration(langConfig.Docker.CodeTimeoutMS)
+	if debug && langConfig.Docker.DebugTimeoutMS > 0 {
+		codeTimeout = time.Duration(langConfig.Docker.DebugTimeoutMS)
+	}
 
 	startLine := termColor(TcYellow, fmt.Sprintf("[ start executing code ]"))
 	startLine = "\r\n" + startLine + "\r\n"
@@ -652,12 +676,15 @@ func (rt *LangRuntime) startCodeProcess(ctx context.Context, code *Code) error {
 	startOptions := types.ContainerStartOptions{}
 	err = dockerClient.ContainerStart(ctx, createResult.ID, startOptions)
 	if err != nil {
+		rt.stopCodeProcess(ctx)
+		removeContainer(dockerClient, createResult.ID)
 		return fmt.Errorf("start container err = %v", err)
 	}
 	log.Printf("repl container start success: %s", rt.codeName)
 
 	// wait and cleanup code process
 	go func() {
+		exitStatus := int32(-1)
 		select {
 		case <-codeTimeoutTicker.C:
 			log.Printf("code execution timeout: %s", createResult.ID)
@@ -666,7 +693,8 @@ func (rt *LangRuntime) startCodeProcess(ctx context.Context, code *Code) error {
 		case <-ctx.Done():
 			log.Printf("context canceled: %s", createResult.ID)
 			rt.stopCodeProcess(context.Background())
-		case <-okWaiter:
+		case res := <-okWaiter:
+			exitStatus = int32(res.StatusCode)
 		case <-errWaiter:
 		}
 
@@ -687,11 +715,144 @@ func (rt *LangRuntime) startCodeProcess(ctx context.Context, code *Code) error {
 
 		<-outputDone
 		rt.stopCodeProcess(context.Background())
+		rt.retainFiles(dockerClient, seq, createResult.ID)
+		rt.writeRunResult(code.ID, exitStatus, executeTime*1000)
 	}()
 
 	return nil
 }
 
+// yscript payload of code, Action is empty for runs
+func (rt *LangRuntime) codeRequest(code *Code) *ReqRunCode {
+	req := &ReqRunCode{
+		Language: code.Language,
+		Files: []CodeFile{
+			CodeFile{
+				// TODO: filename with extesion should be passed from web client
+				Name:    code.Filename + rt.langEnv.Ext,
+				Content: code.Content,
+			},
+		},
+		Stdin:        code.Stdin,
+		Options:      code.Options,
+		Dependencies: code.Dependencies,
+	}
+	if rt.runEnv.Database != nil {
+		req.Database = rt.runEnv.Database.Name
+	}
+	if code.Debug != nil {
+		req.Action = "debug"
+		req.Breakpoints = code.Debug.Breakpoints
+	}
+	return req
+}
+
+func (rt *LangRuntime) copyRequest(ctx context.Context, containerID string, reqPayload *ReqRunCode) error {
+	reqContent, err := json.Marshal(reqPayload)
+	if err != nil {
+		return fmt.Errorf("marshal req code run err = %v, req = %+v", err, reqPayload)
+	}
+	return rt.copyFile(ctx, containerID, rt.requestName, reqContent)
+}
+
+// copyFile writes a file to home directory of container
+func (rt *LangRuntime) copyFile(ctx context.Context, containerID string, name string, content []byte) error {
+	// TODO: query WorkingDir and User from container inspect
+
+	tarBuffer := bytes.NewBuffer(nil)
+	tarWriter := tar.NewWriter(tarBuffer)
+	tarWriter.WriteHeader(&tar.Header{
+		Typeflag:   tar.TypeReg,
+		Name:       name,
+		Mode:       0644,
+		Size:       int64(len(content)),
+		Uname:      "ye",
+		Gname:      "ye",
+		ModTime:    time.Now(),
+		AccessTime: time.Now(),
+		ChangeTime: time.Now(),
+	})
+	tarWriter.Write(content)
+
+	dockerClient := rt.listener.GetDockerClient()
+	return dockerClient.CopyToContainer(ctx, containerID, "/home/ye/", tarBuffer, types.CopyToContainerOptions{})
+}
+
+// output of yscript format or lint
+type ToolOutput struct {
+	Stdout   []byte
+	Stderr   []byte
+	ExitCode int64
+}
+
+// RunTool formats or lints code in a container of its own, output is captured instead of
+// written to terminal. Tools may run while code is running.
+func (rt *LangRuntime) RunTool(ctx context.Context, action string, code *Code) (*ToolOutput, error) {
+	toolName := fmt.Sprintf("%s.%d", rt.makeUniqueName(RunTypeTool), code.ID)
+	config := &container.Config{
+		Labels:       rt.buildCommonLabels(RunTypeTool),
+		Image:        rt.langEnv.Image,
+		Cmd:          rt.replaceParams(rt.langEnv.Run.Cmd),
+		Env:          rt.replaceParams(rt.langEnv.Run.Env),
+		Tty:          false,
+		AttachStdout: true,
+		AttachStderr: true,
+	}
+
+	dockerClient := rt.listener.GetDockerClient()
+	createResult, err := dockerClient.ContainerCreate(ctx, config, rt.defaultHostConfig(), rt.defaultNetworkingConfig(), toolName)
+	if err != nil {
+		return nil, fmt.Errorf("create tool container err = %v", err)
+	}
+	toolContainer := createResult.ID
+	// removed after exit by AutoRemove
+	defer dockerClient.ContainerKill(context.Background(), toolContainer, "SIGKILL")
+
+	reqPayload := rt.codeRequest(code)
+	reqPayload.Action = action
+	if err := rt.copyRequest(ctx, toolContainer, reqPayload); err != nil {
+		return nil, fmt.Errorf("copy tool request err = %v", err)
+	}
+
+	attachResp, err := dockerClient.ContainerAttach(ctx, toolContainer, types.ContainerAttachOptions{
+		Stream: true,
+		Stdout: true,
+		Stderr: true,
+	})
+	if err != nil {
+		return nil, fmt.Errorf("attach tool container %s, err = %v", toolContainer, err)
+	}
+	defer attachResp.Close()
+
+	okWaiter, errWaiter := dockerClient.ContainerWait(ctx, toolContainer, container.WaitConditionRemoved)
+	if err := dockerClient.ContainerStart(ctx, toolContainer, types.ContainerStartOptions{}); err != nil {
+		return nil, fmt.Errorf("start tool container err = %v", err)
+	}
+
+	stdout, stderr := bytes.NewBuffer(nil), bytes.NewBuffer(nil)
+	outputDone := make(chan error, 1)
+	go func() {
+		_, copyError := stdcopy.StdCopy(stdout, stderr, attachResp.Reader)
+		outputDone <- copyError
+	}()
+
+	output := &ToolOutput{ExitCode: -1}
+	toolTimeout := time.Duration(rt.listener.GetLangConfig().Docker.CodeTimeoutMS) * time.Millisecond
+	select {
+	case res := <-okWaiter:
+		output.ExitCode = res.StatusCode
+	case err := <-errWaiter:
+		return nil, fmt.Errorf("wait tool container %s, err = %v", toolContainer, err)
+	case <-time.After(toolTimeout):
+		return nil, fmt.Errorf("tool timeout: %s", action)
+	}
+
+	<-outputDone
+	output.Stdout = stdout.Bytes()
+	output.Stderr = stderr.Bytes()
+	return output, nil
+}
+
 func termColor(color, text string) string {
 	return fmt.Sprintf("\u001b[%sm\u001b[K%s\u001b[m\u001b[K", color, text)
 }
@@ -702,9 +863,16 @@ func (rt *LangRuntime) writeStdout(id CodeID, content []byte) {
 	}
 }
 
+func (rt *LangRuntime) writeRunResult(id CodeID, exitStatus int32, durationMillis float64) {
+	if rt.listener != nil {
+		rt.listener.WriteRunResult(id, exitStatus, durationMillis)
+	}
+}
+
 func (rt *LangRuntime) stopCodeProcess(ctx context.Context) error {
 	rt.closeAttachResponse(rt.codeStdio)
 	rt.codeStdio = nil
+	rt.codeDebug = false
 
 	if rt.codeContainer != "" {
 		dockerClient := rt.listener.GetDockerClient()
@@ -733,6 +901,12 @@ type CodeFile struct {
 }
 
 type ReqRunCode struct {
-	Language string     `json:"language"`
-	Files    []CodeFile `json:"files"`
+	Language     string       `json:"language"`
+	Files        []CodeFile   `json:"files"`
+	Stdin        *string      `json:"stdin,omitempty"`
+	Options      *RunOptions  `json:"options,omitempty"`
+	Dependencies []Dependency `json:"dependencies,omitempty"`
+	Action       string       `json:"action,omitempty"` // run if empty, debug, format, lint or db_reset
+	Breakpoints  []uint32     `json:"breakpoints,omitempty"`
+	Database     string       `json:"database,omitempty"` // database of room, see DatabaseParams
 }
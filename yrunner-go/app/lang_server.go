@@ -0,0 +1,179 @@
+package app
+
+import (
+	"bufio"
+	"context"
+	"encoding/json"
+	"errors"
+	"fmt"
+	"io"
+	"io/ioutil"
+	"log"
+	"strconv"
+	"strings"
+
+	types "github.com/docker/docker/api/types"
+	container "github.com/docker/docker/api/types/container"
+	stdcopy "github.com/docker/docker/pkg/stdcopy"
+)
+
+// Language server of room in a container of its own, with the code as its only source file.
+// Messages on stdio are framed by Content-Length headers, yeditor gets them without framing.
+
+const (
+	lspHomeDir        = "/home/ye"
+	lspMaxMessageSize = 16 * 1024 * 1024
+)
+
+// StartLsp starts language server of the runtime language, replacing the running one
+func (rt *LangRuntime) StartLsp(ctx context.Context, req *LspStart) (*LspStartResp, error) {
+	rt.stopLspProcess(ctx)
+	if rt.langEnv.Lsp == nil {
+		return nil, errors.New(ErrNotSupported)
+	}
+
+	lspName := fmt.Sprintf("%s.%d", rt.makeUniqueName(RunTypeLsp), req.ID)
+	config := &container.Config{
+		Labels:       rt.buildCommonLabels(RunTypeLsp),
+		Image:        rt.langEnv.Image,
+		Cmd:          rt.replaceParams(rt.langEnv.Lsp.Cmd),
+		Env:          rt.replaceParams(rt.langEnv.Lsp.Env),
+		Tty:          false,
+		AttachStdin:  true,
+		AttachStdout: true,
+		AttachStderr: true,
+		OpenStdin:    true,
+		StdinOnce:    false,
+	}
+
+	dockerClient := rt.listener.GetDockerClient()
+	createResult, err := dockerClient.ContainerCreate(ctx, config, rt.defaultHostConfig(), rt.defaultNetworkingConfig(), lspName)
+	if err != nil {
+		return nil, fmt.Errorf("create lsp container err = %v", err)
+	}
+	rt.lspContainer = createResult.ID
+	rt.lspID = req.ID
+
+	fileName := req.Filename + rt.langEnv.Ext
+	if err := rt.copyFile(ctx, rt.lspContainer, fileName, []byte(req.Content)); err != nil {
+		rt.stopLspProcess(ctx)
+		return nil, fmt.Errorf("copy lsp source err = %v", err)
+	}
+
+	attachResp, err := dockerClient.ContainerAttach(ctx, rt.lspContainer, types.ContainerAttachOptions{
+		Stream: true,
+		Stdin:  true,
+		Stdout: true,
+		Stderr: true,
+	})
+	if err != nil {
+		rt.stopLspProcess(ctx)
+		return nil, fmt.Errorf("attach lsp container %s, err = %v", rt.lspContainer, err)
+	}
+	rt.lspStdio = &attachResp
+	go rt.readLsp(req.ID, attachResp.Reader)
+
+	if err := dockerClient.ContainerStart(ctx, rt.lspContainer, types.ContainerStartOptions{}); err != nil {
+		rt.stopLspProcess(ctx)
+		return nil, fmt.Errorf("start lsp container err = %v", err)
+	}
+	log.Printf("lsp container start success: %s", lspName)
+
+	return &LspStartResp{
+		ID:      req.ID,
+		RootURI: "file://" + lspHomeDir,
+		URI:     "file://" + lspHomeDir + "/" + fileName,
+	}, nil
+}
+
+// SendLsp writes a message to the language server started with the same id
+func (rt *LangRuntime) SendLsp(msg *LspMessage) {
+	if rt.lspStdio == nil || rt.lspID != msg.ID {
+		return
+	}
+	header := fmt.Sprintf("Content-Length: %d\r\n\r\n", len(msg.Message))
+	rt.lspStdio.Conn.Write(append([]byte(header), msg.Message...))
+}
+
+// StopLsp stops the language server started with the id
+func (rt *LangRuntime) StopLsp(ctx context.Context, id CodeID) {
+	if rt.lspID == id {
+		rt.stopLspProcess(ctx)
+	}
+}
+
+func (rt *LangRuntime) stopLspProcess(ctx context.Context) {
+	rt.closeAttachResponse(rt.lspStdio)
+	rt.lspStdio = nil
+
+	if rt.lspContainer != "" {
+		dockerClient := rt.listener.GetDockerClient()
+		dockerClient.ContainerKill(ctx, rt.lspContainer, "SIGKILL")
+
+		rt.lspContainer = ""
+	}
+}
+
+// readLsp forwards messages of language server until its output is closed
+func (rt *LangRuntime) readLsp(id CodeID, reader io.Reader) {
+	stdout, stdoutWriter := io.Pipe()
+	go func() {
+		// stderr of servers is logging
+		_, err := stdcopy.StdCopy(stdoutWriter, ioutil.Discard, reader)
+		stdoutWriter.CloseWithError(err)
+	}()
+
+	bufReader := bufio.NewReader(stdout)
+	for {
+		message, err := readLspFrame(bufReader)
+		if err != nil {
+			log.Printf("stop reading lsp output: id = %d, err = %v", id, err)
+			break
+		}
+		if json.Valid(message) {
+			rt.writeLspMessage(id, message)
+		}
+	}
+	stdout.Close()
+	rt.writeLspStop(id)
+}
+
+// readLspFrame reads body of a message framed by Content-Length header
+func readLspFrame(reader *bufio.Reader) ([]byte, error) {
+	length := -1
+	for {
+		line, err := reader.ReadString('\n')
+		if err != nil {
+			return nil, err
+		}
+		line = strings.TrimSpace(line)
+		if line == "" {
+			break
+		}
+		parts := strings.SplitN(line, ":", 2)
+		if len(parts) == 2 && strings.EqualFold(parts[0], "Content-Length") {
+			length, err = strconv.Atoi(strings.TrimSpace(parts[1]))
+			if err != nil {
+				return nil, fmt.Errorf("invalid header: %s", line)
+			}
+		}
+	}
+	if length < 0 || length > lspMaxMessageSize {
+		return nil, fmt.Errorf("invalid content length: %d", length)
+	}
+	body := make([]byte, length)
+	_, err := io.ReadFull(reader, body)
+	return body, err
+}
+
+func (rt *LangRuntime) writeLspMessage(id CodeID, message []byte) {
+	if rt.listener != nil {
+		rt.listener.WriteLspMessage(id, message)
+	}
+}
+
+func (rt *LangRuntime) writeLspStop(id CodeID) {
+	if rt.listener != nil {
+		rt.listener.WriteLspStop(id)
+	}
+}
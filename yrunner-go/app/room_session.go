@@ -4,6 +4,8 @@ import (
 	"context"
 	"encoding/json"
 	"log"
+	"strconv"
+	"strings"
 	"time"
 
 	docker "github.com/docker/docker/client"
@@ -34,12 +36,20 @@ type RoomSession struct {
 	runtime      *LangRuntime
 	runtimeSeqNo int
 	writeQueue   chan *ServiceResponses
+	protoVersion int
+	stdoutRaw    bool
+	artifact     bool // yeditor receives artifact responses
 }
 
 var _ LangRuntimeListener = (*RoomSession)(nil)
 
 func NewRoomSession(c *gin.Context, conn *websocket.Conn, roomKey string, provider RoomInfoProvider) *RoomSession {
-	log.Printf("new room session: %v, remote = %v", roomKey, conn.RemoteAddr())
+	// yeditor of version 1 does not send version header
+	protoVersion, err := strconv.Atoi(c.GetHeader(ProtocolVersionHeader))
+	if err != nil {
+		protoVersion = 1
+	}
+	log.Printf("new room session: %v, remote = %v, proto = %v", roomKey, conn.RemoteAddr(), protoVersion)
 	return &RoomSession{
 		roomKey:      roomKey,
 		provider:     provider,
@@ -49,6 +59,8 @@ func NewRoomSession(c *gin.Context, conn *websocket.Conn, roomKey string, provid
 		runtime:      nil,
 		runtimeSeqNo: 1,
 		writeQueue:   make(chan *ServiceResponses, 0),
+		protoVersion: protoVersion,
+		stdoutRaw:    protoVersion >= 2,
 	}
 }
 
@@ -65,7 +77,57 @@ func (sess *RoomSession) GetLangConfig() *LangConfig {
 }
 
 func (sess *RoomSession) WriteStdout(id CodeID, buf []byte) {
-	sess.sendStdoutResponse(id, string(buf))
+	if sess.stdoutRaw {
+		// buf is reused by reader after returning
+		data := make([]byte, len(buf))
+		copy(data, buf)
+		sess.sendStdoutRawResponse(id, data)
+	} else {
+		sess.sendStdoutResponse(id, string(buf))
+	}
+}
+
+func (sess *RoomSession) WriteRunResult(id CodeID, exitStatus int32, durationMillis float64) {
+	sess.sendResponse(&ServiceResponses{
+		Run: &ResultRun{
+			Ok: &RunResp{
+				ID:             id,
+				ExitStatus:     exitStatus,
+				DurationMillis: durationMillis,
+			},
+		},
+	})
+}
+
+func (sess *RoomSession) WriteLspMessage(id CodeID, message []byte) {
+	sess.sendResponse(&ServiceResponses{
+		Lsp: &ResultLsp{
+			Ok: &LspMessage{
+				ID:      id,
+				Message: json.RawMessage(message),
+			},
+		},
+	})
+}
+
+func (sess *RoomSession) WriteLspStop(id CodeID) {
+	sess.sendResponse(&ServiceResponses{
+		LspStop: &ResultLspStop{
+			Ok: &LspStop{ID: id},
+		},
+	})
+}
+
+// artifacts are dropped for yeditor without the capability, markers are stripped anyway
+func (sess *RoomSession) WriteArtifact(artifact *Artifact) {
+	if !sess.artifact {
+		return
+	}
+	sess.sendResponse(&ServiceResponses{
+		Artifact: &ResultArtifact{
+			Ok: artifact,
+		},
+	})
 }
 
 func (sess *RoomSession) ProcessEvents(ctx context.Context) {
@@ -76,6 +138,10 @@ func (sess *RoomSession) ProcessEvents(ctx context.Context) {
 	// start write thread
 	go sess.writeLoop(ctx)
 
+	if sess.protoVersion >= 3 {
+		sess.sendHello()
+	}
+
 	// read loop
 	sess.readLoop(ctx)
 }
@@ -92,7 +158,13 @@ func (sess *RoomSession) writeLoop(ctx context.Context) {
 				log.Printf("write queue closed: %v", sess.roomKey)
 				return
 			}
-			sess.conn.WriteJSON(resp)
+			// a single frame, yeditor does not accept continuation frames
+			data, err := json.Marshal(resp)
+			if err != nil {
+				log.Printf("json message encode err = %v", err)
+				continue
+			}
+			sess.conn.WriteMessage(websocket.TextMessage, data)
 		}
 	}
 }
@@ -186,7 +258,50 @@ func (sess *RoomSession) sendStdoutResponse(id CodeID, data string) {
 	})
 }
 
+func (sess *RoomSession) sendStdoutRawResponse(id CodeID, data []byte) {
+	sess.sendResponse(&ServiceResponses{
+		StdoutRaw: &ResultStdoutRaw{
+			Ok: &StdoutRawResp{
+				ID:   id,
+				Data: data,
+			},
+		},
+	})
+}
+
+func (sess *RoomSession) sendHello() {
+	sess.sendResponse(&ServiceResponses{
+		Hello: &ResultHello{
+			Ok: &Hello{
+				Version:      ProtocolVersion,
+				Capabilities: Capabilities,
+			},
+		},
+	})
+}
+
+// hello from yeditor carries negotiated version and capabilities
+func (sess *RoomSession) onHello(hello *Hello) {
+	log.Printf("hello from yeditor: %v, version = %v, capabilities = %v", sess.roomKey, hello.Version, hello.Capabilities)
+	if hello.Version < 3 || hello.Version > ProtocolVersion {
+		sess.sendResponse(&ServiceResponses{
+			Hello: &ResultHello{
+				Err: ErrProtocolVersion,
+			},
+		})
+		return
+	}
+	sess.protoVersion = hello.Version
+	sess.stdoutRaw = hello.HasCapability(CapStdoutRaw)
+	sess.artifact = hello.HasCapability(CapArtifact)
+}
+
 func (sess *RoomSession) onRequestMessage(ctx context.Context, msgReq *ServiceRequests) {
+	// hello is accepted in any state
+	if msgReq.Hello != nil {
+		sess.onHello(msgReq.Hello)
+		return
+	}
 	switch sess.state {
 	case SessStateNone:
 		sess.onEventStateNone(ctx, msgReq)
@@ -228,6 +343,20 @@ func (sess *RoomSession) onEventStateReady(ctx context.Context, msgReq *ServiceR
 		sess.doReplStdin(ctx, msgReq.Stdin)
 	} else if msgReq.Run != nil {
 		sess.doRunCode(ctx, msgReq.Run)
+	} else if msgReq.Format != nil {
+		go sess.doFormatCode(ctx, sess.runtime, msgReq.Format)
+	} else if msgReq.Lint != nil {
+		go sess.doLintCode(ctx, sess.runtime, msgReq.Lint)
+	} else if msgReq.LspStart != nil {
+		sess.doStartLsp(ctx, msgReq.LspStart)
+	} else if msgReq.Lsp != nil {
+		sess.runtime.SendLsp(msgReq.Lsp)
+	} else if msgReq.LspStop != nil {
+		sess.runtime.StopLsp(ctx, msgReq.LspStop.ID)
+	} else if msgReq.ListFiles != nil {
+		go sess.doListFiles(ctx, sess.runtime, msgReq.ListFiles)
+	} else if msgReq.FetchFile != nil {
+		go sess.doFetchFile(ctx, sess.runtime, msgReq.FetchFile)
 	} else {
 		log.Printf("state: Ready, unsupported request: %v", msgReq)
 	}
@@ -298,3 +427,97 @@ func (sess *RoomSession) doRunCode(ctx context.Context, code *Code) {
 		})
 	}
 }
+
+// error output of yscript, without the prefix of its error report
+func toolError(output *ToolOutput) string {
+	return strings.TrimSpace(strings.TrimPrefix(string(output.Stderr), "Error: "))
+}
+
+// formatter runs in a container of its own, not blocking requests of the session
+func (sess *RoomSession) doFormatCode(ctx context.Context, runtime *LangRuntime, code *Code) {
+	output, err := runtime.RunTool(ctx, "format", code)
+	if err != nil {
+		log.Printf("format code %v, err = %v", sess.roomKey, err)
+		sess.sendResponse(&ServiceResponses{
+			Format: &ResultFormat{
+				Err: ErrServiceInternal,
+			},
+		})
+		return
+	}
+	resp := &FormatResp{ID: code.ID}
+	if output.ExitCode == 0 {
+		resp.Content = string(output.Stdout)
+	} else {
+		resp.Error = toolError(output)
+	}
+	sess.sendResponse(&ServiceResponses{
+		Format: &ResultFormat{
+			Ok: resp,
+		},
+	})
+}
+
+func (sess *RoomSession) doLintCode(ctx context.Context, runtime *LangRuntime, code *Code) {
+	output, err := runtime.RunTool(ctx, "lint", code)
+	if err != nil {
+		log.Printf("lint code %v, err = %v", sess.roomKey, err)
+		sess.sendResponse(&ServiceResponses{
+			Lint: &ResultLint{
+				Err: ErrServiceInternal,
+			},
+		})
+		return
+	}
+	resp := &LintResp{ID: code.ID, Diagnostics: []Diagnostic{}}
+	if output.ExitCode != 0 {
+		resp.Error = toolError(output)
+	} else if err := json.Unmarshal(output.Stdout, &resp.Diagnostics); err != nil {
+		resp.Error = "invalid linter output"
+	}
+	sess.sendResponse(&ServiceResponses{
+		Lint: &ResultLint{
+			Ok: resp,
+		},
+	})
+}
+
+// language server is stopped by reset with the runtime
+func (sess *RoomSession) doStartLsp(ctx context.Context, req *LspStart) {
+	resp, err := sess.runtime.StartLsp(ctx, req)
+	if err != nil {
+		log.Printf("start lsp %v, err = %v", sess.roomKey, err)
+		errCode := ErrServiceInternal
+		if err.Error() == ErrNotSupported {
+			errCode = ErrNotSupported
+		}
+		sess.sendResponse(&ServiceResponses{
+			LspStart: &ResultLspStart{
+				Err: errCode,
+			},
+		})
+		return
+	}
+	sess.sendResponse(&ServiceResponses{
+		LspStart: &ResultLspStart{
+			Ok: resp,
+		},
+	})
+}
+
+// files are copied from the container of the latest run, not blocking requests of the session
+func (sess *RoomSession) doListFiles(ctx context.Context, runtime *LangRuntime, req *ListFiles) {
+	sess.sendResponse(&ServiceResponses{
+		ListFiles: &ResultListFiles{
+			Ok: runtime.ListFiles(ctx, req),
+		},
+	})
+}
+
+func (sess *RoomSession) doFetchFile(ctx context.Context, runtime *LangRuntime, req *FetchFile) {
+	sess.sendResponse(&ServiceResponses{
+		FetchFile: &ResultFetchFile{
+			Ok: runtime.FetchFile(ctx, req),
+		},
+	})
+}
@@ -83,11 +83,17 @@ func NewWebService(serviceOptions *ServiceOptions) (*gin.Engine, error) {
 	}
 
 	engine := gin.Default()
+	engine.GET("/health", onGetHealth)
 	engine.GET("/stats", onGetStats(roomService))
 	engine.GET("/ws/:room_key", onRoomSocket(roomService))
 	return engine, nil
 }
 
+// polled by yeditor runner pool
+func onGetHealth(c *gin.Context) {
+	c.String(http.StatusOK, "ok")
+}
+
 func onGetStats(roomService *RoomService) gin.HandlerFunc {
 	return func(c *gin.Context) {
 		stats := roomService.GetStats()
@@ -5,12 +5,37 @@ import (
 	"fmt"
 )
 
+// Protocol version, yeditor sends its version in ProtocolVersionHeader.
+// Stdout is sent as raw bytes (StdoutRaw) to yeditor of version 2 or later.
+// Yeditor of version 3 or later receives Hello first and answers with its Hello.
+const (
+	ProtocolVersion       = 3
+	ProtocolVersionHeader = "X-Yeditor-Runner-Proto"
+)
+
+// Capabilities
+const (
+	CapStdoutRaw = "stdout_raw"
+	CapCodeTools = "code_tools"
+	CapLsp       = "lsp"
+	CapDebug     = "debug"
+	CapDatabase  = "database"
+	CapArtifact  = "artifact"
+	CapRunFiles  = "run_files"
+)
+
+var Capabilities = []string{CapStdoutRaw, CapCodeTools, CapLsp, CapDebug, CapDatabase, CapArtifact, CapRunFiles}
+
 // ServiceResponses Error Messages
 const (
 	ErrServiceInternal = "err_service_internal"
 	ErrInitRoomExists  = "err_init_room_exists"
 	ErrInvalidRoomKey  = "err_invalid_room_key"
 	ErrCodeAlreadyRunning = "err_code_already_running"
+	ErrProtocolVersion = "err_protocol_version"
+	ErrNotSupported    = "err_not_supported"
+	ErrFileNotFound    = "err_file_not_found"
+	ErrFileTooLarge    = "err_file_too_large"
 )
 
 // ServiceStats Response
@@ -24,10 +49,18 @@ type ServiceError struct {
 
 // ServiceRequests
 type ServiceRequests struct {
-	Reset   *RunEnv  `json:"reset,omitempty"`
-	Run     *Code    `json:"run,omitempty"`
-	WinSize *WinSize `json:"win_size,omitempty"`
-	Stdin   string   `json:"stdin,omitempty"`
+	Hello     *Hello      `json:"hello,omitempty"`
+	Reset     *RunEnv     `json:"reset,omitempty"`
+	Run       *Code       `json:"run,omitempty"`
+	WinSize   *WinSize    `json:"win_size,omitempty"`
+	Stdin     string      `json:"stdin,omitempty"`
+	Format    *Code       `json:"format,omitempty"`
+	Lint      *Code       `json:"lint,omitempty"`
+	LspStart  *LspStart   `json:"lsp_start,omitempty"`
+	Lsp       *LspMessage `json:"lsp,omitempty"`
+	LspStop   *LspStop    `json:"lsp_stop,omitempty"`
+	ListFiles *ListFiles  `json:"list_files,omitempty"`
+	FetchFile *FetchFile  `json:"fetch_file,omitempty"`
 }
 
 func ParseServiceRequests(data []byte) (*ServiceRequests, error) {
@@ -41,13 +74,27 @@ func ParseServiceRequests(data []byte) (*ServiceRequests, error) {
 
 // ServiceResponses
 type ServiceResponses struct {
-	Init    *ResultInit    `json:"init,omitempty"`
-	Reset   *ResultReset   `json:"reset,omitempty"`
-	Run     *ResultRun     `json:"run,omitempty"`
-	WinSize *ResultWinSize `json:"win_size,omitempty"`
-	Stdout  *ResultStdout  `json:"stdout,omitempty"`
+	Hello     *ResultHello     `json:"hello,omitempty"`
+	Init      *ResultInit      `json:"init,omitempty"`
+	Reset     *ResultReset     `json:"reset,omitempty"`
+	Run       *ResultRun       `json:"run,omitempty"`
+	WinSize   *ResultWinSize   `json:"win_size,omitempty"`
+	Stdout    *ResultStdout    `json:"stdout,omitempty"`
+	StdoutRaw *ResultStdoutRaw `json:"stdout_raw,omitempty"`
+	Format    *ResultFormat    `json:"format,omitempty"`
+	Lint      *ResultLint      `json:"lint,omitempty"`
+	LspStart  *ResultLspStart  `json:"lsp_start,omitempty"`
+	Lsp       *ResultLsp       `json:"lsp,omitempty"`
+	LspStop   *ResultLspStop   `json:"lsp_stop,omitempty"`
+	Artifact  *ResultArtifact  `json:"artifact,omitempty"`
+	ListFiles *ResultListFiles `json:"list_files,omitempty"`
+	FetchFile *ResultFetchFile `json:"fetch_file,omitempty"`
 }
 
+type ResultHello struct {
+	Ok  *Hello `json:"ok,omitempty"`
+	Err string `json:"err,omitempty"`
+}
 type ResultInit struct {
 	Ok  *InitResp `json:"ok,omitempty"`
 	Err string    `json:"err,omitempty"`
@@ -68,6 +115,57 @@ type ResultStdout struct {
 	Ok  *StdoutResp `json:"ok,omitempty"`
 	Err string      `json:"err,omitempty"`
 }
+type ResultStdoutRaw struct {
+	Ok  *StdoutRawResp `json:"ok,omitempty"`
+	Err string         `json:"err,omitempty"`
+}
+type ResultFormat struct {
+	Ok  *FormatResp `json:"ok,omitempty"`
+	Err string      `json:"err,omitempty"`
+}
+type ResultLint struct {
+	Ok  *LintResp `json:"ok,omitempty"`
+	Err string    `json:"err,omitempty"`
+}
+type ResultLspStart struct {
+	Ok  *LspStartResp `json:"ok,omitempty"`
+	Err string        `json:"err,omitempty"`
+}
+type ResultLsp struct {
+	Ok  *LspMessage `json:"ok,omitempty"`
+	Err string      `json:"err,omitempty"`
+}
+type ResultLspStop struct {
+	Ok  *LspStop `json:"ok,omitempty"`
+	Err string   `json:"err,omitempty"`
+}
+type ResultArtifact struct {
+	Ok  *Artifact `json:"ok,omitempty"`
+	Err string    `json:"err,omitempty"`
+}
+type ResultListFiles struct {
+	Ok  *FileList `json:"ok,omitempty"`
+	Err string    `json:"err,omitempty"`
+}
+type ResultFetchFile struct {
+	Ok  *FileData `json:"ok,omitempty"`
+	Err string    `json:"err,omitempty"`
+}
+
+// hello
+type Hello struct {
+	Version      int      `json:"version"`
+	Capabilities []string `json:"capabilities"`
+}
+
+func (h *Hello) HasCapability(name string) bool {
+	for _, c := range h.Capabilities {
+		if c == name {
+			return true
+		}
+	}
+	return false
+}
 
 // win size
 type WinSize struct {
@@ -78,9 +176,18 @@ type WinSize struct {
 type InitResp struct{}
 
 type RunEnv struct {
-	WinSize  WinSize `json:"win_size,omitempty"`
-	Language string  `json:"language,omitempty"`
-	Boot     *Code   `json:"boot,omitempty"`
+	WinSize  WinSize         `json:"win_size,omitempty"`
+	Language string          `json:"language,omitempty"`
+	Boot     *Code           `json:"boot,omitempty"`
+	Options  *RunOptions     `json:"options,omitempty"`
+	Database *DatabaseParams `json:"database,omitempty"` // database of room for sql languages
+}
+
+// throwaway database of room, recreated from seed by yscript on every reset,
+// dropped with the service container of the runtime
+type DatabaseParams struct {
+	Name string `json:"name"`
+	Seed string `json:"seed,omitempty"`
 }
 
 type ResetResp struct{}
@@ -88,10 +195,34 @@ type ResetResp struct{}
 type CodeID uint32
 
 type Code struct {
-	ID       CodeID `json:"id"`
-	Language string `json:"language"`
-	Filename string `json:"filename"`
-	Content  string `json:"content"`
+	ID           CodeID       `json:"id"`
+	Language     string       `json:"language"`
+	Filename     string       `json:"filename"`
+	Content      string       `json:"content"`
+	Stdin        *string      `json:"stdin,omitempty"` // program input, piped to stdin
+	Options      *RunOptions  `json:"options,omitempty"`
+	Dependencies []Dependency `json:"dependencies,omitempty"`
+	Debug        *DebugParams `json:"debug,omitempty"` // run under debugger of yscript
+}
+
+// lines of breakpoints, stop locations are written to output as
+// ESC ] 5379 ; ye-debug-stop=<line> BEL
+type DebugParams struct {
+	Breakpoints []uint32 `json:"breakpoints"`
+}
+
+// package of pad dependency manifest, resolved by yscript from offline packages
+type Dependency struct {
+	Name    string `json:"name"`
+	Version string `json:"version,omitempty"`
+}
+
+// compiler flags, program args, version and env, validated by yscript
+type RunOptions struct {
+	CompilerFlags []string          `json:"compiler_flags,omitempty"`
+	Args          []string          `json:"args,omitempty"`
+	Version       *string           `json:"version,omitempty"`
+	Env           map[string]string `json:"env,omitempty"`
 }
 
 type RunResp struct {
@@ -104,3 +235,95 @@ type StdoutResp struct {
 	ID   CodeID `json:"id"`
 	Data string `json:"data"`
 }
+
+// raw output, base64 in json
+type StdoutRawResp struct {
+	ID   CodeID `json:"id"`
+	Data []byte `json:"data"`
+}
+
+// format and lint, error is output of formatter or linter when it failed
+type FormatResp struct {
+	ID      CodeID `json:"id"`
+	Content string `json:"content"`
+	Error   string `json:"error,omitempty"`
+}
+
+type LintResp struct {
+	ID          CodeID       `json:"id"`
+	Diagnostics []Diagnostic `json:"diagnostics"`
+	Error       string       `json:"error,omitempty"`
+}
+
+// linter message at 1-based line and column, severity is error, warning or info
+type Diagnostic struct {
+	Line     uint32 `json:"line"`
+	Column   uint32 `json:"column"`
+	Severity string `json:"severity"`
+	Message  string `json:"message"`
+}
+
+// language server of room, started with the code as its only source file
+type LspStart struct {
+	ID       CodeID `json:"id"`
+	Language string `json:"language"`
+	Filename string `json:"filename"`
+	Content  string `json:"content"`
+}
+
+type LspStartResp struct {
+	ID      CodeID `json:"id"`
+	RootURI string `json:"root_uri"`
+	URI     string `json:"uri"`
+}
+
+// json-rpc message of the language server started by LspStart of same id, without framing
+type LspMessage struct {
+	ID      CodeID          `json:"id"`
+	Message json.RawMessage `json:"message"`
+}
+
+type LspStop struct {
+	ID CodeID `json:"id"`
+}
+
+// file written by code to output/, data is base64 in json
+type Artifact struct {
+	ID   CodeID `json:"id"`
+	Name string `json:"name"`
+	Mime string `json:"mime"`
+	Data []byte `json:"data"`
+}
+
+// files of the latest run, kept until FilesTTLMS or the next run
+type ListFiles struct {
+	ID CodeID `json:"id"`
+}
+
+// files over MaxSize bytes are refused with ErrFileTooLarge
+type FetchFile struct {
+	ID      CodeID `json:"id"`
+	Name    string `json:"name"`
+	MaxSize int64  `json:"max_size"`
+}
+
+// regular file of run, name is relative to its work directory
+type RunFile struct {
+	Name string `json:"name"`
+	Size int64  `json:"size"`
+}
+
+// errors are in payload, matched to requests by id
+type FileList struct {
+	ID    CodeID    `json:"id"`
+	Files []RunFile `json:"files"`
+	Error string    `json:"error,omitempty"`
+}
+
+// data is base64 in json, empty on error
+type FileData struct {
+	ID    CodeID `json:"id"`
+	Name  string `json:"name"`
+	Data  []byte `json:"data"`
+	Error string `json:"error,omitempty"`
+}
@@ -0,0 +1,47 @@
+package app
+
+import (
+	"encoding/json"
+	"io/ioutil"
+	"reflect"
+	"testing"
+)
+
+// messages shared with ycommon tests, both sides must read and write them unchanged
+type protocolFixtures struct {
+	Requests  []json.RawMessage `json:"requests"`
+	Responses []json.RawMessage `json:"responses"`
+}
+
+func assertRoundTrip(t *testing.T, data []byte, msg interface{}) {
+	if err := json.Unmarshal(data, msg); err != nil {
+		t.Fatalf("decode failure: %v, json = %s", err, data)
+	}
+	encoded, err := json.Marshal(msg)
+	if err != nil {
+		t.Fatalf("encode failure: %v, msg = %+v", err, msg)
+	}
+	var expected, actual interface{}
+	json.Unmarshal(data, &expected)
+	json.Unmarshal(encoded, &actual)
+	if !reflect.DeepEqual(expected, actual) {
+		t.Errorf("round trip mismatch: expected = %s, actual = %s", data, encoded)
+	}
+}
+
+func TestServiceMessagesRoundTrip(t *testing.T) {
+	data, err := ioutil.ReadFile("../../docs/protocol/runner_messages.json")
+	if err != nil {
+		t.Fatalf("read fixtures failure: %v", err)
+	}
+	fixtures := protocolFixtures{}
+	if err := json.Unmarshal(data, &fixtures); err != nil {
+		t.Fatalf("parse fixtures failure: %v", err)
+	}
+	for _, req := range fixtures.Requests {
+		assertRoundTrip(t, req, &ServiceRequests{})
+	}
+	for _, resp := range fixtures.Responses {
+		assertRoundTrip(t, resp, &ServiceResponses{})
+	}
+}
@@ -0,0 +1,94 @@
+use failure::Fallible;
+use regex::Regex;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+// Rich output of runs. Code writes markdown, html or images to output/ of its directory,
+// the files are written to stdout as OSC sequences at the end of run, runners strip them
+// and send them to yeditor as artifacts.
+
+pub const OUTPUT_DIR: &str = "output";
+
+const MARKER_PREFIX: &str = "\x1b]5379;ye-artifact=";
+const MARKER_SUFFIX: &str = "\x07";
+const MAX_ARTIFACTS: usize = 16;
+const MAX_ARTIFACT_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Empty output directory in run directory, files of previous runs are removed
+pub fn prepare_output_dir(run_dir: &Path) -> Fallible<()> {
+    let output_dir = run_dir.join(OUTPUT_DIR);
+    if output_dir.exists() {
+        fs::remove_dir_all(&output_dir)?;
+    }
+    fs::create_dir_all(&output_dir)?;
+    Ok(())
+}
+
+/// Writes files of output directory to stdout by name, skipped files are reported to stderr
+pub fn write_artifacts(run_dir: &Path) -> Fallible<()> {
+    lazy_static! {
+        // separator of marker fields is never in names
+        static ref VALID_NAME: Regex = Regex::new(r"^[a-zA-Z0-9_]+[.a-zA-Z0-9_-]*$").unwrap();
+    }
+
+    let mut entries = match fs::read_dir(run_dir.join(OUTPUT_DIR)) {
+        Ok(entries) => entries.collect::<Result<Vec<_>, _>>()?,
+        Err(_) => return Ok(()),
+    };
+    entries.sort_by_key(|entry| entry.file_name());
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut count = 0;
+    for entry in entries {
+        // symbolic links and directories are not followed
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let mime = match mime_type(&name) {
+            Some(mime) if VALID_NAME.is_match(&name) => mime,
+            _ => {
+                eprintln!("artifact not supported: {}", name);
+                continue;
+            }
+        };
+        if metadata.len() > MAX_ARTIFACT_SIZE {
+            eprintln!("artifact too large: {}", name);
+            continue;
+        }
+        if count == MAX_ARTIFACTS {
+            eprintln!("too many artifacts, skipped from: {}", name);
+            break;
+        }
+        let data = fs::read(entry.path())?;
+        write!(
+            stdout,
+            "{}{};{};{}{}",
+            MARKER_PREFIX,
+            name,
+            mime,
+            base64::encode(&data),
+            MARKER_SUFFIX
+        )?;
+        count += 1;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+fn mime_type(name: &str) -> Option<&'static str> {
+    let ext = Path::new(name).extension()?.to_str()?.to_lowercase();
+    let mime = match ext.as_str() {
+        "md" | "markdown" => "text/markdown",
+        "html" | "htm" => "text/html",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        _ => return None,
+    };
+    Some(mime)
+}
@@ -3,14 +3,30 @@ use structopt::StructOpt;
 #[derive(Debug, Clone, StructOpt)]
 /// Ported from https://github.com/prasmussen/glot-code-runner
 pub struct CmdLineOpt {
-    #[structopt(short = "w", long = "work-dir")]
-    /// Working directory, if not specified, will use a temporary directory.
+    #[structopt(short = "w", long = "work-dir", env = "YSCRIPT_WORK_DIR")]
+    /// Working directory, if not specified, will use a temporary directory removed after the run.
     pub work_dir: Option<String>,
 
     #[structopt(short = "f", long = "file")]
     /// Read input from file, if not specified, read from STDIN.
     pub file: Option<String>,
 
+    #[structopt(long = "deps-dir", env = "YSCRIPT_DEPS_DIR")]
+    /// Directory of offline packages and environments for dependencies of payload.
+    pub deps_dir: Option<String>,
+
+    #[structopt(long = "cache-dir", env = "YSCRIPT_CACHE_DIR")]
+    /// Directory of compile cache, if not specified, will use yscript-cache in temporary directory.
+    pub cache_dir: Option<String>,
+
+    #[structopt(long = "cache-size-mb", default_value = "512")]
+    /// Size limit of compile cache, least recently used builds are removed over it.
+    pub cache_size_mb: u64,
+
+    #[structopt(long = "no-cache")]
+    /// Always compile, do not read or write compile cache.
+    pub no_cache: bool,
+
     #[structopt(short = "l", long = "list")]
     /// List all supported languages.
     pub list: bool,
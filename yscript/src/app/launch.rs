@@ -34,15 +34,24 @@ pub fn launch() -> Fallible<()> {
     } else if opts.sample {
         println!("{}", *SAMPLE_JSON);
     } else {
+        if !opts.no_cache {
+            let cache_dir = opts
+                .cache_dir
+                .clone()
+                .unwrap_or_else(language::default_cache_dir);
+            language::enable_build_cache(cache_dir.as_str(), opts.cache_size_mb * 1024 * 1024);
+        }
+
         let work_dir = opts.work_dir.as_ref().cloned();
+        let deps_dir = opts.deps_dir.as_ref().cloned();
         match opts.file.as_ref() {
             None => {
-                script::run_from_reader(io::stdin(), work_dir)?;
+                script::run_from_reader(io::stdin(), work_dir, deps_dir)?;
             }
             Some(filename) => {
                 let fin = fs::File::open(filename)?;
                 let fin = io::BufReader::new(fin);
-                script::run_from_reader(fin, opts.work_dir.clone())?;
+                script::run_from_reader(fin, opts.work_dir.clone(), deps_dir)?;
             }
         };
     }
@@ -1,3 +1,4 @@
+pub mod artifact;
 pub mod cli;
 pub mod launch;
 pub mod script;
@@ -2,11 +2,13 @@ use failure::{format_err, Fallible, ResultExt};
 use regex::Regex;
 use std::fs;
 use std::io;
+use std::os::unix::process::ExitStatusExt;
 use std::path::{Path, PathBuf};
 use std::process::ExitStatus;
 use tempfile;
 
-use crate::language;
+use super::artifact;
+use crate::language::{self, Dependency, Manifest, RunOptions};
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Payload {
@@ -14,6 +16,42 @@ pub struct Payload {
     pub files: Vec<InMemoryFile>,
     pub stdin: Option<String>,
     pub command: Option<String>,
+    // compiler flags, program args, version and env, checked by language
+    #[serde(default)]
+    pub options: RunOptions,
+    // packages resolved offline from --deps-dir, by language
+    #[serde(default)]
+    pub dependencies: Vec<Dependency>,
+    // run the code, or format or lint the first file
+    #[serde(default)]
+    pub action: Action,
+    // lines of the first file, 1-based, for debug action
+    #[serde(default)]
+    pub breakpoints: Vec<u32>,
+    // database of room for sql languages, runs connect to it
+    #[serde(default)]
+    pub database: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Run,
+    // formatted content of the first file to stdout
+    Format,
+    // json array of diagnostics to stdout
+    Lint,
+    // run the code under debugger, commands are read from stdin
+    Debug,
+    // recreate database of room, the first file is the seed
+    DbReset,
+    DbDrop,
+}
+
+impl Default for Action {
+    fn default() -> Self {
+        Action::Run
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -22,14 +60,22 @@ pub struct InMemoryFile {
     pub content: String,
 }
 
-pub fn run_from_reader<R: io::Read>(reader: R, work_dir: Option<String>) -> Fallible<ExitStatus> {
+pub fn run_from_reader<R: io::Read>(
+    reader: R,
+    work_dir: Option<String>,
+    deps_dir: Option<String>,
+) -> Fallible<ExitStatus> {
     let payload = serde_json::from_reader::<R, Payload>(reader)
         .context(format_err!("invalid payload format"))?;
 
-    run_from_payload(payload, work_dir)
+    run_from_payload(payload, work_dir, deps_dir)
 }
 
-pub fn run_from_payload(payload: Payload, work_dir: Option<String>) -> Fallible<ExitStatus> {
+pub fn run_from_payload(
+    payload: Payload,
+    work_dir: Option<String>,
+    deps_dir: Option<String>,
+) -> Fallible<ExitStatus> {
     if payload.files.is_empty() {
         return Err(format_err!("no files"));
     }
@@ -39,15 +85,69 @@ pub fn run_from_payload(payload: Payload, work_dir: Option<String>) -> Fallible<
 
     let (file_paths, _temp_dir) = write_files(&payload.files, work_dir.as_ref())?;
 
-    if payload.command.is_none() {
+    if payload.action == Action::Debug {
+        let language = payload.language.as_str();
+        let file_paths = file_paths.iter().map(|s| s.as_str()).collect();
+        let stdin = payload.stdin.as_ref().map(|s| s.as_str());
+        let manifest = Manifest {
+            dependencies: &payload.dependencies,
+            deps_dir: deps_dir.as_ref().map(|s| s.as_str()),
+        };
+        return language::debug_code(
+            language,
+            file_paths,
+            stdin,
+            &payload.breakpoints,
+            &payload.options,
+            &manifest,
+        );
+    }
+    if payload.action == Action::DbReset || payload.action == Action::DbDrop {
+        let language = payload.language.as_str();
+        let database = payload
+            .database
+            .as_ref()
+            .ok_or(format_err!("database not provided"))?;
+        return match payload.action {
+            Action::DbReset => language::reset_database(
+                language,
+                file_paths.iter().map(|s| s.as_str()).collect(),
+                database,
+            ),
+            _ => language::drop_database(language, database),
+        };
+    }
+    if payload.action != Action::Run {
+        return check_code(&payload, file_paths.iter().map(|s| s.as_str()).collect());
+    }
+
+    // files of output directory are written to stdout after the run
+    let run_dir = Path::new(&file_paths[0])
+        .parent()
+        .ok_or(format_err!("invalid run directory"))?;
+    artifact::prepare_output_dir(run_dir)?;
+
+    let status = if payload.command.is_none() {
         let language = payload.language.as_str();
         let file_paths = file_paths.iter().map(|s| s.as_str()).collect();
         let stdin = payload.stdin.as_ref().map(|s| s.as_str());
-        language::run_code(language, file_paths, stdin)
+        let manifest = Manifest {
+            dependencies: &payload.dependencies,
+            deps_dir: deps_dir.as_ref().map(|s| s.as_str()),
+        };
+        match payload.database.as_ref() {
+            Some(database) if language::is_database(language) => language::run_database_code(
+                language,
+                file_paths,
+                database,
+                &payload.options,
+                &manifest,
+            ),
+            _ => language::run_code(language, file_paths, stdin, &payload.options, &manifest),
+        }
     } else {
-        let run_dir = Path::new(&file_paths[0])
-            .parent()
-            .and_then(|p| p.to_str())
+        let run_dir = run_dir
+            .to_str()
             .ok_or(format_err!("invalid run directory"))?;
 
         let command = payload
@@ -57,7 +157,25 @@ pub fn run_from_payload(payload: Payload, work_dir: Option<String>) -> Fallible<
 
         let stdin = payload.stdin.as_ref().map(|s| s.as_str());
         language::run_bash_stdin(run_dir, command.as_str(), stdin)
-    }
+    }?;
+    artifact::write_artifacts(run_dir)?;
+    Ok(status)
+}
+
+// formatter or linter output, errors are reported by exit status
+fn check_code(payload: &Payload, file_paths: Vec<&str>) -> Fallible<ExitStatus> {
+    use std::io::Write;
+
+    let language = payload.language.as_str();
+    let output = match payload.action {
+        Action::Format => language::format_code(language, file_paths)?,
+        _ => serde_json::to_string(&language::lint_code(language, file_paths)?)?,
+    };
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    stdout.write_all(output.as_bytes())?;
+    stdout.flush()?;
+    Ok(ExitStatus::from_raw(0))
 }
 
 fn write_files(
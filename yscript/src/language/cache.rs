@@ -0,0 +1,230 @@
+use failure::{format_err, Fallible};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs;
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, ExitStatus};
+use std::sync::RwLock;
+use std::time::UNIX_EPOCH;
+
+use super::util;
+
+// Content addressed cache of compiler outputs. Entries are directories named by hash of
+// language, toolchain, flags and sources, holding output files of a successful compile.
+// Least recently used entries are removed when the cache grows over its size limit.
+
+// rewritten on every hit, its mtime orders entries for eviction
+const USED_FILE: &str = ".used";
+
+lazy_static! {
+    static ref BUILD_CACHE: RwLock<Option<BuildCache>> = RwLock::new(None);
+}
+
+struct BuildCache {
+    dir: PathBuf,
+    max_bytes: u64,
+}
+
+/// Enables build cache of this process, compiles are not cached otherwise
+pub fn enable_build_cache(dir: &str, max_bytes: u64) {
+    if let Ok(mut cache) = BUILD_CACHE.write() {
+        *cache = Some(BuildCache {
+            dir: PathBuf::from(dir),
+            max_bytes,
+        });
+    }
+}
+
+/// Compile step of a run, output files are restored from cache when sources are unchanged
+pub struct Build<'a> {
+    pub lang: &'a str,
+    // compiler command, its executable identifies the toolchain version
+    pub compiler: &'a str,
+    pub flags: Vec<&'a str>,
+    pub sources: Vec<&'a str>,
+    // file names in work directory, or `*.ext` for all files with extension
+    pub outputs: Vec<&'a str>,
+    // other inputs of compile, e.g. dependency manifest
+    pub extra: String,
+}
+
+impl<'a> Build<'a> {
+    pub fn new(
+        lang: &'a str,
+        compiler: &'a str,
+        sources: Vec<&'a str>,
+        outputs: Vec<&'a str>,
+    ) -> Self {
+        Build {
+            lang,
+            compiler,
+            flags: vec![],
+            sources,
+            outputs,
+            extra: String::new(),
+        }
+    }
+
+    /// Runs `compile` in work_dir unless outputs of the same build are cached
+    pub fn run<F>(&self, work_dir: &str, compile: F) -> Fallible<ExitStatus>
+    where
+        F: FnOnce() -> Fallible<ExitStatus>,
+    {
+        let cache = BUILD_CACHE
+            .read()
+            .map_err(|_| format_err!("build cache poisoned"))?;
+        let cache = match cache.as_ref() {
+            None => return compile(),
+            Some(cache) => cache,
+        };
+        let key = match self.key() {
+            // e.g. compiler not in PATH, compile reports the error
+            Err(_) => return compile(),
+            Ok(key) => key,
+        };
+
+        let entry_dir = cache.dir.join(key.as_str());
+        // cache failures fall back to compiling, output of yscript is output of the program
+        if entry_dir.join(USED_FILE).exists() && restore(&entry_dir, Path::new(work_dir)).is_ok() {
+            return Ok(ExitStatus::from_raw(0));
+        }
+
+        let status = compile()?;
+        if status.success() {
+            let _ = cache.store(key.as_str(), Path::new(work_dir), &self.outputs);
+        }
+        Ok(status)
+    }
+
+    fn key(&self) -> Fallible<String> {
+        let mut hasher = Sha256::new();
+        for part in &[
+            self.lang,
+            toolchain(self.compiler)?.as_str(),
+            self.extra.as_str(),
+        ] {
+            hasher.input(part.as_bytes());
+            hasher.input(b"\0");
+        }
+        for flag in self.flags.iter() {
+            hasher.input(flag.as_bytes());
+            hasher.input(b"\0");
+        }
+        for source in self.sources.iter() {
+            hasher.input(util::basename(source)?.as_bytes());
+            hasher.input(b"\0");
+            hasher.input(util::read_file(source)?);
+            hasher.input(b"\0");
+        }
+        Ok(format!("{:x}", hasher.result()))
+    }
+}
+
+impl BuildCache {
+    fn store(&self, key: &str, work_dir: &Path, outputs: &[&str]) -> Fallible<()> {
+        let tmp_dir = self.dir.join(format!("{}.{}.tmp", key, process::id()));
+        fs::create_dir_all(&tmp_dir)?;
+        let copied = copy_outputs(work_dir, &tmp_dir, outputs)
+            .and_then(|_| Ok(fs::write(tmp_dir.join(USED_FILE), "")?));
+        if let Err(err) = copied {
+            let _ = fs::remove_dir_all(&tmp_dir);
+            return Err(err);
+        }
+        if fs::rename(&tmp_dir, self.dir.join(key)).is_err() {
+            // stored by another run in the meantime
+            let _ = fs::remove_dir_all(&tmp_dir);
+        }
+        self.evict()
+    }
+
+    // removes least recently used entries over max_bytes
+    fn evict(&self) -> Fallible<()> {
+        let mut entries = vec![];
+        let mut total: u64 = 0;
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let used_at = match fs::metadata(path.join(USED_FILE)).and_then(|m| m.modified()) {
+                // in-flight stores
+                Err(_) => continue,
+                Ok(time) => time,
+            };
+            let size = dir_size(&path)?;
+            total += size;
+            entries.push((used_at, size, path));
+        }
+
+        entries.sort();
+        for (_, size, path) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            fs::remove_dir_all(&path)?;
+            total -= size;
+        }
+        Ok(())
+    }
+}
+
+fn restore(entry_dir: &Path, work_dir: &Path) -> Fallible<()> {
+    for entry in fs::read_dir(entry_dir)? {
+        let entry = entry?;
+        if entry.file_name() != USED_FILE {
+            fs::copy(entry.path(), work_dir.join(entry.file_name()))?;
+        }
+    }
+    fs::write(entry_dir.join(USED_FILE), "")?;
+    Ok(())
+}
+
+fn copy_outputs(work_dir: &Path, to_dir: &Path, outputs: &[&str]) -> Fallible<()> {
+    for entry in fs::read_dir(work_dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_str().unwrap_or("");
+        let matched = outputs.iter().any(|output| {
+            if output.starts_with("*.") {
+                name.ends_with(&output[1..])
+            } else {
+                name == *output
+            }
+        });
+        if matched && entry.file_type()?.is_file() {
+            fs::copy(entry.path(), to_dir.join(name))?;
+        }
+    }
+    Ok(())
+}
+
+fn dir_size(dir: &Path) -> Fallible<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(dir)? {
+        size += entry?.metadata()?.len();
+    }
+    Ok(size)
+}
+
+// path, size and mtime of compiler executable found in PATH
+fn toolchain(compiler: &str) -> Fallible<String> {
+    let paths = env::var_os("PATH").ok_or(format_err!("PATH not set"))?;
+    let exe = env::split_paths(&paths)
+        .map(|dir| dir.join(compiler))
+        .find(|path| path.is_file())
+        .ok_or(format_err!("compiler not found: {}", compiler))?
+        .canonicalize()?;
+    let meta = fs::metadata(&exe)?;
+    let mtime = meta
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    Ok(format!("{}:{}:{}", exe.display(), meta.len(), mtime))
+}
+
+/// Cache directory if none is given
+pub fn default_cache_dir() -> String {
+    env::temp_dir()
+        .join("yscript-cache")
+        .to_string_lossy()
+        .into_owned()
+}
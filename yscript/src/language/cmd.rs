@@ -1,11 +1,22 @@
 use failure::{format_err, Fallible, ResultExt};
+use std::collections::BTreeMap;
 use std::io::{self, BufWriter, Write};
 use std::path::Path;
-use std::process::{Child, Command, ExitStatus, Stdio};
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
 
 pub fn run_stdin(work_dir: &str, args: Vec<&str>, stdin: &str) -> Fallible<ExitStatus> {
+    run_stdin_env(work_dir, args, stdin, &BTreeMap::new())
+}
+
+pub fn run_stdin_env(
+    work_dir: &str,
+    args: Vec<&str>,
+    stdin: &str,
+    env: &BTreeMap<String, String>,
+) -> Fallible<ExitStatus> {
     let mut child: Child = Command::new(args[0])
         .args(&args[1..])
+        .envs(env)
         .current_dir(work_dir)
         .stdin(Stdio::piped())
         .stdout(Stdio::inherit())
@@ -32,6 +43,24 @@ pub fn run_stdin(work_dir: &str, args: Vec<&str>, stdin: &str) -> Fallible<ExitS
     Ok(exit_status)
 }
 
+/// Runs command reading stdin of this process, e.g. the terminal of debuggers
+pub fn run_terminal_env(
+    work_dir: &str,
+    args: Vec<&str>,
+    env: &BTreeMap<String, String>,
+) -> Fallible<ExitStatus> {
+    let exit_status = Command::new(args[0])
+        .args(&args[1..])
+        .envs(env)
+        .current_dir(work_dir)
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+        .context(format_err!("run command failure: {:?}", args))?;
+    Ok(exit_status)
+}
+
 pub fn run(work_dir: &str, args: Vec<&str>) -> Fallible<ExitStatus> {
     run_stdin(work_dir, args, "")
 }
@@ -43,3 +72,14 @@ pub fn run_bash_stdin(work_dir: &str, command: &str, stdin: &str) -> Fallible<Ex
 pub fn run_bash(work_dir: &str, command: &str) -> Fallible<ExitStatus> {
     run_bash_stdin(work_dir, command, "")
 }
+
+/// Runs command without stdin, output is captured instead of written to stdout
+pub fn output(work_dir: &str, args: Vec<&str>) -> Fallible<Output> {
+    let output = Command::new(args[0])
+        .args(&args[1..])
+        .current_dir(work_dir)
+        .stdin(Stdio::null())
+        .output()
+        .context(format_err!("spawn command failure: {:?}", args))?;
+    Ok(output)
+}
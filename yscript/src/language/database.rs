@@ -0,0 +1,182 @@
+use failure::{format_err, Fallible, ResultExt};
+use regex::Regex;
+use std::env;
+use std::process::ExitStatus;
+
+use super::{cmd, util};
+
+// Databases of rooms for sql languages. A database is created with a user of the same name,
+// granted nothing but the database, runs of the code connect as this user. Query results
+// are printed as bordered tables.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Engine {
+    Mysql,
+    Postgres,
+}
+
+impl Engine {
+    fn of(lang: &str) -> Fallible<Self> {
+        match lang {
+            "mysql" => Ok(Engine::Mysql),
+            "postgres" => Ok(Engine::Postgres),
+            _ => Err(format_err!("database not supported: {}", lang)),
+        }
+    }
+
+    fn host(&self) -> String {
+        let (name, default_host) = match self {
+            Engine::Mysql => ("MYSQL_HOST", "127.0.0.1"),
+            Engine::Postgres => ("POSTGRES_HOST", "127.0.0.1"),
+        };
+        env::var(name).unwrap_or(default_host.to_owned())
+    }
+
+    fn admin_user(&self) -> String {
+        let (name, default_user) = match self {
+            Engine::Mysql => ("MYSQL_USER", "root"),
+            Engine::Postgres => ("POSTGRES_USER", "postgres"),
+        };
+        env::var(name).unwrap_or(default_user.to_owned())
+    }
+
+    // client of user, connected to database if any, stopping at the first error in batch mode
+    fn client_args<'a>(
+        &self,
+        host: &'a str,
+        user: &'a str,
+        database: Option<&'a str>,
+        batch: bool,
+    ) -> Vec<&'a str> {
+        let mut args = match self {
+            Engine::Mysql => vec!["mysql", "--host", host, "--user", user],
+            Engine::Postgres => vec!["psql", "--no-psqlrc", "--host", host, "--username", user],
+        };
+        if let Some(database) = database {
+            match self {
+                Engine::Mysql => args.extend(&["--database", database]),
+                Engine::Postgres => args.extend(&["--dbname", database]),
+            }
+        }
+        match (self, batch) {
+            (Engine::Mysql, false) => args.push("--table"),
+            (Engine::Postgres, false) => args.extend(&["--pset", "border=2"]),
+            // mysql stops at errors in batch mode by default
+            (Engine::Mysql, true) => {}
+            (Engine::Postgres, true) => args.extend(&["--quiet", "--set", "ON_ERROR_STOP=1"]),
+        }
+        args
+    }
+
+    fn reset_statements(&self, name: &str) -> String {
+        match self {
+            Engine::Mysql => format!(
+                "DROP DATABASE IF EXISTS `{name}`;\n\
+                 DROP USER IF EXISTS '{name}'@'%';\n\
+                 CREATE DATABASE `{name}`;\n\
+                 CREATE USER '{name}'@'%';\n\
+                 GRANT ALL PRIVILEGES ON `{name}`.* TO '{name}'@'%';\n",
+                name = name
+            ),
+            // databases are open to every role by default
+            Engine::Postgres => format!(
+                "DROP DATABASE IF EXISTS \"{name}\";\n\
+                 DROP ROLE IF EXISTS \"{name}\";\n\
+                 CREATE ROLE \"{name}\" LOGIN;\n\
+                 CREATE DATABASE \"{name}\" OWNER \"{name}\";\n\
+                 REVOKE ALL ON DATABASE \"{name}\" FROM PUBLIC;\n",
+                name = name
+            ),
+        }
+    }
+
+    fn drop_statements(&self, name: &str) -> String {
+        match self {
+            Engine::Mysql => format!(
+                "DROP DATABASE IF EXISTS `{name}`;\n\
+                 DROP USER IF EXISTS '{name}'@'%';\n",
+                name = name
+            ),
+            Engine::Postgres => format!(
+                "DROP DATABASE IF EXISTS \"{name}\";\n\
+                 DROP ROLE IF EXISTS \"{name}\";\n",
+                name = name
+            ),
+        }
+    }
+}
+
+pub fn is_supported(lang: &str) -> bool {
+    Engine::of(lang).is_ok()
+}
+
+/// Runs statements of the first file in database as its user, or as admin without database
+pub fn run_query(lang: &str, files: Vec<&str>, database: Option<&str>) -> Fallible<ExitStatus> {
+    let engine = Engine::of(lang)?;
+    let work_dir = util::dirname(files[0])?;
+    let content = read_sql(files[0])?;
+
+    let host = engine.host();
+    let user = match database {
+        Some(name) => validate_name(name)?.to_owned(),
+        None => engine.admin_user(),
+    };
+    let args = engine.client_args(&host, &user, database, false);
+    cmd::run_stdin(work_dir, args, &content)
+}
+
+/// Recreates database and its user, then runs statements of the first file as the user
+pub fn reset_database(lang: &str, files: Vec<&str>, name: &str) -> Fallible<ExitStatus> {
+    let engine = Engine::of(lang)?;
+    let name = validate_name(name)?;
+    let work_dir = util::dirname(files[0])?;
+    let seed = read_sql(files[0])?;
+
+    let host = engine.host();
+    let admin_user = engine.admin_user();
+    let admin_database = match engine {
+        Engine::Mysql => None,
+        Engine::Postgres => Some("postgres"),
+    };
+    let args = engine.client_args(&host, &admin_user, admin_database, true);
+    let status = cmd::run_stdin(work_dir, args, &engine.reset_statements(name))?;
+    if !status.success() || seed.trim().is_empty() {
+        return Ok(status);
+    }
+
+    let args = engine.client_args(&host, name, Some(name), true);
+    cmd::run_stdin(work_dir, args, &seed)
+}
+
+/// Drops database and its user
+pub fn drop_database(lang: &str, name: &str) -> Fallible<ExitStatus> {
+    let engine = Engine::of(lang)?;
+    let name = validate_name(name)?;
+
+    let host = engine.host();
+    let admin_user = engine.admin_user();
+    let admin_database = match engine {
+        Engine::Mysql => None,
+        Engine::Postgres => Some("postgres"),
+    };
+    let args = engine.client_args(&host, &admin_user, admin_database, true);
+    cmd::run_stdin(".", args, &engine.drop_statements(name))
+}
+
+// names are quoted in statements, user names of mysql are at most 32 characters
+fn validate_name(name: &str) -> Fallible<&str> {
+    lazy_static! {
+        static ref VALID_NAME: Regex = Regex::new(r"^[a-z][a-z0-9_]{0,31}$").unwrap();
+    }
+    if !VALID_NAME.is_match(name) {
+        return Err(format_err!("invalid database name: {}", name));
+    }
+    Ok(name)
+}
+
+fn read_sql(file: &str) -> Fallible<String> {
+    let file_bytes: Vec<u8> = util::read_file(file)?;
+    let content =
+        String::from_utf8(file_bytes).context(format_err!("file content is not utf8: {}", file))?;
+    Ok(content)
+}
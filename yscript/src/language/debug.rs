@@ -0,0 +1,461 @@
+use failure::{format_err, Fallible, ResultExt};
+use regex::Regex;
+use std::collections;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread;
+
+use super::options::RunOptions;
+use super::{cmd, util};
+
+// Debuggers of languages, attached to the terminal of the run. Breakpoints are 1-based lines
+// of the first file. Debuggers write STOP_MARKER to terminal whenever the program stops,
+// with the line of first file or nothing when stopped elsewhere, yeditor shows it in editors.
+
+const STOP_MARKER_PREFIX: &str = "\x1b]5379;ye-debug-stop=";
+const STOP_MARKER_SUFFIX: &str = "\x07";
+// the marker escaped in python strings of debugger scripts
+const SCRIPT_MARKER_PREFIX: &str = "\\x1b]5379;ye-debug-stop=";
+const SCRIPT_MARKER_SUFFIX: &str = "\\x07";
+
+// program input of debug runs, debuggers read commands from terminal
+const STDIN_FILE: &str = "ye_debug_stdin";
+
+type DebugFn = fn(
+    files: &[&str],
+    stdin: &str,
+    breakpoints: &[u32],
+    options: &RunOptions,
+) -> Fallible<ExitStatus>;
+type DebuggerMap = collections::HashMap<&'static str, DebugFn>;
+
+lazy_static! {
+    static ref DEBUGGERS: DebuggerMap = {
+        let mut debuggers = DebuggerMap::new();
+
+        debuggers.insert("c", debug_c);
+        debuggers.insert("cpp", debug_cpp);
+        debuggers.insert("rust", debug_rust);
+        debuggers.insert("swift", debug_swift);
+        debuggers.insert("python2", debug_python2);
+        debuggers.insert("python3", debug_python3);
+        debuggers.insert("javascript", debug_javascript);
+
+        debuggers
+    };
+
+    // stop of node inspect, e.g. `break in /tmp/x/source.js:12`
+    static ref NODE_BREAK_LINE: Regex = Regex::new(
+        r"(?i)break (?:on start )?in (?:file://)?(?P<file>[^\s:]+):(?P<line>\d+)"
+    ).unwrap();
+}
+
+/// Runs the code under debugger of language, commands are read from terminal
+pub fn debug_code(
+    lang: &str,
+    file_paths: Vec<&str>,
+    stdin: &str,
+    breakpoints: &[u32],
+    options: &RunOptions,
+) -> Fallible<ExitStatus> {
+    let debug_fn = DEBUGGERS
+        .get(lang)
+        .ok_or(format_err!("debugger not supported: {}", lang))?;
+    let mut breakpoints = breakpoints.to_vec();
+    breakpoints.sort();
+    breakpoints.dedup();
+    debug_fn(&file_paths, stdin, &breakpoints, options)
+}
+
+fn debug_c(
+    files: &[&str],
+    stdin: &str,
+    breakpoints: &[u32],
+    options: &RunOptions,
+) -> Fallible<ExitStatus> {
+    let work_dir = util::dirname(files[0])?;
+    let std_flag = options.version.as_ref().map(|v| format!("-std={}", v));
+    let mut args = vec!["clang", "-o", "main_c", "-lm"];
+    args.extend(std_flag.as_ref().map(|s| s.as_str()));
+    args.extend(options.compiler_flags.iter().map(|s| s.as_str()));
+    args.extend(&["-g", "-O0"]);
+    args.extend(util::filter_by_extension(&files.to_vec(), ".c"));
+    let status = cmd::run(work_dir, args)?;
+    if !status.success() {
+        return Ok(status);
+    }
+    run_gdb("gdb", files[0], "main_c", stdin, breakpoints, options)
+}
+
+fn debug_cpp(
+    files: &[&str],
+    stdin: &str,
+    breakpoints: &[u32],
+    options: &RunOptions,
+) -> Fallible<ExitStatus> {
+    let work_dir = util::dirname(files[0])?;
+    let std_flag = format!(
+        "-std={}",
+        options
+            .version
+            .as_ref()
+            .map(|v| v.as_str())
+            .unwrap_or("c++11")
+    );
+    let mut args = vec!["clang++", std_flag.as_str(), "-o", "main_cpp"];
+    args.extend(options.compiler_flags.iter().map(|s| s.as_str()));
+    args.extend(&["-g", "-O0"]);
+    args.extend(util::filter_by_extension(&files.to_vec(), ".cpp"));
+    let status = cmd::run(work_dir, args)?;
+    if !status.success() {
+        return Ok(status);
+    }
+    run_gdb("gdb", files[0], "main_cpp", stdin, breakpoints, options)
+}
+
+fn debug_rust(
+    files: &[&str],
+    stdin: &str,
+    breakpoints: &[u32],
+    options: &RunOptions,
+) -> Fallible<ExitStatus> {
+    let work_dir = util::dirname(files[0])?;
+    let edition_flag = options.version.as_ref().map(|v| format!("--edition={}", v));
+    let mut args = vec!["rustc", "-o", "main_rs"];
+    args.extend(edition_flag.as_ref().map(|s| s.as_str()));
+    args.extend(options.compiler_flags.iter().map(|s| s.as_str()));
+    args.extend(&["-g", "-Copt-level=0"]);
+    args.push(files[0]);
+    let status = cmd::run(work_dir, args)?;
+    if !status.success() {
+        return Ok(status);
+    }
+    // pretty printers of std types
+    run_gdb("rust-gdb", files[0], "main_rs", stdin, breakpoints, options)
+}
+
+fn debug_swift(
+    files: &[&str],
+    stdin: &str,
+    breakpoints: &[u32],
+    options: &RunOptions,
+) -> Fallible<ExitStatus> {
+    let work_dir = util::dirname(files[0])?;
+    let status = cmd::run(
+        work_dir,
+        vec!["swiftc", "-g", "-Onone", "-o", "main_swift", files[0]],
+    )?;
+    if !status.success() {
+        return Ok(status);
+    }
+    run_lldb(files[0], "main_swift", stdin, breakpoints, options)
+}
+
+fn debug_python2(
+    files: &[&str],
+    stdin: &str,
+    breakpoints: &[u32],
+    options: &RunOptions,
+) -> Fallible<ExitStatus> {
+    run_pdb("python2", files[0], stdin, breakpoints, options)
+}
+
+fn debug_python3(
+    files: &[&str],
+    stdin: &str,
+    breakpoints: &[u32],
+    options: &RunOptions,
+) -> Fallible<ExitStatus> {
+    let python = match options.version.as_ref() {
+        Some(version) => format!("python{}", version),
+        None => "python3".to_owned(),
+    };
+    run_pdb(python.as_str(), files[0], stdin, breakpoints, options)
+}
+
+// node inspect reads its commands from stdin, breakpoints are typed before terminal input.
+// Program input is not supported, debuggee shares stdin with the debugger.
+fn debug_javascript(
+    files: &[&str],
+    _stdin: &str,
+    breakpoints: &[u32],
+    options: &RunOptions,
+) -> Fallible<ExitStatus> {
+    let work_dir = util::dirname(files[0])?;
+    let source = util::basename(files[0])?;
+    let mut commands = String::new();
+    for line in breakpoints {
+        commands.push_str(format!("sb('{}', {})\n", source, line).as_str());
+    }
+    commands.push_str("cont\n");
+
+    let mut args = vec!["node"];
+    args.extend(options.compiler_flags.iter().map(|s| s.as_str()));
+    args.extend(&["inspect", files[0]]);
+    args.extend(options.args.iter().map(|s| s.as_str()));
+    let mut child = Command::new(args[0])
+        .args(&args[1..])
+        .envs(&options.env)
+        .current_dir(work_dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .context(format_err!("spawn command failure: {:?}", args))?;
+
+    let mut child_stdin = child
+        .stdin
+        .take()
+        .ok_or(format_err!("get child stdin failure: {:?}", args))?;
+    thread::spawn(move || {
+        if child_stdin.write_all(commands.as_bytes()).is_err() {
+            return;
+        }
+        let _ = io::copy(&mut io::stdin(), &mut child_stdin);
+    });
+
+    let mut child_stdout = child
+        .stdout
+        .take()
+        .ok_or(format_err!("get child stdout failure: {:?}", args))?;
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut line = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let count = child_stdout.read(&mut buf)?;
+        if count == 0 {
+            break;
+        }
+        stdout.write_all(&buf[..count])?;
+        for b in &buf[..count] {
+            if *b != b'\n' {
+                line.push(*b);
+                continue;
+            }
+            let text = String::from_utf8_lossy(&line).into_owned();
+            if let Some(caps) = NODE_BREAK_LINE.captures(text.as_str()) {
+                let stop = match util::basename(&caps["file"]) {
+                    Ok(file) if file == source => &caps["line"],
+                    _ => "",
+                };
+                write!(
+                    stdout,
+                    "{}{}{}",
+                    STOP_MARKER_PREFIX, stop, STOP_MARKER_SUFFIX
+                )?;
+            }
+            line.clear();
+        }
+        stdout.flush()?;
+    }
+
+    let status = child
+        .wait()
+        .context(format_err!("wait for command failure: {:?}", args))?;
+    write!(stdout, "{}{}", STOP_MARKER_PREFIX, STOP_MARKER_SUFFIX)?;
+    Ok(status)
+}
+
+fn run_gdb(
+    gdb: &str,
+    source_path: &str,
+    bin_file: &str,
+    stdin: &str,
+    breakpoints: &[u32],
+    options: &RunOptions,
+) -> Fallible<ExitStatus> {
+    let work_dir = util::dirname(source_path)?;
+    let source = util::basename(source_path)?;
+    let mut script = format!(
+        r#"set pagination off
+set confirm off
+python
+import os
+import gdb
+
+def ye_debug_stop(line):
+    gdb.write('{prefix}%s{suffix}' % line)
+    gdb.flush()
+
+def ye_debug_on_stop(event):
+    line = ''
+    try:
+        sal = gdb.selected_frame().find_sal()
+        if sal.symtab and os.path.basename(sal.symtab.filename) == '{source}':
+            line = str(sal.line)
+    except gdb.error:
+        pass
+    ye_debug_stop(line)
+
+gdb.events.stop.connect(ye_debug_on_stop)
+gdb.events.exited.connect(lambda event: ye_debug_stop(''))
+end
+"#,
+        prefix = SCRIPT_MARKER_PREFIX,
+        suffix = SCRIPT_MARKER_SUFFIX,
+        source = source,
+    );
+    for line in breakpoints {
+        script.push_str(format!("break {}:{}\n", source, line).as_str());
+    }
+    let mut run = "run".to_owned();
+    for arg in options.args.iter() {
+        run.push(' ');
+        run.push_str(shell_quote(arg).as_str());
+    }
+    if !stdin.is_empty() {
+        write_stdin(work_dir, stdin)?;
+        run.push_str(format!(" < {}", STDIN_FILE).as_str());
+    }
+    script.push_str(run.as_str());
+    script.push('\n');
+    fs::write(Path::new(work_dir).join("ye_debug.gdb"), script)?;
+
+    let bin_path = format!("./{}", bin_file);
+    let args = vec![gdb, "-q", "-x", "ye_debug.gdb", bin_path.as_str()];
+    cmd::run_terminal_env(work_dir, args, &options.env)
+}
+
+fn run_lldb(
+    source_path: &str,
+    bin_file: &str,
+    stdin: &str,
+    breakpoints: &[u32],
+    options: &RunOptions,
+) -> Fallible<ExitStatus> {
+    let work_dir = util::dirname(source_path)?;
+    let source = util::basename(source_path)?;
+    let module = format!(
+        r#"import os
+import lldb
+
+def stop(frame):
+    line = ''
+    entry = frame.GetLineEntry() if frame else None
+    if entry and entry.GetFileSpec().GetFilename() == '{source}':
+        line = str(entry.GetLine())
+    print('{prefix}%s{suffix}' % line)
+"#,
+        prefix = SCRIPT_MARKER_PREFIX,
+        suffix = SCRIPT_MARKER_SUFFIX,
+        source = source,
+    );
+    fs::write(Path::new(work_dir).join("ye_debug_lldb.py"), module)?;
+
+    let mut script = "command script import ye_debug_lldb.py\n".to_owned();
+    script.push_str("target stop-hook add --one-liner \"script ye_debug_lldb.stop(lldb.frame)\"\n");
+    for line in breakpoints {
+        script.push_str(format!("breakpoint set --file {} --line {}\n", source, line).as_str());
+    }
+    let mut launch = "process launch".to_owned();
+    if !stdin.is_empty() {
+        write_stdin(work_dir, stdin)?;
+        launch.push_str(format!(" --stdin {}", STDIN_FILE).as_str());
+    }
+    launch.push_str(" --");
+    for arg in options.args.iter() {
+        launch.push(' ');
+        launch.push_str(shell_quote(arg).as_str());
+    }
+    script.push_str(launch.as_str());
+    script.push('\n');
+    fs::write(Path::new(work_dir).join("ye_debug.lldb"), script)?;
+
+    let bin_path = format!("./{}", bin_file);
+    let args = vec!["lldb", "-s", "ye_debug.lldb", bin_path.as_str()];
+    cmd::run_terminal_env(work_dir, args, &options.env)
+}
+
+// pdb of the interpreter, runs the program once instead of restarting it at exit.
+// The debugger is a module of its own, pdb clears globals of __main__ for the program.
+fn run_pdb(
+    python: &str,
+    source_path: &str,
+    stdin: &str,
+    breakpoints: &[u32],
+    options: &RunOptions,
+) -> Fallible<ExitStatus> {
+    let work_dir = util::dirname(source_path)?;
+    let stdin_file = if stdin.is_empty() {
+        "None".to_owned()
+    } else {
+        write_stdin(work_dir, stdin)?;
+        format!("'{}'", STDIN_FILE)
+    };
+    let breakpoints: Vec<String> = breakpoints.iter().map(|line| line.to_string()).collect();
+    let module = format!(
+        r#"import bdb
+import os
+import pdb
+import sys
+
+
+class Debugger(pdb.Pdb):
+    started = False
+
+    def interaction(self, frame, traceback):
+        # program starts stopped at first line, it continues to breakpoints if any
+        if not self.started:
+            self.started = True
+            if self.get_all_breaks():
+                self.set_continue()
+                return
+        line = ''
+        if frame is not None and os.path.abspath(frame.f_code.co_filename) == self.source:
+            line = str(frame.f_lineno)
+        stop(line)
+        pdb.Pdb.interaction(self, frame, traceback)
+
+
+def stop(line):
+    sys.stdout.write('{prefix}%s{suffix}' % line)
+    sys.stdout.flush()
+
+
+def main():
+    source = os.path.abspath(sys.argv[1])
+    debugger = Debugger(stdin=sys.stdin, stdout=sys.stdout)
+    debugger.source = source
+    debugger.use_rawinput = False
+    for line in [{breakpoints}]:
+        debugger.set_break(source, line)
+
+    sys.argv = sys.argv[1:]
+    sys.path[0] = os.path.dirname(source)
+    if {stdin_file} is not None:
+        sys.stdin = open({stdin_file})
+    try:
+        debugger._runscript(source)
+    except (SystemExit, bdb.BdbQuit):
+        pass
+    stop('')
+"#,
+        prefix = SCRIPT_MARKER_PREFIX,
+        suffix = SCRIPT_MARKER_SUFFIX,
+        breakpoints = breakpoints.join(", "),
+        stdin_file = stdin_file,
+    );
+    fs::write(Path::new(work_dir).join("ye_debug.py"), module)?;
+
+    let mut args = vec![
+        python,
+        "-c",
+        "import ye_debug; ye_debug.main()",
+        source_path,
+    ];
+    args.extend(options.args.iter().map(|s| s.as_str()));
+    cmd::run_terminal_env(work_dir, args, &options.env)
+}
+
+fn write_stdin(work_dir: &str, stdin: &str) -> Fallible<()> {
+    fs::write(Path::new(work_dir).join(STDIN_FILE), stdin)?;
+    Ok(())
+}
+
+// single quoted argument of gdb and lldb commands
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
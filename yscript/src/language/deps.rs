@@ -0,0 +1,152 @@
+use failure::{format_err, Fallible, ResultExt};
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+// Dependency manifest of payload, resolved without network access against
+// offline packages of the dependency directory (--deps-dir):
+//
+//   cargo/vendor              crates vendored by `cargo vendor`
+//   npm/cache                 npm cache filled by `npm cache add`
+//   pip/wheels                wheels downloaded by `pip download`
+//   envs/{lang}-{hash}        environments built from a manifest, keyed by manifest hash
+
+const MAX_DEPENDENCIES: usize = 64;
+const MAX_NAME_LEN: usize = 128;
+const MAX_VERSION_LEN: usize = 64;
+
+// marks a completely built environment
+const READY_FILE: &str = ".ready";
+
+lazy_static! {
+    // crate, python distribution, or npm package with optional scope
+    static ref VALID_NAME: Regex =
+        Regex::new(r"^(@[a-zA-Z0-9_.-]+/)?[a-zA-Z0-9][a-zA-Z0-9_.-]*$").unwrap();
+    // version requirement of any package manager, no quotes or spaces
+    static ref VALID_VERSION: Regex = Regex::new(r"^[a-zA-Z0-9_.*^~<>=!,+-]*$").unwrap();
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Dependency {
+    pub name: String,
+    // version requirement, any version if empty
+    #[serde(default)]
+    pub version: String,
+}
+
+/// Dependencies of a run and directory of offline packages
+pub struct Manifest<'a> {
+    pub dependencies: &'a [Dependency],
+    pub deps_dir: Option<&'a str>,
+}
+
+impl<'a> Manifest<'a> {
+    pub fn is_empty(&self) -> bool {
+        self.dependencies.is_empty()
+    }
+
+    pub fn validate(&self) -> Fallible<()> {
+        if self.dependencies.len() > MAX_DEPENDENCIES {
+            return Err(format_err!(
+                "too many dependencies, limit is {}",
+                MAX_DEPENDENCIES
+            ));
+        }
+        for dep in self.dependencies.iter() {
+            if dep.name.len() > MAX_NAME_LEN || !VALID_NAME.is_match(dep.name.as_str()) {
+                return Err(format_err!("invalid dependency name: {:?}", dep.name));
+            }
+            if dep.version.len() > MAX_VERSION_LEN || !VALID_VERSION.is_match(dep.version.as_str())
+            {
+                return Err(format_err!(
+                    "invalid dependency version: {} {:?}",
+                    dep.name,
+                    dep.version
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Dependencies sorted by name, order of manifest does not change its environment
+    pub fn sorted(&self) -> Vec<&'a Dependency> {
+        let mut deps: Vec<_> = self.dependencies.iter().collect();
+        deps.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+        deps
+    }
+
+    pub fn deps_dir(&self) -> Fallible<&Path> {
+        let dir = self
+            .deps_dir
+            .ok_or(format_err!("dependencies not available, no --deps-dir"))?;
+        Ok(Path::new(dir))
+    }
+
+    /// Hex sha256 of sorted manifest and toolchain of environment
+    pub fn hash(&self, toolchain: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.input(toolchain.as_bytes());
+        for dep in self.sorted() {
+            hasher.input(b"\n");
+            hasher.input(dep.name.as_bytes());
+            hasher.input(b"@");
+            hasher.input(dep.version.as_bytes());
+        }
+        format!("{:x}", hasher.result())
+    }
+
+    /// Environment of the manifest, built by `build` into an empty directory on first use.
+    /// Built environments are kept and shared by all runs with the same manifest.
+    pub fn environment<F>(&self, lang: &str, toolchain: &str, build: F) -> Fallible<PathBuf>
+    where
+        F: FnOnce(&Path, &Path) -> Fallible<()>,
+    {
+        let deps_dir = self.deps_dir()?;
+        let envs_dir = deps_dir.join("envs");
+        let env_dir = envs_dir.join(format!("{}-{}", lang, self.hash(toolchain)));
+        if env_dir.join(READY_FILE).exists() {
+            return Ok(env_dir);
+        }
+
+        // build aside and rename, concurrent builds of a manifest do not see partial results
+        let build_dir = envs_dir.join(format!(
+            "{}.{}.tmp",
+            env_dir.file_name().and_then(|s| s.to_str()).unwrap_or(lang),
+            process::id()
+        ));
+        if build_dir.exists() {
+            fs::remove_dir_all(&build_dir)?;
+        }
+        fs::create_dir_all(&build_dir).context(format_err!(
+            "create environment directory failure: {:?}",
+            build_dir
+        ))?;
+
+        let built = build(&build_dir, deps_dir)
+            .and_then(|_| Ok(fs::write(build_dir.join(READY_FILE), "")?));
+        if let Err(err) = built {
+            let _ = fs::remove_dir_all(&build_dir);
+            return Err(err);
+        }
+
+        if let Err(err) = fs::rename(&build_dir, &env_dir) {
+            let _ = fs::remove_dir_all(&build_dir);
+            // built by another run in the meantime
+            if !env_dir.join(READY_FILE).exists() {
+                return Err(format_err!(
+                    "install environment failure: {:?}, err = {}",
+                    env_dir,
+                    err
+                ));
+            }
+        }
+        Ok(env_dir)
+    }
+}
+
+pub fn path_str(path: &Path) -> Fallible<&str> {
+    path.to_str()
+        .ok_or(format_err!("invalid dependency path: {:?}", path))
+}
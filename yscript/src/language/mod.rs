@@ -2,9 +2,21 @@ use failure::{format_err, Fallible, ResultExt};
 use std::collections;
 use std::process::ExitStatus;
 
+mod cache;
 mod cmd;
+mod database;
+mod debug;
+mod deps;
+mod options;
+mod tools;
 mod util;
 
+pub use self::cache::{default_cache_dir, enable_build_cache};
+pub use self::deps::{Dependency, Manifest};
+use self::options::AllowList;
+pub use self::options::RunOptions;
+pub use self::tools::{format_code, lint_code};
+
 mod run_assembly;
 mod run_bash;
 mod run_c;
@@ -43,48 +55,77 @@ mod run_typescript;
 mod run_vb;
 
 type LangRunFn = fn(files: Vec<&str>, stdin: &str) -> Fallible<ExitStatus>;
-type LangRunFnMap = collections::HashMap<&'static str, LangRunFn>;
+type LangRunOptionsFn = fn(
+    files: Vec<&str>,
+    stdin: &str,
+    options: &RunOptions,
+    manifest: &Manifest,
+) -> Fallible<ExitStatus>;
+
+#[derive(Clone, Copy)]
+enum LangRunner {
+    Plain(LangRunFn),
+    // options are validated by allow list of the language module before running
+    WithOptions(LangRunOptionsFn, &'static AllowList),
+}
+
+type LangRunFnMap = collections::HashMap<&'static str, LangRunner>;
 
 lazy_static! {
     static ref LANGUAGES: LangRunFnMap = {
         let mut languages = LangRunFnMap::new();
 
-        languages.insert("assembly", run_assembly::run as LangRunFn);
-        languages.insert("bash", run_bash::run as LangRunFn);
-        languages.insert("c", run_c::run as LangRunFn);
-        languages.insert("clojure", run_clojure::run as LangRunFn);
-        languages.insert("coffeescript", run_coffeescript::run as LangRunFn);
-        languages.insert("cpp", run_cpp::run as LangRunFn);
-        languages.insert("crystal", run_crystal::run as LangRunFn);
-        languages.insert("csharp", run_csharp::run as LangRunFn);
-        languages.insert("elixir", run_elixir::run as LangRunFn);
-        languages.insert("erlang", run_erlang::run as LangRunFn);
-        languages.insert("fsharp", run_fsharp::run as LangRunFn);
-        languages.insert("go", run_go::run as LangRunFn);
-        languages.insert("groovy", run_groovy::run as LangRunFn);
-        languages.insert("haskell", run_haskell::run as LangRunFn);
-        languages.insert("java", run_java::run as LangRunFn);
-        languages.insert("javascript", run_javascript::run as LangRunFn);
-        languages.insert("julia", run_julia::run as LangRunFn);
-        languages.insert("kotlin", run_kotlin::run as LangRunFn);
-        languages.insert("lua", run_lua::run as LangRunFn);
-        languages.insert("markdown", run_markdown::run as LangRunFn);
-        languages.insert("mysql", run_mysql::run as LangRunFn);
-        languages.insert("objc", run_objc::run as LangRunFn);
-        languages.insert("ocaml", run_ocaml::run as LangRunFn);
-        languages.insert("perl", run_perl::run as LangRunFn);
-        languages.insert("perl6", run_perl6::run as LangRunFn);
-        languages.insert("php", run_php::run as LangRunFn);
-        languages.insert("postgres", run_postgres::run as LangRunFn);
-        languages.insert("python2", run_python2::run as LangRunFn);
-        languages.insert("python3", run_python3::run as LangRunFn);
-        languages.insert("r", run_r::run as LangRunFn);
-        languages.insert("ruby", run_ruby::run as LangRunFn);
-        languages.insert("rust", run_rust::run as LangRunFn);
-        languages.insert("scala", run_scala::run as LangRunFn);
-        languages.insert("swift", run_swift::run as LangRunFn);
-        languages.insert("typescript", run_typescript::run as LangRunFn);
-        languages.insert("vb", run_vb::run as LangRunFn);
+        languages.insert("assembly", LangRunner::Plain(run_assembly::run));
+        languages.insert("bash", LangRunner::Plain(run_bash::run));
+        languages.insert("c", LangRunner::WithOptions(run_c::run, &run_c::ALLOW_LIST));
+        languages.insert("clojure", LangRunner::Plain(run_clojure::run));
+        languages.insert("coffeescript", LangRunner::Plain(run_coffeescript::run));
+        languages.insert(
+            "cpp",
+            LangRunner::WithOptions(run_cpp::run, &run_cpp::ALLOW_LIST),
+        );
+        languages.insert("crystal", LangRunner::Plain(run_crystal::run));
+        languages.insert("csharp", LangRunner::Plain(run_csharp::run));
+        languages.insert("elixir", LangRunner::Plain(run_elixir::run));
+        languages.insert("erlang", LangRunner::Plain(run_erlang::run));
+        languages.insert("fsharp", LangRunner::Plain(run_fsharp::run));
+        languages.insert(
+            "go",
+            LangRunner::WithOptions(run_go::run, &run_go::ALLOW_LIST),
+        );
+        languages.insert("groovy", LangRunner::Plain(run_groovy::run));
+        languages.insert("haskell", LangRunner::Plain(run_haskell::run));
+        languages.insert("java", LangRunner::Plain(run_java::run));
+        languages.insert(
+            "javascript",
+            LangRunner::WithOptions(run_javascript::run, &run_javascript::ALLOW_LIST),
+        );
+        languages.insert("julia", LangRunner::Plain(run_julia::run));
+        languages.insert("kotlin", LangRunner::Plain(run_kotlin::run));
+        languages.insert("lua", LangRunner::Plain(run_lua::run));
+        languages.insert("markdown", LangRunner::Plain(run_markdown::run));
+        languages.insert("mysql", LangRunner::Plain(run_mysql::run));
+        languages.insert("objc", LangRunner::Plain(run_objc::run));
+        languages.insert("ocaml", LangRunner::Plain(run_ocaml::run));
+        languages.insert("perl", LangRunner::Plain(run_perl::run));
+        languages.insert("perl6", LangRunner::Plain(run_perl6::run));
+        languages.insert("php", LangRunner::Plain(run_php::run));
+        languages.insert("postgres", LangRunner::Plain(run_postgres::run));
+        languages.insert("python2", LangRunner::Plain(run_python2::run));
+        languages.insert(
+            "python3",
+            LangRunner::WithOptions(run_python3::run, &run_python3::ALLOW_LIST),
+        );
+        languages.insert("r", LangRunner::Plain(run_r::run));
+        languages.insert("ruby", LangRunner::Plain(run_ruby::run));
+        languages.insert(
+            "rust",
+            LangRunner::WithOptions(run_rust::run, &run_rust::ALLOW_LIST),
+        );
+        languages.insert("scala", LangRunner::Plain(run_scala::run));
+        languages.insert("swift", LangRunner::Plain(run_swift::run));
+        languages.insert("typescript", LangRunner::Plain(run_typescript::run));
+        languages.insert("vb", LangRunner::Plain(run_vb::run));
 
         languages
     };
@@ -104,12 +145,94 @@ pub fn is_supported(language: &str) -> bool {
     languages().contains_key(language)
 }
 
-pub fn run_code(lang: &str, file_paths: Vec<&str>, stdin: Option<&str>) -> Fallible<ExitStatus> {
+pub fn run_code(
+    lang: &str,
+    file_paths: Vec<&str>,
+    stdin: Option<&str>,
+    options: &RunOptions,
+    manifest: &Manifest,
+) -> Fallible<ExitStatus> {
     assert!(file_paths.len() > 0);
+    let stdin = stdin.unwrap_or("");
     match languages().get(lang) {
         None => Err(format_err!("not supported language")),
-        Some(run_fn) => run_fn(file_paths, stdin.unwrap_or("")),
+        Some(LangRunner::Plain(run_fn)) => {
+            AllowList::NONE
+                .validate(options)
+                .context(format_err!("run options not supported: {}", lang))?;
+            if !manifest.is_empty() {
+                return Err(format_err!("dependencies not supported: {}", lang));
+            }
+            run_fn(file_paths, stdin)
+        }
+        Some(LangRunner::WithOptions(run_fn, allow_list)) => {
+            allow_list.validate(options)?;
+            if !manifest.is_empty() {
+                if !allow_list.dependencies {
+                    return Err(format_err!("dependencies not supported: {}", lang));
+                }
+                manifest.validate()?;
+            }
+            run_fn(file_paths, stdin, options, manifest)
+        }
+    }
+}
+
+/// Runs code under debugger of language with breakpoints at lines of the first file
+pub fn debug_code(
+    lang: &str,
+    file_paths: Vec<&str>,
+    stdin: Option<&str>,
+    breakpoints: &[u32],
+    options: &RunOptions,
+    manifest: &Manifest,
+) -> Fallible<ExitStatus> {
+    assert!(file_paths.len() > 0);
+    let allow_list = match languages().get(lang) {
+        None => return Err(format_err!("not supported language")),
+        Some(LangRunner::Plain(_)) => &AllowList::NONE,
+        Some(LangRunner::WithOptions(_, allow_list)) => *allow_list,
+    };
+    allow_list.validate(options)?;
+    if !manifest.is_empty() {
+        return Err(format_err!(
+            "dependencies not supported in debug runs: {}",
+            lang
+        ));
+    }
+    debug::debug_code(lang, file_paths, stdin.unwrap_or(""), breakpoints, options)
+}
+
+/// Runs sql of the first file in database of room as its user, tables are printed bordered
+pub fn run_database_code(
+    lang: &str,
+    file_paths: Vec<&str>,
+    database: &str,
+    options: &RunOptions,
+    manifest: &Manifest,
+) -> Fallible<ExitStatus> {
+    assert!(file_paths.len() > 0);
+    AllowList::NONE
+        .validate(options)
+        .context(format_err!("run options not supported: {}", lang))?;
+    if !manifest.is_empty() {
+        return Err(format_err!("dependencies not supported: {}", lang));
     }
+    database::run_query(lang, file_paths, Some(database))
+}
+
+/// Recreates database of room and runs the seed of the first file
+pub fn reset_database(lang: &str, file_paths: Vec<&str>, database: &str) -> Fallible<ExitStatus> {
+    assert!(file_paths.len() > 0);
+    database::reset_database(lang, file_paths, database)
+}
+
+pub fn drop_database(lang: &str, database: &str) -> Fallible<ExitStatus> {
+    database::drop_database(lang, database)
+}
+
+pub fn is_database(lang: &str) -> bool {
+    database::is_supported(lang)
 }
 
 pub fn run_bash_stdin(work_dir: &str, command: &str, stdin: Option<&str>) -> Fallible<ExitStatus> {
@@ -0,0 +1,114 @@
+use failure::{format_err, Fallible};
+use std::collections::BTreeMap;
+
+// Run options of payload, checked against allow list of language modules.
+// Languages without an allow list reject any option.
+
+const MAX_ITEMS: usize = 32;
+const MAX_ITEM_LEN: usize = 256;
+
+// characters after the prefix of a `*` flag pattern
+const FLAG_VALUE_CHARS: &str = "_=.,+-";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RunOptions {
+    pub compiler_flags: Vec<String>,
+    // arguments of the program
+    pub args: Vec<String>,
+    // language version, toolchain default if none
+    pub version: Option<String>,
+    // environment of the program
+    pub env: BTreeMap<String, String>,
+}
+
+impl RunOptions {
+    pub fn is_empty(&self) -> bool {
+        self.compiler_flags.is_empty()
+            && self.args.is_empty()
+            && self.version.is_none()
+            && self.env.is_empty()
+    }
+}
+
+/// Options accepted by a language module
+pub struct AllowList {
+    // exact flags, or prefixes ending with `*`
+    pub compiler_flags: &'static [&'static str],
+    pub versions: &'static [&'static str],
+    pub env: &'static [&'static str],
+    pub args: bool,
+    // dependency manifest, see deps
+    pub dependencies: bool,
+}
+
+impl AllowList {
+    pub const NONE: AllowList = AllowList {
+        compiler_flags: &[],
+        versions: &[],
+        env: &[],
+        args: false,
+        dependencies: false,
+    };
+
+    pub fn validate(&self, options: &RunOptions) -> Fallible<()> {
+        let counts = [
+            options.compiler_flags.len(),
+            options.args.len(),
+            options.env.len(),
+        ];
+        if counts.iter().any(|count| *count > MAX_ITEMS) {
+            return Err(format_err!("too many run options, limit is {}", MAX_ITEMS));
+        }
+
+        for flag in options.compiler_flags.iter() {
+            if !self.compiler_flags.iter().any(|p| flag_matches(p, flag)) {
+                return Err(format_err!("compiler flag not allowed: {}", flag));
+            }
+        }
+
+        if !options.args.is_empty() && !self.args {
+            return Err(format_err!("program arguments not supported"));
+        }
+        for arg in options.args.iter() {
+            check_value(arg)?;
+        }
+
+        if let Some(version) = options.version.as_ref() {
+            if !self.versions.contains(&version.as_str()) {
+                return Err(format_err!(
+                    "version not supported: {}, supported = [{}]",
+                    version,
+                    self.versions.join(", ")
+                ));
+            }
+        }
+
+        for (name, value) in options.env.iter() {
+            if !self.env.contains(&name.as_str()) {
+                return Err(format_err!("environment variable not allowed: {}", name));
+            }
+            check_value(value)?;
+        }
+        Ok(())
+    }
+}
+
+fn flag_matches(pattern: &str, flag: &str) -> bool {
+    if !pattern.ends_with('*') {
+        return pattern == flag;
+    }
+    let prefix = &pattern[..pattern.len() - 1];
+    flag.len() <= MAX_ITEM_LEN
+        && flag.starts_with(prefix)
+        && flag[prefix.len()..]
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || FLAG_VALUE_CHARS.contains(c))
+}
+
+fn check_value(value: &str) -> Fallible<()> {
+    if value.len() > MAX_ITEM_LEN || value.contains('\0') {
+        return Err(format_err!("invalid run option value: {:?}", value));
+    }
+    Ok(())
+}
@@ -2,14 +2,48 @@ use failure::{format_err, Fallible};
 use std::path::Path;
 use std::process::ExitStatus;
 
+use super::deps::Manifest;
+use super::options::{AllowList, RunOptions};
 use super::{cmd, util};
 
-pub fn run(files: Vec<&str>, stdin: &str) -> Fallible<ExitStatus> {
+pub const ALLOW_LIST: AllowList = AllowList {
+    compiler_flags: &[
+        "-O0",
+        "-O1",
+        "-O2",
+        "-O3",
+        "-Os",
+        "-g",
+        "-w",
+        "-Wall",
+        "-Wextra",
+        "-Werror",
+        "-pedantic",
+        "-D*",
+        "-fsanitize=address",
+        "-fsanitize=undefined",
+    ],
+    // -std of clang
+    versions: &["c89", "c99", "c11", "c17", "gnu99", "gnu11", "gnu17"],
+    env: &["ASAN_OPTIONS", "UBSAN_OPTIONS", "TZ"],
+    args: true,
+    dependencies: false,
+};
+
+pub fn run(
+    files: Vec<&str>,
+    stdin: &str,
+    options: &RunOptions,
+    _manifest: &Manifest,
+) -> Fallible<ExitStatus> {
     let work_dir = util::dirname(files[0])?;
     let bin_file = "main_c";
 
     let mut source_files = util::filter_by_extension(&files, ".c");
     let mut args = vec!["clang", "-o", bin_file, "-lm"];
+    let std_flag = options.version.as_ref().map(|v| format!("-std={}", v));
+    args.extend(std_flag.as_ref().map(|s| s.as_str()));
+    args.extend(options.compiler_flags.iter().map(|s| s.as_str()));
     args.append(&mut source_files);
     let status: ExitStatus = cmd::run(work_dir, args)?;
 
@@ -21,5 +55,7 @@ pub fn run(files: Vec<&str>, stdin: &str) -> Fallible<ExitStatus> {
     let bin_path = bin_path_buf
         .to_str()
         .ok_or(format_err!("invalid bin_path"))?;
-    cmd::run_stdin(work_dir, vec![bin_path], stdin)
+    let mut args = vec![bin_path];
+    args.extend(options.args.iter().map(|s| s.as_str()));
+    cmd::run_stdin_env(work_dir, args, stdin, &options.env)
 }
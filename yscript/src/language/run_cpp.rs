@@ -2,14 +2,54 @@ use failure::{format_err, Fallible};
 use std::path::Path;
 use std::process::ExitStatus;
 
+use super::deps::Manifest;
+use super::options::{AllowList, RunOptions};
 use super::{cmd, util};
 
-pub fn run(files: Vec<&str>, stdin: &str) -> Fallible<ExitStatus> {
+pub const ALLOW_LIST: AllowList = AllowList {
+    compiler_flags: &[
+        "-O0",
+        "-O1",
+        "-O2",
+        "-O3",
+        "-Os",
+        "-g",
+        "-w",
+        "-Wall",
+        "-Wextra",
+        "-Werror",
+        "-pedantic",
+        "-D*",
+        "-fsanitize=address",
+        "-fsanitize=undefined",
+    ],
+    // -std of clang++, c++11 if none
+    versions: &["c++11", "c++14", "c++17", "c++20"],
+    env: &["ASAN_OPTIONS", "UBSAN_OPTIONS", "TZ"],
+    args: true,
+    dependencies: false,
+};
+
+pub fn run(
+    files: Vec<&str>,
+    stdin: &str,
+    options: &RunOptions,
+    _manifest: &Manifest,
+) -> Fallible<ExitStatus> {
     let work_dir = util::dirname(files[0])?;
     let bin_file = "main_cpp";
 
+    let std_flag = format!(
+        "-std={}",
+        options
+            .version
+            .as_ref()
+            .map(|v| v.as_str())
+            .unwrap_or("c++11")
+    );
     let mut source_files = util::filter_by_extension(&files, ".cpp");
-    let mut args = vec!["clang++", "-std=c++11", "-o", bin_file];
+    let mut args = vec!["clang++", std_flag.as_str(), "-o", bin_file];
+    args.extend(options.compiler_flags.iter().map(|s| s.as_str()));
     args.append(&mut source_files);
     let status: ExitStatus = cmd::run(work_dir, args)?;
 
@@ -22,5 +62,7 @@ pub fn run(files: Vec<&str>, stdin: &str) -> Fallible<ExitStatus> {
         .to_str()
         .ok_or(format_err!("invalid bin_path"))?;
 
-    cmd::run_stdin(work_dir, vec![bin_path], stdin)
+    let mut args = vec![bin_path];
+    args.extend(options.args.iter().map(|s| s.as_str()));
+    cmd::run_stdin_env(work_dir, args, stdin, &options.env)
 }
@@ -2,18 +2,20 @@ use failure::{format_err, Fallible};
 use std::path::Path;
 use std::process::ExitStatus;
 
-use super::{cmd, util};
+use super::{cache, cmd, util};
 
 pub fn run(files: Vec<&str>, stdin: &str) -> Fallible<ExitStatus> {
     let work_dir = util::dirname(files[0])?;
     let bin_file = "main_cs.exe";
 
-    let mut source_files = util::filter_by_extension(&files, ".cs");
-    let bin_arg = format!("-out:{}", bin_file);
-    let mut args = vec!["mcs", bin_arg.as_str()];
-    args.append(&mut source_files);
-
-    let status: ExitStatus = cmd::run(work_dir, args)?;
+    let source_files = util::filter_by_extension(&files, ".cs");
+    let build = cache::Build::new("csharp", "mcs", source_files.clone(), vec![bin_file]);
+    let status: ExitStatus = build.run(work_dir, || {
+        let bin_arg = format!("-out:{}", bin_file);
+        let mut args = vec!["mcs", bin_arg.as_str()];
+        args.extend(source_files);
+        cmd::run(work_dir, args)
+    })?;
 
     if !status.success() {
         return Ok(status);
@@ -1,9 +1,28 @@
 use failure::{format_err, Fallible};
 use std::process::ExitStatus;
 
+use super::deps::Manifest;
+use super::options::{AllowList, RunOptions};
 use super::{cmd, util};
 
-pub fn run(files: Vec<&str>, stdin: &str) -> Fallible<ExitStatus> {
+pub const ALLOW_LIST: AllowList = AllowList {
+    compiler_flags: &["-race", "-tags=*"],
+    versions: &[],
+    env: &["GOGC", "GODEBUG", "GOMAXPROCS", "TZ"],
+    args: true,
+    dependencies: false,
+};
+
+pub fn run(
+    files: Vec<&str>,
+    stdin: &str,
+    options: &RunOptions,
+    _manifest: &Manifest,
+) -> Fallible<ExitStatus> {
     let work_dir = util::dirname(files[0])?;
-    cmd::run_stdin(work_dir, vec!["go", "run", files[0]], stdin)
+    let mut args = vec!["go", "run"];
+    args.extend(options.compiler_flags.iter().map(|s| s.as_str()));
+    args.push(files[0]);
+    args.extend(options.args.iter().map(|s| s.as_str()));
+    cmd::run_stdin_env(work_dir, args, stdin, &options.env)
 }
@@ -3,14 +3,14 @@ use std::ffi::OsStr;
 use std::path::Path;
 use std::process::ExitStatus;
 
-use super::{cmd, util};
+use super::{cache, cmd, util};
 
 pub fn run(files: Vec<&str>, stdin: &str) -> Fallible<ExitStatus> {
     let work_dir = util::dirname(files[0])?;
 
     let filename = util::basename(files[0])?;
-    let args = vec!["javac", filename];
-    let status: ExitStatus = cmd::run(work_dir, args)?;
+    let build = cache::Build::new("java", "javac", vec![files[0]], vec!["*.class"]);
+    let status: ExitStatus = build.run(work_dir, || cmd::run(work_dir, vec!["javac", filename]))?;
 
     if !status.success() {
         return Ok(status);
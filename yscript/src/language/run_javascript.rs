@@ -1,9 +1,88 @@
 use failure::{format_err, Fallible};
+use std::fs;
+use std::path::PathBuf;
 use std::process::ExitStatus;
 
-use super::{cmd, util};
+use super::deps::Manifest;
+use super::options::{AllowList, RunOptions};
+use super::{cmd, deps, util};
 
-pub fn run(files: Vec<&str>, stdin: &str) -> Fallible<ExitStatus> {
+pub const ALLOW_LIST: AllowList = AllowList {
+    compiler_flags: &[
+        "--harmony",
+        "--trace-warnings",
+        "--max-old-space-size=*",
+        "--stack-size=*",
+    ],
+    versions: &[],
+    env: &["NODE_ENV", "TZ"],
+    args: true,
+    dependencies: true,
+};
+
+pub fn run(
+    files: Vec<&str>,
+    stdin: &str,
+    options: &RunOptions,
+    manifest: &Manifest,
+) -> Fallible<ExitStatus> {
     let work_dir = util::dirname(files[0])?;
-    cmd::run_stdin(work_dir, vec!["node", files[0]], stdin)
+
+    let mut env = options.env.clone();
+    if !manifest.is_empty() {
+        let env_dir = npm_install(manifest)?;
+        let modules_dir = env_dir.join("node_modules");
+        env.insert(
+            "NODE_PATH".to_owned(),
+            deps::path_str(&modules_dir)?.to_owned(),
+        );
+    }
+
+    let mut args = vec!["node"];
+    args.extend(options.compiler_flags.iter().map(|s| s.as_str()));
+    args.push(files[0]);
+    args.extend(options.args.iter().map(|s| s.as_str()));
+    cmd::run_stdin_env(work_dir, args, stdin, &env)
+}
+
+// Installs packages of manifest from offline npm cache
+fn npm_install(manifest: &Manifest) -> Fallible<PathBuf> {
+    manifest.environment("javascript", "", |build_dir, deps_dir| {
+        let mut dependencies = serde_json::Map::new();
+        for dep in manifest.sorted() {
+            let version = if dep.version.is_empty() {
+                "*"
+            } else {
+                dep.version.as_str()
+            };
+            dependencies.insert(dep.name.clone(), version.into());
+        }
+        let package = serde_json::json!({
+            "name": "pad",
+            "private": true,
+            "dependencies": dependencies,
+        });
+        fs::write(
+            build_dir.join("package.json"),
+            serde_json::to_vec(&package)?,
+        )?;
+
+        let cache_dir = deps_dir.join("npm").join("cache");
+        let args = vec![
+            "npm",
+            "install",
+            "--offline",
+            "--no-audit",
+            "--no-fund",
+            "--no-package-lock",
+            "--loglevel=error",
+            "--cache",
+            deps::path_str(&cache_dir)?,
+        ];
+        let status = cmd::run(deps::path_str(build_dir)?, args)?;
+        if !status.success() {
+            return Err(format_err!("install dependencies failure: {}", status));
+        }
+        Ok(())
+    })
 }
@@ -3,7 +3,7 @@ use std::ffi::OsStr;
 use std::path::Path;
 use std::process::ExitStatus;
 
-use super::{cmd, util};
+use super::{cache, cmd, util};
 
 pub fn run(files: Vec<&str>, stdin: &str) -> Fallible<ExitStatus> {
     let work_dir = util::dirname(files[0])?;
@@ -11,8 +11,10 @@ pub fn run(files: Vec<&str>, stdin: &str) -> Fallible<ExitStatus> {
     let filename = util::basename(files[0])?;
     let main_jar = "main_kt.jar";
 
-    let args = vec!["kotlinc", "-d", main_jar, filename];
-    let status: ExitStatus = cmd::run(work_dir, args)?;
+    let build = cache::Build::new("kotlin", "kotlinc", vec![files[0]], vec![main_jar]);
+    let status: ExitStatus = build.run(work_dir, || {
+        cmd::run(work_dir, vec!["kotlinc", "-d", main_jar, filename])
+    })?;
 
     if !status.success() {
         return Ok(status);
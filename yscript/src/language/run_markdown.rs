@@ -1,9 +1,16 @@
 use failure::{format_err, Fallible};
+use std::fs;
+use std::path::Path;
 use std::process::ExitStatus;
 
 use super::{cmd, util};
+use crate::app::artifact::OUTPUT_DIR;
 
+// rendered as text in terminal, the source is an artifact rendered by clients
 pub fn run(files: Vec<&str>, stdin: &str) -> Fallible<ExitStatus> {
     let work_dir = util::dirname(files[0])?;
+    let output_dir = Path::new(work_dir).join(OUTPUT_DIR);
+    fs::create_dir_all(&output_dir)?;
+    fs::copy(files[0], output_dir.join(util::basename(files[0])?))?;
     cmd::run_stdin(work_dir, vec!["mdv", files[0]], stdin)
 }
@@ -1,9 +1,86 @@
 use failure::{format_err, Fallible};
+use std::fs;
+use std::path::PathBuf;
 use std::process::ExitStatus;
 
-use super::{cmd, util};
+use super::deps::Manifest;
+use super::options::{AllowList, RunOptions};
+use super::{cmd, deps, util};
 
-pub fn run(files: Vec<&str>, stdin: &str) -> Fallible<ExitStatus> {
+pub const ALLOW_LIST: AllowList = AllowList {
+    compiler_flags: &["-B", "-O", "-OO", "-u", "-X*", "-W*"],
+    // python3.x interpreter, python3 if none
+    versions: &["3.6", "3.7", "3.8", "3.9", "3.10", "3.11"],
+    env: &[
+        "PYTHONHASHSEED",
+        "PYTHONIOENCODING",
+        "PYTHONUNBUFFERED",
+        "PYTHONWARNINGS",
+        "TZ",
+    ],
+    args: true,
+    dependencies: true,
+};
+
+pub fn run(
+    files: Vec<&str>,
+    stdin: &str,
+    options: &RunOptions,
+    manifest: &Manifest,
+) -> Fallible<ExitStatus> {
     let work_dir = util::dirname(files[0])?;
-    cmd::run_stdin(work_dir, vec!["python3", files[0]], stdin)
+    let python = match options.version.as_ref() {
+        Some(version) => format!("python{}", version),
+        None => "python3".to_owned(),
+    };
+
+    let mut env = options.env.clone();
+    if !manifest.is_empty() {
+        let env_dir = pip_install(python.as_str(), manifest)?;
+        env.insert(
+            "PYTHONPATH".to_owned(),
+            deps::path_str(&env_dir)?.to_owned(),
+        );
+    }
+
+    let mut args = vec![python.as_str()];
+    args.extend(options.compiler_flags.iter().map(|s| s.as_str()));
+    args.push(files[0]);
+    args.extend(options.args.iter().map(|s| s.as_str()));
+    cmd::run_stdin_env(work_dir, args, stdin, &env)
+}
+
+// Installs distributions of manifest from offline wheels, per interpreter
+fn pip_install(python: &str, manifest: &Manifest) -> Fallible<PathBuf> {
+    manifest.environment("python3", python, |build_dir, deps_dir| {
+        let mut requirements = String::new();
+        for dep in manifest.sorted() {
+            let pinned = dep.version.starts_with(|c: char| c.is_ascii_digit());
+            let op = if pinned { "==" } else { "" };
+            requirements.push_str(format!("{}{}{}\n", dep.name, op, dep.version).as_str());
+        }
+        fs::write(build_dir.join("requirements.txt"), requirements)?;
+
+        let wheels_dir = deps_dir.join("pip").join("wheels");
+        let args = vec![
+            python,
+            "-m",
+            "pip",
+            "install",
+            "--quiet",
+            "--disable-pip-version-check",
+            "--no-index",
+            "--find-links",
+            deps::path_str(&wheels_dir)?,
+            "--target",
+            ".",
+            "--requirement",
+            "requirements.txt",
+        ];
+        let status = cmd::run(deps::path_str(build_dir)?, args)?;
+        if !status.success() {
+            return Err(format_err!("install dependencies failure: {}", status));
+        }
+        Ok(())
+    })
 }
@@ -1,15 +1,69 @@
 use failure::{format_err, Fallible};
+use std::collections::BTreeMap;
+use std::fs;
 use std::path::Path;
-use std::process::ExitStatus;
+use std::process::{self, ExitStatus};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use super::{cmd, util};
+use super::deps::Manifest;
+use super::options::{AllowList, RunOptions};
+use super::{cache, cmd, deps, util};
 
-pub fn run(files: Vec<&str>, stdin: &str) -> Fallible<ExitStatus> {
+pub const ALLOW_LIST: AllowList = AllowList {
+    compiler_flags: &[
+        "-O",
+        "-g",
+        "-Copt-level=*",
+        "-Cdebug-assertions=*",
+        "-Coverflow-checks=*",
+        "-A*",
+        "-W*",
+        "-D*",
+    ],
+    // --edition of rustc, 2015 if none
+    versions: &["2015", "2018"],
+    env: &["RUST_BACKTRACE", "RUST_MIN_STACK", "TZ"],
+    args: true,
+    dependencies: true,
+};
+
+pub fn run(
+    files: Vec<&str>,
+    stdin: &str,
+    options: &RunOptions,
+    manifest: &Manifest,
+) -> Fallible<ExitStatus> {
     let work_dir = util::dirname(files[0])?;
     let bin_file = "main_rs";
 
-    let args = vec!["rustc", "-o", bin_file, files[0]];
-    let status: ExitStatus = cmd::run(work_dir, args)?;
+    let edition_flag = options.version.as_ref().map(|v| format!("--edition={}", v));
+    let compiler = if manifest.is_empty() {
+        "rustc"
+    } else {
+        "cargo"
+    };
+    let mut build = cache::Build::new("rust", compiler, vec![files[0]], vec![bin_file]);
+    build
+        .flags
+        .extend(edition_flag.as_ref().map(|s| s.as_str()));
+    build
+        .flags
+        .extend(options.compiler_flags.iter().map(|s| s.as_str()));
+    if !manifest.is_empty() {
+        build.extra = manifest.hash("");
+    }
+
+    let status: ExitStatus = build.run(work_dir, || {
+        if manifest.is_empty() {
+            let mut args = vec!["rustc", "-o", bin_file];
+            args.extend(edition_flag.as_ref().map(|s| s.as_str()));
+            args.extend(options.compiler_flags.iter().map(|s| s.as_str()));
+            args.push(files[0]);
+            cmd::run(work_dir, args)
+        } else {
+            cargo_build(work_dir, files[0], bin_file, options, manifest)
+        }
+    })?;
 
     if !status.success() {
         return Ok(status);
@@ -19,5 +73,108 @@ pub fn run(files: Vec<&str>, stdin: &str) -> Fallible<ExitStatus> {
     let bin_path = bin_path_buf
         .to_str()
         .ok_or(format_err!("invalid bin_path"))?;
-    cmd::run_stdin(work_dir, vec![bin_path], stdin)
+    let mut args = vec![bin_path];
+    args.extend(options.args.iter().map(|s| s.as_str()));
+    cmd::run_stdin_env(work_dir, args, stdin, &options.env)
+}
+
+// Compiles main file as a cargo package with dependencies of manifest. Target directory
+// of the manifest environment is shared by runs, dependencies are compiled only once.
+fn cargo_build(
+    work_dir: &str,
+    main_file: &str,
+    bin_file: &str,
+    options: &RunOptions,
+    manifest: &Manifest,
+) -> Fallible<ExitStatus> {
+    let edition = options
+        .version
+        .as_ref()
+        .map(|s| s.as_str())
+        .unwrap_or("2015");
+    let env_dir = manifest.environment("rust", "", |build_dir, deps_dir| {
+        fs::write(build_dir.join("main.rs"), "fn main() {}\n")?;
+        write_cargo_package(build_dir, "main", "main.rs", edition, manifest, deps_dir)?;
+        let args = vec!["cargo", "build", "--offline", "--quiet"];
+        let status = cmd::run(deps::path_str(build_dir)?, args)?;
+        if !status.success() {
+            return Err(format_err!("build dependencies failure: {}", status));
+        }
+        Ok(())
+    })?;
+
+    // unique binary name, concurrent runs of a manifest build into the same target
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    let bin_name = format!("pad_{}_{}", process::id(), nanos);
+
+    let work_path = Path::new(work_dir);
+    let main_file = util::basename(main_file)?;
+    write_cargo_package(
+        work_path,
+        bin_name.as_str(),
+        main_file,
+        edition,
+        manifest,
+        manifest.deps_dir()?,
+    )?;
+    // same versions as compiled into environment
+    fs::copy(env_dir.join("Cargo.lock"), work_path.join("Cargo.lock"))?;
+
+    let target_dir = env_dir.join("target");
+    let mut env = BTreeMap::new();
+    env.insert(
+        "CARGO_TARGET_DIR".to_owned(),
+        deps::path_str(&target_dir)?.to_owned(),
+    );
+    let mut args = vec!["cargo", "rustc", "--offline", "--quiet", "--bin"];
+    args.push(bin_name.as_str());
+    args.push("--");
+    args.extend(options.compiler_flags.iter().map(|s| s.as_str()));
+    let status = cmd::run_stdin_env(work_dir, args, "", &env)?;
+    if !status.success() {
+        return Ok(status);
+    }
+
+    let target_bin = target_dir.join("debug").join(bin_name.as_str());
+    fs::copy(&target_bin, work_path.join(bin_file))?;
+    fs::remove_file(&target_bin)?;
+    Ok(status)
+}
+
+fn write_cargo_package(
+    dir: &Path,
+    bin_name: &str,
+    main_file: &str,
+    edition: &str,
+    manifest: &Manifest,
+    deps_dir: &Path,
+) -> Fallible<()> {
+    let mut cargo_toml = format!(
+        "[package]\nname = \"pad\"\nversion = \"0.1.0\"\nedition = \"{}\"\n\n\
+         [[bin]]\nname = \"{}\"\npath = \"{}\"\n\n[dependencies]\n",
+        edition, bin_name, main_file
+    );
+    for dep in manifest.sorted() {
+        let version = if dep.version.is_empty() {
+            "*"
+        } else {
+            dep.version.as_str()
+        };
+        cargo_toml.push_str(format!("\"{}\" = \"{}\"\n", dep.name, version).as_str());
+    }
+    fs::write(dir.join("Cargo.toml"), cargo_toml)?;
+
+    // crates.io replaced by vendored crates
+    let vendor_dir = deps_dir.join("cargo").join("vendor");
+    let cargo_config = format!(
+        "[source.crates-io]\nreplace-with = \"vendored-sources\"\n\n\
+         [source.vendored-sources]\ndirectory = {:?}\n",
+        deps::path_str(&vendor_dir)?
+    );
+    fs::create_dir_all(dir.join(".cargo"))?;
+    fs::write(dir.join(".cargo").join("config"), cargo_config)?;
+    Ok(())
 }
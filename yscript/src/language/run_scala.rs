@@ -1,9 +1,24 @@
 use failure::{format_err, Fallible};
 use std::process::ExitStatus;
 
-use super::{cmd, util};
+use super::{cache, cmd, util};
 
 pub fn run(files: Vec<&str>, stdin: &str) -> Fallible<ExitStatus> {
     let work_dir = util::dirname(files[0])?;
-    cmd::run_stdin(work_dir, vec!["scala", "-nocompdaemon", files[0]], stdin)
+    // scalac sets main class of the jar manifest
+    let main_jar = "main_scala.jar";
+
+    let build = cache::Build::new("scala", "scalac", vec![files[0]], vec![main_jar]);
+    let status: ExitStatus = build.run(work_dir, || {
+        cmd::run(
+            work_dir,
+            vec!["scalac", "-nocompdaemon", "-d", main_jar, files[0]],
+        )
+    })?;
+
+    if !status.success() {
+        return Ok(status);
+    }
+
+    cmd::run_stdin(work_dir, vec!["scala", "-nocompdaemon", main_jar], stdin)
 }
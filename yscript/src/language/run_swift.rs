@@ -1,9 +1,25 @@
 use failure::{format_err, Fallible};
+use std::path::Path;
 use std::process::ExitStatus;
 
-use super::{cmd, util};
+use super::{cache, cmd, util};
 
 pub fn run(files: Vec<&str>, stdin: &str) -> Fallible<ExitStatus> {
     let work_dir = util::dirname(files[0])?;
-    cmd::run_stdin(work_dir, vec!["swift", files[0]], stdin)
+    let bin_file = "main_swift";
+
+    let build = cache::Build::new("swift", "swiftc", vec![files[0]], vec![bin_file]);
+    let status: ExitStatus = build.run(work_dir, || {
+        cmd::run(work_dir, vec!["swiftc", "-o", bin_file, files[0]])
+    })?;
+
+    if !status.success() {
+        return Ok(status);
+    }
+
+    let bin_path_buf = Path::new(work_dir).join(bin_file);
+    let bin_path = bin_path_buf
+        .to_str()
+        .ok_or(format_err!("invalid bin_path"))?;
+    cmd::run_stdin(work_dir, vec![bin_path], stdin)
 }
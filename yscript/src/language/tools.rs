@@ -0,0 +1,245 @@
+use failure::{format_err, Fallible};
+use regex::Regex;
+use std::collections;
+
+use super::{cmd, util};
+
+// Formatters and linters of languages. Formatters rewrite the first file in place,
+// linters report diagnostics of all files with positions of the source, 1-based like editors.
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    pub line: u32,
+    pub column: u32,
+    pub severity: Severity,
+    pub message: String,
+}
+
+struct Linter {
+    // source files are appended
+    args: &'static [&'static str],
+    parse: fn(output: &str, sources: &[&str]) -> Vec<Diagnostic>,
+}
+
+type FormatterMap = collections::HashMap<&'static str, &'static [&'static str]>;
+type LinterMap = collections::HashMap<&'static str, Linter>;
+
+lazy_static! {
+    // formatters rewriting the file given as last argument
+    static ref FORMATTERS: FormatterMap = {
+        let mut formatters = FormatterMap::new();
+        let clang_format: &'static [&'static str] = &["clang-format", "-i", "-style=file"];
+        let prettier: &'static [&'static str] = &["prettier", "--write", "--loglevel", "warn"];
+
+        formatters.insert("c", clang_format);
+        formatters.insert("cpp", clang_format);
+        formatters.insert("objc", clang_format);
+        formatters.insert("java", clang_format);
+        formatters.insert("crystal", &["crystal", "tool", "format"]);
+        formatters.insert("elixir", &["mix", "format"]);
+        formatters.insert("go", &["gofmt", "-w"]);
+        formatters.insert("javascript", prettier);
+        formatters.insert("typescript", prettier);
+        formatters.insert("kotlin", &["ktlint", "--format"]);
+        formatters.insert("python2", &["black", "--quiet", "--target-version", "py27"]);
+        formatters.insert("python3", &["black", "--quiet"]);
+        formatters.insert("rust", &["rustfmt", "--edition", "2018"]);
+
+        formatters
+    };
+
+    static ref LINTERS: LinterMap = {
+        let mut linters = LinterMap::new();
+        let clang: &'static [&'static str] = &["clang", "-fsyntax-only", "-Wall", "-Wextra"];
+
+        linters.insert("bash", Linter {
+            args: &["shellcheck", "--format=gcc"],
+            parse: parse_gcc,
+        });
+        linters.insert("c", Linter { args: clang, parse: parse_gcc });
+        linters.insert("cpp", Linter {
+            args: &["clang++", "-fsyntax-only", "-Wall", "-Wextra", "-std=c++11"],
+            parse: parse_gcc,
+        });
+        linters.insert("objc", Linter { args: clang, parse: parse_gcc });
+        linters.insert("go", Linter { args: &["go", "vet"], parse: parse_gcc });
+        linters.insert("javascript", Linter {
+            args: &[
+                "eslint", "--no-eslintrc", "--env", "es2017,node",
+                "--parser-options", "ecmaVersion:2018",
+                "--rule", "no-undef:error", "--rule", "no-unused-vars:warn",
+                "--format", "compact",
+            ],
+            parse: parse_eslint,
+        });
+        linters.insert("kotlin", Linter { args: &["ktlint"], parse: parse_gcc });
+        linters.insert("python3", Linter {
+            args: &["flake8", "--max-line-length", "100"],
+            parse: parse_gcc,
+        });
+        linters.insert("rust", Linter {
+            args: &[
+                "clippy-driver", "--edition", "2018", "--crate-type", "bin",
+                "--emit", "metadata", "--out-dir", ".", "--error-format", "json",
+            ],
+            parse: parse_rustc_json,
+        });
+
+        linters
+    };
+
+    // file:line:column: [severity:] message, of compilers and most linters
+    static ref GCC_LINE: Regex = Regex::new(
+        r"^(?P<file>[^:\s][^:]*):(?P<line>\d+):(?P<column>\d+):\s*(?:(?P<severity>fatal error|error|warning|note|info|style)\s*:\s*)?(?P<message>.+)$"
+    ).unwrap();
+
+    // file: line 1, col 2, Error - message (rule)
+    static ref ESLINT_LINE: Regex = Regex::new(
+        r"^(?P<file>[^:]+): line (?P<line>\d+), col (?P<column>\d+), (?P<severity>\w+) - (?P<message>.+)$"
+    ).unwrap();
+}
+
+/// Formatted content of the first file, error output of formatter if the source is invalid
+pub fn format_code(lang: &str, file_paths: Vec<&str>) -> Fallible<String> {
+    let formatter = FORMATTERS
+        .get(lang)
+        .ok_or(format_err!("formatter not supported: {}", lang))?;
+    let work_dir = util::dirname(file_paths[0])?;
+    let mut args = formatter.to_vec();
+    args.push(util::basename(file_paths[0])?);
+
+    let output = cmd::output(work_dir, args)?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let message = if stderr.trim().is_empty() {
+            stdout
+        } else {
+            stderr
+        };
+        return Err(format_err!("{}", message.trim()));
+    }
+
+    let content = util::read_file(file_paths[0])?;
+    let content =
+        String::from_utf8(content).map_err(|_| format_err!("formatted code is not utf-8"))?;
+    Ok(content)
+}
+
+/// Diagnostics of linter, sorted by position
+pub fn lint_code(lang: &str, file_paths: Vec<&str>) -> Fallible<Vec<Diagnostic>> {
+    let linter = LINTERS
+        .get(lang)
+        .ok_or(format_err!("linter not supported: {}", lang))?;
+    let work_dir = util::dirname(file_paths[0])?;
+    let mut sources = vec![];
+    for file_path in file_paths.iter() {
+        sources.push(util::basename(file_path)?);
+    }
+    let mut args = linter.args.to_vec();
+    // other rust files are modules of the first one
+    match lang {
+        "rust" => args.push(sources[0]),
+        _ => args.extend(sources.iter()),
+    }
+
+    // linters exit with failure when they found problems, output tells the result
+    let output = cmd::output(work_dir, args)?;
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push_str(String::from_utf8_lossy(&output.stderr).as_ref());
+
+    let mut diagnostics = (linter.parse)(text.as_str(), &sources);
+    if diagnostics.is_empty() && !output.status.success() && output.status.code() != Some(1) {
+        return Err(format_err!("linter failure: {}", text.trim()));
+    }
+    diagnostics.sort_by_key(|d| (d.line, d.column));
+    Ok(diagnostics)
+}
+
+fn severity_of(name: &str, default: Severity) -> Severity {
+    match name.to_lowercase().as_str() {
+        "error" | "fatal error" => Severity::Error,
+        "warning" => Severity::Warning,
+        "note" | "info" | "style" | "help" => Severity::Info,
+        _ => default,
+    }
+}
+
+fn is_source(file: &str, sources: &[&str]) -> bool {
+    let name = util::basename(file).unwrap_or(file);
+    sources.iter().any(|source| *source == name)
+}
+
+fn parse_lines(
+    output: &str,
+    sources: &[&str],
+    pattern: &Regex,
+    default: Severity,
+) -> Vec<Diagnostic> {
+    output
+        .lines()
+        .filter_map(|line| pattern.captures(line.trim_end()))
+        .filter(|caps| is_source(&caps["file"], sources))
+        .map(|caps| Diagnostic {
+            line: caps["line"].parse().unwrap_or(1),
+            column: caps["column"].parse().unwrap_or(1),
+            severity: caps
+                .name("severity")
+                .map(|s| severity_of(s.as_str(), default))
+                .unwrap_or(default),
+            message: caps["message"].to_owned(),
+        })
+        .collect()
+}
+
+// linters of style, e.g. flake8, ktlint, report without severity
+fn parse_gcc(output: &str, sources: &[&str]) -> Vec<Diagnostic> {
+    parse_lines(output, sources, &GCC_LINE, Severity::Warning)
+}
+
+fn parse_eslint(output: &str, sources: &[&str]) -> Vec<Diagnostic> {
+    parse_lines(output, sources, &ESLINT_LINE, Severity::Warning)
+}
+
+#[derive(Deserialize)]
+struct RustcDiagnostic {
+    message: String,
+    level: String,
+    spans: Vec<RustcSpan>,
+}
+
+#[derive(Deserialize)]
+struct RustcSpan {
+    file_name: String,
+    line_start: u32,
+    column_start: u32,
+    is_primary: bool,
+}
+
+// one json diagnostic per line
+fn parse_rustc_json(output: &str, sources: &[&str]) -> Vec<Diagnostic> {
+    output
+        .lines()
+        .filter_map(|line| serde_json::from_str::<RustcDiagnostic>(line).ok())
+        .filter_map(|diag| {
+            let span = diag
+                .spans
+                .iter()
+                .find(|span| span.is_primary && is_source(&span.file_name, sources))?;
+            Some(Diagnostic {
+                line: span.line_start,
+                column: span.column_start,
+                severity: severity_of(diag.level.as_str(), Severity::Info),
+                message: diag.message.clone(),
+            })
+        })
+        .collect()
+}